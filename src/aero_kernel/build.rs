@@ -21,6 +21,7 @@ use std::error::Error;
 use std::ffi::OsString;
 use std::fs::DirEntry;
 use std::path::Path;
+use std::process::Command;
 
 // this is all magic, yes dont ever let anyone see this shit
 
@@ -69,6 +70,34 @@ fn main() -> Result<(), Box<dyn Error>> {
         }
     })?;
 
+    // Assemble the flat binaries (eg. the vDSO image), which are included in the kernel
+    // using `include_bytes!`.
+    let out_dir = std::env::var("OUT_DIR").expect("out dir is not set");
+
+    visit_dirs(Path::new("src"), &mut |entry| {
+        let path = entry.path();
+
+        match path.extension() {
+            Some(ext) if ext.eq(&OsString::from("real")) => {
+                let stem = path.file_stem().expect("Failed to get file name");
+                let output = Path::new(&out_dir).join(stem).with_extension("bin");
+
+                let status = Command::new("nasm")
+                    .arg("-fbin")
+                    .arg(&path)
+                    .arg("-o")
+                    .arg(&output)
+                    .status()
+                    .expect("failed to run nasm");
+
+                assert!(status.success(), "failed to assemble {:?}", path);
+                println!("cargo:rerun-if-changed={}", path.display());
+            }
+
+            _ => (),
+        }
+    })?;
+
     // more magic
     inc_files = inc_files
         .iter()
@@ -15,13 +15,38 @@
 // You should have received a copy of the GNU General Public License
 // along with Aero. If not, see <https://www.gnu.org/licenses/>.
 
+//! The HPET (High Precision Event Timer) provides a monotonically increasing main counter
+//! with a fixed frequency, which is used as a clocksource and to calibrate the TSC.
+//!
+//! **Notes**: <https://wiki.osdev.org/HPET>
+
 use core::ptr;
 
+use spin::Once;
+
+use crate::mem::paging::{PhysAddr, VirtAddr};
+
 use super::sdt::Sdt;
 use super::GenericAddressStructure;
 
 pub const SIGNATURE: &str = "HPET";
 
+/// General Capabilities and ID Register.
+const HPET_GCAP_ID: u64 = 0x00;
+/// General Configuration Register.
+const HPET_GEN_CONF: u64 = 0x10;
+/// Main Counter Value Register.
+const HPET_MAIN_COUNTER: u64 = 0xf0;
+
+/// Set if the main counter is 64 bits wide.
+const GCAP_COUNT_SIZE: u64 = 1 << 13;
+/// Starts the main counter.
+const GEN_CONF_ENABLE: u64 = 1 << 0;
+
+const FEMTOS_PER_SEC: u64 = 1_000_000_000_000_000;
+
+static HPET: Once<HpetDevice> = Once::new();
+
 #[repr(C, packed)]
 pub(super) struct Hpet {
     header: Sdt,
@@ -38,4 +63,102 @@ impl Hpet {
     pub fn new(sdt: &'static Sdt) -> Self {
         unsafe { ptr::read((sdt as *const Sdt) as *const Self) }
     }
+
+    /// Enables the main counter of the HPET described by this table.
+    pub fn init(&self) {
+        let base = PhysAddr::new(self.base_address.address).as_hhdm_virt();
+
+        HPET.call_once(|| {
+            let device = HpetDevice::new(base);
+            log::debug!(
+                "hpet: found HPET at {:#x} (frequency={}Hz, 64-bit={})",
+                { self.base_address.address },
+                device.frequency(),
+                device.is_64bit
+            );
+
+            device
+        });
+    }
+}
+
+pub struct HpetDevice {
+    base: VirtAddr,
+    /// The period of the main counter in femtoseconds.
+    period: u64,
+    is_64bit: bool,
+}
+
+impl HpetDevice {
+    fn new(base: VirtAddr) -> Self {
+        let mut this = Self {
+            base,
+            period: 0,
+            is_64bit: false,
+        };
+
+        let capabilities = this.read(HPET_GCAP_ID);
+
+        this.period = capabilities >> 32;
+        this.is_64bit = capabilities & GCAP_COUNT_SIZE != 0;
+
+        let config = this.read(HPET_GEN_CONF);
+        this.write(HPET_GEN_CONF, config | GEN_CONF_ENABLE);
+
+        this
+    }
+
+    fn read(&self, register: u64) -> u64 {
+        unsafe { (self.base + register).as_ptr::<u64>().read_volatile() }
+    }
+
+    fn write(&self, register: u64, value: u64) {
+        unsafe {
+            (self.base + register)
+                .as_mut_ptr::<u64>()
+                .write_volatile(value)
+        }
+    }
+
+    /// Returns the current value of the main counter.
+    pub fn counter(&self) -> u64 {
+        if self.is_64bit {
+            self.read(HPET_MAIN_COUNTER)
+        } else {
+            self.read(HPET_MAIN_COUNTER) & u32::MAX as u64
+        }
+    }
+
+    /// Returns the amount of main counter ticks elapsed since the counter value `since`,
+    /// taking care of the wrap around of a 32-bit main counter.
+    pub fn elapsed(&self, since: u64) -> u64 {
+        let elapsed = self.counter().wrapping_sub(since);
+
+        if self.is_64bit {
+            elapsed
+        } else {
+            elapsed & u32::MAX as u64
+        }
+    }
+
+    /// Returns the frequency of the main counter in Hz.
+    pub fn frequency(&self) -> u64 {
+        FEMTOS_PER_SEC / self.period
+    }
+
+    /// Converts the provided amount of main counter ticks to nanoseconds.
+    pub fn ticks_to_nanos(&self, ticks: u64) -> u64 {
+        ((ticks as u128 * self.period as u128) / 1_000_000) as u64
+    }
+
+    /// Returns [`true`] if the main counter is 64 bits wide. A 32-bit main counter wraps
+    /// around within minutes and is therefore not suitable as a clocksource.
+    pub fn is_64bit(&self) -> bool {
+        self.is_64bit
+    }
+}
+
+/// Returns the HPET, if one was found in the ACPI tables.
+pub fn get() -> Option<&'static HpetDevice> {
+    HPET.get()
 }
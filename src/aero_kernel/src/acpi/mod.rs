@@ -110,12 +110,6 @@ pub fn init(rsdp_address: VirtAddr) {
 
     let acpi_table = get_acpi_table();
 
-    macro init_table($sig:path => $ty:ty) {
-        if let Some(table) = acpi_table.lookup_entry($sig, 0) {
-            <$ty>::new(table);
-        }
-    }
-
     if let Some(header) = acpi_table.lookup_entry(mcfg::SIGNATURE, 0) {
         unsafe {
             let mcfg: &'static Mcfg = header.as_ref();
@@ -138,5 +132,7 @@ pub fn init(rsdp_address: VirtAddr) {
         }
     }
 
-    init_table!(hpet::SIGNATURE => Hpet);
+    if let Some(header) = acpi_table.lookup_entry(hpet::SIGNATURE, 0) {
+        Hpet::new(header).init();
+    }
 }
@@ -17,6 +17,7 @@
 
 use crate::fs::cache::DirCacheItem;
 use crate::mem::paging::*;
+use crate::mem::AddressSpace;
 use crate::syscall::ExecArgs;
 use crate::userland::vm::Vm;
 
@@ -57,6 +58,22 @@ impl ArchTask {
     pub fn fork(&self) -> Result<Self, MapToError<Size4KiB>> {
         unimplemented!()
     }
+
+    pub fn user_stack_pointer(&self) -> u64 {
+        unimplemented!()
+    }
+
+    pub fn is_user(&self) -> bool {
+        unimplemented!()
+    }
+
+    pub fn address_space(&mut self) -> &mut AddressSpace {
+        unimplemented!()
+    }
+
+    pub fn set_single_step(&mut self, enable: bool) {
+        unimplemented!()
+    }
 }
 
 pub fn userland_last_address() -> VirtAddr {
@@ -21,10 +21,26 @@ pub fn get_uptime_ticks() -> usize {
     unimplemented!()
 }
 
+pub fn get_monotonic_nanos() -> u64 {
+    unimplemented!()
+}
+
+pub fn get_monotonic_clock() -> TimeSpec {
+    unimplemented!()
+}
+
 pub fn get_realtime_clock() -> TimeSpec {
     unimplemented!()
 }
 
+pub fn set_realtime_clock(_time: u64) {
+    unimplemented!()
+}
+
+pub fn get_clock_resolution() -> TimeSpec {
+    unimplemented!()
+}
+
 pub fn init() {
     unimplemented!()
 }
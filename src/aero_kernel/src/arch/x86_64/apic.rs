@@ -16,7 +16,7 @@
 // along with Aero. If not, see <https://www.gnu.org/licenses/>.
 
 use core::ptr;
-use core::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use core::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, AtomicUsize, Ordering};
 
 use crate::arch::interrupts;
 use crate::arch::interrupts::InterruptStack;
@@ -74,6 +74,13 @@ pub static CPU_COUNT: AtomicUsize = AtomicUsize::new(0);
 
 static BSP_READY: AtomicBool = AtomicBool::new(false);
 
+/// The LVT error vector, shared between the local APICs of all of the CPUs.
+static LVT_ERROR_VECTOR: Once<u8> = Once::new();
+
+/// The local APIC timer frequency calibrated by the BSP. The APs reuse this value instead of
+/// calibrating their own timer since the PIT is already in use by the time they are started.
+static CALIBRATED_TIMER_FREQUENCY: AtomicU32 = AtomicU32::new(0);
+
 #[derive(Debug, Clone, Copy, PartialEq)]
 pub enum ApicType {
     Xapic,
@@ -144,8 +151,12 @@ impl LocalApic {
             // Enable local APIC; set spurious interrupt vector.
             self.write(XAPIC_SVR, 0x100 | APIC_SPURIOUS_VECTOR);
 
-            let lvt_err_vector = interrupts::allocate_vector();
-            interrupts::register_handler(lvt_err_vector, lapic_error_handler);
+            let lvt_err_vector = *LVT_ERROR_VECTOR.call_once(|| {
+                let vector = interrupts::allocate_vector();
+                interrupts::register_handler(vector, lapic_error_handler);
+
+                vector
+            });
 
             // Set up LVT (Local Vector Table) error.
             self.write(XAPIC_LVT_ERROR, lvt_err_vector as u32);
@@ -229,6 +240,7 @@ impl LocalApic {
             let timer_frequency = (SAMPLES / pit_ticks as u32) * time::PIT_DIVIDEND as u32;
 
             *LAPIC_TIMER_FREQUENCY = timer_frequency;
+            CALIBRATED_TIMER_FREQUENCY.store(timer_frequency, Ordering::SeqCst);
         }
 
         self.timer_stop();
@@ -473,3 +485,21 @@ pub fn init() -> ApicType {
 
     apic_type
 }
+
+/// Initialize the local APIC of the current application processor.
+///
+/// ## Panics
+/// * If the BSP has not initialized its local APIC yet.
+pub fn init_ap() {
+    let mut local_apic = get_local_apic();
+    local_apic.init();
+
+    let timer_frequency = CALIBRATED_TIMER_FREQUENCY.load(Ordering::SeqCst);
+    assert_ne!(timer_frequency, 0, "apic: timer was not calibrated by the BSP");
+
+    unsafe {
+        *LAPIC_TIMER_FREQUENCY = timer_frequency;
+    }
+
+    local_apic.timer_stop();
+}
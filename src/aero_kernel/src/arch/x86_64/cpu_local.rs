@@ -96,3 +96,9 @@ pub fn init(cpu_id: usize) {
         *CPUID = cpu_id;
     }
 }
+
+/// Returns the logical ID of the current CPU. The BSP is always CPU `0` and the APs
+/// are numbered contiguously after it.
+pub fn get_cpuid() -> usize {
+    unsafe { *CPUID }
+}
@@ -15,6 +15,8 @@
 // You should have received a copy of the GNU General Public License
 // along with Aero. If not, see <https://www.gnu.org/licenses/>.
 
+use aero_syscall::signal::*;
+
 use super::{io, InterruptErrorStack};
 
 use crate::arch::controlregs;
@@ -28,54 +30,150 @@ pub static mut PF_RESUME: VirtAddr = VirtAddr::new(0);
 
 const LOG_PF_PTABLE: bool = true;
 
+/// Halts the kernel after an unrecoverable exception.
+fn kernel_exception(message: &str, stack: &InterruptErrorStack) -> ! {
+    unwind::prepare_panic();
+
+    log::error!("EXCEPTION: {}", message);
+    log::error!("Stack: {:#x?}", stack);
+
+    unwind::unwind_stack_trace();
+
+    unsafe {
+        loop {
+            super::halt();
+        }
+    }
+}
+
+/// Sends `signal` to the current task for an exception raised in userland.
+fn user_exception(message: &str, signal: usize, code: i32, address: usize) {
+    let task = scheduler::get_scheduler().current_task();
+
+    log::debug!(
+        "{} in userland: (tid={}, pid={}, signal={}, code={}, address={:#x})",
+        message,
+        task.tid().as_usize(),
+        task.pid().as_usize(),
+        signal,
+        code,
+        address
+    );
+
+    task.signals()
+        .force(signal, SigInfo::from_fault(signal, code, address));
+}
+
 macro interrupt_exception(fn $name:ident() => $message:expr) {
     pub fn $name(stack: &mut InterruptErrorStack) {
-        unwind::prepare_panic();
+        kernel_exception($message, stack)
+    }
+}
 
-        log::error!("EXCEPTION: {}", $message);
-        log::error!("Stack: {:#x?}", stack);
+/// Exceptions that deliver `$signal` if they are raised in userland. The faulting address
+/// is the instruction pointer if the handler is declared with `rip`, or `0` otherwise.
+macro signal_exception {
+    (fn $name:ident() => $message:expr, $signal:expr, $code:expr, rip) => {
+        pub fn $name(stack: &mut InterruptErrorStack) {
+            if !stack.stack.iret.is_user() {
+                kernel_exception($message, stack)
+            }
 
-        unwind::unwind_stack_trace();
+            let rip = stack.stack.iret.rip as usize;
+            user_exception($message, $signal, $code, rip);
+        }
+    },
 
-        unsafe {
-            loop {
-                super::halt();
+    (fn $name:ident() => $message:expr, $signal:expr, $code:expr) => {
+        pub fn $name(stack: &mut InterruptErrorStack) {
+            if !stack.stack.iret.is_user() {
+                kernel_exception($message, stack)
             }
+
+            user_exception($message, $signal, $code, 0);
         }
-    }
+    },
 }
 
-interrupt_exception!(fn divide_by_zero() => "Division by zero");
-interrupt_exception!(fn debug() => "Debug");
+signal_exception!(fn divide_by_zero() => "Division by zero", SIGFPE, FPE_INTDIV, rip);
+signal_exception!(fn debug() => "Debug", SIGTRAP, TRAP_TRACE, rip);
 interrupt_exception!(fn non_maskable() => "Non Maskable");
-interrupt_exception!(fn overflow() => "Stack Overflow");
-interrupt_exception!(fn bound_range() => "Out of Bounds");
-interrupt_exception!(fn device_not_available() => "Device not Available");
+signal_exception!(fn overflow() => "Stack Overflow", SIGSEGV, SI_KERNEL);
+signal_exception!(fn bound_range() => "Out of Bounds", SIGSEGV, SI_KERNEL);
+signal_exception!(fn device_not_available() => "Device not Available", SIGILL, ILL_COPROC, rip);
 interrupt_exception!(fn double_fault() => "Double Fault");
-interrupt_exception!(fn invalid_tss() => "Invalid TSS");
-interrupt_exception!(fn segment_not_present() => "Segment not Present");
-interrupt_exception!(fn stack_segment() => "Stack Segment Fault");
-interrupt_exception!(fn protection() => "Protection Fault");
-interrupt_exception!(fn fpu_fault() => "FPU floating point fault");
-interrupt_exception!(fn alignment_check() => "Alignment check fault");
+signal_exception!(fn invalid_tss() => "Invalid TSS", SIGSEGV, SI_KERNEL);
+signal_exception!(fn segment_not_present() => "Segment not Present", SIGBUS, SI_KERNEL);
+signal_exception!(fn stack_segment() => "Stack Segment Fault", SIGBUS, SI_KERNEL);
+signal_exception!(fn protection() => "Protection Fault", SIGSEGV, SI_KERNEL);
+signal_exception!(fn alignment_check() => "Alignment check fault", SIGBUS, BUS_ADRALN);
 interrupt_exception!(fn machine_check() => "Machine check fault");
 interrupt_exception!(fn virtualization() => "Virtualization fault");
 interrupt_exception!(fn security() => "Security exception");
 
-pub fn simd(stack: &mut InterruptErrorStack) {
-    unwind::prepare_panic();
+/// Returns the `si_code` of a floating point exception from the exception `status` flags
+/// and the exception `mask` (the layout of the x87 and SSE flags is the same), or [`None`]
+/// if all of the raised exceptions are masked.
+fn fpu_exception_code(status: u32, mask: u32) -> Option<i32> {
+    let unmasked = status & !mask & 0x3f;
+
+    if unmasked & 0x01 != 0 {
+        Some(FPE_FLTINV) // invalid operation
+    } else if unmasked & 0x04 != 0 {
+        Some(FPE_FLTDIV) // divide by zero
+    } else if unmasked & 0x08 != 0 {
+        Some(FPE_FLTOVF) // overflow
+    } else if unmasked & 0x12 != 0 {
+        Some(FPE_FLTUND) // underflow or denormal
+    } else if unmasked & 0x20 != 0 {
+        Some(FPE_FLTRES) // precision
+    } else {
+        None
+    }
+}
 
-    log::error!("EXCEPTION: SIMD floating point fault");
-    log::error!("Stack: {:#x?}", stack);
-    log::error!("MXCSR: {:?}", controlregs::read_mxcsr());
+pub fn fpu_fault(stack: &mut InterruptErrorStack) {
+    if !stack.stack.iret.is_user() {
+        kernel_exception("FPU floating point fault", stack)
+    }
 
-    unwind::unwind_stack_trace();
+    let status: u16;
+    let mut control: u16 = 0;
 
     unsafe {
-        loop {
-            super::halt();
+        asm!("fnstsw ax", out("ax") status, options(nomem, nostack, preserves_flags));
+        asm!("fnstcw [{}]", in(reg) &mut control, options(nostack, preserves_flags));
+    }
+
+    if let Some(code) = fpu_exception_code(status as u32, control as u32) {
+        let rip = stack.stack.iret.rip as usize;
+        user_exception("FPU floating point fault", SIGFPE, code, rip);
+    }
+}
+
+pub fn simd(stack: &mut InterruptErrorStack) {
+    if !stack.stack.iret.is_user() {
+        unwind::prepare_panic();
+
+        log::error!("EXCEPTION: SIMD floating point fault");
+        log::error!("Stack: {:#x?}", stack);
+        log::error!("MXCSR: {:?}", controlregs::read_mxcsr());
+
+        unwind::unwind_stack_trace();
+
+        unsafe {
+            loop {
+                super::halt();
+            }
         }
     }
+
+    let status = controlregs::read_mxcsr().bits();
+
+    if let Some(code) = fpu_exception_code(status, status >> 7) {
+        let rip = stack.stack.iret.rip as usize;
+        user_exception("SIMD floating point fault", SIGFPE, code, rip);
+    }
 }
 
 pub fn invalid_opcode(stack: &mut InterruptErrorStack) {
@@ -98,22 +196,22 @@ pub fn invalid_opcode(stack: &mut InterruptErrorStack) {
     }
 
     // Otherwise handle the exception as normal.
-
-    unwind::prepare_panic();
-
-    log::error!("EXCEPTION: Invalid Opcode");
-    log::error!("Stack: {:#x?}", stack);
-
-    unwind::unwind_stack_trace();
-
-    unsafe {
-        loop {
-            super::halt();
-        }
+    if !stack.stack.iret.is_user() {
+        kernel_exception("Invalid Opcode", stack)
     }
+
+    let rip = stack.stack.iret.rip as usize;
+    user_exception("Invalid Opcode", SIGILL, ILL_ILLOPN, rip);
 }
 
 pub fn breakpoint(stack: &mut InterruptErrorStack) {
+    // The RIP on the stack points after the int3 instruction, which is what the signal
+    // handler expects.
+    if stack.stack.iret.is_user() {
+        user_exception("Breakpoint", SIGTRAP, SI_KERNEL, 0);
+        return;
+    }
+
     // We will need to prevent RIP from going out of sync with
     // instructions.
     //
@@ -186,10 +284,18 @@ pub(super) fn page_fault(stack: &mut InterruptErrorStack) {
 
             unwind::unwind_stack_trace();
 
-            let task = scheduler::get_scheduler().current_task();
-            task.signal(aero_syscall::signal::SIGSEGV);
-        } else if !signal {
-        } else {
+            let code = if reason.contains(PageFaultErrorCode::PROTECTION_VIOLATION) {
+                SEGV_ACCERR
+            } else {
+                SEGV_MAPERR
+            };
+
+            let address = accessed_address.as_u64() as usize;
+            task.signals()
+                .force(SIGSEGV, SigInfo::from_fault(SIGSEGV, code, address));
+
+            return;
+        } else if signal {
             return;
         }
     }
@@ -231,6 +231,19 @@ pub fn init() {
     }
 }
 
+/// Loads the IDT on the current application processor. The interrupt handlers are shared
+/// between all of the CPUs and are installed by the BSP in [`init`].
+pub fn init_ap() {
+    unsafe {
+        let idt_descriptor = IdtDescriptor::new(
+            ((IDT.len() * size_of::<IdtEntry>()) - 1) as u16,
+            (&IDT as *const _) as u64,
+        );
+
+        load_idt(&idt_descriptor);
+    }
+}
+
 #[inline(always)]
 unsafe fn load_idt(idt_descriptor: &IdtDescriptor) {
     asm!("lidt [{}]", in(reg) idt_descriptor, options(nostack));
@@ -218,6 +218,13 @@ impl PicController {
 #[no_mangle]
 extern "C" fn generic_interrupt_handler(isr: usize, stack_frame: *mut InterruptErrorStack) {
     let stack_frame = unsafe { &mut *stack_frame };
+
+    // The arrival time of IRQs is a source of entropy. Exceptions are skipped since they
+    // can be raised while the entropy pool is locked (e.g. NMIs).
+    if isr >= 32 {
+        crate::random::add_interrupt_randomness(isr);
+    }
+
     let handlers = idt::INTERRUPT_HANDLERS.lock();
 
     match &handlers[isr] {
@@ -148,6 +148,11 @@ extern "C" fn arch_aero_main() -> ! {
     let smp_response = SMP.get_response().get_mut().unwrap();
     let bsp_lapic_id = smp_response.bsp_lapic_id;
 
+    // The logical CPU IDs are contiguous and the BSP is always CPU 0. The processor ID
+    // provided by the bootloader is the ACPI processor UID, which does not have to be
+    // contiguous, so the logical ID is passed to the AP through the extra argument.
+    let mut next_ap_id = 1;
+
     for cpu in smp_response.cpus().iter_mut() {
         apic::CPU_COUNT.fetch_add(1, Ordering::SeqCst);
 
@@ -155,6 +160,9 @@ extern "C" fn arch_aero_main() -> ! {
             continue;
         }
 
+        cpu.extra_argument = next_ap_id;
+        next_ap_id += 1;
+
         cpu.goto_address = x86_64_aero_ap_main;
     }
 
@@ -207,10 +215,12 @@ extern "C" fn arch_aero_main() -> ! {
 #[no_mangle]
 extern "C" fn x86_64_aero_ap_main(boot_info: *const SmpInfo) -> ! {
     let boot_info = unsafe { &*boot_info };
-    let ap_id = boot_info.processor_id as usize;
+    let ap_id = boot_info.extra_argument as usize;
 
     log::debug!("booting CPU {}", ap_id);
 
+    init_cpu();
+
     gdt::init_boot();
     log::info!("AP{}: loaded boot GDT", ap_id);
 
@@ -221,6 +231,9 @@ extern "C" fn x86_64_aero_ap_main(boot_info: *const SmpInfo) -> ! {
     gdt::init();
     log::info!("AP{}: loaded GDT", ap_id);
 
+    interrupts::init_ap();
+    log::info!("AP{}: loaded IDT", ap_id);
+
     syscall::init();
 
     // Wait for the BSP to be ready (after the BSP has initialized
@@ -229,6 +242,9 @@ extern "C" fn x86_64_aero_ap_main(boot_info: *const SmpInfo) -> ! {
         core::hint::spin_loop();
     }
 
+    apic::init_ap();
+    log::info!("AP{}: loaded APIC", ap_id);
+
     // Architecture init is done. Now move on to the non-architecture specific
     // initialization of the AP.
     crate::aero_ap_main(ap_id);
@@ -15,56 +15,290 @@
 // You should have received a copy of the GNU General Public License
 // along with Aero. If not, see <https://www.gnu.org/licenses/>.
 
-use aero_syscall::signal::{SigProcMask, SignalFlags};
+//! Signal delivery.
+//!
+//! When a signal handler is invoked, a [`SignalFrame`] is pushed on the userland stack (or on
+//! the alternate signal stack if `SA_ONSTACK` is set), followed by the address of the
+//! sigreturn trampoline which the handler returns to. The frame matches the layout of the
+//! Linux `rt_sigframe`, so the handler is called with `(signal, &frame.info, &frame.ucontext)`
+//! and may modify the context that is restored by `sigreturn`.
+
+use core::mem::MaybeUninit;
+
+use aero_syscall::signal::*;
 use aero_syscall::SyscallError;
 
+use crate::mem::alloc_boxed_buffer;
+use crate::mem::paging::{align_down, VirtAddr};
 use crate::userland;
-use crate::userland::scheduler;
-use crate::utils::StackHelper;
+use crate::userland::scheduler::{self, ExitStatus};
+use crate::userland::signals::{is_on_stack, SignalEntry};
 
 use super::interrupts::InterruptStack;
+use super::task::{self, userland_last_address};
+use super::{controlregs, user_copy};
 
 const REDZONE_SIZE: u64 = 128;
 const SYSCALL_INSTRUCTION_SIZE: u64 = 2;
 
+/// The flags in RFLAGS that the signal handler is allowed to modify.
+pub(super) const USER_RFLAGS_MASK: u64 = 0x50dd5; // AC | RF | OF | DF | TF | SF | ZF | AF | PF | CF
+
+// The software reserved bytes of the legacy FXSAVE area are used to mark that the FPU
+// state holds the extended XSAVE state (see `struct _fpx_sw_bytes` in Linux).
+const FPX_SW_BYTES_OFFSET: usize = 464;
+const FP_XSTATE_MAGIC1: u32 = 0x46505853;
+const FP_XSTATE_MAGIC2: u32 = 0x46505845;
+
+const XSAVE_HEADER_OFFSET: usize = 512;
+const XSAVE_HEADER_SIZE: usize = 64;
+const MXCSR_OFFSET: usize = 24;
+
 #[repr(C)]
-#[derive(Debug)]
-pub struct SignalFrame {
+struct SignalFrame {
+    ucontext: UContext,
+    info: SigInfo,
+    /// The number of the interrupted syscall if it has to be restarted on sigreturn or
+    /// [`u64::MAX`] otherwise.
     restart_syscall: u64,
-    frame: InterruptStack,
-    sigmask: u64,
 }
 
-impl SignalFrame {
-    fn from_interrupt(frame: &mut InterruptStack, sigmask: u64) -> SignalFrame {
-        SignalFrame {
-            restart_syscall: u64::MAX,
-            frame: *frame,
-            sigmask,
-        }
+fn save_mcontext(stack: &InterruptStack, fpstate: u64) -> MContext {
+    MContext {
+        r8: stack.scratch.r8,
+        r9: stack.scratch.r9,
+        r10: stack.scratch.r10,
+        r11: stack.scratch.r11,
+        r12: stack.preserved.r12,
+        r13: stack.preserved.r13,
+        r14: stack.preserved.r14,
+        r15: stack.preserved.r15,
+        rdi: stack.scratch.rdi,
+        rsi: stack.scratch.rsi,
+        rbp: stack.preserved.rbp,
+        rbx: stack.preserved.rbx,
+        rdx: stack.scratch.rdx,
+        rax: stack.scratch.rax,
+        rcx: stack.scratch.rcx,
+        rsp: stack.iret.rsp,
+        rip: stack.iret.rip,
+        rflags: stack.iret.rflags,
+        cs: stack.iret.cs as u16,
+        ss: stack.iret.ss as u16,
+        fpstate,
+        ..Default::default()
+    }
+}
+
+fn restore_mcontext(stack: &mut InterruptStack, mcontext: &MContext) {
+    stack.scratch.r8 = mcontext.r8;
+    stack.scratch.r9 = mcontext.r9;
+    stack.scratch.r10 = mcontext.r10;
+    stack.scratch.r11 = mcontext.r11;
+    stack.preserved.r12 = mcontext.r12;
+    stack.preserved.r13 = mcontext.r13;
+    stack.preserved.r14 = mcontext.r14;
+    stack.preserved.r15 = mcontext.r15;
+    stack.scratch.rdi = mcontext.rdi;
+    stack.scratch.rsi = mcontext.rsi;
+    stack.preserved.rbp = mcontext.rbp;
+    stack.preserved.rbx = mcontext.rbx;
+    stack.scratch.rdx = mcontext.rdx;
+    stack.scratch.rax = mcontext.rax;
+    stack.scratch.rcx = mcontext.rcx;
+    stack.iret.rsp = mcontext.rsp;
+    stack.iret.rip = mcontext.rip;
+
+    // The code and stack segments are not restored, so the handler cannot return to
+    // another privilege level.
+    stack.iret.rflags =
+        (stack.iret.rflags & !USER_RFLAGS_MASK) | (mcontext.rflags & USER_RFLAGS_MASK);
+}
+
+/// Returns the size of the FPU state saved by [`save_fpu`].
+fn fpu_state_size() -> u64 {
+    task::xsave_size() as u64 + core::mem::size_of::<u32>() as u64
+}
+
+/// Saves the FPU state of the current task (in the XSAVE format) at `fpstate`. Returns
+/// [`false`] if the area is not mapped.
+fn save_fpu(fpstate: u64) -> bool {
+    let size = task::xsave_size() as usize;
+
+    let mut fpu = alloc_boxed_buffer::<u8>(size);
+    task::xsave(&mut fpu);
+
+    let extended_size = fpu_state_size() as u32;
+    let xfeatures = controlregs::read_xcr0().bits();
+
+    let sw_bytes = &mut fpu[FPX_SW_BYTES_OFFSET..];
+    sw_bytes[..4].copy_from_slice(&FP_XSTATE_MAGIC1.to_ne_bytes());
+    sw_bytes[4..8].copy_from_slice(&extended_size.to_ne_bytes());
+    sw_bytes[8..16].copy_from_slice(&xfeatures.to_ne_bytes());
+    sw_bytes[16..20].copy_from_slice(&(size as u32).to_ne_bytes());
+
+    user_copy::copy_slice_to_user(VirtAddr::new(fpstate), &fpu)
+        && user_copy::copy_to_user((fpstate + size as u64) as *mut u32, &FP_XSTATE_MAGIC2)
+}
+
+/// Returns the addresses of the FPU state, the [`SignalFrame`] and the return address when
+/// they are pushed below `sp`, or [`None`] if they do not fit in the userland address space.
+fn frame_layout(sp: u64) -> Option<(u64, u64, u64)> {
+    if sp > userland_last_address().as_u64() {
+        return None;
+    }
+
+    // XSAVE requires the area to be aligned to 64 bytes.
+    let fpstate = align_down(sp.checked_sub(fpu_state_size())?, 64);
+
+    // The frame is aligned such that the stack is aligned to 16 bytes after the return
+    // address is pushed, like it would be after a `call` instruction.
+    let frame_ptr = fpstate.checked_sub(core::mem::size_of::<SignalFrame>() as u64)?;
+    let frame_ptr = align_down(frame_ptr, 16);
+    let return_ptr = frame_ptr.checked_sub(core::mem::size_of::<u64>() as u64)?;
+
+    Some((fpstate, frame_ptr, return_ptr))
+}
+
+/// Restores the FPU state of the current task from the XSAVE area at `fpstate`. Returns
+/// [`false`] if the address of the area is invalid.
+fn restore_fpu(fpstate: u64) -> bool {
+    let size = task::xsave_size() as usize;
+
+    let in_userland = fpstate
+        .checked_add(size as u64)
+        .map_or(false, |end| end <= userland_last_address().as_u64());
+
+    if fpstate % 64 != 0 || !in_userland {
+        return false;
+    }
+
+    let mut fpu = alloc_boxed_buffer::<u8>(size);
+
+    if !user_copy::copy_slice_from_user(&mut fpu, VirtAddr::new(fpstate)) {
+        return false;
     }
 
-    fn from_syscall(
-        restart: bool,
-        syscall_result: u64,
-        frame: &mut InterruptStack,
-        sigmask: u64,
-    ) -> SignalFrame {
-        let mut frame = SignalFrame {
-            restart_syscall: if restart {
-                frame.scratch.rax // syscall number
-            } else {
-                u64::MAX
+    // XRSTOR raises #GP if the reserved bits of MXCSR or the XSAVE header are set, so
+    // clear them instead of trusting the state provided by userland.
+    let mxcsr = u32::from_ne_bytes(fpu[MXCSR_OFFSET..MXCSR_OFFSET + 4].try_into().unwrap());
+    fpu[MXCSR_OFFSET..MXCSR_OFFSET + 4].copy_from_slice(&(mxcsr & 0xffff).to_ne_bytes());
+
+    let header = &mut fpu[XSAVE_HEADER_OFFSET..XSAVE_HEADER_OFFSET + XSAVE_HEADER_SIZE];
+    let xstate_bv = u64::from_ne_bytes(header[..8].try_into().unwrap());
+    let xstate_bv = xstate_bv & controlregs::read_xcr0().bits();
+
+    header.fill(0);
+    header[..8].copy_from_slice(&xstate_bv.to_ne_bytes());
+
+    task::xrstor(&fpu);
+    true
+}
+
+fn setup_frame(
+    stack: &mut InterruptStack,
+    signal: usize,
+    entry: SignalEntry,
+    mut info: SigInfo,
+    restart_syscall: u64,
+) {
+    let handler = match entry.handler() {
+        SignalHandler::Handle(handler) => handler,
+        _ => unreachable!(),
+    };
+
+    let task = scheduler::get_scheduler().current_task();
+    let signals = task.signals();
+
+    // If the mask was replaced by `sigsuspend(2)`, the original mask is restored once the
+    // handler returns.
+    let old_mask = signals
+        .take_saved_mask()
+        .unwrap_or_else(|| signals.blocked_mask());
+
+    let alt_stack = signals.alt_stack();
+    let on_alt_stack = is_on_stack(&alt_stack, stack.iret.rsp);
+
+    let sp = if entry.flags().contains(SignalFlags::SA_ONSTACK)
+        && alt_stack.ss_flags & SS_DISABLE == 0
+        && !on_alt_stack
+    {
+        alt_stack.ss_sp.saturating_add(alt_stack.ss_size as u64)
+    } else {
+        // Signal handlers are executed on the same stack, but 128 bytes
+        // known as the red zone is subtracted from the stack before
+        // anything is pushed to the stack. This allows small leaf
+        // functions to use 128 bytes of stack space without reserving
+        // stack space by subtracting from the stack pointer.
+        stack.iret.rsp.wrapping_sub(REDZONE_SIZE)
+    };
+
+    let Some((fpstate, frame_ptr, return_ptr)) = frame_layout(sp) else {
+        log::error!("signal: invalid signal stack at {:#x}", sp);
+
+        core::mem::drop(task);
+        scheduler::get_scheduler().exit(ExitStatus::Signal(SIGSEGV));
+    };
+
+    info.si_signo = signal as i32;
+
+    let frame = SignalFrame {
+        ucontext: UContext {
+            uc_flags: UC_FP_XSTATE,
+            uc_link: 0,
+            uc_stack: SignalStack {
+                ss_flags: if on_alt_stack {
+                    SS_ONSTACK
+                } else {
+                    alt_stack.ss_flags
+                },
+                ..alt_stack
             },
-            frame: *frame,
-            sigmask,
-        };
+            uc_mcontext: save_mcontext(stack, fpstate),
+            uc_sigmask: old_mask,
+        },
+        info,
+        restart_syscall,
+    };
+
+    // The stack is provided by userland, so it might not be mapped.
+    if !save_fpu(fpstate)
+        || !user_copy::copy_to_user(frame_ptr as *mut SignalFrame, &frame)
+        || !user_copy::copy_to_user(return_ptr as *mut u64, &(entry.sigreturn() as u64))
+    {
+        log::error!(
+            "signal: failed to push the signal frame at {:#x}",
+            frame_ptr
+        );
+
+        core::mem::drop(task);
+        scheduler::get_scheduler().exit(ExitStatus::Signal(SIGSEGV));
+    }
+
+    let mut mask = entry.mask();
+
+    if !entry.flags().contains(SignalFlags::SA_NODEFER) {
+        mask |= 1u64 << signal;
+    }
+
+    signals.set_mask(SigProcMask::Set, Some(old_mask | mask), None);
+
+    stack.iret.rsp = return_ptr;
+    stack.iret.rip = handler as u64;
 
-        if !restart {
-            frame.frame.scratch.rax = syscall_result;
-        }
+    stack.scratch.rdi = signal as u64;
+    stack.scratch.rsi = frame_ptr + core::mem::offset_of!(SignalFrame, info) as u64;
+    stack.scratch.rdx = frame_ptr + core::mem::offset_of!(SignalFrame, ucontext) as u64;
+    stack.scratch.rax = 0;
+}
+
+/// Restores the blocked mask replaced by `sigsuspend(2)` if no handler was invoked.
+fn restore_saved_mask() {
+    let task = scheduler::get_scheduler().current_task();
+    let signals = task.signals();
 
-        frame
+    if let Some(mask) = signals.take_saved_mask() {
+        signals.set_mask(SigProcMask::Set, Some(mask), None);
     }
 }
 
@@ -75,104 +309,83 @@ pub fn interrupt_check_signals(stack: &mut InterruptStack) {
         return;
     }
 
-    if let Some((signal, entry)) = userland::signals::check_for_signals() {
-        if let aero_syscall::signal::SignalHandler::Handle(func) = entry.handler() {
-            let task = scheduler::get_scheduler().current_task();
-
-            let signals = task.signals();
-            let old_mask = signals.blocked_mask();
-
-            let signal_frame = SignalFrame::from_interrupt(stack, old_mask);
-            signals.set_mask(SigProcMask::Block, Some(1u64 << signal), None);
-
-            // We cannot straight away update the stack pointer from the stack
-            // helper, since it will created a reference to a packed field which
-            // is undefined behavior. So we create a copy of the current rsp and
-            // update the actual rsp with the updated rsp.
-            let mut ptr = stack.iret.rsp;
-            let mut writer = StackHelper::new(&mut ptr);
-
-            // Signal handlers are executed on the same stack, but 128 bytes
-            // known as the red zone is subtracted from the stack before
-            // anything is pushed to the stack. This allows small leaf
-            // functions to use 128 bytes of stack space without reserving
-            // stack space by subtracting from the stack pointer.
-            writer.skip_by(REDZONE_SIZE);
-
-            unsafe {
-                writer.write(signal_frame);
-                writer.write(entry.sigreturn());
-            }
-
-            stack.iret.rsp = ptr;
-            stack.iret.rip = func as u64;
-            stack.scratch.rdi = signal as u64;
-        }
+    if let Some((signal, entry, info)) = userland::signals::check_for_signals() {
+        setup_frame(stack, signal, entry, info, u64::MAX);
     }
 }
 
 pub fn syscall_check_signals(syscall_result: isize, stack: &mut InterruptStack) {
-    if let Some((signal, entry)) = userland::signals::check_for_signals() {
-        if let aero_syscall::signal::SignalHandler::Handle(func) = entry.handler() {
-            let task = scheduler::get_scheduler().current_task();
-
-            let signals = task.signals();
-            let old_mask = signals.blocked_mask();
-
-            let syscall_rresult = aero_syscall::isize_as_syscall_result(syscall_result);
-            let restart_syscall = syscall_rresult == Err(SyscallError::EINTR)
-                && entry.flags().contains(SignalFlags::SA_RESTART);
-
-            #[cfg(feature = "syslog")]
-            log::warn!("syscall routine signaled: (restart={restart_syscall})");
-
-            let signal_frame =
-                SignalFrame::from_syscall(restart_syscall, syscall_result as _, stack, old_mask);
-            signals.set_mask(SigProcMask::Block, Some(1u64 << signal), None);
-
-            // We cannot straight away update the stack pointer from the stack
-            // helper, since it will created a reference to a packed field which
-            // is undefined behavior. So we create a copy of the current rsp and
-            // update the actual rsp with the updated rsp.
-            let mut ptr = stack.iret.rsp;
-            let mut writer = StackHelper::new(&mut ptr);
-
-            // Signal handlers are executed on the same stack, but 128 bytes
-            // known as the red zone is subtracted from the stack before
-            // anything is pushed to the stack. This allows small leaf
-            // functions to use 128 bytes of stack space without reserving
-            // stack space by subtracting from the stack pointer.
-            writer.skip_by(REDZONE_SIZE);
-
-            unsafe {
-                writer.write(signal_frame);
-                writer.write(entry.sigreturn());
-            }
-
-            stack.iret.rsp = ptr;
-            stack.iret.rip = func as u64;
-            stack.scratch.rdi = signal as u64;
-        }
+    if let Some((signal, entry, info)) = userland::signals::check_for_signals() {
+        let syscall_rresult = aero_syscall::isize_as_syscall_result(syscall_result);
+        let restart_syscall = syscall_rresult == Err(SyscallError::EINTR)
+            && entry.flags().contains(SignalFlags::SA_RESTART);
+
+        #[cfg(feature = "syslog")]
+        log::warn!("syscall routine signaled: (restart={restart_syscall})");
+
+        let restart_syscall = if restart_syscall {
+            stack.scratch.rax // syscall number
+        } else {
+            stack.scratch.rax = syscall_result as u64;
+            u64::MAX
+        };
+
+        setup_frame(stack, signal, entry, info, restart_syscall);
+    } else {
+        restore_saved_mask();
     }
 }
 
 pub fn sigreturn(stack: &mut InterruptStack) {
-    let mut writer = StackHelper::new(&mut stack.iret.rsp);
-    let signal_frame = unsafe { writer.get::<SignalFrame>() };
+    let frame_ptr = stack.iret.rsp;
+    let max_user_addr = userland_last_address().as_u64();
 
-    let current_task = scheduler::get_scheduler().current_task();
+    let in_userland = frame_ptr
+        .checked_add(core::mem::size_of::<SignalFrame>() as u64)
+        .map_or(false, |end| end <= max_user_addr);
+
+    let mut frame = MaybeUninit::<SignalFrame>::uninit();
+
+    if !in_userland || !user_copy::copy_from_user(&mut frame, frame_ptr as *const SignalFrame) {
+        log::error!("sigreturn: invalid signal frame at {:#x}", frame_ptr);
+        scheduler::get_scheduler().exit(ExitStatus::Signal(SIGSEGV));
+    }
+
+    // SAFETY: The frame was initialized by `copy_from_user` above and consists of plain
+    // integers, so any bit pattern is valid.
+    let frame = unsafe { frame.assume_init() };
+    let mcontext = &frame.ucontext.uc_mcontext;
+
+    // We cannot return to non-canonical addresses with `sysret`, or we will take a fault in
+    // the kernel with the user's GS base already swapped back.
+    if mcontext.rip > max_user_addr || mcontext.rsp > max_user_addr {
+        log::error!(
+            "sigreturn: bad context: rip={:#018x},rsp={:#018x}",
+            mcontext.rip,
+            mcontext.rsp
+        );
+
+        scheduler::get_scheduler().exit(ExitStatus::Signal(SIGSEGV));
+    }
 
-    current_task.signals().set_mask(
-        aero_syscall::signal::SigProcMask::Set,
-        Some(signal_frame.sigmask),
-        None,
-    );
+    if mcontext.fpstate != 0 && !restore_fpu(mcontext.fpstate) {
+        log::error!("sigreturn: invalid FPU state at {:#x}", mcontext.fpstate);
+        scheduler::get_scheduler().exit(ExitStatus::Signal(SIGSEGV));
+    }
+
+    restore_mcontext(stack, mcontext);
+
+    let current_task = scheduler::get_scheduler().current_task();
+    let signals = current_task.signals();
 
-    writer.get_by(REDZONE_SIZE);
+    signals.set_mask(SigProcMask::Set, Some(frame.ucontext.uc_sigmask), None);
 
-    *stack = signal_frame.frame;
+    // NOTE: This fails if the handler is still running on the alternate signal stack, in
+    // which case it is left as is.
+    let _ = signals.set_alt_stack(frame.ucontext.uc_stack, mcontext.rsp);
 
-    if signal_frame.restart_syscall != u64::MAX {
+    if frame.restart_syscall != u64::MAX {
+        stack.scratch.rax = frame.restart_syscall;
         stack.iret.rip -= SYSCALL_INSTRUCTION_SIZE;
     }
 }
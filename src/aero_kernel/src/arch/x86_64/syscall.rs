@@ -4,6 +4,7 @@ use raw_cpuid::CpuId;
 use crate::arch::gdt::GdtEntryType;
 use crate::mem::paging::VirtAddr;
 use crate::userland::scheduler::{self, ExitStatus};
+use crate::userland::task::ptrace;
 use crate::utils::sync::IrqGuard;
 
 use super::interrupts::InterruptErrorStack;
@@ -82,12 +83,6 @@ pub(super) extern "C" fn x86_64_do_syscall(stack: &mut InterruptErrorStack) {
     let stack = &mut stack.stack;
 
     let syscall_number = stack.scratch.rax as usize; // syscall number
-    let a = stack.scratch.rdi as usize; // argument 1
-    let b = stack.scratch.rsi as usize; // argument 2
-    let c = stack.scratch.rdx as usize; // argument 3
-    let d = stack.scratch.r10 as usize; // argument 4
-    let e = stack.scratch.r8 as usize; // argument 5
-    let f = stack.scratch.r9 as usize; // argument 6
 
     match syscall_number {
         // handle arch-specific syscalls (`sigreturn` and `arch_prctl`):
@@ -97,7 +92,7 @@ pub(super) extern "C" fn x86_64_do_syscall(stack: &mut InterruptErrorStack) {
         }
 
         aero_syscall::prelude::SYS_ARCH_PRCTL => {
-            let result = self::arch_prctl(a, b);
+            let result = self::arch_prctl(stack.scratch.rdi as _, stack.scratch.rsi as _);
             let result_usize = aero_syscall::syscall_result_as_usize(result);
 
             stack.scratch.rax = result_usize as _;
@@ -108,7 +103,42 @@ pub(super) extern "C" fn x86_64_do_syscall(stack: &mut InterruptErrorStack) {
         _ => unsafe { super::interrupts::enable_interrupts() },
     }
 
-    let result_usize = crate::syscall::generic_do_syscall(syscall_number, a, b, c, d, e, f);
+    let traced = ptrace::is_syscall_traced(&scheduler::current_thread());
+
+    // The tracer may change the syscall and its arguments while the task is stopped at the
+    // entry of the syscall, so they are read afterwards. Like on Linux, `rax` holds -ENOSYS
+    // during the stop, which is the result if the tracer skips the syscall.
+    let syscall_number = if traced {
+        let enosys = aero_syscall::syscall_result_as_usize(Err(SyscallError::ENOSYS));
+        stack.scratch.rax = enosys as _;
+
+        ptrace::syscall_entry(&scheduler::current_thread(), syscall_number)
+    } else {
+        syscall_number
+    };
+
+    let a = stack.scratch.rdi as usize; // argument 1
+    let b = stack.scratch.rsi as usize; // argument 2
+    let c = stack.scratch.rdx as usize; // argument 3
+    let d = stack.scratch.r10 as usize; // argument 4
+    let e = stack.scratch.r8 as usize; // argument 5
+    let f = stack.scratch.r9 as usize; // argument 6
+
+    let mut result_usize = if syscall_number == usize::MAX {
+        stack.scratch.rax as usize
+    } else {
+        crate::syscall::generic_do_syscall(syscall_number, a, b, c, d, e, f)
+    };
+
+    if traced {
+        // The result can be changed by the tracer while the task is stopped at the exit of
+        // the syscall.
+        stack.scratch.rax = result_usize as _;
+        ptrace::syscall_exit(&scheduler::current_thread());
+
+        result_usize = stack.scratch.rax as usize;
+        stack.scratch.rax = syscall_number as _;
+    }
 
     super::signals::syscall_check_signals(result_usize as isize, stack);
     stack.scratch.rax = result_usize as _;
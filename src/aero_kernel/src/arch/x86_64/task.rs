@@ -32,6 +32,7 @@
 
 use alloc::alloc::alloc_zeroed;
 
+use aero_syscall::ptrace::UserRegs;
 use aero_syscall::{MMapFlags, MMapProt};
 use alloc::boxed::Box;
 use alloc::vec::Vec;
@@ -49,6 +50,7 @@ use crate::syscall::ExecArgs;
 use crate::userland::vm::Vm;
 use crate::utils::StackHelper;
 
+use super::signals::USER_RFLAGS_MASK;
 use super::{controlregs, io};
 
 use crate::mem::AddressSpace;
@@ -77,6 +79,7 @@ pub enum AuxvType {
     PhEnt = 4,
     PhNum = 5,
     Entry = 9,
+    SysInfoEhdr = 33,
 }
 
 /// Returns the first address outside the user range.
@@ -105,8 +108,14 @@ pub fn userland_last_address() -> VirtAddr {
 
 /// Returns whether the given pointer is within the userland address space.
 pub fn user_access_ok<T>(ptr: *const T) -> bool {
-    let size = core::mem::size_of::<T>();
-    VirtAddr::new(ptr as u64 + size as u64) <= super::task::userland_last_address()
+    user_range_ok(ptr as u64, core::mem::size_of::<T>())
+}
+
+/// Returns whether `address..address + size` is within the userland address space.
+pub fn user_range_ok(address: u64, size: usize) -> bool {
+    address
+        .checked_add(size as u64)
+        .map_or(false, |end| end <= userland_last_address().as_u64())
 }
 
 const USERLAND_STACK_SIZE: u64 = 0x64000;
@@ -330,6 +339,7 @@ impl ArchTask {
             USERLAND_STACK_BOTTOM,
             USERLAND_STACK_SIZE as usize,
             MMapProt::PROT_WRITE | MMapProt::PROT_READ,
+            MMapProt::all(),
             MMapFlags::MAP_FIXED | MMapFlags::MAP_PRIVATE | MMapFlags::MAP_ANONYOMUS,
             0,
             None,
@@ -401,7 +411,7 @@ impl ArchTask {
         let p2_header = loaded_binary.elf.header.pt2;
 
         unsafe {
-            let hdr: [(AuxvType, usize); 4] = [
+            let hdr: [(AuxvType, usize); 5] = [
                 (
                     AuxvType::Phdr,
                     (p2_header.ph_offset() + loaded_binary.base_addr.as_u64()) as usize,
@@ -409,6 +419,10 @@ impl ArchTask {
                 (AuxvType::PhEnt, p2_header.ph_entry_size() as usize),
                 (AuxvType::PhNum, p2_header.ph_count() as usize),
                 (AuxvType::Entry, p2_header.entry_point() as usize),
+                (
+                    AuxvType::SysInfoEhdr,
+                    loaded_binary.vdso_base.as_u64() as usize,
+                ),
             ];
 
             stack.write(0usize); // Make it 16 bytes aligned
@@ -515,9 +529,151 @@ impl ArchTask {
     pub unsafe fn set_fs_base(&mut self, base: VirtAddr) {
         io::set_fsbase(base);
     }
+
+    /// Returns the registers of the userland context of this task, which are saved at the
+    /// top of its kernel stack when it enters the kernel.
+    pub fn user_registers(&self) -> &mut InterruptErrorStack {
+        assert!(self.user, "kernel tasks do not have a userland context");
+
+        let top = self.context_switch_rsp.as_u64() as *mut InterruptErrorStack;
+        unsafe { &mut *top.sub(1) }
+    }
+
+    /// Returns the userland stack pointer at the time the task entered the kernel.
+    pub fn user_stack_pointer(&self) -> u64 {
+        self.user_registers().stack.iret.rsp
+    }
+
+    /// Returns [`true`] if the task has a userland context.
+    pub fn is_user(&self) -> bool {
+        self.user
+    }
+
+    pub fn address_space(&mut self) -> &mut AddressSpace {
+        &mut self.address_space
+    }
+
+    /// Returns the general purpose registers of the userland context of this task.
+    ///
+    /// ## Panics
+    /// * The task is running, since the FS and GS bases are only saved on a switch.
+    pub fn user_regs(&self) -> UserRegs {
+        let stack = &self.user_registers().stack;
+
+        UserRegs {
+            r15: stack.preserved.r15,
+            r14: stack.preserved.r14,
+            r13: stack.preserved.r13,
+            r12: stack.preserved.r12,
+            rbp: stack.preserved.rbp,
+            rbx: stack.preserved.rbx,
+            r11: stack.scratch.r11,
+            r10: stack.scratch.r10,
+            r9: stack.scratch.r9,
+            r8: stack.scratch.r8,
+            rax: stack.scratch.rax,
+            rcx: stack.scratch.rcx,
+            rdx: stack.scratch.rdx,
+            rsi: stack.scratch.rsi,
+            rdi: stack.scratch.rdi,
+            orig_rax: u64::MAX,
+            rip: stack.iret.rip,
+            cs: stack.iret.cs,
+            rflags: stack.iret.rflags,
+            rsp: stack.iret.rsp,
+            ss: stack.iret.ss,
+            fs_base: self.fs_base.as_u64(),
+            gs_base: self.gs_base.as_u64(),
+            ..Default::default()
+        }
+    }
+
+    /// Updates the general purpose registers of the userland context of this task. The
+    /// segment registers and the privileged flags are left untouched. Returns [`false`] if
+    /// one of the addresses is not a userland address.
+    pub fn set_user_regs(&mut self, regs: &UserRegs) -> bool {
+        let max_user_addr = userland_last_address().as_u64();
+
+        // The task returns to userland using `sysret` if it is stopped in a syscall, which
+        // faults in the kernel if the instruction pointer is not canonical.
+        if [regs.rip, regs.rsp, regs.fs_base, regs.gs_base]
+            .iter()
+            .any(|addr| *addr > max_user_addr)
+        {
+            return false;
+        }
+
+        let stack = &mut self.user_registers().stack;
+
+        stack.preserved.r15 = regs.r15;
+        stack.preserved.r14 = regs.r14;
+        stack.preserved.r13 = regs.r13;
+        stack.preserved.r12 = regs.r12;
+        stack.preserved.rbp = regs.rbp;
+        stack.preserved.rbx = regs.rbx;
+        stack.scratch.r11 = regs.r11;
+        stack.scratch.r10 = regs.r10;
+        stack.scratch.r9 = regs.r9;
+        stack.scratch.r8 = regs.r8;
+        stack.scratch.rax = regs.rax;
+        stack.scratch.rcx = regs.rcx;
+        stack.scratch.rdx = regs.rdx;
+        stack.scratch.rsi = regs.rsi;
+        stack.scratch.rdi = regs.rdi;
+        stack.iret.rip = regs.rip;
+        stack.iret.rsp = regs.rsp;
+        stack.iret.rflags =
+            (stack.iret.rflags & !USER_RFLAGS_MASK) | (regs.rflags & USER_RFLAGS_MASK);
+
+        self.fs_base = VirtAddr::new(regs.fs_base);
+        self.gs_base = VirtAddr::new(regs.gs_base);
+
+        true
+    }
+
+    /// Sets or clears the trap flag in the userland context of this task. If the trap flag
+    /// is set, a debug exception is raised after the next instruction is executed.
+    pub fn set_single_step(&mut self, enable: bool) {
+        const RFLAGS_TF: u64 = 1 << 8;
+
+        let stack = &mut self.user_registers().stack;
+
+        if enable {
+            stack.iret.rflags |= RFLAGS_TF;
+        } else {
+            stack.iret.rflags &= !RFLAGS_TF;
+        }
+    }
+
+    /// Returns the x87 FPU and SSE state of this task (in the FXSAVE format).
+    pub fn fp_regs(&self) -> [u8; FXSAVE_SIZE] {
+        let mut regs = [0; FXSAVE_SIZE];
+        regs.copy_from_slice(&self.fpu_storage.as_ref().unwrap()[..FXSAVE_SIZE]);
+        regs
+    }
+
+    /// Updates the x87 FPU and SSE state of this task (in the FXSAVE format).
+    pub fn set_fp_regs(&mut self, regs: &[u8; FXSAVE_SIZE]) {
+        const MXCSR_OFFSET: usize = 24;
+        const XSTATE_BV_OFFSET: usize = 512;
+
+        let fpu = self.fpu_storage.as_mut().unwrap();
+        fpu[..FXSAVE_SIZE].copy_from_slice(regs);
+
+        // XRSTOR raises #GP if the reserved bits of MXCSR are set.
+        let mxcsr = u32::from_ne_bytes(fpu[MXCSR_OFFSET..MXCSR_OFFSET + 4].try_into().unwrap());
+        fpu[MXCSR_OFFSET..MXCSR_OFFSET + 4].copy_from_slice(&(mxcsr & 0xffff).to_ne_bytes());
+
+        // Mark the x87 and SSE state as present in the XSAVE header, otherwise XRSTOR resets
+        // them to their initial state.
+        fpu[XSTATE_BV_OFFSET] |= 0b11;
+    }
 }
 
-fn xsave_size() -> u32 {
+/// Size of the legacy region of the XSAVE area, which holds the x87 FPU and SSE state.
+pub const FXSAVE_SIZE: usize = 512;
+
+pub(super) fn xsave_size() -> u32 {
     static XSAVE_SIZE: Option<u32> = None;
     XSAVE_SIZE.unwrap_or_else(|| {
         CpuId::new()
@@ -527,13 +683,13 @@ fn xsave_size() -> u32 {
     })
 }
 
-fn xsave(fpu: &mut Box<[u8]>) {
+pub(super) fn xsave(fpu: &mut Box<[u8]>) {
     unsafe {
         asm!("xsave [{}]", in(reg) fpu.as_ptr(), in("eax") 0xffffffffu32, in("edx") 0xffffffffu32)
     }
 }
 
-fn xrstor(fpu: &Box<[u8]>) {
+pub(super) fn xrstor(fpu: &Box<[u8]>) {
     unsafe {
         asm!("xrstor [{}]", in(reg) fpu.as_ptr(), in("eax") 0xffffffffu32, in("edx") 0xffffffffu32);
     }
@@ -19,19 +19,27 @@
 //! a prescaler and 3 independent frequency dividers and it is used to create time intervals
 //! and calculate *estimate* time since epoch.
 //!
+//! The monotonic clock is backed by a clocksource, which is the invariant TSC (calibrated
+//! against the HPET or the PIT) if available, otherwise the HPET's main counter and the PIT
+//! ticks as the last resort. The realtime clock is kept as an offset from the monotonic
+//! clock.
+//!
 //! **Notes**: <https://wiki.osdev.org/Programmable_Interval_Timer>
 
-use core::sync::atomic::{AtomicUsize, Ordering};
+use core::sync::atomic::{AtomicI64, AtomicU64, AtomicUsize, Ordering};
 
 use aero_syscall::TimeSpec;
+use raw_cpuid::CpuId;
+use spin::Once;
 
 use super::apic;
 
+use crate::acpi::hpet;
 use crate::arch::interrupts;
 use crate::arch::interrupts::InterruptStack;
 
 use crate::arch::io;
-use crate::utils::sync::Mutex;
+use crate::userland::vdso;
 
 const PIT_FREQUENCY_HZ: usize = 1000;
 pub const PIT_DIVIDEND: usize = 1193182;
@@ -40,17 +48,87 @@ static UPTIME_RAW: AtomicUsize = AtomicUsize::new(0);
 static UPTIME_SEC: AtomicUsize = AtomicUsize::new(0);
 
 pub static EPOCH: AtomicUsize = AtomicUsize::new(usize::MAX);
-pub static REALTIME_CLOCK: Mutex<aero_syscall::TimeSpec> = Mutex::new(aero_syscall::TimeSpec {
-    tv_sec: 0,
-    tv_nsec: 0,
-});
+
+/// The offset of the realtime clock from the monotonic clock in nanoseconds.
+static REALTIME_OFFSET: AtomicI64 = AtomicI64::new(0);
+
+#[derive(Debug, Copy, Clone)]
+enum ClockSource {
+    /// The invariant TSC, with its frequency in Hz and the ticks to nanoseconds
+    /// multiplier (as a 32.32 fixed point number).
+    Tsc { frequency: u64, mult: u64 },
+    /// The main counter of the HPET.
+    Hpet,
+    /// The PIT ticks.
+    Pit,
+}
+
+static CLOCK_SOURCE: Once<ClockSource> = Once::new();
+/// The value of the clocksource's counter when the monotonic clock was started.
+static CLOCK_BASE: AtomicU64 = AtomicU64::new(0);
+
+fn rdtsc() -> u64 {
+    unsafe { core::arch::x86_64::_rdtsc() }
+}
+
+fn read_clock_source(source: ClockSource) -> u64 {
+    match source {
+        ClockSource::Tsc { .. } => rdtsc(),
+        ClockSource::Hpet => hpet::get().unwrap().counter(),
+        ClockSource::Pit => UPTIME_RAW.load(Ordering::Relaxed) as u64,
+    }
+}
 
 pub fn get_uptime_ticks() -> usize {
     UPTIME_SEC.load(Ordering::SeqCst)
 }
 
+/// Returns the time elapsed since the monotonic clock was started, in nanoseconds.
+pub fn get_monotonic_nanos() -> u64 {
+    let Some(&source) = CLOCK_SOURCE.get() else {
+        return 0;
+    };
+
+    let ticks = read_clock_source(source).wrapping_sub(CLOCK_BASE.load(Ordering::Relaxed));
+
+    match source {
+        // NOTE: This must match the computation in the vDSO.
+        ClockSource::Tsc { mult, .. } => ((ticks as u128 * mult as u128) >> 32) as u64,
+
+        ClockSource::Hpet => hpet::get().unwrap().ticks_to_nanos(ticks),
+        ClockSource::Pit => ticks * (TimeSpec::NANOS_PER_SEC / PIT_FREQUENCY_HZ as u64),
+    }
+}
+
+pub fn get_monotonic_clock() -> TimeSpec {
+    TimeSpec::from_nanos(get_monotonic_nanos())
+}
+
 pub fn get_realtime_clock() -> TimeSpec {
-    REALTIME_CLOCK.lock_irq().clone()
+    let nanos = get_monotonic_nanos() as i64 + REALTIME_OFFSET.load(Ordering::SeqCst);
+    TimeSpec::from_nanos(nanos.max(0) as u64)
+}
+
+/// Sets the realtime clock to the provided `time` (in nanoseconds since epoch).
+pub fn set_realtime_clock(time: u64) {
+    let offset = time as i64 - get_monotonic_nanos() as i64;
+    REALTIME_OFFSET.store(offset, Ordering::SeqCst);
+
+    vdso::set_realtime_offset(offset);
+}
+
+/// Returns the resolution of the monotonic and realtime clocks.
+pub fn get_clock_resolution() -> TimeSpec {
+    let nanos = match CLOCK_SOURCE.get() {
+        Some(ClockSource::Tsc { frequency, .. }) => {
+            TimeSpec::NANOS_PER_SEC.div_ceil(*frequency).max(1)
+        }
+
+        Some(ClockSource::Hpet) => hpet::get().unwrap().ticks_to_nanos(1).max(1),
+        _ => TimeSpec::NANOS_PER_SEC / PIT_FREQUENCY_HZ as u64,
+    };
+
+    TimeSpec::from_nanos(nanos)
 }
 
 /// Returns the current amount of PIT ticks.
@@ -85,40 +163,93 @@ pub fn set_frequency(frequency: usize) {
 }
 
 fn pit_irq_handler(_stack: &mut InterruptStack) {
-    {
-        let interval = aero_syscall::TimeSpec {
-            tv_sec: 0,
-            tv_nsec: (1000000000 / PIT_FREQUENCY_HZ) as isize,
-        };
+    let value = UPTIME_RAW.fetch_add(1, Ordering::Relaxed); // Increment uptime raw ticks.
 
-        let mut this = REALTIME_CLOCK.lock_irq();
+    if value % PIT_FREQUENCY_HZ == 0 {
+        UPTIME_SEC.fetch_add(1, Ordering::Relaxed); // Increment uptime seconds
+        crate::syscall::check_timers();
+    }
+}
+
+/// Measures the frequency of the TSC against the HPET, or the PIT if there is no HPET.
+fn calibrate_tsc() -> u64 {
+    if let Some(hpet) = hpet::get() {
+        // Wait for 10ms.
+        let ticks = hpet.frequency() / 100;
 
-        if this.tv_nsec + interval.tv_nsec > 999999999 {
-            let diff = (this.tv_nsec + interval.tv_nsec) - 1000000000;
+        let start = hpet.counter();
+        let start_tsc = rdtsc();
 
-            this.tv_nsec = diff;
-            this.tv_sec += 1;
-        } else {
-            this.tv_nsec += interval.tv_nsec
+        while hpet.elapsed(start) < ticks {
+            core::hint::spin_loop();
         }
 
-        this.tv_sec += interval.tv_sec;
+        let elapsed = hpet.elapsed(start);
+        let elapsed_tsc = rdtsc() - start_tsc;
+
+        return ((elapsed_tsc as u128 * hpet.frequency() as u128) / elapsed as u128) as u64;
     }
 
-    let value = UPTIME_RAW.fetch_add(1, Ordering::Relaxed); // Increment uptime raw ticks.
+    // Wait for roughly 50ms, which fits in the 16-bit PIT counter.
+    const PIT_TICKS: u16 = 0xf000;
 
-    if value % PIT_FREQUENCY_HZ == 0 {
-        UPTIME_SEC.fetch_add(1, Ordering::Relaxed); // Increment uptime seconds
-        crate::syscall::check_timers();
+    set_reload_value(0xffff);
+
+    let start = get_current_count();
+    let start_tsc = rdtsc();
+
+    while start.wrapping_sub(get_current_count()) < PIT_TICKS {
+        core::hint::spin_loop();
+    }
+
+    let elapsed = start.wrapping_sub(get_current_count());
+    let elapsed_tsc = rdtsc() - start_tsc;
+
+    (elapsed_tsc * PIT_DIVIDEND as u64) / elapsed as u64
+}
+
+fn init_clock_source() -> ClockSource {
+    let invariant_tsc = CpuId::new()
+        .get_advanced_power_mgmt_info()
+        .map_or(false, |info| info.has_invariant_tsc());
+
+    if invariant_tsc {
+        let frequency = calibrate_tsc();
+        let mult = ((TimeSpec::NANOS_PER_SEC as u128) << 32) / frequency as u128;
+
+        return ClockSource::Tsc {
+            frequency,
+            mult: mult as u64,
+        };
+    }
+
+    match hpet::get() {
+        Some(hpet) if hpet.is_64bit() => ClockSource::Hpet,
+        _ => ClockSource::Pit,
     }
 }
 
-/// This function is responsible for initializing the PIT chip and setting
-/// up the IRQ.
+/// This function is responsible for initializing the PIT chip, the clocksource and
+/// setting up the IRQ.
 pub fn init() {
     apic::get_local_apic().timer_calibrate();
 
-    REALTIME_CLOCK.lock().tv_sec = EPOCH.load(Ordering::SeqCst) as _;
+    let source = init_clock_source();
+    let base = read_clock_source(source);
+
+    CLOCK_BASE.store(base, Ordering::SeqCst);
+    CLOCK_SOURCE.call_once(|| source);
+
+    // The vDSO can only read the clock in userland if it is backed by the TSC.
+    match source {
+        ClockSource::Tsc { mult, .. } => vdso::set_clock(vdso::VDSO_MODE_TSC, base, mult),
+        _ => vdso::set_clock(vdso::VDSO_MODE_FALLBACK, 0, 0),
+    }
+
+    log::debug!("time: using {source:?} as the clocksource");
+
+    let epoch = EPOCH.load(Ordering::SeqCst) as u64;
+    set_realtime_clock(epoch * TimeSpec::NANOS_PER_SEC);
 
     set_frequency(PIT_FREQUENCY_HZ);
 
@@ -104,7 +104,7 @@ pub struct CpuInfo {
 }
 
 pub fn get_cpuid() -> usize {
-    0
+    super::cpu_local::get_cpuid()
 }
 
 pub fn init() {
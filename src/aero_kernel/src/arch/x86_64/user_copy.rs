@@ -21,7 +21,7 @@ use core::ops::{Deref, DerefMut};
 use crate::interrupts::exceptions::PF_RESUME;
 use crate::mem::paging::VirtAddr;
 
-use super::task::user_access_ok;
+use super::task::{user_access_ok, user_range_ok};
 
 /// Copy to/from a block of data from user space. Returns whether the copy was successful.
 ///
@@ -86,29 +86,59 @@ unsafe extern "C" fn copy_to_from_user(
 
 /// Copy a structure from userspace memory. Returns whether the copy was successful.
 #[must_use]
-fn copy_from_user<T>(dest: &mut MaybeUninit<T>, src: *const T) -> bool {
+pub fn copy_from_user<T>(dest: &mut MaybeUninit<T>, src: *const T) -> bool {
     let fault_resume = unsafe { PF_RESUME.addr() }.as_ptr();
     let size = core::mem::size_of::<T>();
 
-    user_access_ok(src);
+    if !user_access_ok(src) {
+        return false;
+    }
 
     // SAFETY: We have verified that the `src` pointer is within the userland address space.
     unsafe { copy_to_from_user(dest.as_mut_ptr().cast(), src.cast(), size, fault_resume) }
 }
 
-/// Copy a structure from userspace memory. Returns whether the copy was successful.
+/// Copy a structure to userspace memory. Returns whether the copy was successful.
 #[must_use]
-fn copy_to_user<T>(dest: *mut T, src: &T) -> bool {
+pub fn copy_to_user<T>(dest: *mut T, src: &T) -> bool {
     let fault_resume = unsafe { PF_RESUME.addr() }.as_ptr();
     let size = core::mem::size_of::<T>();
     let src_ptr = src as *const T;
 
-    user_access_ok(dest);
+    if !user_access_ok(dest) {
+        return false;
+    }
 
     // SAFETY: We have verified that the `dest` pointer is within the userland address space.
     unsafe { copy_to_from_user(dest.cast(), src_ptr.cast(), size, fault_resume) }
 }
 
+/// Copy a block of bytes from userspace memory. Returns whether the copy was successful.
+#[must_use]
+pub fn copy_slice_from_user(dest: &mut [u8], src: VirtAddr) -> bool {
+    let fault_resume = unsafe { PF_RESUME.addr() }.as_ptr();
+
+    if !user_range_ok(src.as_u64(), dest.len()) {
+        return false;
+    }
+
+    // SAFETY: We have verified that the `src` range is within the userland address space.
+    unsafe { copy_to_from_user(dest.as_mut_ptr(), src.as_ptr(), dest.len(), fault_resume) }
+}
+
+/// Copy a block of bytes to userspace memory. Returns whether the copy was successful.
+#[must_use]
+pub fn copy_slice_to_user(dest: VirtAddr, src: &[u8]) -> bool {
+    let fault_resume = unsafe { PF_RESUME.addr() }.as_ptr();
+
+    if !user_range_ok(dest.as_u64(), src.len()) {
+        return false;
+    }
+
+    // SAFETY: We have verified that the `dest` range is within the userland address space.
+    unsafe { copy_to_from_user(dest.as_mut_ptr(), src.as_ptr(), src.len(), fault_resume) }
+}
+
 /// A reference to a structure in userspace memory, which can be either read-only or read-write.
 ///
 /// Concurrent access, *including data races to/from userspace memory*, are permitted. See the
@@ -6,6 +6,7 @@ use spin::Once;
 use crate::rendy;
 
 static RAW_CMDLINE_STR: Once<&'static str> = Once::new();
+static INITRAMFS: Once<&'static [u8]> = Once::new();
 
 pub struct CommandLine {
     /// If set, then the kernel logs will be redirected onto the framebuffer until
@@ -76,6 +77,10 @@ pub fn parse(cmdline: &'static str, modules: &[NonNullPtr<limine::File>]) -> Com
                         let value = pair.next().expect("missing operand");
 
                         match name {
+                            "initramfs" => {
+                                INITRAMFS.call_once(|| resolve_module(modules, value));
+                            }
+
                             "term-background" => {
                                 result.term_background = Some(resolve_module(modules, value))
                             }
@@ -118,6 +123,12 @@ pub fn get_raw_cmdline() -> &'static str {
         .expect("get_raw_cmdline: called before cmdline was parsed")
 }
 
+/// Returns the initramfs module, if it was provided using the `initramfs=<module>`
+/// kernel command line option.
+pub fn get_initramfs() -> Option<&'static [u8]> {
+    INITRAMFS.get().copied()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -15,6 +15,7 @@
 // You should have received a copy of the GNU General Public License
 // along with Aero. If not, see <https://www.gnu.org/licenses/>.
 
+use aero_syscall::Mode;
 use alloc::sync::Arc;
 
 use crate::fs::{devfs, FileSystem};
@@ -121,7 +122,7 @@ fn init() {
     let dri = devfs::DEV_FILESYSTEM
         .root_dir()
         .inode()
-        .mkdir("dri")
+        .mkdir("dri", Mode::from_bits_truncate(0o755))
         .expect("devfs: failed to create DRM directory");
 
     rfb.install_crtc(crtc);
@@ -17,7 +17,7 @@
 
 use core::sync::atomic::{AtomicU32, Ordering};
 
-use aero_syscall::{Termios, WinSize};
+use aero_syscall::{Mode, Termios, WinSize};
 use alloc::collections::BTreeMap;
 use alloc::string::ToString;
 use alloc::sync::{Arc, Weak};
@@ -34,6 +34,7 @@ use crate::fs::{self, cache, devfs, FileSystem, FileSystemError, Path, MOUNT_MAN
 use crate::mem::paging::VirtAddr;
 use crate::userland::scheduler;
 use crate::userland::scheduler::ExitStatus;
+use crate::userland::task::credentials::Credentials;
 use crate::userland::task::Task;
 use crate::userland::terminal::{LineDiscipline, TerminalDevice};
 use crate::utils::sync::{Mutex, WaitQueue};
@@ -191,13 +192,21 @@ impl TerminalDevice for Slave {
 struct Slave {
     sref: Weak<Self>,
     master: Arc<Master>,
+
+    /// The owner of the slave, ie. the task that opened the master.
+    uid: u32,
+    gid: u32,
 }
 
 impl Slave {
     pub fn new(master: Arc<Master>) -> Arc<Self> {
+        let credentials = Credentials::current();
+
         Arc::new_cyclic(|sref| Self {
             sref: sref.clone(),
             master,
+            uid: credentials.euid,
+            gid: credentials.egid,
         })
     }
 
@@ -217,7 +226,12 @@ impl INodeInterface for Slave {
     }
 
     fn stat(&self) -> fs::Result<aero_syscall::Stat> {
-        Ok(aero_syscall::Stat::default())
+        Ok(aero_syscall::Stat {
+            st_mode: Mode::S_IFCHR | Mode::from_bits_truncate(0o620),
+            st_uid: self.uid,
+            st_gid: self.gid,
+            ..Default::default()
+        })
     }
 
     fn ioctl(&self, command: usize, arg: usize) -> fs::Result<usize> {
@@ -362,7 +376,10 @@ impl INodeInterface for PtsINode {
     }
 
     fn stat(&self) -> fs::Result<aero_syscall::Stat> {
-        Ok(aero_syscall::Stat::default())
+        Ok(aero_syscall::Stat {
+            st_mode: Mode::S_IFDIR | Mode::from_bits_truncate(0o755),
+            ..Default::default()
+        })
     }
 
     fn dirent(&self, parent: DirCacheItem, index: usize) -> fs::Result<Option<DirCacheItem>> {
@@ -445,6 +462,15 @@ impl fs::FileSystem for PtsFs {
     fn root_dir(&self) -> DirCacheItem {
         self.root_dir.clone()
     }
+
+    fn name(&self) -> &'static str {
+        "devpts"
+    }
+}
+
+/// Returns the pseudo-terminal filesystem (akin `devpts`).
+pub fn pts_filesystem() -> Arc<dyn FileSystem> {
+    PTS_FS.call_once(PtsFs::new).clone()
 }
 
 fn pty_init() {
@@ -453,7 +479,7 @@ fn pty_init() {
     let fs = PTS_FS.call_once(PtsFs::new);
 
     let root = DEV_FILESYSTEM.root_dir().inode();
-    root.mkdir("pts").unwrap();
+    root.mkdir("pts", Mode::from_bits_truncate(0o755)).unwrap();
 
     let pts_dir = fs::lookup_path(Path::new("/dev/pts")).unwrap();
     MOUNT_MANAGER.mount(pts_dir, fs.clone()).unwrap();
@@ -27,8 +27,10 @@ use alloc::collections::BTreeMap;
 use alloc::sync::{Arc, Weak};
 use alloc::vec::Vec;
 
+use aero_syscall::consts::MountFlags;
+
 use crate::fs::devfs::install_device;
-use crate::fs::{FileSystem, Result};
+use crate::fs::Result;
 
 use crate::fs::ext2::Ext2;
 use crate::mem::paging::*;
@@ -269,6 +271,15 @@ pub fn install_block_device(dev: Arc<BlockDevice>) -> Result<()> {
     Ok(())
 }
 
+/// Returns the installed block device with the provided `name` (e.g. `nvme0n1p1`).
+pub fn find_block_device(name: &str) -> Option<Arc<BlockDevice>> {
+    BLOCK_DEVS
+        .lock()
+        .values()
+        .find(|device| device.name == name)
+        .cloned()
+}
+
 pub struct BlockDevice {
     id: usize,
     name: String,
@@ -426,8 +437,14 @@ pub fn launch() -> Result<()> {
                 if let Some(ext2) = Ext2::new(device.clone()) {
                     log::info!("gpt: found ext2 filesystem on {}!", device.name());
 
-                    super::ROOT_FS.call_once(|| ext2.clone());
-                    super::ROOT_DIR.call_once(|| ext2.root_dir());
+                    // The disk can still be mounted from userland if we booted from an
+                    // initramfs.
+                    if super::MOUNT_MANAGER.is_root_mounted() {
+                        continue;
+                    }
+
+                    let source = alloc::format!("/dev/{}", device.name());
+                    super::MOUNT_MANAGER.mount_root(ext2, source, MountFlags::empty());
                 }
             }
         }
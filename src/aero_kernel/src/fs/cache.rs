@@ -275,11 +275,17 @@ impl<K: CacheKey, V: Cacheable<K>> Cache<K, V> {
         }
     }
 
-    /// Removes the item with the provided `key` from the cache.
+    /// Removes the item with the provided `key` from the cache. If the item is still in
+    /// use, it is dropped once its last reference is dropped instead of being kept around
+    /// as an unused item.
     pub fn remove(&self, key: &K) {
         let mut index = self.index.lock();
 
-        if index.used.remove(key).is_none() {
+        if let Some(item) = index.used.remove(key) {
+            if let Some(item) = item.upgrade() {
+                item.set_used(false);
+            }
+        } else {
             let _ = index.unused.pop(key);
         }
     }
@@ -26,17 +26,18 @@ use alloc::sync::Arc;
 use spin::{Once, RwLock};
 
 use crate::fs::{lookup_path, Path};
-use crate::logger;
 use crate::mem::paging::*;
 use crate::rendy::RendyInfo;
+use crate::{logger, random};
 
 use super::cache::{DirCacheItem, INodeCacheItem};
 use super::inode::{INodeInterface, PollFlags, PollTable};
 use super::ramfs::RamFs;
+use super::shmem::TmpFs;
 use super::{FileSystem, FileSystemError, Result, MOUNT_MANAGER};
 
 use aero_syscall::prelude::*;
-use aero_syscall::MMapFlags;
+use aero_syscall::{MMapFlags, Mode};
 
 lazy_static::lazy_static! {
     pub static ref DEV_FILESYSTEM: Arc<DevFs> = DevFs::new();
@@ -152,6 +153,10 @@ impl FileSystem for DevFs {
     fn root_dir(&self) -> DirCacheItem {
         self.0.root_dir()
     }
+
+    fn name(&self) -> &'static str {
+        "devfs"
+    }
 }
 
 /// Implementation of the null device (akin `/dev/null`).
@@ -405,6 +410,7 @@ impl INodeInterface for DevFb {
     }
 }
 
+/// Implementation of the non-blocking random device (akin `/dev/urandom`).
 struct DevUrandom(usize);
 
 impl DevUrandom {
@@ -429,18 +435,71 @@ impl Device for DevUrandom {
 
 impl INodeInterface for DevUrandom {
     fn read_at(&self, _offset: usize, buffer: &mut [u8]) -> Result<usize> {
-        for (_, b) in buffer.iter_mut().enumerate() {
-            *b = 0;
-        }
+        random::get_random_bytes(buffer);
+        Ok(buffer.len())
+    }
 
+    fn write_at(&self, _offset: usize, buffer: &[u8]) -> Result<usize> {
+        random::add_device_randomness(buffer);
         Ok(buffer.len())
     }
+
+    fn poll(&self, _table: Option<&mut PollTable>) -> Result<PollFlags> {
+        Ok(PollFlags::IN | PollFlags::OUT)
+    }
+}
+
+/// Implementation of the blocking random device (akin `/dev/random`). Reads block until the
+/// kernel random number generator has been seeded with enough entropy.
+struct DevRandom(usize);
+
+impl DevRandom {
+    fn new() -> Arc<Self> {
+        Arc::new(Self(alloc_device_marker()))
+    }
+}
+
+impl Device for DevRandom {
+    fn device_marker(&self) -> usize {
+        self.0
+    }
+
+    fn device_name(&self) -> String {
+        String::from("random")
+    }
+
+    fn inode(&self) -> Arc<dyn INodeInterface> {
+        DEV_RANDOM.get().expect("device not initialized").clone()
+    }
+}
+
+impl INodeInterface for DevRandom {
+    fn read_at(&self, _offset: usize, buffer: &mut [u8]) -> Result<usize> {
+        random::wait_for_entropy()?;
+        random::get_random_bytes(buffer);
+
+        Ok(buffer.len())
+    }
+
+    fn write_at(&self, _offset: usize, buffer: &[u8]) -> Result<usize> {
+        random::add_device_randomness(buffer);
+        Ok(buffer.len())
+    }
+
+    fn poll(&self, _table: Option<&mut PollTable>) -> Result<PollFlags> {
+        if random::is_initialized() {
+            Ok(PollFlags::IN | PollFlags::OUT)
+        } else {
+            Ok(PollFlags::OUT)
+        }
+    }
 }
 
 static DEV_NULL: Once<Arc<DevNull>> = Once::new();
 static DEV_KMSG: Once<Arc<DevKmsg>> = Once::new();
 static DEV_FB: Once<Arc<DevFb>> = Once::new();
 static DEV_URANDOM: Once<Arc<DevUrandom>> = Once::new();
+static DEV_RANDOM: Once<Arc<DevRandom>> = Once::new();
 
 /// Initializes the dev filesystem. (See the module-level documentation for more information).
 pub(super) fn init() -> Result<()> {
@@ -456,12 +515,23 @@ pub(super) fn init() -> Result<()> {
         let kmsg = DEV_KMSG.call_once(DevKmsg::new);
         let fb = DEV_FB.call_once(|| DevFb::new(rendy_info));
         let urandom = DEV_URANDOM.call_once(DevUrandom::new);
+        let random = DEV_RANDOM.call_once(DevRandom::new);
 
         install_device(null.clone())?;
         install_device(kmsg.clone())?;
         install_device(fb.clone())?;
         install_device(urandom.clone())?;
+        install_device(random.clone())?;
     }
 
+    // POSIX shared memory objects (see `shm_open(3)`) are files in `/dev/shm`.
+    DEV_FILESYSTEM
+        .root_dir()
+        .inode()
+        .mkdir("shm", Mode::from_bits_truncate(0o755))?;
+
+    let shm_dir = lookup_path(Path::new("/dev/shm"))?;
+    MOUNT_MANAGER.mount(shm_dir, TmpFs::new())?;
+
     Ok(())
 }
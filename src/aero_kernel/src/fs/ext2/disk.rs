@@ -90,12 +90,21 @@ pub struct SuperBlock {
 
 impl SuperBlock {
     pub const MAGIC: u16 = 0xef53;
+    /// The superblock is always located at byte offset 1024 from the start of the device.
+    pub const OFFSET: usize = 1024;
 
     /// Returns the number of entries per block.
     pub fn entries_per_block(&self) -> usize {
         self.block_size() / core::mem::size_of::<u32>()
     }
 
+    /// Returns the largest file size that can be mapped by the direct and indirect block
+    /// pointers of an inode.
+    pub fn max_file_size(&self) -> usize {
+        let entries = self.entries_per_block();
+        (12 + entries + entries.pow(2) + entries.pow(3)) * self.block_size()
+    }
+
     pub fn revision(&self) -> Revision {
         match self.rev_level {
             0 => Revision::Revision0,
@@ -238,10 +247,38 @@ impl INode {
 
     pub fn set_permissions(&mut self, permissions: u16) {
         let mut val = self.type_and_perm;
-        val.set_bits(..13, permissions);
+        val.set_bits(..12, permissions);
         self.type_and_perm = val;
     }
 
+    pub fn permissions(&self) -> u16 {
+        self.type_and_perm.get_bits(..12)
+    }
+
+    /// Returns the user ID of the owner. The high 16 bits of the user ID are stored in
+    /// the OS dependent area of the inode.
+    pub fn uid(&self) -> u32 {
+        let high = u16::from_le_bytes([self.os_specific2[4], self.os_specific2[5]]);
+        self.user_id as u32 | (high as u32) << 16
+    }
+
+    pub fn set_uid(&mut self, uid: u32) {
+        self.user_id = uid as u16;
+        self.os_specific2[4..6].copy_from_slice(&((uid >> 16) as u16).to_le_bytes());
+    }
+
+    /// Returns the group ID of the owner. The high 16 bits of the group ID are stored in
+    /// the OS dependent area of the inode.
+    pub fn gid(&self) -> u32 {
+        let high = u16::from_le_bytes([self.os_specific2[6], self.os_specific2[7]]);
+        self.group_id as u32 | (high as u32) << 16
+    }
+
+    pub fn set_gid(&mut self, gid: u32) {
+        self.group_id = gid as u16;
+        self.os_specific2[6..8].copy_from_slice(&((gid >> 16) as u16).to_le_bytes());
+    }
+
     pub fn file_type(&self) -> FileType {
         let ty = self.type_and_perm >> 12;
 
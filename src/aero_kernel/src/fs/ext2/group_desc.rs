@@ -23,7 +23,7 @@ use alloc::sync::{Arc, Weak};
 use bit_field::BitField;
 use spin::RwLock;
 
-use crate::fs::block::{BlockDevice, CachedAccess};
+use crate::fs::block::{BlockDevice, CachedAccess, DirtyRef};
 
 use super::{disk, Ext2};
 
@@ -97,7 +97,8 @@ impl GroupDescriptors {
         Some(index)
     }
 
-    pub fn find_inode(&self, id: usize) -> Option<Box<disk::INode>> {
+    /// Returns the offset of the inode with the provided `id` on the disk.
+    fn inode_offset(&self, id: usize) -> Option<usize> {
         let fs = self.ext2.upgrade()?;
         let this = self.descriptors.read();
         let superblock = &fs.superblock;
@@ -110,43 +111,84 @@ impl GroupDescriptors {
         let ino_block_group = (id - 1) / ino_per_group;
         let ino_table_index = (id - 1) % ino_per_group;
 
-        let group_descriptor = this[ino_block_group];
+        let group_descriptor = this.get(ino_block_group)?;
         let table_offset = group_descriptor.inode_table as usize * superblock.block_size();
 
+        Some(table_offset + (ino_table_index * core::mem::size_of::<disk::INode>()))
+    }
+
+    pub fn find_inode(&self, id: usize) -> Option<Box<disk::INode>> {
+        let fs = self.ext2.upgrade()?;
         let mut inode = Box::<disk::INode>::new_uninit();
 
-        fs.block.read(
-            table_offset + (ino_table_index * core::mem::size_of::<disk::INode>()),
-            inode.as_bytes_mut(),
-        )?;
+        fs.block
+            .read(self.inode_offset(id)?, inode.as_bytes_mut())?;
 
         // SAFETY: We have initialized the inode above.
         let inode = unsafe { inode.assume_init() };
         Some(inode)
     }
 
+    /// Writes the provided `inode` back to the inode table. The inode is written through
+    /// the block cache and is flushed to the disk along with the rest of the dirty pages.
+    pub fn write_inode(&self, id: usize, inode: &disk::INode) -> Option<()> {
+        let fs = self.ext2.upgrade()?;
+
+        let mut on_disk = DirtyRef::<disk::INode>::new(fs.block.sref(), self.inode_offset(id)?);
+        *on_disk = *inode;
+
+        Some(())
+    }
+
     /// Allocates a block pointer using the first fit allocation strategy.
     pub fn alloc_block_ptr(&self) -> Option<usize> {
         let fs = self.ext2.upgrade()?;
         let blocks_per_group = fs.superblock.blocks_per_group as usize;
+        let first_data_block = fs.superblock.first_data_block as usize;
 
         if let Some(block_group_idx) = self.find_free_block() {
             let mut descriptors = self.descriptors.write();
             let block_group = &mut descriptors[block_group_idx];
 
-            let mut bitmap = Bitmap::new(fs, block_group.block_bitmap as usize)?;
-            let block_id = block_group_idx * blocks_per_group + bitmap.alloc()?;
+            let mut bitmap = Bitmap::new(fs.clone(), block_group.block_bitmap as usize)?;
+            // The first bit in the first block group's block bitmap represents the first data
+            // block (which is block 1 on filesystems with a block size of 1KiB).
+            let block_id =
+                first_data_block + block_group_idx * blocks_per_group + bitmap.alloc()?;
 
             block_group.free_blocks_count -= 1;
+            self.sync_descriptor(&fs, block_group_idx, block_group);
             drop(descriptors);
 
-            // TODO: decrement the number of free blocks in the superblock.
+            Self::update_free_counts(&fs, -1, 0);
             return Some(block_id);
         }
 
         None
     }
 
+    /// Returns the block `block` to its block group's block bitmap.
+    pub fn free_block_ptr(&self, block: usize) -> Option<()> {
+        let fs = self.ext2.upgrade()?;
+        let blocks_per_group = fs.superblock.blocks_per_group as usize;
+        let block = block - fs.superblock.first_data_block as usize;
+
+        let block_group_idx = block / blocks_per_group;
+
+        let mut descriptors = self.descriptors.write();
+        let block_group = descriptors.get_mut(block_group_idx)?;
+
+        let mut bitmap = Bitmap::new(fs.clone(), block_group.block_bitmap as usize)?;
+        bitmap.free(block % blocks_per_group);
+
+        block_group.free_blocks_count += 1;
+        self.sync_descriptor(&fs, block_group_idx, block_group);
+        drop(descriptors);
+
+        Self::update_free_counts(&fs, 1, 0);
+        Some(())
+    }
+
     /// Allocates a new inode using the first fit allocation strategy.
     pub fn alloc_inode(&self) -> Option<usize> {
         let fs = self.ext2.upgrade()?;
@@ -156,20 +198,60 @@ impl GroupDescriptors {
             let mut descriptors = self.descriptors.write();
             let block_group = &mut descriptors[block_group_idx];
 
-            let mut bitmap = Bitmap::new(fs, block_group.inode_bitmap as usize)?;
+            let mut bitmap = Bitmap::new(fs.clone(), block_group.inode_bitmap as usize)?;
             // Since inode numbers start from 1 rather than 0, the first bit in the first block
             // group's inode bitmap represent inode number 1. Thus, we add 1 to the allocated
             // inode number.
             let inode_id = block_group_idx * ino_per_group + bitmap.alloc()? + 1;
 
             block_group.free_inodes_count -= 1;
+            self.sync_descriptor(&fs, block_group_idx, block_group);
             drop(descriptors); // release the lock
 
+            Self::update_free_counts(&fs, 0, -1);
             return Some(inode_id);
         }
 
         None
     }
+
+    /// Returns the inode `id` to its block group's inode bitmap.
+    pub fn free_inode(&self, id: usize) -> Option<()> {
+        let fs = self.ext2.upgrade()?;
+        let ino_per_group = fs.superblock.inodes_per_group as usize;
+
+        let block_group_idx = (id - 1) / ino_per_group;
+
+        let mut descriptors = self.descriptors.write();
+        let block_group = descriptors.get_mut(block_group_idx)?;
+
+        let mut bitmap = Bitmap::new(fs.clone(), block_group.inode_bitmap as usize)?;
+        bitmap.free((id - 1) % ino_per_group);
+
+        block_group.free_inodes_count += 1;
+        self.sync_descriptor(&fs, block_group_idx, block_group);
+        drop(descriptors);
+
+        Self::update_free_counts(&fs, 0, 1);
+        Some(())
+    }
+
+    /// Writes the group descriptor at `index` back to the block group descriptor table.
+    fn sync_descriptor(&self, fs: &Ext2, index: usize, descriptor: &disk::GroupDescriptor) {
+        let offset =
+            fs.superblock.bgdt_block() + index * core::mem::size_of::<disk::GroupDescriptor>();
+
+        *DirtyRef::<disk::GroupDescriptor>::new(fs.block.sref(), offset) = *descriptor;
+    }
+
+    /// Adjusts the number of free blocks and inodes in the superblock on the disk.
+    fn update_free_counts(fs: &Ext2, blocks: isize, inodes: isize) {
+        let mut superblock =
+            DirtyRef::<disk::SuperBlock>::new(fs.block.sref(), disk::SuperBlock::OFFSET);
+
+        superblock.free_blocks_count = (superblock.free_blocks_count as isize + blocks) as u32;
+        superblock.free_inodes_count = (superblock.free_inodes_count as isize + inodes) as u32;
+    }
 }
 
 struct Bitmap {
@@ -218,6 +300,17 @@ impl Bitmap {
 
         None
     }
+
+    /// Marks the bit at `index` in the bitmap as free.
+    pub fn free(&mut self, index: usize) {
+        let byte = &mut self.bitmap[index / 8];
+
+        if !byte.get_bit(index % 8) {
+            log::warn!("ext2: bit {index} in the bitmap is already free");
+        }
+
+        byte.set_bit(index % 8, false);
+    }
 }
 
 impl Drop for Bitmap {
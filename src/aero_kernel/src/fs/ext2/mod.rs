@@ -21,7 +21,7 @@ mod group_desc;
 use core::mem::MaybeUninit;
 
 use aero_syscall::socket::{MessageFlags, MessageHeader};
-use aero_syscall::{MMapFlags, SyscallError};
+use aero_syscall::{MMapFlags, Mode, SyscallError};
 use alloc::boxed::Box;
 use alloc::string::ToString;
 use alloc::sync::{Arc, Weak};
@@ -34,6 +34,7 @@ use crate::mem::paging::*;
 
 use crate::socket::unix::UnixSocket;
 use crate::socket::SocketAddrRef;
+use crate::userland::task::credentials::Credentials;
 
 use self::group_desc::GroupDescriptors;
 
@@ -42,7 +43,8 @@ use super::block::{self, BlockDevice, CachedAccess};
 use super::cache::{DirCacheItem, INodeCacheItem};
 use super::{cache, FileSystemError};
 
-use super::inode::{DirEntry, INodeInterface, Metadata, PollFlags, PollTable};
+use super::inode::{DirEntry, INodeInterface, Metadata, PollFlags, PollTable, SetAttr};
+use super::shmem::SharedPages;
 use super::FileSystem;
 
 pub struct INode {
@@ -53,6 +55,9 @@ pub struct INode {
     // proxy inode is not saved on the disk. (e.g. This is useful for binding
     // a socket inode to a file).
     proxy: Option<Arc<dyn INodeInterface>>,
+    // The pages of the shared mappings of the file. The resident pages hold the most
+    // recent contents of the file and are written back on msync(2).
+    shared_pages: SharedPages,
 
     // TODO: Do not store this in the inode, but rather in a different
     // cache using the API provided by fs::cache (consider LRU only?).
@@ -82,6 +87,7 @@ impl INode {
                     id,
                     fs: ext2,
                     proxy,
+                    shared_pages: SharedPages::new(),
 
                     sref: sref.clone(),
                 }))),
@@ -112,6 +118,10 @@ impl INode {
         let filesystem = self.fs.upgrade().unwrap();
         let block_size = filesystem.superblock.block_size();
 
+        if offset >= inode.size() {
+            return Ok(0);
+        }
+
         let mut progress = 0;
         let count = core::cmp::min(inode.size() - offset, buffer.len());
 
@@ -125,15 +135,20 @@ impl INode {
                 chunk = block_size - loc;
             }
 
-            let block_index = self.get_block(block).unwrap() as usize;
+            let block_index = self.get_block(block).ok_or(FileSystemError::FileTooLarge)? as usize;
 
-            filesystem
-                .block
-                .read(
-                    (block_index * block_size) + loc,
-                    &mut buffer[progress..progress + chunk],
-                )
-                .expect("inode: read failed");
+            // Blocks that have not been allocated (ie. holes) read as zeros.
+            if block_index == 0 {
+                buffer[progress..progress + chunk].fill(MaybeUninit::new(0));
+            } else {
+                filesystem
+                    .block
+                    .read(
+                        (block_index * block_size) + loc,
+                        &mut buffer[progress..progress + chunk],
+                    )
+                    .expect("inode: read failed");
+            }
 
             progress += chunk;
         }
@@ -158,10 +173,28 @@ impl INode {
                 chunk = block_size - loc;
             }
 
-            let mut block_index = self.get_block(block).unwrap() as usize;
+            // The write is cut short at the largest file size.
+            let Some(block_index) = self.get_block(block) else {
+                if progress == 0 {
+                    return Err(FileSystemError::FileTooLarge);
+                }
+
+                break;
+            };
+
+            let mut block_index = block_index as usize;
 
             if block_index == 0 {
-                block_index = self.append_block().unwrap();
+                // The write is cut short if the disk is full.
+                let Some(new_block) = self.alloc_block(block) else {
+                    if progress == 0 {
+                        return Err(FileSystemError::NoSpace);
+                    }
+
+                    break;
+                };
+
+                block_index = new_block;
             }
 
             filesystem
@@ -175,117 +208,205 @@ impl INode {
             progress += chunk;
         }
 
-        Ok(count)
+        {
+            let mut inode = self.inode.write();
+
+            if offset + progress > inode.size() {
+                inode.set_size(offset + progress);
+            }
+        }
+
+        self.sync();
+        Ok(progress)
     }
 
-    pub fn append_block(&self) -> Option<usize> {
-        let fs = self.fs.upgrade().expect("ext2: filesystem was dropped");
+    /// Allocates a new block and fills it with zeros. The block is accounted for in the
+    /// number of blocks used by the inode.
+    fn alloc_zeroed_block(&self, fs: &Ext2) -> Option<usize> {
         let block_size = fs.superblock.block_size();
-        let entries_per_block = fs.superblock.entries_per_block();
+        let block = fs.bgdt.alloc_block_ptr()?;
 
-        let new_block = fs.bgdt.alloc_block_ptr()?;
+        fs.block
+            .write(block * block_size, &alloc::vec![0u8; block_size])
+            .expect("ext2: failed to zero the allocated block");
 
-        let mut next_block_num = self.inode.read().size().div_ceil(block_size);
+        self.inode.write().block_count += (block_size / 512) as u32;
+        Some(block)
+    }
 
-        if next_block_num < 12 {
-            let mut inode = self.inode.write();
+    /// Allocates a data block for the logical block `block` of the file, along with any
+    /// indirect blocks required to map it. Returns the allocated block.
+    pub fn alloc_block(&self, block: usize) -> Option<usize> {
+        let fs = self.fs.upgrade().expect("ext2: filesystem was dropped");
+        let path = BlockPath::new(block, fs.superblock.entries_per_block())?;
 
-            assert_eq!(inode.data_ptr[next_block_num], 0);
+        let new_block = self.alloc_zeroed_block(&fs)?;
 
-            inode.data_ptr[next_block_num] = new_block as u32;
+        if path.depth == 0 {
+            let mut inode = self.inode.write();
 
-            let size = inode.size() + block_size;
-            inode.set_size(size);
+            assert_eq!(inode.data_ptr[path.slot], 0);
+            inode.data_ptr[path.slot] = new_block as u32;
 
             return Some(new_block);
         }
 
-        // indirect block
-        next_block_num -= 12;
+        let mut block_ptrs = self.inode.read().data_ptr[path.slot] as usize;
 
-        if next_block_num >= entries_per_block {
-            unimplemented!("append_block: doubly and triply indirect")
-        } else {
-            // singly indirect block
-            let mut block_ptrs = self.inode.read().data_ptr[12] as usize;
+        if block_ptrs == 0 {
+            block_ptrs = self.alloc_zeroed_block(&fs)?;
+            self.inode.write().data_ptr[path.slot] = block_ptrs as u32;
+        }
 
-            if block_ptrs == 0 {
-                block_ptrs = fs.bgdt.alloc_block_ptr()?;
-            }
+        // Walk down the indirect blocks, allocating the missing ones on the way.
+        for &index in &path.indices[..path.depth - 1] {
+            let mut next = fs.read_block_ptr(block_ptrs, index) as usize;
 
-            let block_ptrs = block_ptrs * block_size;
-            let offset = block_ptrs + (new_block * core::mem::size_of::<u32>());
+            if next == 0 {
+                next = self.alloc_zeroed_block(&fs)?;
+                fs.write_block_ptr(block_ptrs, index, next as u32);
+            }
 
-            fs.block.write(offset, &new_block.to_le_bytes());
+            block_ptrs = next;
         }
 
+        fs.write_block_ptr(block_ptrs, path.indices[path.depth - 1], new_block as u32);
         Some(new_block)
     }
 
-    pub fn get_block(&self, mut block: usize) -> Option<u32> {
-        // There are pointers to the first 12 blocks which contain the file's
-        // data in the inode. There is a pointer to an indirect block (which
-        // contains pointers to the next set of blocks), a pointer to a doubly
-        // indirect block and a pointer to a triply indirect block.
-        if block < 12 {
-            // direct block
-            return Some(self.inode.read().data_ptr[block]);
-        }
+    /// Allocates a new block at the end of the inode and grows the inode by a block.
+    pub fn append_block(&self) -> Option<usize> {
+        let fs = self.fs.upgrade().expect("ext2: filesystem was dropped");
+        let block_size = fs.superblock.block_size();
 
-        // indirect block
-        block -= 12;
+        let next_block_num = self.inode.read().size().div_ceil(block_size);
+        let new_block = self.alloc_block(next_block_num)?;
 
-        let fs = self.fs.upgrade()?;
-        let superblock = &fs.superblock;
+        self.inode
+            .write()
+            .set_size((next_block_num + 1) * block_size);
 
-        let entries_per_block = superblock.entries_per_block();
-        let block_size = superblock.block_size();
+        Some(new_block)
+    }
+
+    /// Frees all of the data blocks of the inode starting from the logical block `from`,
+    /// along with the indirect blocks that no longer map any data blocks.
+    fn free_blocks(&self, from: usize) {
+        let fs = self.fs.upgrade().expect("ext2: filesystem was dropped");
+        let entries_per_block = fs.superblock.entries_per_block();
 
-        if block >= entries_per_block {
-            // doubly indirect block
-            block -= entries_per_block;
+        let mut inode = self.inode.write();
+        let mut freed = 0;
 
-            let index = block / entries_per_block;
-            let mut indirect_block = MaybeUninit::<u32>::uninit();
+        // direct blocks
+        for ptr in inode.data_ptr[from.min(12)..12].iter_mut() {
+            if *ptr != 0 {
+                fs.bgdt.free_block_ptr(*ptr as usize);
+                *ptr = 0;
+                freed += 1;
+            }
+        }
 
-            if index >= entries_per_block {
-                // treply indirect block
-                todo!()
-            } else {
-                let block_ptrs = self.inode.read().data_ptr[13] as usize * block_size;
-                let offset = block_ptrs + (index * core::mem::size_of::<u32>());
+        // singly, doubly and triply indirect blocks
+        let mut start = 12;
 
-                fs.block
-                    .read(offset, indirect_block.as_bytes_mut())
-                    .unwrap();
+        for (depth, slot) in (1..=3).zip(12..15) {
+            let ptr = inode.data_ptr[slot] as usize;
+            let keep = from.saturating_sub(start);
+
+            if ptr != 0 && fs.free_indirect(ptr, depth, keep, &mut freed) {
+                inode.data_ptr[slot] = 0;
             }
 
-            // SAFETY: We have initialized the variable above.
-            let indirect_block = unsafe { indirect_block.assume_init() } as usize * block_size;
-            let offset = indirect_block + (block % entries_per_block) * core::mem::size_of::<u32>();
+            start += entries_per_block.pow(depth);
+        }
+
+        let sectors = (freed * (fs.superblock.block_size() / 512)) as u32;
+        inode.block_count = inode.block_count.saturating_sub(sectors);
+    }
+
+    /// Returns [`true`] if the inode is a symbolic link whose target is stored inline in
+    /// the block pointers.
+    fn is_fast_symlink(&self) -> bool {
+        let inode = self.inode.read();
+
+        inode.file_type() == FileType::Symlink
+            && inode.size() <= core::mem::size_of_val(&inode.data_ptr)
+    }
+
+    /// Drops a hard link to the inode. Once the last link is gone, the inode is removed
+    /// from the inode cache and becomes an orphan: its blocks and the inode itself are
+    /// returned to the block group bitmaps when the last reference to it (e.g. an open
+    /// file or a mapping) is dropped. See [`INode::free`].
+    fn drop_link(&self) {
+        let now = crate::arch::time::get_realtime_clock().tv_sec as u32;
+
+        let links = {
+            let mut inode = self.inode.write();
+
+            inode.hl_count = inode.hl_count.saturating_sub(1);
+            inode.creation_time = now;
+            inode.hl_count
+        };
+
+        self.sync();
 
-            let mut res = MaybeUninit::<u32>::uninit();
-            fs.block.read(offset, res.as_bytes_mut());
+        if links == 0 {
+            cache::icache().remove(&INodeCacheItem::make_key(self.fs.clone(), self.id));
+        }
+    }
 
-            // SAFETY: We have initialized the variable above.
-            Some(unsafe { res.assume_init() })
+    /// Frees the blocks of an orphaned inode and the inode itself.
+    fn free(&self) {
+        let Some(fs) = self.fs.upgrade() else {
+            // The filesystem is gone, so the orphan is left for fsck to clean up.
+            return;
+        };
+
+        if self.is_fast_symlink() {
+            self.inode.write().data_ptr.fill(0);
         } else {
-            // singly indirect block
-            let block_ptrs = self.inode.read().data_ptr[12] as usize * block_size;
-            let offset = block_ptrs + (block * core::mem::size_of::<u32>());
+            self.free_blocks(0);
+        }
+
+        {
+            let mut inode = self.inode.write();
+
+            inode.set_size(0);
+            inode.deletion_time = crate::arch::time::get_realtime_clock().tv_sec as u32;
+        }
 
-            let mut res = MaybeUninit::<u32>::uninit();
-            fs.block.read(offset, res.as_bytes_mut());
+        self.sync();
+        fs.bgdt.free_inode(self.id);
+    }
+
+    pub fn get_block(&self, block: usize) -> Option<u32> {
+        let fs = self.fs.upgrade()?;
+        let path = BlockPath::new(block, fs.superblock.entries_per_block())?;
+
+        let mut ptr = self.inode.read().data_ptr[path.slot];
 
-            // SAFETY: We have initialized the variable above.
-            Some(unsafe { res.assume_init() })
+        for &index in &path.indices[..path.depth] {
+            // The indirect block has not been allocated (ie. a hole).
+            if ptr == 0 {
+                return Some(0);
+            }
+
+            ptr = fs.read_block_ptr(ptr as usize, index);
         }
+
+        Some(ptr)
     }
 
-    pub fn make_disk_dirent(&self, inode: Arc<INode>, file_type: u8, name: &str) {
+    pub fn make_disk_dirent(
+        &self,
+        inode: Arc<INode>,
+        file_type: u8,
+        name: &str,
+    ) -> super::Result<()> {
         // TODO: scan for unused directory entries and check if this can be
         //       inserted into the existing block.
-        let block = self.append_block().unwrap();
+        let block = self.append_block().ok_or(FileSystemError::NoSpace)?;
         let fs = self.fs.upgrade().expect("ext2: filesystem was dropped");
         let block_size = fs.superblock.block_size();
 
@@ -294,6 +415,8 @@ impl INode {
         entry.inode = inode.id as _;
         entry.file_type = file_type;
         entry.set_name(name);
+
+        Ok(())
     }
 
     pub fn make_inode(
@@ -314,7 +437,7 @@ impl INode {
 
         let fs = self.fs.upgrade().expect("ext2: filesystem was dropped");
 
-        let inode = fs.bgdt.alloc_inode().expect("ext2: out of inodes");
+        let inode = fs.bgdt.alloc_inode().ok_or(FileSystemError::NoSpace)?;
         let inode = fs.find_inode(inode, proxy).expect("ext2: inode not found");
 
         let ext2_inode = inode.downcast_arc::<INode>().expect("ext2: invalid inode");
@@ -323,17 +446,46 @@ impl INode {
             let mut inode = ext2_inode.inode.write();
             **inode = disk::INode::default();
 
+            let credentials = Credentials::current();
+
             inode.set_file_type(typ);
-            inode.set_permissions(0o755);
+            inode.set_permissions(super::inode::FileType::from(typ).default_permissions() as u16);
+            inode.set_uid(credentials.euid);
+            inode.set_gid(credentials.egid);
+
+            let now = crate::arch::time::get_realtime_clock().tv_sec as u32;
+
+            inode.last_access = now;
+            inode.creation_time = now;
+            inode.last_modification = now;
 
             inode.hl_count += 1;
         }
 
+        ext2_inode.sync();
+
         // FIXME: Fix the filetype!
-        self.make_disk_dirent(ext2_inode, 2, name);
+        if let Err(err) = self.make_disk_dirent(ext2_inode.clone(), 2, name) {
+            // The inode is freed once the last reference to it is dropped.
+            ext2_inode.drop_link();
+            return Err(err);
+        }
+
+        self.sync();
+
         Ok(inode)
     }
 
+    /// Writes the in-memory copy of the inode back to the inode table.
+    pub fn sync(&self) {
+        let fs = self.fs.upgrade().expect("ext2: filesystem was dropped");
+        let inode = self.inode.read();
+
+        fs.bgdt
+            .write_inode(self.id, &inode)
+            .expect("ext2: failed to write back the inode");
+    }
+
     pub fn make_dirent(
         &self,
         parent: DirCacheItem,
@@ -349,6 +501,14 @@ impl INode {
     }
 }
 
+impl Drop for INode {
+    fn drop(&mut self) {
+        if self.inode.read().hl_count == 0 {
+            self.free();
+        }
+    }
+}
+
 impl INodeInterface for INode {
     fn weak_filesystem(&self) -> Option<Weak<dyn FileSystem>> {
         Some(self.fs.clone())
@@ -366,37 +526,75 @@ impl INodeInterface for INode {
     }
 
     fn stat(&self) -> super::Result<aero_syscall::Stat> {
-        use super::inode::FileType;
-        use aero_syscall::{Mode, Stat};
+        use aero_syscall::{Mode, Stat, TimeSpec};
 
         let inode = self.inode.read();
 
         let filesystem = self.fs.upgrade().unwrap();
         let filetype = self.metadata()?.file_type();
 
-        let mut mode = Mode::empty();
-
-        match filetype {
-            FileType::File => mode.insert(Mode::S_IFREG),
-            FileType::Directory => mode.insert(Mode::S_IFDIR),
-            FileType::Device => mode.insert(Mode::S_IFCHR),
-            FileType::Socket => mode.insert(Mode::S_IFSOCK),
-            FileType::Symlink => mode.insert(Mode::S_IFLNK),
-        }
-
-        // FIXME: read permission bits from the inode.
-        mode.insert(Mode::S_IRWXU | Mode::S_IRWXG | Mode::S_IRWXO);
+        let mut mode = Mode::from(filetype);
+        mode.insert(Mode::from_bits_truncate(inode.permissions() as u32));
 
         Ok(Stat {
             st_ino: self.id as _,
             st_blksize: filesystem.superblock.block_size() as _,
             st_size: inode.size() as _,
             st_mode: mode,
+            st_uid: inode.uid(),
+            st_gid: inode.gid(),
+            st_nlink: inode.hl_count as _,
+
+            st_atim: TimeSpec {
+                tv_sec: inode.last_access as _,
+                tv_nsec: 0,
+            },
+            st_mtim: TimeSpec {
+                tv_sec: inode.last_modification as _,
+                tv_nsec: 0,
+            },
+            st_ctim: TimeSpec {
+                tv_sec: inode.creation_time as _,
+                tv_nsec: 0,
+            },
 
             ..Default::default()
         })
     }
 
+    fn set_attr(&self, attr: &SetAttr) -> super::Result<()> {
+        {
+            let mut inode = self.inode.write();
+
+            if let Some(mode) = attr.mode {
+                inode.set_permissions((mode & 0o7777) as u16);
+            }
+
+            if let Some(uid) = attr.uid {
+                inode.set_uid(uid);
+            }
+
+            if let Some(gid) = attr.gid {
+                inode.set_gid(gid);
+            }
+
+            if let Some(atime) = attr.atime.as_ref() {
+                inode.last_access = atime.tv_sec as u32;
+            }
+
+            if let Some(mtime) = attr.mtime.as_ref() {
+                inode.last_modification = mtime.tv_sec as u32;
+            }
+
+            // XXX: The `creation_time` field is the inode change time (ctime) and not the
+            // creation time of the inode.
+            inode.creation_time = crate::arch::time::get_realtime_clock().tv_sec as u32;
+        }
+
+        self.sync();
+        Ok(())
+    }
+
     fn dirent(&self, parent: DirCacheItem, index: usize) -> super::Result<Option<DirCacheItem>> {
         if let Some((name, entry)) = DirEntryIter::new(self.sref()).nth(index) {
             Ok(self.make_dirent(parent, &name, &entry))
@@ -426,7 +624,12 @@ impl INodeInterface for INode {
             core::slice::from_raw_parts_mut(usr_buffer.as_mut_ptr().cast(), usr_buffer.len())
         };
 
-        self.read(offset, buffer)
+        let count = self.read(offset, buffer)?;
+
+        // The pages of the shared mappings may have been modified after they were last
+        // written back.
+        self.shared_pages.read(offset, &mut usr_buffer[..count]);
+        Ok(count)
     }
 
     fn write_at(&self, offset: usize, usr_buffer: &[u8]) -> super::Result<usize> {
@@ -438,7 +641,10 @@ impl INodeInterface for INode {
             return Err(FileSystemError::NotSupported);
         }
 
-        self.write(offset, usr_buffer)
+        let count = self.write(offset, usr_buffer)?;
+
+        self.shared_pages.write(offset, &usr_buffer[..count]);
+        Ok(count)
     }
 
     fn rename(&self, old: DirCacheItem, dest: &str) -> super::Result<()> {
@@ -450,7 +656,7 @@ impl INodeInterface for INode {
 
         if let Some(_parent) = old.parent() {
             // FIXME: Remove the directory entry from the parent
-            self.make_disk_dirent(old.inode().downcast_arc().unwrap(), 2, dest);
+            self.make_disk_dirent(old.inode().downcast_arc().unwrap(), 2, dest)?;
             return Ok(());
         }
 
@@ -472,26 +678,109 @@ impl INodeInterface for INode {
         Ok(())
     }
 
-    fn truncate(&self, _size: usize) -> super::Result<()> {
-        log::warn!("ext2::truncate is a stub!");
+    fn truncate(&self, size: usize) -> super::Result<()> {
+        if !self.metadata()?.is_file() {
+            return Err(FileSystemError::NotSupported);
+        }
+
+        let fs = self.fs.upgrade().expect("ext2: filesystem was dropped");
+        let block_size = fs.superblock.block_size();
+
+        if size > fs.superblock.max_file_size() {
+            return Err(FileSystemError::FileTooLarge);
+        }
+
+        if size < self.inode.read().size() {
+            self.free_blocks(size.div_ceil(block_size));
+
+            // Zero the rest of the last block, so that the data past the new end of the file
+            // reads as zeros if the file is grown again.
+            let loc = size % block_size;
+
+            if loc != 0 {
+                let block = self.get_block(size / block_size).unwrap() as usize;
+
+                if block != 0 {
+                    fs.block.write(
+                        block * block_size + loc,
+                        &alloc::vec![0u8; block_size - loc],
+                    );
+                }
+            }
+        }
+
+        // NOTE: Growing the file does not allocate any blocks; the blocks past the old end of
+        // the file are holes which read as zeros until they are written to.
+        {
+            let now = crate::arch::time::get_realtime_clock().tv_sec as u32;
+            let mut inode = self.inode.write();
+
+            inode.set_size(size);
+            inode.last_modification = now;
+            inode.creation_time = now;
+        }
+
+        self.shared_pages.truncate(size);
+
+        self.sync();
+        Ok(())
+    }
+
+    fn unlink(&self, name: &str) -> super::Result<()> {
+        if !self.metadata()?.is_directory() {
+            return Err(FileSystemError::NotDirectory);
+        }
+
+        let (_, mut entry) = DirEntryIter::new(self.sref())
+            .find(|(ename, _)| ename == name)
+            .ok_or(FileSystemError::EntryNotFound)?;
+
+        let fs = self.fs.upgrade().expect("ext2: filesystem was dropped");
+        let inode = fs
+            .find_inode(entry.inode as usize, None)
+            .ok_or(FileSystemError::EntryNotFound)?;
+
+        if inode.metadata()?.is_directory() {
+            return Err(FileSystemError::IsDir);
+        }
+
+        // Mark the directory entry as unused.
+        entry.inode = 0;
+
+        inode
+            .downcast_arc::<INode>()
+            .expect("ext2: invalid inode")
+            .drop_link();
+
         Ok(())
     }
 
-    fn touch(&self, parent: DirCacheItem, name: &str) -> super::Result<DirCacheItem> {
+    fn touch(&self, parent: DirCacheItem, name: &str, mode: Mode) -> super::Result<DirCacheItem> {
         if !self.metadata()?.is_directory() {
             return Err(FileSystemError::NotSupported);
         }
 
         let inode = self.make_inode(name, FileType::File, None)?;
+        inode.set_attr(&SetAttr {
+            mode: Some(mode.bits()),
+            ..Default::default()
+        })?;
+
         Ok(DirEntry::new(parent, inode, name.to_string()))
     }
 
-    fn mkdir(&self, name: &str) -> super::Result<INodeCacheItem> {
+    fn mkdir(&self, name: &str, mode: Mode) -> super::Result<INodeCacheItem> {
         if !self.metadata()?.is_directory() {
             return Err(FileSystemError::NotSupported);
         }
 
-        self.make_inode(name, FileType::Directory, None)
+        let inode = self.make_inode(name, FileType::Directory, None)?;
+        inode.set_attr(&SetAttr {
+            mode: Some(mode.bits()),
+            ..Default::default()
+        })?;
+
+        Ok(inode)
     }
 
     fn make_local_socket_inode(
@@ -531,8 +820,18 @@ impl INodeInterface for INode {
     fn mmap(&self, offset: usize, size: usize, flags: MMapFlags) -> super::Result<PhysFrame> {
         assert!(self.proxy.is_none());
 
-        // TODO: support shared file mappings.
-        assert!(!flags.contains(MMapFlags::MAP_SHARED));
+        if flags.contains(MMapFlags::MAP_SHARED) {
+            // The page is read in full, so it has the data past the end of the mapping as
+            // well. The part of the page past the end of the file reads as zeros.
+            return self.shared_pages.get_or_insert_with(offset, |page| {
+                let buffer = unsafe {
+                    core::slice::from_raw_parts_mut(page.as_mut_ptr().cast(), page.len())
+                };
+
+                self.read(offset, buffer)?;
+                Ok(())
+            });
+        }
 
         let private_cp: PhysFrame = FRAME_ALLOCATOR.allocate_frame().unwrap();
         private_cp.as_slice_mut().fill(0);
@@ -543,6 +842,21 @@ impl INodeInterface for INode {
         Ok(private_cp)
     }
 
+    fn msync(&self, offset: usize, size: usize) -> super::Result<()> {
+        let file_size = self.inode.read().size();
+
+        self.shared_pages
+            .for_each(offset..offset + size, |page_offset, page| {
+                // Do not grow the file if it was truncated while it was mapped.
+                if page_offset < file_size {
+                    let size = core::cmp::min(page.len(), file_size - page_offset);
+                    self.write(page_offset, &page[..size])?;
+                }
+
+                Ok(())
+            })
+    }
+
     fn listen(&self, backlog: usize) -> Result<(), SyscallError> {
         if let Some(proxy) = self.proxy.as_ref() {
             return proxy.listen(backlog);
@@ -602,6 +916,59 @@ impl INodeInterface for INode {
     }
 }
 
+/// The location of a logical block of a file in the block pointers of an inode.
+///
+/// There are pointers to the first 12 blocks which contain the file's data in the
+/// inode. There is a pointer to an indirect block (which contains pointers to the
+/// next set of blocks), a pointer to a doubly indirect block and a pointer to a
+/// triply indirect block.
+#[derive(Debug, PartialEq)]
+struct BlockPath {
+    /// Index into the block pointers of the inode.
+    slot: usize,
+    /// Number of indirect blocks to go through (0 for a direct block).
+    depth: usize,
+    /// Index into each of the indirect blocks, starting from the outermost one.
+    indices: [usize; 3],
+}
+
+impl BlockPath {
+    /// Returns [`None`] if `block` is past the largest block that can be mapped.
+    fn new(mut block: usize, entries_per_block: usize) -> Option<Self> {
+        if block < 12 {
+            return Some(Self {
+                slot: block,
+                depth: 0,
+                indices: [0; 3],
+            });
+        }
+
+        block -= 12;
+
+        for depth in 1..=3 {
+            let span = entries_per_block.pow(depth as u32);
+
+            if block < span {
+                let mut indices = [0; 3];
+
+                for (level, index) in indices[..depth].iter_mut().rev().enumerate() {
+                    *index = (block / entries_per_block.pow(level as u32)) % entries_per_block;
+                }
+
+                return Some(Self {
+                    slot: 11 + depth,
+                    depth,
+                    indices,
+                });
+            }
+
+            block -= span;
+        }
+
+        None
+    }
+}
+
 pub struct DirEntryIter {
     inode: Arc<INode>,
     offset: usize,
@@ -619,14 +986,25 @@ impl Iterator for DirEntryIter {
     fn next(&mut self) -> Option<Self::Item> {
         let file_size = self.inode.inode.read().size();
 
-        if self.offset + core::mem::size_of::<disk::DirEntry>() > file_size {
-            return None;
-        }
+        let entry = loop {
+            if self.offset + core::mem::size_of::<disk::DirEntry>() > file_size {
+                return None;
+            }
 
-        let entry = unsafe { self.inode.read_mut::<disk::DirEntry>(self.offset) };
-        if entry.inode == 0 {
-            return None;
-        }
+            let entry = unsafe { self.inode.read_mut::<disk::DirEntry>(self.offset) };
+
+            if entry.entry_size == 0 {
+                return None;
+            }
+
+            // Skip the unused directory entries (e.g. the ones that have been unlinked).
+            if entry.inode == 0 {
+                self.offset += entry.entry_size as usize;
+                continue;
+            }
+
+            break entry;
+        };
 
         let mut name = Box::<[u8]>::new_uninit_slice(entry.name_size as usize);
         self.inode
@@ -696,6 +1074,93 @@ impl Ext2 {
     ) -> Option<INodeCacheItem> {
         INode::new(self.sref.clone(), id, proxy)
     }
+
+    /// Reads the `index`th block pointer stored in the indirect block `block`.
+    fn read_block_ptr(&self, block: usize, index: usize) -> u32 {
+        let offset = block * self.superblock.block_size() + index * core::mem::size_of::<u32>();
+        let mut ptr = MaybeUninit::<u32>::uninit();
+
+        self.block
+            .read(offset, ptr.as_bytes_mut())
+            .expect("ext2: failed to read the indirect block");
+
+        // SAFETY: We have initialized the variable above.
+        unsafe { ptr.assume_init() }
+    }
+
+    /// Writes the `index`th block pointer stored in the indirect block `block`.
+    fn write_block_ptr(&self, block: usize, index: usize, ptr: u32) {
+        let offset = block * self.superblock.block_size() + index * core::mem::size_of::<u32>();
+        self.block.write(offset, &ptr.to_le_bytes());
+    }
+
+    /// Reads the block pointers stored in the indirect block `block`.
+    fn read_block_ptrs(&self, block: usize) -> Box<[u32]> {
+        let entries_per_block = self.superblock.entries_per_block();
+        let mut ptrs = Box::<[u32]>::new_uninit_slice(entries_per_block);
+
+        self.block
+            .read(
+                block * self.superblock.block_size(),
+                MaybeUninit::slice_as_bytes_mut(&mut ptrs),
+            )
+            .expect("ext2: failed to read the indirect block");
+
+        // SAFETY: We have initialized the block pointers above.
+        unsafe { ptrs.assume_init() }
+    }
+
+    /// Frees the data blocks mapped by the indirect block `block` of the provided `depth`
+    /// (where a depth of 1 is a singly indirect block), except for the first `keep` data
+    /// blocks. The number of freed blocks is added to `freed`. Returns [`true`] if the
+    /// indirect block itself has been freed.
+    fn free_indirect(&self, block: usize, depth: u32, keep: usize, freed: &mut usize) -> bool {
+        // The number of data blocks mapped by each of the entries.
+        let span = self.superblock.entries_per_block().pow(depth - 1);
+
+        let mut ptrs = self.read_block_ptrs(block);
+        let mut dirty = false;
+
+        for (i, ptr) in ptrs.iter_mut().enumerate() {
+            let start = i * span;
+
+            if *ptr == 0 || start + span <= keep {
+                continue;
+            }
+
+            if depth == 1 {
+                self.bgdt.free_block_ptr(*ptr as usize);
+                *freed += 1;
+            } else if !self.free_indirect(
+                *ptr as usize,
+                depth - 1,
+                keep.saturating_sub(start),
+                freed,
+            ) {
+                // The entry still maps some of the kept data blocks.
+                continue;
+            }
+
+            *ptr = 0;
+            dirty = true;
+        }
+
+        if keep == 0 {
+            self.bgdt.free_block_ptr(block);
+            *freed += 1;
+
+            return true;
+        }
+
+        if dirty {
+            self.block.write(
+                block * self.superblock.block_size(),
+                bytemuck::cast_slice(&ptrs),
+            );
+        }
+
+        false
+    }
 }
 
 impl FileSystem for Ext2 {
@@ -706,4 +1171,89 @@ impl FileSystem for Ext2 {
 
         DirEntry::new_root(inode, String::from("/"))
     }
+
+    fn name(&self) -> &'static str {
+        "ext2"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::fs::{self, Path};
+
+    #[test]
+    fn block_path() {
+        // 1 KiB blocks.
+        let entries = 256;
+
+        let direct = BlockPath::new(11, entries).unwrap();
+        assert_eq!((direct.slot, direct.depth), (11, 0));
+
+        let singly = BlockPath::new(12 + 255, entries).unwrap();
+        assert_eq!((singly.slot, singly.depth), (12, 1));
+        assert_eq!(singly.indices[0], 255);
+
+        let doubly = BlockPath::new(12 + 256 + 256 * 3 + 7, entries).unwrap();
+        assert_eq!((doubly.slot, doubly.depth), (13, 2));
+        assert_eq!(doubly.indices[..2], [3, 7]);
+
+        let triply =
+            BlockPath::new(12 + 256 + 256 * 256 + 256 * 256 * 2 + 256 * 5 + 9, entries).unwrap();
+        assert_eq!((triply.slot, triply.depth), (14, 3));
+        assert_eq!(triply.indices, [2, 5, 9]);
+
+        assert!(BlockPath::new(12 + 256 + 256 * 256 + 256 * 256 * 256, entries).is_none());
+    }
+
+    #[test]
+    fn large_file() {
+        // Large enough to go through the triply indirect block with 1 KiB blocks and the
+        // doubly indirect block with 4 KiB blocks.
+        const FILE_SIZE: usize = 256 * 1024 * 1024;
+        const CHUNK_SIZE: usize = 1024 * 1024;
+
+        let root = fs::lookup_path(Path::new("/")).unwrap();
+        let file = root
+            .inode()
+            .touch(
+                root.clone(),
+                "ext2_large_file",
+                Mode::from_bits_truncate(0o644),
+            )
+            .unwrap();
+        let inode = file.inode();
+
+        // Fill each chunk with a different pattern, so that blocks mapped at the wrong
+        // location are caught when reading the file back.
+        let pattern = |chunk: usize, buffer: &mut [u8]| {
+            for (i, byte) in buffer.iter_mut().enumerate() {
+                *byte = (chunk + i / 4096) as u8;
+            }
+        };
+
+        let mut expected = alloc::vec![0u8; CHUNK_SIZE];
+        let mut buffer = alloc::vec![0u8; CHUNK_SIZE];
+
+        for chunk in 0..FILE_SIZE / CHUNK_SIZE {
+            pattern(chunk, &mut expected);
+
+            let written = inode.write_at(chunk * CHUNK_SIZE, &expected).unwrap();
+            assert_eq!(written, CHUNK_SIZE);
+        }
+
+        assert_eq!(inode.stat().unwrap().st_size as usize, FILE_SIZE);
+
+        for chunk in 0..FILE_SIZE / CHUNK_SIZE {
+            pattern(chunk, &mut expected);
+
+            let read = inode.read_at(chunk * CHUNK_SIZE, &mut buffer).unwrap();
+            assert_eq!(read, CHUNK_SIZE);
+            assert!(buffer == expected, "ext2: chunk {chunk} was not read back");
+        }
+
+        root.inode().unlink("ext2_large_file").unwrap();
+        file.drop_from_cache();
+    }
 }
@@ -28,7 +28,7 @@ use crate::fs::cache::DirCacheImpl;
 
 use super::cache::{DirCacheItem, INodeCacheItem};
 use super::inode::FileType;
-use super::FileSystemError;
+use super::{FileSystem, FileSystemError};
 
 pub enum DuplicateHint {
     Exact(usize),
@@ -211,6 +211,16 @@ impl FileTable {
         }
     }
 
+    /// Returns whether any of the open files belong to the provided `filesystem`.
+    pub fn is_using_filesystem(&self, filesystem: &Arc<dyn FileSystem>) -> bool {
+        let files = self.0.read();
+
+        files
+            .iter()
+            .flatten()
+            .any(|handle| handle.inode.is_on_filesystem(filesystem))
+    }
+
     pub fn close_on_exec(&self) {
         let mut files = self.0.write();
 
@@ -342,6 +352,31 @@ impl FileTable {
         }
     }
 
+    /// Installs a duplicate of `handle`, which may belong to another task (e.g. when it is
+    /// passed over a UNIX socket), at the lowest available file descriptor.
+    pub fn install(&self, handle: &FileHandle, cloexec: bool) -> super::Result<usize> {
+        let mut files = self.0.write();
+        let fd = files
+            .iter()
+            .position(|file| file.is_none())
+            .unwrap_or(files.len());
+
+        if fd >= 256 {
+            return Err(FileSystemError::Busy);
+        }
+
+        let handle = handle.duplicate(fd, OpenFlags::empty())?;
+        handle.flags.write().set(OpenFlags::O_CLOEXEC, cloexec);
+
+        if fd == files.len() {
+            files.push(Some(handle));
+        } else {
+            files[fd] = Some(handle);
+        }
+
+        Ok(fd)
+    }
+
     /// Closes a file descriptor, so that its no longer refers to any file
     /// and can be reused. This function will return false if the provided file
     /// descriptor index was invalid.
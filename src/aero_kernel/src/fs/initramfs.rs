@@ -0,0 +1,159 @@
+// Copyright (C) 2021-2023 The Aero Project Developers.
+//
+// This file is part of The Aero Project.
+//
+// Aero is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Aero is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Aero. If not, see <https://www.gnu.org/licenses/>.
+
+//! The initramfs is a `newc` cpio archive, passed to the kernel as a bootloader module
+//! (see the `initramfs=<module>` kernel command line option), which is unpacked into a
+//! [`RamFs`] and mounted as the root filesystem. This allows booting without a disk.
+
+use aero_syscall::consts::MountFlags;
+use aero_syscall::{Mode, TimeSpec};
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+
+use super::cache::DirCacheItem;
+use super::inode::{DirEntry, SetAttr};
+use super::ramfs::RamFs;
+use super::{FileSystem, FileSystemError, Result, MOUNT_MANAGER};
+
+/// Directories that the kernel mounts filesystems on.
+const MOUNT_POINTS: &[&str] = &["dev", "proc"];
+
+/// Looks up `name` in the directory `parent`, creating it as a directory if it does not
+/// exist.
+fn lookup_or_mkdir(parent: &DirCacheItem, name: &str) -> Result<DirCacheItem> {
+    match parent.inode().lookup(parent.clone(), name) {
+        Ok(entry) => Ok(entry),
+
+        Err(FileSystemError::EntryNotFound) => {
+            let inode = parent
+                .inode()
+                .mkdir(name, Mode::from_bits_truncate(0o755))?;
+            Ok(DirEntry::new(parent.clone(), inode, String::from(name)))
+        }
+
+        Err(err) => Err(err),
+    }
+}
+
+/// Unpacks `entry` relative to `root`.
+///
+/// `links` maps the inode number of each regular file with more than one link to the
+/// first entry unpacked for it, so that the remaining names are created as hard links.
+fn unpack(
+    root: &DirCacheItem,
+    entry: &cpio_reader::Entry,
+    links: &mut BTreeMap<u32, DirCacheItem>,
+) -> Result<()> {
+    let path = entry.name().trim_start_matches("./").trim_matches('/');
+
+    if path.is_empty() || path == "." {
+        return Ok(());
+    }
+
+    // Create the leading directories if they are not present in the archive.
+    let (parent, name) = match path.rsplit_once('/') {
+        Some((parent, name)) => {
+            let mut dir = root.clone();
+
+            for component in parent.split('/').filter(|c| !c.is_empty()) {
+                dir = lookup_or_mkdir(&dir, component)?;
+            }
+
+            (dir, name)
+        }
+
+        None => (root.clone(), path),
+    };
+
+    let mode = Mode::from_bits_truncate(entry.mode().bits());
+
+    let inode = match mode & Mode::S_IFMT {
+        Mode::S_IFDIR => lookup_or_mkdir(&parent, name)?.inode(),
+
+        Mode::S_IFREG => {
+            let file = match links.get(&entry.ino()) {
+                Some(first) if entry.nlink() > 1 => {
+                    parent.inode().link(name, first.clone())?;
+                    first.clone()
+                }
+
+                _ => {
+                    let file = parent
+                        .inode()
+                        .touch(parent.clone(), name, mode & !Mode::S_IFMT)?;
+
+                    if entry.nlink() > 1 {
+                        links.insert(entry.ino(), file.clone());
+                    }
+
+                    file
+                }
+            };
+
+            // The newc format stores the contents of a hard linked file with the last
+            // entry for it; the other entries are empty.
+            if !entry.file().is_empty() {
+                file.inode().write_at(0, entry.file())?;
+            }
+
+            file.inode()
+        }
+
+        Mode::S_IFLNK => {
+            let target =
+                core::str::from_utf8(entry.file()).map_err(|_| FileSystemError::InvalidPath)?;
+            parent.inode().symlink(name, target)?
+        }
+
+        _ => {
+            log::warn!("initramfs: skipping special file `{path}` (mode={mode:?})");
+            return Ok(());
+        }
+    };
+
+    let mtime = TimeSpec {
+        tv_sec: entry.mtime() as _,
+        tv_nsec: 0,
+    };
+
+    inode.set_attr(&SetAttr {
+        mode: Some(mode.bits() & 0o7777),
+        uid: Some(entry.uid()),
+        gid: Some(entry.gid()),
+        atime: Some(mtime.clone()),
+        mtime: Some(mtime),
+    })
+}
+
+/// Unpacks the provided initramfs `archive` and mounts it as the root filesystem.
+pub fn init(archive: &'static [u8]) -> Result<()> {
+    let ramfs = RamFs::new();
+    let root = ramfs.root_dir();
+
+    let mut links = BTreeMap::new();
+
+    for entry in cpio_reader::iter_files(archive) {
+        unpack(&root, &entry, &mut links)?;
+    }
+
+    for mount_point in MOUNT_POINTS {
+        lookup_or_mkdir(&root, mount_point)?;
+    }
+
+    MOUNT_MANAGER.mount_root(ramfs, String::from("initramfs"), MountFlags::empty());
+    Ok(())
+}
@@ -19,7 +19,7 @@ use core::sync::atomic::{AtomicUsize, Ordering};
 
 use aero_syscall::prelude::{EPollEventFlags, PollEventFlags};
 use aero_syscall::socket::{MessageFlags, MessageHeader};
-use aero_syscall::{MMapFlags, OpenFlags, SyscallError};
+use aero_syscall::{MMapFlags, Mode, OpenFlags, SyscallError, TimeSpec};
 
 use alloc::sync::{Arc, Weak};
 
@@ -28,7 +28,6 @@ use intrusive_collections::UnsafeRef;
 use spin::Once;
 
 use crate::mem::paging::{PhysFrame, VirtAddr};
-use crate::socket::unix::UnixSocket;
 use crate::socket::{SocketAddr, SocketAddrRef};
 use crate::userland::scheduler;
 use crate::utils::sync::{BMutex, Mutex, WaitQueue};
@@ -36,6 +35,7 @@ use crate::utils::sync::{BMutex, Mutex, WaitQueue};
 use super::cache::{Cacheable, CachedINode, DirCacheItem, INodeCacheItem};
 use super::devfs::DevINode;
 use super::file_table::FileHandle;
+use super::shmem::SharedMemory;
 use super::{cache, FileSystem, FileSystemError, Result};
 
 static DIR_CACHE_MARKER: AtomicUsize = AtomicUsize::new(0x00);
@@ -69,6 +69,8 @@ bitflags::bitflags! {
         const OUT = 1 << 2;
         /// Error condition happened on the associated file descriptor.
         const ERR = 1 << 3;
+        /// The peer of the associated file has closed its end.
+        const HUP = 1 << 4;
     }
 }
 
@@ -85,6 +87,9 @@ impl From<PollFlags> for EPollEventFlags {
         if poll.contains(PollFlags::ERR) {
             flags |= Self::ERR;
         }
+        if poll.contains(PollFlags::HUP) {
+            flags |= Self::HUP;
+        }
 
         flags
     }
@@ -103,11 +108,28 @@ impl From<PollFlags> for PollEventFlags {
         if poll.contains(PollFlags::ERR) {
             flags |= Self::ERR;
         }
+        if poll.contains(PollFlags::HUP) {
+            flags |= Self::HUP;
+        }
 
         flags
     }
 }
 
+/// Attributes of an inode that are changed by [`INodeInterface::set_attr`]. Attributes
+/// that are [`None`] are left unchanged.
+#[derive(Debug, Default)]
+pub struct SetAttr {
+    /// The permission bits (including the set-user-ID, set-group-ID and sticky bits).
+    pub mode: Option<u32>,
+    pub uid: Option<u32>,
+    pub gid: Option<u32>,
+    /// The last access time.
+    pub atime: Option<TimeSpec>,
+    /// The last modification time.
+    pub mtime: Option<TimeSpec>,
+}
+
 /// An inode describes a file. An inode structure holds metadata of the
 /// inode which includes its type, size, the number of links referring to it,
 /// and the list of blocks holding the file's content. For example device files,
@@ -139,8 +161,9 @@ pub trait INodeInterface: Send + Sync {
         Err(FileSystemError::NotSupported)
     }
 
-    /// Creates a new directory with the provided `name` in the filesystem.
-    fn mkdir(&self, _name: &str) -> Result<INodeCacheItem> {
+    /// Creates a new directory with the provided `name` and the permission bits in `mode`
+    /// in the filesystem.
+    fn mkdir(&self, _name: &str, _mode: Mode) -> Result<INodeCacheItem> {
         Err(FileSystemError::NotSupported)
     }
 
@@ -148,8 +171,15 @@ pub trait INodeInterface: Send + Sync {
         Err(FileSystemError::NotSupported)
     }
 
-    /// Creates a new file with the provided `name` in the filesystem.
-    fn touch(&self, _parent: DirCacheItem, _name: &str) -> Result<DirCacheItem> {
+    /// Creates a new file with the provided `name` and the permission bits in `mode` in
+    /// the filesystem.
+    fn touch(&self, _parent: DirCacheItem, _name: &str, _mode: Mode) -> Result<DirCacheItem> {
+        Err(FileSystemError::NotSupported)
+    }
+
+    /// Creates a new symbolic link with the provided `name` pointing to `target` in the
+    /// filesystem.
+    fn symlink(&self, _name: &str, _target: &str) -> Result<INodeCacheItem> {
         Err(FileSystemError::NotSupported)
     }
 
@@ -157,6 +187,12 @@ pub trait INodeInterface: Send + Sync {
         Ok(aero_syscall::Stat::default())
     }
 
+    /// Changes the attributes of the inode (akin `chmod`, `chown` and `utimensat`). The
+    /// status change time of the inode is updated as well.
+    fn set_attr(&self, _attr: &SetAttr) -> Result<()> {
+        Err(FileSystemError::NotSupported)
+    }
+
     fn shutdown(&self, _how: usize) -> Result<()> {
         Err(FileSystemError::NotSupported)
     }
@@ -233,6 +269,13 @@ pub trait INodeInterface: Send + Sync {
         Err(FileSystemError::NotSupported)
     }
 
+    /// Writes back the pages of the shared mappings of the file that overlap
+    /// `offset..offset + size`. Files whose shared mappings are not backed by storage
+    /// have nothing to write back.
+    fn msync(&self, _offset: usize, _size: usize) -> Result<()> {
+        Ok(())
+    }
+
     // Socket operations:
     fn bind(&self, _address: SocketAddrRef, _length: usize) -> Result<()> {
         Err(FileSystemError::NotSocket)
@@ -246,7 +289,7 @@ pub trait INodeInterface: Send + Sync {
         Err(SyscallError::ENOTSOCK)
     }
 
-    fn accept(&self, _address: Option<(VirtAddr, &mut u32)>) -> Result<Arc<UnixSocket>> {
+    fn accept(&self, _address: Option<(VirtAddr, &mut u32)>) -> Result<Arc<dyn INodeInterface>> {
         Err(FileSystemError::NotSocket)
     }
 
@@ -266,6 +309,17 @@ pub trait INodeInterface: Send + Sync {
         Err(FileSystemError::NotSupported)
     }
 
+    /// Sets the socket option `name` at the protocol `level` (e.g. `SOL_SOCKET`) to `value`.
+    fn set_sockopt(&self, _level: usize, _name: usize, _value: &[u8]) -> Result<()> {
+        Err(FileSystemError::NotSocket)
+    }
+
+    /// Writes the value of the socket option `name` at the protocol `level` into `value`
+    /// and returns its size.
+    fn get_sockopt(&self, _level: usize, _name: usize, _value: &mut [u8]) -> Result<usize> {
+        Err(FileSystemError::NotSocket)
+    }
+
     /// Returns the inner UNIX socket inode if bound to one.
     fn as_unix_socket(&self) -> Result<Arc<dyn INodeInterface>> {
         Err(FileSystemError::NotSocket)
@@ -336,6 +390,10 @@ pub enum FileContents {
     /// in bytes) and is protected by a spin lock.
     Content(Mutex<Vec<u8>>),
 
+    /// A regular file whose contents are stored in memory pages, which are shared with
+    /// its `MAP_SHARED` mappings (see `fs/shmem.rs`).
+    Memory(Arc<SharedMemory>),
+
     /// This variant is similar to the one above, except it's read only
     /// and is backed by a static byte buffer
     StaticContent(&'static [u8]),
@@ -367,6 +425,18 @@ pub enum FileType {
     Symlink,
 }
 
+impl FileType {
+    /// Returns the permission bits that a newly created inode of this type gets.
+    pub fn default_permissions(&self) -> u32 {
+        match self {
+            FileType::File => 0o644,
+            FileType::Directory => 0o755,
+            FileType::Device => 0o666,
+            FileType::Socket | FileType::Symlink => 0o777,
+        }
+    }
+}
+
 impl From<FileType> for aero_syscall::SysFileType {
     fn from(file: FileType) -> Self {
         match file {
@@ -381,6 +451,18 @@ impl From<FileType> for aero_syscall::SysFileType {
     }
 }
 
+impl From<FileType> for aero_syscall::Mode {
+    fn from(file: FileType) -> Self {
+        match file {
+            FileType::File => aero_syscall::Mode::S_IFREG,
+            FileType::Directory => aero_syscall::Mode::S_IFDIR,
+            FileType::Device => aero_syscall::Mode::S_IFCHR,
+            FileType::Socket => aero_syscall::Mode::S_IFSOCK,
+            FileType::Symlink => aero_syscall::Mode::S_IFLNK,
+        }
+    }
+}
+
 impl Default for FileType {
     fn default() -> Self {
         Self::File
@@ -405,6 +487,22 @@ impl DirEntry {
     /// Creates a new cached directory entry, where the entry has the provided `parent` and
     /// uses the weak filesystem pointer that the provided `inode` holds.
     pub fn new(parent: DirCacheItem, inode: INodeCacheItem, name: String) -> DirCacheItem {
+        Self::with_parent(parent, inode, name, true)
+    }
+
+    /// Creates a new directory entry like [`DirEntry::new`], without adding it to the
+    /// directory cache. This is used for the entries that are generated on each lookup and
+    /// can disappear at any time (e.g. `/proc/<pid>`).
+    pub fn new_uncached(parent: DirCacheItem, inode: INodeCacheItem, name: String) -> DirCacheItem {
+        Self::with_parent(parent, inode, name, false)
+    }
+
+    fn with_parent(
+        parent: DirCacheItem,
+        inode: INodeCacheItem,
+        name: String,
+        cached: bool,
+    ) -> DirCacheItem {
         let dcache = cache::dcache();
 
         // Helper bool to avoid situations where the directory entry is already cached. The
@@ -434,7 +532,9 @@ impl DirEntry {
             },
         };
 
-        if cache_me {
+        // The entry gets its own cache marker even if it is not cached, since the marker is
+        // the key of its children in the directory cache.
+        if cache_me && cached {
             dcache.make_item_cached(entry)
         } else {
             dcache.make_item_no_cache(entry)
@@ -526,6 +626,14 @@ impl DirEntry {
         self.data.lock().parent.clone()
     }
 
+    /// Returns whether the inode of this directory entry belongs to the provided
+    /// `filesystem`.
+    pub fn is_on_filesystem(&self, filesystem: &Arc<dyn FileSystem>) -> bool {
+        self.inode().weak_filesystem().map_or(false, |fs| {
+            Weak::as_ptr(&fs) as *const () == Arc::as_ptr(filesystem) as *const ()
+        })
+    }
+
     /// Drops the directory entry from the cache.
     pub fn drop_from_cache(&self) {
         cache::dcache().remove(&self.cache_key());
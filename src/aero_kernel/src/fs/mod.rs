@@ -17,15 +17,19 @@
 
 use core::mem;
 
-use aero_syscall::SyscallError;
+use aero_syscall::consts::{AccessMode, MountFlags};
+use aero_syscall::{Mode, SyscallError};
 use alloc::collections::BTreeMap;
-use alloc::sync::Arc;
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
 
 use crate::userland::scheduler;
+use crate::userland::task::credentials::Credentials;
 use crate::utils::sync::Mutex;
 use spin::Once;
 
-use self::cache::{Cacheable, DirCacheItem};
+use self::cache::{Cacheable, DirCacheImpl, DirCacheItem, INodeCacheItem};
+use self::inode::FileType;
 
 pub mod block;
 pub mod cache;
@@ -34,10 +38,12 @@ pub mod epoll;
 pub mod eventfd;
 pub mod ext2;
 pub mod file_table;
+pub mod initramfs;
 pub mod inode;
 pub mod pipe;
 pub mod procfs;
 pub mod ramfs;
+pub mod shmem;
 
 static ROOT_FS: Once<Arc<dyn FileSystem>> = Once::new();
 static ROOT_DIR: Once<DirCacheItem> = Once::new();
@@ -52,26 +58,63 @@ type MountKey = (usize, String);
 #[derive(Clone)]
 struct MountPoint {
     filesystem: Arc<dyn FileSystem>,
+    source: String,
+    flags: MountFlags,
 
     root_entry: DirCacheItem,
     origin_entry: DirCacheItem,
 }
 
-#[repr(transparent)]
-pub struct MountManager(Mutex<BTreeMap<MountKey, MountPoint>>);
+/// Information about a mounted filesystem, as listed in `/proc/mounts`.
+pub struct MountInfo {
+    pub source: String,
+    pub target: String,
+    pub fs_type: &'static str,
+    pub flags: MountFlags,
+}
+
+pub struct MountManager {
+    mounts: Mutex<BTreeMap<MountKey, MountPoint>>,
+    root: Once<MountPoint>,
+}
 
 impl MountManager {
     #[inline]
     fn new() -> Self {
-        Self(Mutex::new(BTreeMap::new()))
+        Self {
+            mounts: Mutex::new(BTreeMap::new()),
+            root: Once::new(),
+        }
     }
 
+    /// Mounts the provided `filesystem` at `directory` using the name of the filesystem
+    /// as the mount source.
     pub fn mount(&self, directory: DirCacheItem, filesystem: Arc<dyn FileSystem>) -> Result<()> {
-        let mut this = self.0.lock();
+        let source = String::from(filesystem.name());
+        self.mount_with(directory, filesystem, source, MountFlags::empty())
+    }
+
+    pub fn mount_with(
+        &self,
+        directory: DirCacheItem,
+        filesystem: Arc<dyn FileSystem>,
+        source: String,
+        flags: MountFlags,
+    ) -> Result<()> {
+        let mut this = self.mounts.lock();
         let mount_key = directory.cache_key();
 
         if this.contains_key(&mount_key) {
-            return Err(FileSystemError::EntryExists);
+            return Err(FileSystemError::Busy);
+        }
+
+        // Filesystems may hand out the same root directory entry on each call to
+        // `root_dir`, which takes the name and parent of the mount point below. So a
+        // filesystem instance can only be mounted once.
+        if this.values().any(|mount| {
+            Arc::as_ptr(&mount.filesystem) as *const () == Arc::as_ptr(&filesystem) as *const ()
+        }) {
+            return Err(FileSystemError::Busy);
         }
 
         let root_dir = filesystem.root_dir();
@@ -89,6 +132,8 @@ impl MountManager {
             mount_key,
             MountPoint {
                 filesystem,
+                source,
+                flags,
                 root_entry: root_dir,
                 origin_entry: directory,
             },
@@ -97,8 +142,104 @@ impl MountManager {
         Ok(())
     }
 
+    /// Returns [`true`] if the root filesystem has been mounted.
+    pub fn is_root_mounted(&self) -> bool {
+        self.root.get().is_some()
+    }
+
+    /// Mounts the provided `filesystem` as the root filesystem. Only the first call
+    /// takes effect.
+    pub fn mount_root(&self, filesystem: Arc<dyn FileSystem>, source: String, flags: MountFlags) {
+        self.root.call_once(|| {
+            let root_dir = ROOT_DIR.call_once(|| filesystem.root_dir()).clone();
+            ROOT_FS.call_once(|| filesystem.clone());
+
+            MountPoint {
+                filesystem,
+                source,
+                flags,
+                root_entry: root_dir.clone(),
+                origin_entry: root_dir,
+            }
+        });
+    }
+
+    /// Unmounts the filesystem mounted at `directory`, where `directory` is the root
+    /// directory entry of the mounted filesystem (ie. the result of looking up the
+    /// mount point).
+    ///
+    /// ## Errors
+    /// * [`FileSystemError::InvalidPath`] - `directory` is not a mount point.
+    /// * [`FileSystemError::Busy`] - a task has a file open or mapped or its working directory on
+    ///   the filesystem or another filesystem is mounted on top of it.
+    pub fn unmount(&self, directory: DirCacheItem) -> Result<()> {
+        let mut this = self.mounts.lock();
+        let mount_key = directory.cache_key();
+
+        let mount_point = this
+            .get(&mount_key)
+            .filter(|mount| mount.root_entry.cache_marker == directory.cache_marker)
+            .ok_or(FileSystemError::InvalidPath)?;
+
+        let filesystem = mount_point.filesystem.clone();
+
+        if this
+            .values()
+            .any(|mount| mount.origin_entry.is_on_filesystem(&filesystem))
+        {
+            return Err(FileSystemError::Busy);
+        }
+
+        let mut busy = false;
+
+        scheduler::get_scheduler().for_each_task(|task| {
+            busy |= task.is_using_filesystem(&filesystem);
+        });
+
+        if busy {
+            return Err(FileSystemError::Busy);
+        }
+
+        this.remove(&mount_key);
+        Ok(())
+    }
+
+    /// Returns whether the filesystem that `inode` belongs to has been mounted read-only.
+    pub fn is_read_only(&self, inode: &INodeCacheItem) -> bool {
+        let filesystem = match inode.weak_filesystem() {
+            Some(filesystem) => Weak::as_ptr(&filesystem) as *const (),
+            None => return false,
+        };
+
+        let mounts = self.mounts.lock();
+
+        self.root
+            .get()
+            .into_iter()
+            .chain(mounts.values())
+            .find(|mount| Arc::as_ptr(&mount.filesystem) as *const () == filesystem)
+            .map_or(false, |mount| mount.flags.contains(MountFlags::RDONLY))
+    }
+
+    /// Returns a list of all of the mounted filesystems, starting with the root filesystem.
+    pub fn mounts(&self) -> Vec<MountInfo> {
+        let this = self.mounts.lock();
+
+        self.root
+            .get()
+            .into_iter()
+            .chain(this.values())
+            .map(|mount| MountInfo {
+                source: mount.source.clone(),
+                target: mount.origin_entry.absolute_path_str(),
+                fs_type: mount.filesystem.name(),
+                flags: mount.flags,
+            })
+            .collect()
+    }
+
     fn find_mount(&self, directory: DirCacheItem) -> Result<MountPoint> {
-        let this = self.0.lock();
+        let this = self.mounts.lock();
         let cache_key = directory.cache_key();
 
         if let Some(mount_point) = this.get(&cache_key) {
@@ -113,6 +254,39 @@ pub trait FileSystem: Send + Sync {
     fn root_dir(&self) -> DirCacheItem {
         todo!()
     }
+
+    /// Returns the name of the filesystem type (e.g. `ext2`).
+    fn name(&self) -> &'static str;
+}
+
+/// Returns [`FileSystemError::ReadOnly`] if the filesystem that `inode` belongs to has
+/// been mounted read-only.
+pub fn ensure_writable(inode: &INodeCacheItem) -> Result<()> {
+    if MOUNT_MANAGER.is_read_only(inode) {
+        Err(FileSystemError::ReadOnly)
+    } else {
+        Ok(())
+    }
+}
+
+/// Returns [`FileSystemError::PermissionDenied`] if the current task is not permitted the
+/// `access` to `inode`.
+pub fn check_access(inode: &INodeCacheItem, access: AccessMode) -> Result<()> {
+    check_access_with(&Credentials::current(), inode, access)
+}
+
+/// Same as [`check_access`], except the permissions are checked against the provided
+/// `credentials`.
+pub fn check_access_with(
+    credentials: &Credentials,
+    inode: &INodeCacheItem,
+    access: AccessMode,
+) -> Result<()> {
+    if credentials.may_access(&inode.stat()?, access) {
+        Ok(())
+    } else {
+        Err(FileSystemError::PermissionDenied)
+    }
 }
 
 #[derive(Debug, PartialEq)]
@@ -132,6 +306,23 @@ pub enum FileSystemError {
     NotConnected,
     WouldBlock,
     NoTty,
+    ReadOnly,
+    PermissionDenied,
+    NoDevice,
+    AddressInUse,
+    NetworkUnreachable,
+    InvalidArgument,
+    NoProtocolOption,
+    MessageTooLong,
+    BadDescriptor,
+    NotPermitted,
+    WrongProtocolType,
+    OperationNotSupported,
+    InProgress,
+    FileTooLarge,
+    NoSpace,
+    AlreadyInProgress,
+    IsConnected,
 }
 
 impl From<FileSystemError> for SyscallError {
@@ -152,6 +343,23 @@ impl From<FileSystemError> for SyscallError {
             FileSystemError::NotConnected => Self::ENOTCONN,
             FileSystemError::WouldBlock => Self::EAGAIN,
             FileSystemError::NoTty => Self::ENOTTY,
+            FileSystemError::ReadOnly => Self::EROFS,
+            FileSystemError::PermissionDenied => Self::EACCES,
+            FileSystemError::NoDevice => Self::ENODEV,
+            FileSystemError::AddressInUse => Self::EADDRINUSE,
+            FileSystemError::NetworkUnreachable => Self::ENETUNREACH,
+            FileSystemError::InvalidArgument => Self::EINVAL,
+            FileSystemError::NoProtocolOption => Self::ENOPROTOOPT,
+            FileSystemError::MessageTooLong => Self::EMSGSIZE,
+            FileSystemError::BadDescriptor => Self::EBADF,
+            FileSystemError::NotPermitted => Self::EPERM,
+            FileSystemError::WrongProtocolType => Self::EPROTOTYPE,
+            FileSystemError::OperationNotSupported => Self::EOPNOTSUPP,
+            FileSystemError::InProgress => Self::EINPROGRESS,
+            FileSystemError::FileTooLarge => Self::EFBIG,
+            FileSystemError::NoSpace => Self::ENOSPC,
+            FileSystemError::AlreadyInProgress => Self::EALREADY,
+            FileSystemError::IsConnected => Self::EISCONN,
         }
     }
 }
@@ -201,8 +409,10 @@ impl Path {
 #[derive(Debug, PartialEq)]
 pub enum LookupMode {
     None,
-    /// Creates the file if it does not exist.
-    Create,
+    /// Creates the file with the provided permission bits if it does not exist.
+    Create(Mode),
+    /// Does not resolve the last component of the path if it is a symbolic link.
+    NoFollow,
 }
 
 pub fn lookup_path_with(
@@ -210,6 +420,12 @@ pub fn lookup_path_with(
     path: &Path,
     mode: LookupMode,
 ) -> Result<DirCacheItem> {
+    let credentials = Credentials::current();
+    let create_mode = match mode {
+        LookupMode::Create(file_mode) => Some(file_mode),
+        _ => None,
+    };
+
     // Iterate and resolve each component. For example `a`, `b`, and `c` in `a/b/c`.
     for (i, component) in path.components().enumerate() {
         match component {
@@ -228,6 +444,9 @@ pub fn lookup_path_with(
             }
 
             _ => {
+                // Searching a directory requires execute permission on it.
+                check_access_with(&credentials, &cwd.inode(), AccessMode::X_OK)?;
+
                 // After we have resolved all of the special cases that might occur in a path, now
                 // we have to resolve the directory entry itself. For example `a` in `./a/`.
                 let cache_entry = inode::fetch_dir_entry(cwd.clone(), String::from(component));
@@ -236,31 +455,37 @@ pub fn lookup_path_with(
                 if let Some(entry) = cache_entry {
                     cwd = entry;
                 } else {
-                    match cwd.inode().lookup(cwd.clone(), component) {
-                        Ok(entry) => cwd = entry,
+                    match (cwd.inode().lookup(cwd.clone(), component), create_mode) {
+                        (Ok(entry), _) => cwd = entry,
+
+                        (Err(FileSystemError::EntryNotFound), Some(file_mode)) => {
+                            ensure_writable(&cwd.inode())?;
+                            check_access_with(&credentials, &cwd.inode(), AccessMode::W_OK)?;
 
-                        Err(err)
-                            if err == FileSystemError::EntryNotFound
-                                && mode == LookupMode::Create =>
-                        {
                             if i == path.components().count() - 1 {
-                                cwd = cwd.inode().touch(cwd.clone(), component)?;
+                                cwd = cwd.inode().touch(cwd.clone(), component, file_mode)?;
                             } else {
                                 // todo: fix this shit
-                                cwd.inode().mkdir(component)?;
+                                let dir_mode = Mode::from_bits_truncate(
+                                    FileType::Directory.default_permissions(),
+                                );
+
+                                cwd.inode().mkdir(component, dir_mode)?;
                                 cwd =
                                     lookup_path_with(cwd, Path::new(component), LookupMode::None)?;
                             }
                         }
 
-                        Err(err) => return Err(err),
+                        (Err(err), _) => return Err(err),
                     }
                 }
 
                 let inode = cwd.inode();
                 let metadata = inode.metadata()?;
 
-                if metadata.is_symlink() {
+                let is_last = i == path.components().count() - 1;
+
+                if metadata.is_symlink() && !(is_last && mode == LookupMode::NoFollow) {
                     let resolved_path_str = inode.resolve_link()?;
                     let resolved_path = Path::new(&resolved_path_str);
 
@@ -1,14 +1,23 @@
 use core::sync::atomic::{AtomicUsize, Ordering};
 
+use aero_syscall::consts::MountFlags;
+use aero_syscall::{MMapFlags, MMapProt, Mode};
+use alloc::borrow::Cow;
 use alloc::collections::BTreeMap;
 use alloc::string::{String, ToString};
 use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
 
+use crabnet::network::Ipv4Addr;
 use spin::{Once, RwLock};
 
 use crate::fs::inode::FileType;
 
 use crate::arch::tls;
+use crate::net;
+use crate::userland::scheduler;
+use crate::userland::task::credentials::Credentials;
+use crate::userland::task::{Task, TaskId, TaskState};
 
 use super::cache;
 use super::cache::*;
@@ -79,6 +88,260 @@ fn get_cpuinfo_cached() -> &'static str {
     })
 }
 
+/// Returns the list of mounted filesystems in the format of `/proc/mounts` on Linux:
+///
+/// ```text
+/// <source> <target> <filesystem type> <options> 0 0
+/// ```
+fn get_mounts() -> String {
+    let mut result = String::new();
+
+    for mount in MOUNT_MANAGER.mounts() {
+        let options = if mount.flags.contains(MountFlags::RDONLY) {
+            "ro"
+        } else {
+            "rw"
+        };
+
+        result.push_str(&alloc::format!(
+            "{} {} {} {} 0 0\n",
+            mount.source,
+            mount.target,
+            mount.fs_type,
+            options
+        ));
+    }
+
+    result
+}
+
+/// Returns the contents of `/proc/net/route`, in the same format as Linux. The addresses
+/// are printed in hexadecimal, in network byte order:
+/// ```text
+/// Iface   Destination Gateway     Flags   RefCnt  Use Metric  Mask        MTU Window  IRTT
+/// eth0    00000000    0202000A    0003    0       0   0       00000000    0   0       0
+/// ```
+fn get_net_route() -> String {
+    let mut result = alloc::format!(
+        "{:<127}\n",
+        "Iface\tDestination\tGateway \tFlags\tRefCnt\tUse\tMetric\tMask\t\tMTU\tWindow\tIRTT"
+    );
+
+    let hex = |addr: Ipv4Addr| u32::from_le_bytes(addr.0);
+
+    for route in net::route::routes() {
+        let line = alloc::format!(
+            "{}\t{:08X}\t{:08X}\t{:04X}\t0\t0\t{}\t{:08X}\t0\t0\t0",
+            route.device.name(),
+            hex(route.dest),
+            hex(route.gateway.unwrap_or(Ipv4Addr::new(0, 0, 0, 0))),
+            route.flags().bits(),
+            route.metric,
+            hex(route.mask)
+        );
+
+        result.push_str(&alloc::format!("{line:<127}\n"));
+    }
+
+    result
+}
+
+/// Number of clock ticks per second, in which the CPU times in `/proc/<pid>/stat` are
+/// reported (`sysconf(_SC_CLK_TCK)`).
+const USER_HZ: u64 = 100;
+
+/// Returns whether `task` leads its thread group.
+///
+/// Threads are created by `clone_process` with their own PID and share the address space
+/// of the task that created them, so a thread group is made up of the tasks that share an
+/// address space and its leader is the one whose parent does not share it.
+fn is_thread_leader(task: &Task) -> bool {
+    task.get_parent()
+        .map_or(true, |parent| !Arc::ptr_eq(&parent.vm, &task.vm))
+}
+
+/// Returns the PID of the leader of the thread group of `task` (ie. the thread group ID).
+/// See [`is_thread_leader`].
+fn get_thread_group_id(task: &Task) -> TaskId {
+    let mut tgid = task.pid();
+    let mut parent = task.get_parent();
+
+    while let Some(leader) = parent.filter(|parent| Arc::ptr_eq(&parent.vm, &task.vm)) {
+        tgid = leader.pid();
+        parent = leader.get_parent();
+    }
+
+    tgid
+}
+
+/// Returns the threads in the thread group of `task` (including the leader).
+fn get_threads(task: &Task) -> Vec<Arc<Task>> {
+    let mut threads = Vec::new();
+
+    scheduler::get_scheduler().for_each_task(|thread| {
+        if Arc::ptr_eq(&thread.vm, &task.vm) {
+            threads.push(thread.clone());
+        }
+    });
+
+    threads
+}
+
+/// Returns the absolute path of `entry`, without the trailing path separator.
+fn get_path(entry: &DirCacheItem) -> String {
+    let path = entry.absolute_path_str();
+
+    match path.trim_end_matches('/') {
+        "" => String::from("/"),
+        path => String::from(path),
+    }
+}
+
+/// Returns the name of the executable of the task, truncated to 15 characters like the
+/// `comm` of a task on Linux.
+fn get_task_name(task: &Task) -> String {
+    let name = task
+        .executable()
+        .map(|executable| executable.name())
+        .unwrap_or_else(|| String::from("kernel"));
+
+    name.chars().take(15).collect()
+}
+
+fn get_task_state(task: &Task) -> (char, &'static str) {
+    match task.state() {
+        TaskState::Runnable => ('R', "running"),
+        TaskState::AwaitingIo => ('S', "sleeping"),
+        TaskState::Zombie => ('Z', "zombie"),
+    }
+}
+
+/// Returns the total size of the mappings of the task, in bytes.
+fn get_vm_size(task: &Task) -> u64 {
+    let mut size = 0;
+
+    task.vm()
+        .for_each_mapping(|mapping| size += mapping.end - mapping.start);
+
+    size
+}
+
+/// Returns the contents of `/proc/<pid>/stat`, in the same format as Linux. The fields
+/// that are not tracked are reported as zero:
+///
+/// ```text
+/// <pid> (<name>) <state> <ppid> <pgrp> <session> <tty_nr> <tpgid> ... <utime> ...
+/// ```
+fn get_task_stat(task: &Task) -> String {
+    let (state, _) = get_task_state(task);
+
+    let cpu_time = if is_thread_leader(task) {
+        task.process_cpu_time()
+    } else {
+        task.thread_cpu_time()
+    };
+
+    alloc::format!(
+        "{} ({}) {} {} {} {} 0 -1 0 0 0 0 0 {} 0 0 0 20 0 {} 0 0 {} 0 {} \
+         0 0 0 0 0 0 0 0 0 0 0 0 17 {} 0 0 0 0 0 0 0 0 0 0 0 0 0\n",
+        task.pid().as_usize(),
+        get_task_name(task),
+        state,
+        task.get_parent()
+            .map_or(0, |parent| parent.pid().as_usize()),
+        task.group_id(),
+        task.session_id(),
+        cpu_time / (1_000_000_000 / USER_HZ),
+        get_threads(task).len(),
+        get_vm_size(task),
+        u64::MAX,
+        task.cpu().unwrap_or(0)
+    )
+}
+
+/// Returns the contents of `/proc/<pid>/status`, a human readable subset of the
+/// fields of the Linux equivalent.
+fn get_task_status(task: &Task) -> String {
+    let (state, state_name) = get_task_state(task);
+    let credentials = task.credentials();
+
+    let groups = credentials
+        .groups
+        .iter()
+        .map(|group| group.to_string())
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    alloc::format!(
+        "Name:\t{}\nState:\t{} ({})\nTgid:\t{}\nPid:\t{}\nPPid:\t{}\nTracerPid:\t{}\n\
+         Uid:\t{}\t{}\t{}\t{}\nGid:\t{}\t{}\t{}\t{}\nGroups:\t{}\nVmSize:\t{} kB\nThreads:\t{}\n",
+        get_task_name(task),
+        state,
+        state_name,
+        get_thread_group_id(task).as_usize(),
+        task.pid().as_usize(),
+        task.get_parent()
+            .map_or(0, |parent| parent.pid().as_usize()),
+        task.ptrace()
+            .tracer()
+            .map_or(0, |tracer| tracer.pid().as_usize()),
+        credentials.uid,
+        credentials.euid,
+        credentials.suid,
+        credentials.euid,
+        credentials.gid,
+        credentials.egid,
+        credentials.sgid,
+        credentials.egid,
+        groups,
+        get_vm_size(task) / 1024,
+        get_threads(task).len()
+    )
+}
+
+/// Returns the contents of `/proc/<pid>/maps`, in the same format as Linux:
+///
+/// ```text
+/// <start>-<end> <perms> <offset> <dev> <inode> <path>
+/// 00400000-00452000 r-xp 00000000 00:00 173 /usr/bin/bash
+/// ```
+fn get_task_maps(task: &Task) -> String {
+    let mut result = String::new();
+
+    task.vm().for_each_mapping(|mapping| {
+        let flag = |set: bool, c: char| if set { c } else { '-' };
+
+        let offset = mapping.file.map_or(0, |(_, offset)| offset);
+        let inode = mapping
+            .file
+            .and_then(|(file, _)| file.inode().stat().ok())
+            .map_or(0, |stat| stat.st_ino);
+
+        let line = alloc::format!(
+            "{:08x}-{:08x} {}{}{}{} {:08x} 00:00 {}",
+            mapping.start,
+            mapping.end,
+            flag(mapping.protection.contains(MMapProt::PROT_READ), 'r'),
+            flag(mapping.protection.contains(MMapProt::PROT_WRITE), 'w'),
+            flag(mapping.protection.contains(MMapProt::PROT_EXEC), 'x'),
+            if mapping.flags.contains(MMapFlags::MAP_SHARED) {
+                's'
+            } else {
+                'p'
+            },
+            offset,
+            inode
+        );
+
+        match mapping.file {
+            Some((file, _)) => result.push_str(&alloc::format!("{line:<72} {}\n", get_path(file))),
+            None => result.push_str(&alloc::format!("{line}\n")),
+        }
+    });
+
+    result
+}
+
 #[derive(Default)]
 struct ProcINode {
     id: usize,
@@ -90,13 +353,100 @@ struct ProcINode {
     contents: FileContents,
 }
 
+/// The files in the directory of a task (`/proc/<pid>` and `/proc/<pid>/task/<tid>`).
+#[derive(Copy, Clone, PartialEq)]
+enum TaskFile {
+    Dir,
+    Stat,
+    Status,
+    CmdLine,
+    Environ,
+    Maps,
+    Cwd,
+    Exe,
+    Fds,
+    Fd(usize),
+    Threads,
+}
+
+impl TaskFile {
+    const ENTRIES: &'static [(&'static str, TaskFile)] = &[
+        ("cmdline", TaskFile::CmdLine),
+        ("cwd", TaskFile::Cwd),
+        ("environ", TaskFile::Environ),
+        ("exe", TaskFile::Exe),
+        ("fd", TaskFile::Fds),
+        ("maps", TaskFile::Maps),
+        ("stat", TaskFile::Stat),
+        ("status", TaskFile::Status),
+        ("task", TaskFile::Threads),
+    ];
+
+    fn file_type(&self) -> FileType {
+        match self {
+            TaskFile::Dir | TaskFile::Fds | TaskFile::Threads => FileType::Directory,
+            TaskFile::Cwd | TaskFile::Exe | TaskFile::Fd(_) => FileType::Symlink,
+            _ => FileType::File,
+        }
+    }
+
+    /// Returns whether the file can only be accessed by the user that the task runs as
+    /// (and the superuser). See [`ensure_owner`].
+    fn is_private(&self) -> bool {
+        matches!(
+            self,
+            TaskFile::Environ
+                | TaskFile::Maps
+                | TaskFile::Cwd
+                | TaskFile::Exe
+                | TaskFile::Fds
+                | TaskFile::Fd(_)
+        )
+    }
+}
+
+/// Returns [`FileSystemError::PermissionDenied`] unless the current task may inspect
+/// `task`. See [`Credentials::may_inspect`].
+fn ensure_owner(task: &Task) -> Result<()> {
+    if Credentials::current().may_inspect(&task.credentials()) {
+        Ok(())
+    } else {
+        Err(FileSystemError::PermissionDenied)
+    }
+}
+
 enum FileContents {
     CpuInfo,
     CmdLine,
+    Mounts,
+    NetRoute,
+
+    /// The root directory, which contains a directory for each process.
+    Root,
+    /// `/proc/self`, a link to the directory of the current process.
+    SelfLink,
+    Task(TaskId, TaskFile),
 
     None,
 }
 
+impl FileContents {
+    /// Returns the task that the file belongs to (if any).
+    ///
+    /// ## Errors
+    /// * [`FileSystemError::EntryNotFound`] - The task has exited.
+    fn task(&self) -> Result<Option<Arc<Task>>> {
+        match self {
+            FileContents::Task(pid, _) => scheduler::get_scheduler()
+                .find_task(*pid)
+                .map(Some)
+                .ok_or(FileSystemError::EntryNotFound),
+
+            _ => Ok(None),
+        }
+    }
+}
+
 impl Default for FileContents {
     fn default() -> Self {
         Self::None
@@ -131,13 +481,27 @@ impl LockedProcINode {
         file_type: FileType,
         contents: FileContents,
     ) -> Result<INodeCacheItem> {
-        let icache = cache::icache();
         let mut this = self.0.write();
 
         if this.children.contains_key(name) || ["", ".", ".."].contains(&name) {
             return Err(FileSystemError::EntryExists);
         }
 
+        let inode_cached = Self::allocate_child(&this, file_type, contents);
+
+        this.children
+            .insert(String::from(name), inode_cached.clone());
+
+        Ok(inode_cached)
+    }
+
+    /// Allocates an inode whose parent is `this`, without adding it to its children.
+    fn allocate_child(
+        this: &ProcINode,
+        file_type: FileType,
+        contents: FileContents,
+    ) -> INodeCacheItem {
+        let icache = cache::icache();
         let filesystem = this.filesystem.upgrade().unwrap();
 
         let inode = filesystem.allocate_inode(file_type, contents);
@@ -154,10 +518,67 @@ impl LockedProcINode {
                 file_type,
             );
 
-        this.children
-            .insert(String::from(name), inode_cached.clone());
+        inode_cached
+    }
 
-        Ok(inode_cached)
+    /// Returns the entries of the directory that are generated on each lookup, as the
+    /// task directories come and go with the tasks.
+    fn dynamic_entries(this: &ProcINode) -> Result<Vec<(String, TaskId, TaskFile)>> {
+        let mut entries = Vec::new();
+
+        match &this.contents {
+            FileContents::Root => scheduler::get_scheduler().for_each_task(|task| {
+                if is_thread_leader(task) {
+                    entries.push((task.pid().as_usize().to_string(), task.pid(), TaskFile::Dir));
+                }
+            }),
+
+            FileContents::Task(pid, TaskFile::Dir) => {
+                let task = this.contents.task()?.unwrap();
+
+                for (name, file) in TaskFile::ENTRIES {
+                    // The directories in `/proc/<pid>/task` do not have a `task` directory.
+                    if *file != TaskFile::Threads || is_thread_leader(&task) {
+                        entries.push((String::from(*name), *pid, *file));
+                    }
+                }
+            }
+
+            FileContents::Task(pid, TaskFile::Fds) => {
+                let task = this.contents.task()?.unwrap();
+                ensure_owner(&task)?;
+
+                let files = task.file_table.0.read();
+
+                for handle in files.iter().flatten() {
+                    entries.push((handle.fd.to_string(), *pid, TaskFile::Fd(handle.fd)));
+                }
+            }
+
+            FileContents::Task(_, TaskFile::Threads) => {
+                let task = this.contents.task()?.unwrap();
+
+                for thread in get_threads(&task) {
+                    let tid = thread.tid();
+                    entries.push((tid.as_usize().to_string(), tid, TaskFile::Dir));
+                }
+            }
+
+            _ => {}
+        }
+
+        Ok(entries)
+    }
+
+    /// Creates the directory entry for the generated `entry`. The directory entry is not
+    /// cached, so the entry disappears as soon as the task exits.
+    fn make_dynamic_entry(
+        this: &ProcINode,
+        parent: DirCacheItem,
+        (name, pid, file): (String, TaskId, TaskFile),
+    ) -> DirCacheItem {
+        let inode = Self::allocate_child(this, file.file_type(), FileContents::Task(pid, file));
+        DirEntry::new_uncached(parent, inode, name)
     }
 }
 
@@ -166,26 +587,101 @@ impl INodeInterface for LockedProcINode {
         let this = self.0.read();
 
         let data = match &this.contents {
-            FileContents::CpuInfo => Ok(get_cpuinfo_cached()),
-            FileContents::CmdLine => Ok(get_cmdline_cached()),
+            FileContents::CpuInfo => Ok(Cow::Borrowed(get_cpuinfo_cached().as_bytes())),
+            FileContents::CmdLine => Ok(Cow::Borrowed(get_cmdline_cached().as_bytes())),
+            FileContents::Mounts => Ok(Cow::Owned(get_mounts().into_bytes())),
+            FileContents::NetRoute => Ok(Cow::Owned(get_net_route().into_bytes())),
+
+            FileContents::Task(_, file) => {
+                let task = this.contents.task()?.unwrap();
+
+                if file.is_private() {
+                    ensure_owner(&task)?;
+                }
+
+                match file {
+                    TaskFile::Stat => Ok(Cow::Owned(get_task_stat(&task).into_bytes())),
+                    TaskFile::Status => Ok(Cow::Owned(get_task_status(&task).into_bytes())),
+                    TaskFile::Maps => Ok(Cow::Owned(get_task_maps(&task).into_bytes())),
+
+                    // The arguments and the environment are shared between the threads.
+                    TaskFile::CmdLine => Ok(Cow::Owned(task.process_leader().cmdline())),
+                    TaskFile::Environ => Ok(Cow::Owned(task.process_leader().environ())),
+
+                    _ => Err(FileSystemError::NotSupported),
+                }
+            }
 
             _ => Err(FileSystemError::NotSupported),
         }?;
 
+        // The contents of some of the files (e.g. `mounts`) are generated on each read,
+        // so they may have shrunk since the previous read.
+        if offset >= data.len() {
+            return Ok(0);
+        }
+
         let count = core::cmp::min(buffer.len(), data.len() - offset);
-        buffer[..count].copy_from_slice(&data.as_bytes()[offset..offset + count]);
+        buffer[..count].copy_from_slice(&data[offset..offset + count]);
 
         Ok(count)
     }
 
+    fn resolve_link(&self) -> Result<String> {
+        let this = self.0.read();
+
+        match &this.contents {
+            FileContents::SelfLink => {
+                let task = scheduler::get_scheduler().current_task();
+                Ok(get_thread_group_id(&task).as_usize().to_string())
+            }
+
+            FileContents::Task(_, file) => {
+                let task = this.contents.task()?.unwrap();
+
+                if file.is_private() {
+                    ensure_owner(&task)?;
+                }
+
+                match file {
+                    // Kernel tasks do not have an executable nor a working directory.
+                    TaskFile::Cwd => task
+                        .executable()
+                        .map(|_| task.get_cwd())
+                        .ok_or(FileSystemError::EntryNotFound),
+
+                    TaskFile::Exe => task
+                        .executable()
+                        .map(|executable| get_path(&executable))
+                        .ok_or(FileSystemError::EntryNotFound),
+
+                    TaskFile::Fd(fd) => task
+                        .file_table
+                        .get_handle(*fd)
+                        .map(|handle| get_path(&handle.inode))
+                        .ok_or(FileSystemError::EntryNotFound),
+
+                    _ => Err(FileSystemError::NotSupported),
+                }
+            }
+
+            _ => Err(FileSystemError::NotSupported),
+        }
+    }
+
     fn lookup(&self, dir: DirCacheItem, name: &str) -> Result<DirCacheItem> {
         let this = self.0.read();
-        let child = this
-            .children
-            .get(name)
+
+        if let Some(child) = this.children.get(name) {
+            return Ok(DirEntry::new(dir, child.clone(), String::from(name)));
+        }
+
+        let entry = LockedProcINode::dynamic_entries(&this)?
+            .into_iter()
+            .find(|(entry, _, _)| entry == name)
             .ok_or(FileSystemError::EntryNotFound)?;
 
-        Ok(DirEntry::new(dir, child.clone(), String::from(name)))
+        Ok(LockedProcINode::make_dynamic_entry(&this, dir, entry))
     }
 
     fn metadata(&self) -> Result<Metadata> {
@@ -199,6 +695,33 @@ impl INodeInterface for LockedProcINode {
         })
     }
 
+    fn stat(&self) -> Result<aero_syscall::Stat> {
+        let this = self.0.read();
+
+        // The process filesystem is read-only and owned by the superuser, except for the
+        // files of the tasks which are owned by the user the task runs as.
+        let permissions = match (&this.contents, this.file_type) {
+            (FileContents::Task(_, TaskFile::Environ | TaskFile::Maps), _) => 0o400,
+            (FileContents::Task(_, TaskFile::Fds), _) => 0o500,
+            (_, FileType::Symlink) => 0o777,
+            (_, FileType::Directory) => 0o555,
+            _ => 0o444,
+        };
+
+        let (uid, gid) = this.contents.task()?.map_or((0, 0), |task| {
+            let credentials = task.credentials();
+            (credentials.euid, credentials.egid)
+        });
+
+        Ok(aero_syscall::Stat {
+            st_ino: this.id as _,
+            st_mode: Mode::from(this.file_type) | Mode::from_bits_truncate(permissions),
+            st_uid: uid,
+            st_gid: gid,
+            ..Default::default()
+        })
+    }
+
     fn dirent(&self, parent: DirCacheItem, index: usize) -> Result<Option<DirCacheItem>> {
         let this = self.0.read();
 
@@ -224,11 +747,16 @@ impl INodeInterface for LockedProcINode {
             }
 
             // Subtract two because of the "." and ".." entries.
-            _ => this
+            _ if index - 2 < this.children.len() => this
                 .children
                 .iter()
                 .nth(index - 2)
                 .map(|(name, inode)| DirEntry::new(parent, inode.clone(), name.clone())),
+
+            _ => LockedProcINode::dynamic_entries(&this)?
+                .into_iter()
+                .nth(index - 2 - this.children.len())
+                .map(|entry| LockedProcINode::make_dynamic_entry(&this, parent, entry)),
         })
     }
 
@@ -237,7 +765,7 @@ impl INodeInterface for LockedProcINode {
     }
 }
 
-struct ProcFs {
+pub struct ProcFs {
     root_inode: INodeCacheItem,
     root_dir: DirCacheItem,
     next_id: AtomicUsize,
@@ -247,7 +775,10 @@ impl ProcFs {
     pub fn new() -> Result<Arc<Self>> {
         let icache = cache::icache();
 
-        let root_node = Arc::new(LockedProcINode::new(ProcINode::default()));
+        let root_node = Arc::new(LockedProcINode::new(ProcINode {
+            contents: FileContents::Root,
+            ..Default::default()
+        }));
         let root_cached = icache.make_item_no_cache(CachedINode::new(root_node));
 
         let root_dir = DirEntry::new_root(root_cached.clone(), String::from("/"));
@@ -276,6 +807,13 @@ impl ProcFs {
 
         inode.make_inode("cpuinfo", FileType::File, FileContents::CpuInfo)?;
         inode.make_inode("cmdline", FileType::File, FileContents::CmdLine)?;
+        inode.make_inode("mounts", FileType::File, FileContents::Mounts)?;
+        inode.make_inode("self", FileType::Symlink, FileContents::SelfLink)?;
+
+        let net = inode.make_inode("net", FileType::Directory, FileContents::None)?;
+        let net = net.inner().downcast_arc::<LockedProcINode>().unwrap();
+
+        net.make_inode("route", FileType::File, FileContents::NetRoute)?;
 
         Ok(ramfs)
     }
@@ -298,6 +836,10 @@ impl FileSystem for ProcFs {
     fn root_dir(&self) -> DirCacheItem {
         self.root_dir.clone()
     }
+
+    fn name(&self) -> &'static str {
+        "proc"
+    }
 }
 
 static PROC_FS: Once<Arc<ProcFs>> = Once::new();
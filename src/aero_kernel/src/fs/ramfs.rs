@@ -17,15 +17,15 @@
 
 use core::sync::atomic::{AtomicUsize, Ordering};
 
-use aero_syscall::MMapFlags;
+use aero_syscall::{MMapFlags, Mode, TimeSpec};
 use alloc::collections::BTreeMap;
 use alloc::string::{String, ToString};
 use alloc::sync::{Arc, Weak};
 
-use alloc::vec::Vec;
 use spin::RwLock;
 
 use crate::mem::paging::*;
+use crate::userland::task::credentials::Credentials;
 use crate::utils::sync::Mutex;
 
 use super::cache::{
@@ -33,8 +33,9 @@ use super::cache::{
 };
 use super::devfs::DevINode;
 use super::inode::{
-    DirEntry, FileContents, FileType, INodeInterface, Metadata, PollFlags, PollTable,
+    DirEntry, FileContents, FileType, INodeInterface, Metadata, PollFlags, PollTable, SetAttr,
 };
+use super::shmem::SharedMemory;
 use super::{FileSystem, FileSystemError, Result};
 
 #[derive(Default)]
@@ -46,6 +47,14 @@ pub struct RamINode {
     filesystem: Weak<RamFs>,
     file_type: FileType,
     contents: FileContents,
+
+    permissions: u32,
+    uid: u32,
+    gid: u32,
+
+    atime: TimeSpec,
+    mtime: TimeSpec,
+    ctime: TimeSpec,
 }
 
 pub struct LockedRamINode(RwLock<RamINode>);
@@ -116,6 +125,14 @@ impl INodeInterface for LockedRamINode {
 
         let this = self.0.read();
 
+        stat.st_mode = Mode::from(this.file_type) | Mode::from_bits_truncate(this.permissions);
+        stat.st_uid = this.uid;
+        stat.st_gid = this.gid;
+
+        stat.st_atim = this.atime.clone();
+        stat.st_mtim = this.mtime.clone();
+        stat.st_ctim = this.ctime.clone();
+
         match &this.contents {
             FileContents::Content(contents) => {
                 stat.st_size = contents.lock().len() as _;
@@ -125,27 +142,87 @@ impl INodeInterface for LockedRamINode {
                 stat.st_size = contents.len() as _;
             }
 
+            FileContents::Memory(memory) => {
+                stat.st_size = memory.metadata()?.size as _;
+            }
+
             _ => {}
         }
 
         Ok(stat)
     }
 
-    fn touch(&self, parent: DirCacheItem, name: &str) -> Result<DirCacheItem> {
-        Ok(DirEntry::new(
-            parent,
-            self.make_inode(
-                name,
-                FileType::File,
-                FileContents::Content(Mutex::new(Vec::new())),
-            )?,
-            String::from(name),
-        ))
+    fn set_attr(&self, attr: &SetAttr) -> Result<()> {
+        let mut this = self.0.write();
+
+        if let Some(mode) = attr.mode {
+            this.permissions = mode & 0o7777;
+        }
+
+        if let Some(uid) = attr.uid {
+            this.uid = uid;
+        }
+
+        if let Some(gid) = attr.gid {
+            this.gid = gid;
+        }
+
+        if let Some(atime) = attr.atime.as_ref() {
+            this.atime = atime.clone();
+        }
+
+        if let Some(mtime) = attr.mtime.as_ref() {
+            this.mtime = mtime.clone();
+        }
+
+        this.ctime = crate::arch::time::get_realtime_clock();
+        Ok(())
     }
 
-    #[inline]
-    fn mkdir(&self, name: &str) -> Result<INodeCacheItem> {
-        self.make_inode(name, FileType::Directory, FileContents::None)
+    fn touch(&self, parent: DirCacheItem, name: &str, mode: Mode) -> Result<DirCacheItem> {
+        let inode = self.make_inode(
+            name,
+            FileType::File,
+            FileContents::Memory(SharedMemory::new(0)),
+        )?;
+
+        inode.set_attr(&SetAttr {
+            mode: Some(mode.bits()),
+            ..Default::default()
+        })?;
+
+        Ok(DirEntry::new(parent, inode, String::from(name)))
+    }
+
+    fn mkdir(&self, name: &str, mode: Mode) -> Result<INodeCacheItem> {
+        let inode = self.make_inode(name, FileType::Directory, FileContents::None)?;
+
+        inode.set_attr(&SetAttr {
+            mode: Some(mode.bits()),
+            ..Default::default()
+        })?;
+
+        Ok(inode)
+    }
+
+    fn symlink(&self, name: &str, target: &str) -> Result<INodeCacheItem> {
+        self.make_inode(
+            name,
+            FileType::Symlink,
+            FileContents::Content(Mutex::new(target.as_bytes().to_vec())),
+        )
+    }
+
+    fn resolve_link(&self) -> Result<String> {
+        let this = self.0.read();
+
+        match (&this.file_type, &this.contents) {
+            (FileType::Symlink, FileContents::Content(target)) => {
+                Ok(String::from_utf8_lossy(&target.lock()).into_owned())
+            }
+
+            _ => Err(FileSystemError::NotSupported),
+        }
     }
 
     #[inline]
@@ -187,6 +264,7 @@ impl INodeInterface for LockedRamINode {
             }
 
             FileContents::StaticContent(_) => Err(FileSystemError::NotSupported),
+            FileContents::Memory(memory) => memory.write_at(offset, buffer),
 
             FileContents::Device(dev) => {
                 let device = dev.clone();
@@ -254,6 +332,8 @@ impl INodeInterface for LockedRamINode {
                 Ok(())
             }
 
+            FileContents::Memory(memory) => memory.truncate(size),
+
             _ => {
                 log::warn!("ramfs: truncation is not supported");
                 Ok(())
@@ -290,6 +370,8 @@ impl INodeInterface for LockedRamINode {
                 Ok(size)
             }
 
+            FileContents::Memory(memory) => memory.read_at(offset, buffer),
+
             FileContents::Device(device) => {
                 let device = device.clone();
                 drop(this);
@@ -331,6 +413,7 @@ impl INodeInterface for LockedRamINode {
                 FileContents::Content(bytes) => bytes.lock().len(), // Temporary value dropped
                 // and lock is unlocked!
                 FileContents::StaticContent(bytes) => bytes.len(),
+                FileContents::Memory(memory) => memory.metadata()?.size,
                 _ => 0x00,
             },
             children_len: this.children.len(),
@@ -372,6 +455,8 @@ impl INodeInterface for LockedRamINode {
                 Ok(private_cp)
             }
 
+            FileContents::Memory(memory) => memory.mmap(offset, size, flags),
+
             FileContents::Content(contents) => {
                 // TODO: Support shared content ramfs file mappings.
                 assert!(!flags.contains(MMapFlags::MAP_SHARED));
@@ -470,7 +555,10 @@ impl RamFs {
     pub fn new() -> Arc<Self> {
         let icache = cache::icache();
 
-        let root_node = Arc::new(LockedRamINode::new(RamINode::default()));
+        let root_node = Arc::new(LockedRamINode::new(RamINode {
+            permissions: FileType::Directory.default_permissions(),
+            ..Default::default()
+        }));
         let root_cached = icache.make_item_no_cache(CachedINode::new(root_node));
 
         let root_dir = DirEntry::new_root(root_cached.clone(), String::from("/"));
@@ -500,6 +588,9 @@ impl RamFs {
     }
 
     fn allocate_inode(&self, file_type: FileType, contents: FileContents) -> Arc<LockedRamINode> {
+        let credentials = Credentials::current();
+        let now = crate::arch::time::get_realtime_clock();
+
         Arc::new(LockedRamINode::new(RamINode {
             parent: CacheWeak::new(),
             node: CacheWeak::new(),
@@ -508,6 +599,13 @@ impl RamFs {
             id: self.next_id.fetch_add(1, Ordering::SeqCst),
             contents,
             file_type,
+            permissions: file_type.default_permissions(),
+            uid: credentials.euid,
+            gid: credentials.egid,
+
+            atime: now.clone(),
+            mtime: now.clone(),
+            ctime: now,
         }))
     }
 }
@@ -517,4 +615,8 @@ impl FileSystem for RamFs {
     fn root_dir(&self) -> DirCacheItem {
         self.root_dir.clone()
     }
+
+    fn name(&self) -> &'static str {
+        "ramfs"
+    }
 }
@@ -0,0 +1,411 @@
+// Copyright (C) 2021-2023 The Aero Project Developers.
+//
+// This file is part of The Aero Project.
+//
+// Aero is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Aero is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Aero. If not, see <https://www.gnu.org/licenses/>.
+
+//! Shared memory.
+//!
+//! The pages of a `MAP_SHARED` mapping are owned by the object being mapped instead of the
+//! address space, so every mapping of the object (including the ones inherited on fork)
+//! maps the same frames. [`SharedPages`] holds those frames and [`SharedMemory`] is a file
+//! that only lives in memory. It backs shared anonymous mappings, `memfd_create(2)` and the
+//! files in tmpfs (e.g. the POSIX shared memory objects in `/dev/shm`).
+
+use core::ops::Range;
+
+use aero_syscall::prelude::SealFlags;
+use aero_syscall::{MMapFlags, Mode};
+use alloc::collections::BTreeMap;
+use alloc::string::String;
+use alloc::sync::Arc;
+
+use crate::mem::paging::*;
+use crate::utils::sync::BMutex;
+
+use super::cache::DirCacheItem;
+use super::inode::{DirEntry, FileType, INodeInterface, Metadata, SetAttr};
+use super::ramfs::RamFs;
+use super::{FileSystem, FileSystemError, Result};
+
+const PAGE_SIZE: usize = Size4KiB::SIZE as usize;
+
+/// The resident pages of an object, keyed by their page index. The pages are allocated on
+/// the first access and each page holds a reference to its frame, so the frame stays
+/// alive after it is unmapped from every address space.
+pub struct SharedPages(BMutex<BTreeMap<usize, PhysFrame>>);
+
+impl SharedPages {
+    pub fn new() -> Self {
+        Self(BMutex::new(BTreeMap::new()))
+    }
+
+    /// Returns the frame of the page at `offset`. If the page is not resident, a zeroed
+    /// frame is allocated and filled using `fill`.
+    pub fn get_or_insert_with<F>(&self, offset: usize, fill: F) -> Result<PhysFrame>
+    where
+        F: FnOnce(&mut [u8]) -> Result<()>,
+    {
+        let mut pages = self.0.lock();
+        let index = offset / PAGE_SIZE;
+
+        if let Some(frame) = pages.get(&index) {
+            return Ok(*frame);
+        }
+
+        let frame: PhysFrame = FRAME_ALLOCATOR
+            .allocate_frame()
+            .expect("shmem: failed to allocate frame");
+
+        frame.as_slice_mut::<u8>().fill(0);
+
+        if let Err(err) = fill(frame.as_slice_mut::<u8>()) {
+            FRAME_ALLOCATOR.deallocate_frame(frame);
+            return Err(err);
+        }
+
+        frame.start_address().as_vm_frame().unwrap().inc_ref_count();
+        pages.insert(index, frame);
+
+        Ok(frame)
+    }
+
+    /// Calls `f` with the offset and the contents of every resident page that overlaps
+    /// `range`.
+    pub fn for_each<F>(&self, range: Range<usize>, mut f: F) -> Result<()>
+    where
+        F: FnMut(usize, &[u8]) -> Result<()>,
+    {
+        let pages = self.0.lock();
+        let start = range.start / PAGE_SIZE;
+        let end = range.end.div_ceil(PAGE_SIZE);
+
+        for (index, frame) in pages.range(start..end) {
+            f(index * PAGE_SIZE, frame.as_slice_mut::<u8>())?;
+        }
+
+        Ok(())
+    }
+
+    /// Copies the contents of the resident pages at `offset` into `buffer`. The rest of
+    /// the buffer is left untouched.
+    pub fn read(&self, offset: usize, buffer: &mut [u8]) {
+        self.copy(offset, buffer.len(), |page, buffer_range| {
+            buffer[buffer_range.clone()].copy_from_slice(&page[..buffer_range.len()]);
+        });
+    }
+
+    /// Copies `buffer` into the resident pages at `offset`. The pages that are not
+    /// resident are not allocated.
+    pub fn write(&self, offset: usize, buffer: &[u8]) {
+        self.copy(offset, buffer.len(), |page, buffer_range| {
+            page[..buffer_range.len()].copy_from_slice(&buffer[buffer_range]);
+        });
+    }
+
+    /// Copies `buffer` into the pages at `offset`. The pages that are not resident are
+    /// allocated.
+    pub fn store(&self, offset: usize, buffer: &[u8]) -> Result<()> {
+        let mut progress = 0;
+
+        while progress < buffer.len() {
+            let loc = (offset + progress) % PAGE_SIZE;
+            let chunk = core::cmp::min(buffer.len() - progress, PAGE_SIZE - loc);
+
+            let frame = self.get_or_insert_with(offset + progress, |_| Ok(()))?;
+            frame.as_slice_mut::<u8>()[loc..loc + chunk]
+                .copy_from_slice(&buffer[progress..progress + chunk]);
+
+            progress += chunk;
+        }
+
+        Ok(())
+    }
+
+    /// Calls `f` with the part of every resident page in `offset..offset + size` and the
+    /// range of the buffer that it corresponds to.
+    fn copy<F>(&self, offset: usize, size: usize, mut f: F)
+    where
+        F: FnMut(&mut [u8], Range<usize>),
+    {
+        let pages = self.0.lock();
+        let start = offset / PAGE_SIZE;
+        let end = (offset + size).div_ceil(PAGE_SIZE);
+
+        for (index, frame) in pages.range(start..end) {
+            let page_offset = index * PAGE_SIZE;
+
+            let from = core::cmp::max(offset, page_offset);
+            let to = core::cmp::min(offset + size, page_offset + PAGE_SIZE);
+
+            let page = &mut frame.as_slice_mut::<u8>()[from - page_offset..];
+            f(page, from - offset..to - offset);
+        }
+    }
+
+    /// Releases the pages past `size` and zeroes the part of the last page past `size`.
+    pub fn truncate(&self, size: usize) {
+        let mut pages = self.0.lock();
+        let index = size.div_ceil(PAGE_SIZE);
+
+        for (_, frame) in pages.split_off(&index) {
+            release(frame);
+        }
+
+        if size % PAGE_SIZE != 0 {
+            if let Some(frame) = pages.get(&(size / PAGE_SIZE)) {
+                frame.as_slice_mut::<u8>()[size % PAGE_SIZE..].fill(0);
+            }
+        }
+    }
+}
+
+impl Drop for SharedPages {
+    fn drop(&mut self) {
+        for (_, frame) in core::mem::take(&mut *self.0.lock()) {
+            release(frame);
+        }
+    }
+}
+
+/// Drops the reference to `frame` held by [`SharedPages`]. The frame is deallocated if
+/// it is not mapped anywhere.
+fn release(frame: PhysFrame) {
+    let vm_frame = frame.start_address().as_vm_frame().unwrap();
+    vm_frame.dec_ref_count();
+
+    if vm_frame.ref_count() == 0 {
+        FRAME_ALLOCATOR.deallocate_frame(frame);
+    }
+}
+
+struct SharedMemoryInner {
+    size: usize,
+    seals: SealFlags,
+    /// The number of [`WritableMapping`]s of the file.
+    writable_mappings: usize,
+}
+
+/// A file whose contents only live in memory. The contents are freed once the file is
+/// not referenced anymore (ie. it is closed, unlinked and not mapped).
+pub struct SharedMemory {
+    pages: SharedPages,
+    inner: BMutex<SharedMemoryInner>,
+}
+
+impl SharedMemory {
+    /// Creates a file of `size` bytes that cannot be sealed.
+    pub fn new(size: usize) -> Arc<Self> {
+        Self::with_seals(size, SealFlags::F_SEAL_SEAL)
+    }
+
+    pub fn with_seals(size: usize, seals: SealFlags) -> Arc<Self> {
+        Arc::new(Self {
+            pages: SharedPages::new(),
+            inner: BMutex::new(SharedMemoryInner {
+                size,
+                seals,
+                writable_mappings: 0,
+            }),
+        })
+    }
+
+    pub fn seals(&self) -> SealFlags {
+        self.inner.lock().seals
+    }
+
+    /// Adds `seals` to the set of seals of the file.
+    ///
+    /// ## Errors
+    /// * [`FileSystemError::NotPermitted`] - The file has been sealed with `F_SEAL_SEAL`.
+    /// * [`FileSystemError::Busy`] - `seals` contains `F_SEAL_WRITE` and the file has writable
+    ///   shared mappings.
+    pub fn add_seals(&self, seals: SealFlags) -> Result<()> {
+        let mut inner = self.inner.lock();
+
+        if inner.seals.contains(SealFlags::F_SEAL_SEAL) {
+            return Err(FileSystemError::NotPermitted);
+        }
+
+        if seals.contains(SealFlags::F_SEAL_WRITE) && inner.writable_mappings != 0 {
+            return Err(FileSystemError::Busy);
+        }
+
+        inner.seals.insert(seals);
+        Ok(())
+    }
+
+    /// Returns [`true`] if the file cannot be modified through a writable shared mapping.
+    pub fn is_write_sealed(&self) -> bool {
+        self.seals()
+            .intersects(SealFlags::F_SEAL_WRITE | SealFlags::F_SEAL_FUTURE_WRITE)
+    }
+
+    /// Registers a shared mapping of the file that can be made writable.
+    ///
+    /// ## Errors
+    /// * [`FileSystemError::NotPermitted`] - The file has been sealed against writes.
+    pub fn map_writable(self: Arc<Self>) -> Result<WritableMapping> {
+        let mut inner = self.inner.lock();
+
+        if inner
+            .seals
+            .intersects(SealFlags::F_SEAL_WRITE | SealFlags::F_SEAL_FUTURE_WRITE)
+        {
+            return Err(FileSystemError::NotPermitted);
+        }
+
+        inner.writable_mappings += 1;
+        drop(inner);
+
+        Ok(WritableMapping(self))
+    }
+}
+
+/// A shared mapping of a [`SharedMemory`] that can be made writable. The file cannot be
+/// sealed with `F_SEAL_WRITE` while any of them are alive.
+pub struct WritableMapping(Arc<SharedMemory>);
+
+impl Clone for WritableMapping {
+    fn clone(&self) -> Self {
+        self.0.inner.lock().writable_mappings += 1;
+        Self(self.0.clone())
+    }
+}
+
+impl Drop for WritableMapping {
+    fn drop(&mut self) {
+        self.0.inner.lock().writable_mappings -= 1;
+    }
+}
+
+impl INodeInterface for SharedMemory {
+    fn metadata(&self) -> Result<Metadata> {
+        Ok(Metadata {
+            id: 0,
+            file_type: FileType::File,
+            size: self.inner.lock().size,
+            children_len: 0,
+        })
+    }
+
+    fn stat(&self) -> Result<aero_syscall::Stat> {
+        let mut stat = aero_syscall::Stat::default();
+
+        stat.st_mode = Mode::from(FileType::File) | Mode::from_bits_truncate(0o777);
+        stat.st_size = self.inner.lock().size as _;
+
+        Ok(stat)
+    }
+
+    fn read_at(&self, offset: usize, buffer: &mut [u8]) -> Result<usize> {
+        let size = self.inner.lock().size;
+
+        if offset >= size {
+            return Ok(0);
+        }
+
+        let count = core::cmp::min(buffer.len(), size - offset);
+        let buffer = &mut buffer[..count];
+
+        // The pages that are not resident have never been written to, so they read as
+        // zeros.
+        buffer.fill(0);
+        self.pages.read(offset, buffer);
+
+        Ok(count)
+    }
+
+    fn write_at(&self, offset: usize, buffer: &[u8]) -> Result<usize> {
+        let mut inner = self.inner.lock();
+        let end = offset + buffer.len();
+
+        if inner
+            .seals
+            .intersects(SealFlags::F_SEAL_WRITE | SealFlags::F_SEAL_FUTURE_WRITE)
+            || (end > inner.size && inner.seals.contains(SealFlags::F_SEAL_GROW))
+        {
+            return Err(FileSystemError::NotPermitted);
+        }
+
+        self.pages.store(offset, buffer)?;
+        inner.size = core::cmp::max(inner.size, end);
+
+        Ok(buffer.len())
+    }
+
+    fn truncate(&self, size: usize) -> Result<()> {
+        let mut inner = self.inner.lock();
+
+        if (size < inner.size && inner.seals.contains(SealFlags::F_SEAL_SHRINK))
+            || (size > inner.size && inner.seals.contains(SealFlags::F_SEAL_GROW))
+        {
+            return Err(FileSystemError::NotPermitted);
+        }
+
+        self.pages.truncate(size);
+        inner.size = size;
+
+        Ok(())
+    }
+
+    fn mmap(&self, offset: usize, _size: usize, flags: MMapFlags) -> Result<PhysFrame> {
+        let frame = self.pages.get_or_insert_with(offset, |_| Ok(()))?;
+
+        if flags.contains(MMapFlags::MAP_SHARED) {
+            return Ok(frame);
+        }
+
+        let private_cp: PhysFrame = FRAME_ALLOCATOR.allocate_frame().unwrap();
+        private_cp
+            .as_slice_mut::<u8>()
+            .copy_from_slice(frame.as_slice_mut::<u8>());
+
+        Ok(private_cp)
+    }
+}
+
+/// Creates the object backing a shared anonymous mapping of `size` bytes.
+pub fn anonymous(size: usize) -> DirCacheItem {
+    DirEntry::from_inode(SharedMemory::new(size), String::from("<shmem>"))
+}
+
+/// Implementation of the temporary filesystem. It is a ramfs that everyone can create
+/// files in; the regular files of a ramfs are already stored in [`SharedMemory`].
+pub struct TmpFs(Arc<RamFs>);
+
+impl TmpFs {
+    pub fn new() -> Arc<Self> {
+        let ramfs = RamFs::new();
+        let root = ramfs.root_dir().inode();
+
+        root.set_attr(&SetAttr {
+            mode: Some(0o1777),
+            ..Default::default()
+        })
+        .expect("tmpfs: failed to set the permissions of the root directory");
+
+        Arc::new(Self(ramfs))
+    }
+}
+
+impl FileSystem for TmpFs {
+    fn root_dir(&self) -> DirCacheItem {
+        self.0.root_dir()
+    }
+
+    fn name(&self) -> &'static str {
+        "tmpfs"
+    }
+}
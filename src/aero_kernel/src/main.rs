@@ -95,6 +95,7 @@ mod logger;
 mod mem;
 mod modules;
 mod net;
+mod random;
 mod rendy;
 mod socket;
 mod syscall;
@@ -158,6 +159,9 @@ fn aero_main() -> ! {
     userland::scheduler::init();
     log::info!("loaded scheduler");
 
+    random::init();
+    log::info!("initialized random number generator");
+
     #[cfg(target_arch = "x86_64")]
     crate::arch::apic::mark_bsp_ready(true);
 
@@ -180,6 +184,11 @@ fn aero_main() -> ! {
 }
 
 fn kernel_main_thread() {
+    if let Some(initramfs) = cmdline::get_initramfs() {
+        fs::initramfs::init(initramfs).unwrap();
+        log::info!("unpacked initramfs");
+    }
+
     modules::init();
     log::info!("loaded kernel modules");
 
@@ -208,8 +217,15 @@ fn kernel_main_thread() {
 }
 
 extern "C" fn aero_ap_main(ap_id: usize) -> ! {
-    log::info!("AP{}: Loaded userland", ap_id);
+    userland::scheduler::init_ap();
+    log::info!("AP{}: loaded scheduler", ap_id);
+
+    unsafe {
+        interrupts::enable_interrupts();
+    }
 
+    // Same as the BSP, this context becomes the idle task of this CPU once the
+    // scheduler timer fires.
     loop {
         unsafe { interrupts::halt() }
     }
@@ -18,20 +18,23 @@
 //! Address Resolution Protocol
 
 use alloc::collections::BTreeMap;
+use alloc::sync::Arc;
 use alloc::vec::Vec;
 use spin::{Once, RwLock};
 
-use crate::net::default_device;
-use crate::net::shim::PacketSend;
+use crate::utils::dma::DmaAllocator;
 
 use crabnet::data_link::{Arp, ArpAddress, ArpHardwareType, ArpOpcode, Eth, EthType, MacAddr};
 use crabnet::network::Ipv4Addr;
+use crabnet::IntoBoxedBytes;
 
-use super::RawPacket;
+use super::{NetworkDevice, RawPacket};
 
 enum Status {
     Resolved,
-    Pending(Vec<RawPacket>),
+    /// The packets waiting for the address to be resolved and the interface that they
+    /// are sent through.
+    Pending(Arc<NetworkDevice>, Vec<RawPacket>),
 }
 
 struct Entry {
@@ -56,7 +59,7 @@ impl Cache {
         if let Some(entry) = self.0.get_mut(&ip) {
             let status = core::mem::replace(&mut entry.status, Status::Resolved);
 
-            if let Status::Pending(queue) = status {
+            if let Status::Pending(device, queue) = status {
                 entry.mac = mac;
                 entry.status = Status::Resolved;
 
@@ -67,7 +70,7 @@ impl Cache {
                     let eth = unsafe { &mut *packet.as_mut_ptr().cast::<Eth>() };
                     eth.dest_mac = mac;
 
-                    super::default_device().send(packet);
+                    device.send(packet);
                 }
             }
         } else {
@@ -75,16 +78,15 @@ impl Cache {
         }
     }
 
-    fn request(&mut self, ip: Ipv4Addr, packet: RawPacket) {
-        if ip == Ipv4Addr::LOOPBACK {
-            panic!()
-        }
+    fn request(&mut self, device: Arc<NetworkDevice>, ip: Ipv4Addr, packet: RawPacket) {
+        // Local traffic is sent through the loopback interface and is never resolved.
+        debug_assert!(!super::is_local(ip));
 
         if self.0.get_mut(&ip).is_some() {
             todo!()
         } else {
             let queue = alloc::vec![packet];
-            let entry = Entry::new(MacAddr::NULL, Status::Pending(queue));
+            let entry = Entry::new(MacAddr::NULL, Status::Pending(device, queue));
 
             self.0.insert(ip, entry);
         }
@@ -174,7 +176,7 @@ pub fn init() {
 //     }
 // }
 
-pub fn do_recv(arp: &Arp) {
+pub fn do_recv(device: &NetworkDevice, arp: &Arp) {
     CACHE
         .get()
         .as_ref()
@@ -182,18 +184,22 @@ pub fn do_recv(arp: &Arp) {
         .write()
         .insert(arp.src_ip(), arp.src_mac());
 
-    let device = default_device();
-
     if arp.opcode() == ArpOpcode::Request && arp.dest_ip() == device.ip() {
         let addr = ArpAddress::new(arp.src_mac(), arp.src_ip());
-        let reply_arp = make_arp(ArpOpcode::Reply, addr);
+        let reply_arp = make_arp(device, ArpOpcode::Reply, addr);
 
-        reply_arp.send();
+        send(device, reply_arp);
     }
 }
 
-pub fn request_ip(target: Ipv4Addr, to: RawPacket) {
-    let arp = make_arp(ArpOpcode::Request, ArpAddress::new(MacAddr::NULL, target));
+/// Queues the packet `to` until the MAC address of `target` is resolved and broadcasts an
+/// ARP request for it through `device`.
+pub fn request_ip(device: Arc<NetworkDevice>, target: Ipv4Addr, to: RawPacket) {
+    let arp = make_arp(
+        &device,
+        ArpOpcode::Request,
+        ArpAddress::new(MacAddr::NULL, target),
+    );
 
     log::debug!("[ ARP ] (!!) Sending request for {target:?}");
 
@@ -202,13 +208,20 @@ pub fn request_ip(target: Ipv4Addr, to: RawPacket) {
         .as_ref()
         .expect("arp: cache not initialized")
         .write()
-        .request(target, to);
+        .request(device.clone(), target, to);
+
+    send(&device, arp);
+}
+
+fn send(device: &NetworkDevice, arp: Arp) {
+    let eth = Eth::new(MacAddr::NULL, MacAddr::BROADCAST, EthType::Arp)
+        .set_dest_mac(arp.dest_mac())
+        .set_src_mac(device.mac());
 
-    arp.send();
+    device.send((eth / arp).into_boxed_bytes_in(DmaAllocator));
 }
 
-fn make_arp(opcode: ArpOpcode, dest_addr: ArpAddress) -> Arp {
-    let device = default_device();
+fn make_arp(device: &NetworkDevice, opcode: ArpOpcode, dest_addr: ArpAddress) -> Arp {
     let src_addr = ArpAddress::new(device.mac(), device.ip());
 
     Arp::new(
@@ -0,0 +1,189 @@
+// Copyright (C) 2021-2023 The Aero Project Developers.
+//
+// This file is part of The Aero Project.
+//
+// Aero is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Aero is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Aero. If not, see <https://www.gnu.org/licenses/>.
+
+//! Internet Control Message Protocol (ICMP).
+//!
+//! `crabnet` does not implement ICMP, so the messages are parsed and built by hand. Echo
+//! requests are answered by the kernel and every ICMP datagram is also delivered to the
+//! raw sockets (see `socket/ipv4.rs`), which is how `ping` and `traceroute` work.
+
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
+use spin::RwLock;
+
+use crabnet::network::Ipv4Addr;
+
+/// The IPv4 protocol number of ICMP.
+pub const IPV4_PROTOCOL_ICMP: u8 = 1;
+
+pub const TYPE_ECHO_REPLY: u8 = 0;
+pub const TYPE_DEST_UNREACHABLE: u8 = 3;
+pub const TYPE_ECHO_REQUEST: u8 = 8;
+pub const TYPE_TIME_EXCEEDED: u8 = 11;
+
+pub const CODE_PROTOCOL_UNREACHABLE: u8 = 2;
+pub const CODE_PORT_UNREACHABLE: u8 = 3;
+pub const CODE_TTL_EXCEEDED: u8 = 0;
+
+/// Size of the ICMP header.
+const HEADER_SIZE: usize = 8;
+
+/// Computes the internet checksum (RFC 1071) of `data`.
+pub fn checksum(data: &[u8]) -> u16 {
+    let mut sum = data
+        .chunks(2)
+        .map(|chunk| match chunk {
+            [hi, lo] => u16::from_be_bytes([*hi, *lo]) as u32,
+            [hi] => u16::from_be_bytes([*hi, 0]) as u32,
+            _ => unreachable!(),
+        })
+        .sum::<u32>();
+
+    while sum >> 16 != 0 {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+
+    !(sum as u16)
+}
+
+pub trait IcmpHandler: Send + Sync {
+    /// Called with the whole IPv4 datagram carrying the ICMP message.
+    fn recv(&self, datagram: &[u8]);
+}
+
+static HANDLERS: RwLock<Vec<Weak<dyn IcmpHandler>>> = RwLock::new(Vec::new());
+
+/// Registers `handler` to receive every incoming ICMP message. The handler is removed once
+/// it is dropped.
+pub fn register(handler: Arc<dyn IcmpHandler>) {
+    let mut handlers = HANDLERS.write();
+
+    handlers.retain(|handler| handler.strong_count() != 0);
+    handlers.push(Arc::downgrade(&handler));
+}
+
+fn header_size(datagram: &[u8]) -> usize {
+    ((datagram[0] & 0xf) as usize) * 4
+}
+
+fn src_ip(datagram: &[u8]) -> Ipv4Addr {
+    Ipv4Addr::from([datagram[12], datagram[13], datagram[14], datagram[15]])
+}
+
+fn dest_ip(datagram: &[u8]) -> Ipv4Addr {
+    Ipv4Addr::from([datagram[16], datagram[17], datagram[18], datagram[19]])
+}
+
+fn send(dest_ip: Ipv4Addr, typ: u8, code: u8, rest: [u8; 4], data: &[u8]) {
+    let mut message = Vec::with_capacity(HEADER_SIZE + data.len());
+
+    message.extend_from_slice(&[typ, code, 0, 0]);
+    message.extend_from_slice(&rest);
+    message.extend_from_slice(data);
+
+    let checksum = checksum(&message);
+    message[2..4].copy_from_slice(&checksum.to_be_bytes());
+
+    super::send_ipv4(dest_ip, IPV4_PROTOCOL_ICMP, &message);
+}
+
+/// Sends an ICMP error message in response to `datagram`. The message carries the IP
+/// header and the first 8 bytes of the payload of the offending datagram.
+fn send_error(datagram: &[u8], typ: u8, code: u8) {
+    let src_ip = src_ip(datagram);
+
+    // Never respond to broadcasts or to other ICMP error messages (RFC 1122 3.2.2).
+    if src_ip.is_broadcast() || dest_ip(datagram).is_broadcast() {
+        return;
+    }
+
+    let header_size = header_size(datagram);
+
+    if datagram[9] == IPV4_PROTOCOL_ICMP {
+        match datagram.get(header_size) {
+            Some(&TYPE_ECHO_REQUEST) | Some(&TYPE_ECHO_REPLY) => {}
+            _ => return,
+        }
+    }
+
+    let size = core::cmp::min(datagram.len(), header_size + 8);
+    send(src_ip, typ, code, [0; 4], &datagram[..size]);
+}
+
+/// Notifies the sender of `datagram` that it could not be delivered.
+pub fn send_dest_unreachable(datagram: &[u8], code: u8) {
+    send_error(datagram, TYPE_DEST_UNREACHABLE, code);
+}
+
+/// Notifies the sender of `datagram` that its TTL expired in transit.
+pub fn send_time_exceeded(datagram: &[u8]) {
+    send_error(datagram, TYPE_TIME_EXCEEDED, CODE_TTL_EXCEEDED);
+}
+
+pub fn on_packet(datagram: &[u8]) {
+    let header_size = header_size(datagram);
+
+    if datagram.len() < header_size + HEADER_SIZE {
+        return;
+    }
+
+    let message = &datagram[header_size..];
+
+    if checksum(message) != 0 {
+        log::warn!("icmp: dropping message with invalid checksum");
+        return;
+    }
+
+    for handler in HANDLERS.read().iter().filter_map(Weak::upgrade) {
+        handler.recv(datagram);
+    }
+
+    if message[0] == TYPE_ECHO_REQUEST && !dest_ip(datagram).is_broadcast() {
+        // The reply carries the identifier, sequence number and data of the request.
+        let rest = [message[4], message[5], message[6], message[7]];
+        send(
+            src_ip(datagram),
+            TYPE_ECHO_REPLY,
+            0,
+            rest,
+            &message[HEADER_SIZE..],
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn internet_checksum() {
+        // Example IPv4 header with the checksum field zeroed.
+        let mut header = [
+            0x45, 0x00, 0x00, 0x73, 0x00, 0x00, 0x40, 0x00, 0x40, 0x11, 0x00, 0x00, 0xc0, 0xa8,
+            0x00, 0x01, 0xc0, 0xa8, 0x00, 0xc7,
+        ];
+
+        assert_eq!(checksum(&header), 0xb861);
+
+        // The checksum of data that includes its own checksum is zero.
+        header[10..12].copy_from_slice(&0xb861u16.to_be_bytes());
+        assert_eq!(checksum(&header), 0);
+
+        // Odd lengths are padded with a zero byte.
+        assert_eq!(checksum(&[0x01]), !0x0100);
+    }
+}
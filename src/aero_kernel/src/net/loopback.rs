@@ -16,41 +16,91 @@
 // along with Aero. If not, see <https://www.gnu.org/licenses/>.
 
 //! Loopback device.
+//!
+//! Packets sent through the loopback interface are queued and handed back to the packet
+//! processor of the interface, so local traffic never goes through ARP or a NIC.
 
-use alloc::boxed::Box;
+use alloc::collections::VecDeque;
 use alloc::sync::Arc;
 use crabnet::data_link::MacAddr;
 use crabnet::network::Ipv4Addr;
 
-use crate::utils::dma::DmaAllocator;
+use crate::utils::sync::{Mutex, WaitQueue};
 
-use super::{NetworkDevice, NetworkDriver, RecvPacket};
+use super::{NetworkDevice, NetworkDriver, RawPacket, RecvPacket};
 
-pub struct Loopback;
+#[derive(Default)]
+struct LoopbackInner {
+    queue: VecDeque<RawPacket>,
+    /// The packet that is currently being processed.
+    current: Option<RawPacket>,
+    /// The ID of the current packet.
+    id: usize,
+}
+
+pub struct Loopback {
+    inner: Mutex<LoopbackInner>,
+    wq: WaitQueue,
+}
+
+impl Loopback {
+    fn new() -> Self {
+        Self {
+            inner: Mutex::new(LoopbackInner::default()),
+            wq: WaitQueue::new(),
+        }
+    }
+}
 
 impl NetworkDriver for Loopback {
-    fn send(&self, _packet: Box<[u8], DmaAllocator>) {
-        todo!()
+    fn send(&self, packet: RawPacket) {
+        self.inner.lock_irq().queue.push_back(packet);
+        self.wq.notify_all();
     }
 
     fn recv(&self) -> RecvPacket {
-        todo!()
+        let mut inner = loop {
+            // The packet processor is a kernel thread, so it cannot be interrupted by
+            // a signal.
+            if let Ok(inner) = self.wq.block_on(&self.inner, |e| !e.queue.is_empty()) {
+                break inner;
+            }
+        };
+
+        assert!(
+            inner.current.is_none(),
+            "loopback: previous packet not released"
+        );
+
+        let packet = inner.queue.pop_front().unwrap();
+        let (ptr, len) = (packet.as_ptr(), packet.len());
+
+        inner.id = inner.id.wrapping_add(1);
+        inner.current = Some(packet);
+
+        RecvPacket {
+            // SAFETY: The packet is kept alive until `recv_end` is called.
+            packet: unsafe { core::slice::from_raw_parts(ptr, len) },
+            id: inner.id,
+        }
     }
 
-    fn recv_end(&self, _packet_id: usize) {
-        todo!()
+    fn recv_end(&self, packet_id: usize) {
+        let mut inner = self.inner.lock_irq();
+        assert_eq!(inner.id, packet_id);
+
+        inner.current = None;
     }
 
     #[inline]
     fn mac(&self) -> MacAddr {
-        // TODO: What should this really be?
         MacAddr::NULL
     }
 }
 
 lazy_static::lazy_static! {
     pub static ref LOOPBACK: Arc<NetworkDevice> = (|| {
-        let device = Arc::new(NetworkDevice::new(Arc::new(Loopback)));
+        let device = Arc::new(NetworkDevice::new(Arc::new(Loopback::new())));
 
         device.set_ip(Ipv4Addr::LOOPBACK);
         device.set_subnet_mask(Ipv4Addr::new(255, 0, 0, 0));
@@ -58,3 +108,8 @@ lazy_static::lazy_static! {
         device
     })();
 }
+
+/// Returns whether `ip` is in the loopback range (`127.0.0.0/8`).
+pub fn is_loopback(ip: Ipv4Addr) -> bool {
+    ip.0[0] == 127
+}
@@ -15,20 +15,27 @@
 // You should have received a copy of the GNU General Public License
 // along with Aero. If not, see <https://www.gnu.org/licenses/>.
 
+use core::sync::atomic::{AtomicU16, Ordering};
+
+use aero_syscall::prelude::InterfaceFlags;
 use alloc::boxed::Box;
+use alloc::string::String;
 use alloc::sync::Arc;
 use alloc::vec::Vec;
 use crabnet::transport::TcpOptions;
 use spin::RwLock;
 
 pub mod arp;
+pub mod icmp;
 pub mod loopback;
+pub mod route;
 pub mod tcp;
 pub mod udp;
 
 use crate::userland::scheduler;
 use crate::userland::task::Task;
 use crate::utils::dma::DmaAllocator;
+use crate::utils::sync::Mutex;
 
 use crabnet::data_link::MacAddr;
 use crabnet::network::Ipv4Addr;
@@ -43,10 +50,14 @@ pub trait NetworkDriver: Send + Sync {
 
 #[derive(Default)]
 struct Metadata {
+    /// The name of the interface (e.g. `eth0`).
+    name: String,
+    /// The index of the interface, starting from one.
+    index: usize,
+    flags: InterfaceFlags,
+
     ip: Ipv4Addr,
-    #[allow(dead_code)]
     subnet_mask: Ipv4Addr,
-    default_gateway: Ipv4Addr,
 }
 
 // FIXME(andypython): This is very inefficient. We store the driver as an Arc<dyn NetworkDriver> and
@@ -64,8 +75,6 @@ impl NetworkDevice {
         // https://wiki.qemu.org/Documentation/Networking
         let metadata = Metadata {
             ip: Ipv4Addr::new(192, 168, 100, 0),
-            // What should the default be? Also this should really be handled inside dhcpd.
-            default_gateway: Ipv4Addr::new(10, 0, 2, 2),
             subnet_mask: Ipv4Addr::new(255, 255, 255, 0),
             ..Default::default()
         };
@@ -81,7 +90,30 @@ impl NetworkDevice {
     }
 
     pub fn set_subnet_mask(&self, mask: Ipv4Addr) {
-        self.metadata.write().ip = mask;
+        self.metadata.write().subnet_mask = mask;
+    }
+
+    pub fn set_flags(&self, flags: InterfaceFlags) {
+        self.metadata.write().flags = flags;
+    }
+
+    pub fn name(&self) -> String {
+        self.metadata.read().name.clone()
+    }
+
+    #[inline]
+    pub fn index(&self) -> usize {
+        self.metadata.read().index
+    }
+
+    #[inline]
+    pub fn flags(&self) -> InterfaceFlags {
+        self.metadata.read().flags
+    }
+
+    #[inline]
+    pub fn is_up(&self) -> bool {
+        self.flags().contains(InterfaceFlags::UP)
     }
 
     pub fn ip(&self) -> Ipv4Addr {
@@ -91,11 +123,6 @@ impl NetworkDevice {
     pub fn subnet_mask(&self) -> Ipv4Addr {
         self.metadata.read().subnet_mask
     }
-
-    #[inline]
-    pub fn default_gateway(&self) -> Ipv4Addr {
-        self.metadata.read().default_gateway
-    }
 }
 
 impl core::ops::Deref for NetworkDevice {
@@ -106,76 +133,192 @@ impl core::ops::Deref for NetworkDevice {
     }
 }
 
+/// A packet received by a network driver. The driver must be notified using
+/// [`NetworkDriver::recv_end`] once the packet has been processed.
 #[derive(Debug)]
 pub struct RecvPacket<'a> {
     pub packet: &'a [u8],
     pub id: usize,
 }
 
-impl<'a> Drop for RecvPacket<'a> {
-    fn drop(&mut self) {
-        default_device().recv_end(self.id)
-    }
-}
-
 static DEVICES: RwLock<Vec<Arc<NetworkDevice>>> = RwLock::new(Vec::new());
 static DEFAULT_DEVICE: RwLock<Option<Arc<NetworkDevice>>> = RwLock::new(None);
 
+/// Devices that do not have a packet processor thread yet.
+static UNCLAIMED_DEVICES: Mutex<Vec<Arc<NetworkDevice>>> = Mutex::new(Vec::new());
+
+/// Each network device has its own packet processor thread, which claims the device
+/// when it starts.
 fn packet_processor_thread() {
+    let device = UNCLAIMED_DEVICES
+        .lock_irq()
+        .pop()
+        .expect("net: packet processor thread started without a device");
+
+    loop {
+        let packet = device.recv();
+        let id = packet.id;
+
+        // Drop the packets received while the interface is down.
+        if device.is_up() {
+            process_packet(&device, packet.packet);
+        }
+
+        device.recv_end(id);
+    }
+}
+
+fn process_packet(device: &NetworkDevice, packet: &[u8]) {
     use crabnet::data_link::{Arp, Eth, EthType};
     use crabnet::network::{Ipv4, Ipv4Type};
     use crabnet::transport::{Tcp, Udp};
     use crabnet::PacketParser;
 
-    let device = default_device();
+    let mut parser = PacketParser::new(packet);
+    let eth = parser.next::<Eth>();
 
-    loop {
-        let packet = device.recv();
+    match eth.typ() {
+        EthType::Ip => {
+            let datagram = &packet[ETH_HEADER_SIZE..];
 
-        let mut parser = PacketParser::new(packet.packet);
-        let eth = parser.next::<Eth>();
+            if datagram.len() < IPV4_HEADER_SIZE {
+                return;
+            }
 
-        match eth.typ() {
-            EthType::Ip => {
-                let ip = parser.next::<Ipv4>();
+            // TTL and protocol fields of the IPv4 header.
+            let (ttl, protocol) = (datagram[8], datagram[9]);
 
-                match ip.protocol() {
-                    Ipv4Type::Udp => {
-                        let udp = parser.next::<Udp>();
-                        let size = ip.payload_len() as usize - core::mem::size_of::<Udp>();
+            if ttl == 0 {
+                icmp::send_time_exceeded(datagram);
+                return;
+            }
 
-                        let payload = &parser.payload()[..size];
-                        udp::on_packet(udp, payload);
-                    }
+            match protocol {
+                icmp::IPV4_PROTOCOL_ICMP => {
+                    icmp::on_packet(datagram);
+                    return;
+                }
 
-                    Ipv4Type::Tcp => {
-                        let tcp = parser.next::<Tcp>();
-                        let size = ip.payload_len() as usize - tcp.header_size() as usize;
-                        let options = parser.next::<TcpOptions>();
-                        let payload = &parser.payload()[..size];
+                IPV4_PROTOCOL_TCP | IPV4_PROTOCOL_UDP => {}
 
-                        tcp::on_packet(tcp, options, payload)
-                    }
+                // Protocols that are not handled by `crabnet` or by us.
+                _ => {
+                    icmp::send_dest_unreachable(datagram, icmp::CODE_PROTOCOL_UNREACHABLE);
+                    return;
                 }
             }
 
-            EthType::Arp => {
-                arp::do_recv(parser.next::<Arp>());
+            let ip = parser.next::<Ipv4>();
+
+            match ip.protocol() {
+                Ipv4Type::Udp => {
+                    let udp = parser.next::<Udp>();
+                    let size = ip.payload_len() as usize - core::mem::size_of::<Udp>();
+
+                    let payload = &parser.payload()[..size];
+
+                    if !udp::on_packet(udp, payload) {
+                        icmp::send_dest_unreachable(datagram, icmp::CODE_PORT_UNREACHABLE);
+                    }
+                }
+
+                Ipv4Type::Tcp => {
+                    let tcp = parser.next::<Tcp>();
+                    let size = ip.payload_len() as usize - tcp.header_size() as usize;
+                    let options = parser.next::<TcpOptions>();
+                    let payload = &parser.payload()[..size];
+
+                    tcp::on_packet(ip.src_ip(), tcp, options, payload)
+                }
             }
         }
+
+        EthType::Arp => {
+            arp::do_recv(device, parser.next::<Arp>());
+        }
+    }
+}
+
+/// Registers the network interface `device` as `name`, assigning it the next interface
+/// index, and starts its packet processor thread.
+fn register_device(device: Arc<NetworkDevice>, name: String, flags: InterfaceFlags) {
+    {
+        let mut devices = DEVICES.write();
+        let mut metadata = device.metadata.write();
+
+        metadata.name = name;
+        metadata.index = devices.len() + 1;
+        metadata.flags = flags;
+
+        devices.push(device.clone());
     }
+
+    route::update_interface(&device);
+    log::info!("net: registered interface {}", device.name());
+
+    UNCLAIMED_DEVICES.lock_irq().push(device);
+    scheduler::get_scheduler().register_task(Task::new_kernel(packet_processor_thread, true));
 }
 
+/// Registers the ethernet device `device` as the next available `eth<N>` interface.
 pub fn add_device(device: NetworkDevice) {
     let device = Arc::new(device);
-    DEVICES.write().push(device.clone());
+
+    let count = DEVICES
+        .read()
+        .iter()
+        .filter(|device| !device.flags().contains(InterfaceFlags::LOOPBACK))
+        .count();
+
+    register_device(
+        device.clone(),
+        alloc::format!("eth{count}"),
+        InterfaceFlags::UP
+            | InterfaceFlags::BROADCAST
+            | InterfaceFlags::RUNNING
+            | InterfaceFlags::MULTICAST,
+    );
 
     let mut default_device = DEFAULT_DEVICE.write();
     if default_device.is_none() {
+        // Install a default route through the gateway of QEMU's user networking. It can
+        // be replaced by userland (e.g. `dhcpd`) using the `SIOCDELRT` and `SIOCADDRT`
+        // ioctls.
+        //
+        // https://wiki.qemu.org/Documentation/Networking
+        route::add(route::Route::new(
+            Ipv4Addr::new(0, 0, 0, 0),
+            Ipv4Addr::new(0, 0, 0, 0),
+            Some(Ipv4Addr::new(10, 0, 2, 2)),
+            device.clone(),
+            0,
+        ));
+
         *default_device = Some(device);
     }
+}
 
-    scheduler::get_scheduler().register_task(Task::new_kernel(packet_processor_thread, true));
+/// Returns a list of all of the registered network interfaces, ordered by their index.
+pub fn devices() -> Vec<Arc<NetworkDevice>> {
+    DEVICES.read().clone()
+}
+
+/// Returns the network interface with the provided `name`.
+pub fn device_by_name(name: &str) -> Option<Arc<NetworkDevice>> {
+    DEVICES
+        .read()
+        .iter()
+        .find(|device| device.metadata.read().name == name)
+        .cloned()
+}
+
+/// Returns the network interface with the provided `index`.
+pub fn device_by_index(index: usize) -> Option<Arc<NetworkDevice>> {
+    DEVICES
+        .read()
+        .iter()
+        .find(|device| device.index() == index)
+        .cloned()
 }
 
 pub fn has_default_device() -> bool {
@@ -190,96 +333,163 @@ pub fn default_device() -> Arc<NetworkDevice> {
         .clone()
 }
 
+/// Returns whether `ip` is an address of this host, in which case the traffic is sent
+/// through the loopback interface.
+pub fn is_local(ip: Ipv4Addr) -> bool {
+    loopback::is_loopback(ip) || DEVICES.read().iter().any(|device| device.ip() == ip)
+}
+
+/// Returns the network interface used to reach `dest` and the address of the next hop,
+/// which is either `dest` itself or the gateway that the traffic is forwarded to. [`None`]
+/// is returned if `dest` is unreachable.
+pub fn route(dest: Ipv4Addr) -> Option<(Arc<NetworkDevice>, Ipv4Addr)> {
+    if is_local(dest) {
+        return Some((loopback::LOOPBACK.clone(), dest));
+    }
+
+    // Broadcasts (e.g. DHCP discovery) are sent through the default interface, as it may
+    // not have an address (and hence a route) yet.
+    if dest.is_broadcast() {
+        return DEFAULT_DEVICE.read().clone().map(|device| (device, dest));
+    }
+
+    route::lookup(dest)
+}
+
 // Initialize the networking stack.
 pub fn init() {
+    register_device(
+        loopback::LOOPBACK.clone(),
+        String::from("lo"),
+        InterfaceFlags::UP | InterfaceFlags::LOOPBACK | InterfaceFlags::RUNNING,
+    );
+
     if !has_default_device() {
         // No network devices are avaliable.
         return;
     }
 
-    DEVICES.write().push(loopback::LOOPBACK.clone());
     arp::init();
     log::info!("net::arp: initialized cache");
 }
 
 pub type RawPacket = Box<[u8], DmaAllocator>;
 
-pub mod shim {
-    use crate::net::{self, arp};
-    use crate::utils::dma::DmaAllocator;
+/// Size of the ethernet header.
+const ETH_HEADER_SIZE: usize = 14;
+/// Size of the IPv4 header without any options.
+const IPV4_HEADER_SIZE: usize = 20;
 
-    use crabnet::data_link::{Arp, Eth, EthType, MacAddr};
-    use crabnet::network::Ipv4;
-    use crabnet::{IntoBoxedBytes, Protocol, Stacked};
+/// The default time-to-live of the outgoing IPv4 datagrams.
+pub const DEFAULT_TTL: u8 = 64;
 
-    pub trait PacketSend {
-        fn send(self);
-    }
+const IPV4_PROTOCOL_TCP: u8 = 6;
+const IPV4_PROTOCOL_UDP: u8 = 17;
 
-    // Deref<T> for Stacked<T, U> where T: Stacked?
-    //
-    // TODO(andypython): Can all of the packet send impls be refactored?
-    impl<T: Protocol, U: Protocol> PacketSend for Stacked<Stacked<Stacked<Eth, Ipv4>, T>, U> {
-        fn send(mut self) {
-            let device = net::default_device();
+static IPV4_ID: AtomicU16 = AtomicU16::new(0);
 
-            let eth = &mut self.upper.upper.upper;
-            let ip = &self.upper.upper.lower;
+/// Transmits the ethernet frame `packet`, which carries an IPv4 datagram addressed to
+/// `dest_ip`. The MAC addresses of the frame are filled in here; local traffic is sent
+/// through the loopback interface and the rest is resolved using ARP.
+pub fn transmit(dest_ip: Ipv4Addr, mut packet: RawPacket) {
+    let Some((device, next_hop)) = route(dest_ip) else {
+        log::warn!("net: dropping packet to unreachable destination {dest_ip:?}");
+        return;
+    };
 
-            let mut dest_ip = ip.dest_ip();
+    packet[6..12].copy_from_slice(&device.mac().0);
 
-            if !dest_ip.is_broadcast() && !dest_ip.is_same_subnet(device.ip(), device.subnet_mask())
-            {
-                dest_ip = device.default_gateway();
-            }
-
-            eth.src_mac = device.mac();
+    // Local traffic does not need to be resolved.
+    if device.flags().contains(InterfaceFlags::LOOPBACK) {
+        packet[..6].copy_from_slice(&device.mac().0);
+        device.send(packet);
+        return;
+    }
 
-            if let Some(addr) = arp::get(dest_ip) {
-                eth.dest_mac = addr;
-                device.send(self.into_boxed_bytes_in(DmaAllocator));
-            } else {
-                arp::request_ip(dest_ip, self.into_boxed_bytes_in(DmaAllocator));
-            }
-        }
+    if let Some(addr) = arp::get(next_hop) {
+        packet[..6].copy_from_slice(&addr.0);
+        device.send(packet);
+    } else {
+        arp::request_ip(device, next_hop, packet);
     }
+}
 
-    impl<T: Protocol, U: Protocol, S: Protocol> PacketSend
-        for Stacked<Stacked<Stacked<Stacked<Eth, Ipv4>, T>, U>, S>
-    {
-        fn send(mut self) {
-            let device = net::default_device();
+/// Sends an IPv4 datagram, with the provided `protocol` and `payload`, to `dest_ip`. This
+/// is used for the protocols that are not implemented by `crabnet` (e.g. ICMP). Returns
+/// [`false`] if `dest_ip` is unreachable.
+pub fn send_ipv4(dest_ip: Ipv4Addr, protocol: u8, payload: &[u8]) -> bool {
+    let Some((device, _)) = route(dest_ip) else {
+        return false;
+    };
+
+    let src_ip = device.ip();
+    let size = ETH_HEADER_SIZE + IPV4_HEADER_SIZE + payload.len();
+
+    // SAFETY: The DMA allocator returns zeroed memory.
+    let mut packet: RawPacket =
+        unsafe { Box::new_zeroed_slice_in(size, DmaAllocator).assume_init() };
+
+    // Ethernet header (the MAC addresses are filled in by `transmit`).
+    packet[12..ETH_HEADER_SIZE].copy_from_slice(&0x0800u16.to_be_bytes());
+
+    let header = &mut packet[ETH_HEADER_SIZE..ETH_HEADER_SIZE + IPV4_HEADER_SIZE];
+    let id = IPV4_ID.fetch_add(1, Ordering::Relaxed);
+
+    header[0] = 0x45; // version 4, IHL 5
+    header[2..4].copy_from_slice(&((IPV4_HEADER_SIZE + payload.len()) as u16).to_be_bytes());
+    header[4..6].copy_from_slice(&id.to_be_bytes());
+    header[8] = DEFAULT_TTL;
+    header[9] = protocol;
+    header[12..16].copy_from_slice(&src_ip.0);
+    header[16..20].copy_from_slice(&dest_ip.0);
+
+    let checksum = icmp::checksum(header);
+    header[10..12].copy_from_slice(&checksum.to_be_bytes());
+
+    packet[ETH_HEADER_SIZE + IPV4_HEADER_SIZE..].copy_from_slice(payload);
+    transmit(dest_ip, packet);
+    true
+}
 
-            let eth = &mut self.upper.upper.upper.upper;
-            let ip = &self.upper.upper.upper.lower;
+/// Overrides the time-to-live and the type of service of the IPv4 datagram carried by the
+/// ethernet frame `packet`.
+pub fn set_ipv4_options(packet: &mut [u8], ttl: u8, tos: u8) {
+    let header_size = ((packet[ETH_HEADER_SIZE] & 0xf) as usize) * 4;
+    let header = &mut packet[ETH_HEADER_SIZE..ETH_HEADER_SIZE + header_size];
 
-            let mut dest_ip = ip.dest_ip();
+    header[1] = tos;
+    header[8] = ttl;
+    header[10..12].fill(0);
 
-            if !dest_ip.is_broadcast() && !dest_ip.is_same_subnet(device.ip(), device.subnet_mask())
-            {
-                dest_ip = device.default_gateway();
-            }
+    let checksum = icmp::checksum(header);
+    header[10..12].copy_from_slice(&checksum.to_be_bytes());
+}
 
-            eth.src_mac = device.mac();
+pub mod shim {
+    use crate::net;
+    use crate::utils::dma::DmaAllocator;
 
-            if let Some(addr) = arp::get(dest_ip) {
-                eth.dest_mac = addr;
-                device.send(self.into_boxed_bytes_in(DmaAllocator));
-            } else {
-                arp::request_ip(dest_ip, self.into_boxed_bytes_in(DmaAllocator));
-            }
-        }
+    use crabnet::data_link::Eth;
+    use crabnet::network::Ipv4;
+    use crabnet::{IntoBoxedBytes, Protocol, Stacked};
+
+    pub trait PacketSend {
+        fn send(self);
     }
 
-    impl PacketSend for Arp {
+    impl<T: Protocol, U: Protocol> PacketSend for Stacked<Stacked<Stacked<Eth, Ipv4>, T>, U> {
         fn send(self) {
-            let device = net::default_device();
-
-            let eth = Eth::new(MacAddr::NULL, MacAddr::BROADCAST, EthType::Arp)
-                .set_dest_mac(self.dest_mac())
-                .set_src_mac(device.mac());
+            let dest_ip = self.upper.upper.lower.dest_ip();
+            net::transmit(dest_ip, self.into_boxed_bytes_in(DmaAllocator));
+        }
+    }
 
-            device.send((eth / self).into_boxed_bytes_in(DmaAllocator));
+    impl<T: Protocol, U: Protocol, S: Protocol> PacketSend
+        for Stacked<Stacked<Stacked<Stacked<Eth, Ipv4>, T>, U>, S>
+    {
+        fn send(self) {
+            let dest_ip = self.upper.upper.upper.lower.dest_ip();
+            net::transmit(dest_ip, self.into_boxed_bytes_in(DmaAllocator));
         }
     }
 
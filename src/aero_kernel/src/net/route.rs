@@ -0,0 +1,195 @@
+// Copyright (C) 2021-2023 The Aero Project Developers.
+//
+// This file is part of The Aero Project.
+//
+// Aero is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Aero is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Aero. If not, see <https://www.gnu.org/licenses/>.
+
+//! IPv4 routing table.
+//!
+//! Each route maps a destination subnet to the interface that the traffic is sent through
+//! and, optionally, the gateway that the traffic is forwarded to. The route with the
+//! longest matching prefix wins and ties are broken using the metric of the routes.
+//!
+//! A route to the subnet of an interface is added by the kernel whenever the address or
+//! the subnet mask of the interface changes. The rest of the routes (e.g. the default
+//! route) are managed by userland using the `SIOCADDRT` and `SIOCDELRT` ioctls.
+
+use aero_syscall::prelude::{InterfaceFlags, RouteFlags};
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use spin::RwLock;
+
+use crabnet::network::Ipv4Addr;
+
+use super::NetworkDevice;
+
+#[derive(Clone)]
+pub struct Route {
+    pub dest: Ipv4Addr,
+    pub mask: Ipv4Addr,
+    pub gateway: Option<Ipv4Addr>,
+    pub device: Arc<NetworkDevice>,
+    pub metric: u32,
+
+    /// Whether the route was added by the kernel for the subnet of the interface.
+    kernel: bool,
+}
+
+impl Route {
+    pub fn new(
+        dest: Ipv4Addr,
+        mask: Ipv4Addr,
+        gateway: Option<Ipv4Addr>,
+        device: Arc<NetworkDevice>,
+        metric: u32,
+    ) -> Self {
+        Self {
+            dest: mask_addr(dest, mask),
+            mask,
+            gateway,
+            device,
+            metric,
+            kernel: false,
+        }
+    }
+
+    pub fn flags(&self) -> RouteFlags {
+        let mut flags = RouteFlags::empty();
+
+        if self.device.is_up() {
+            flags |= RouteFlags::UP;
+        }
+
+        if self.gateway.is_some() {
+            flags |= RouteFlags::GATEWAY;
+        }
+
+        if self.mask == Ipv4Addr::BROADCAST {
+            flags |= RouteFlags::HOST;
+        }
+
+        flags
+    }
+
+    fn prefix_len(&self) -> u32 {
+        u32::from_be_bytes(self.mask.0).count_ones()
+    }
+
+    fn matches(&self, dest: Ipv4Addr) -> bool {
+        mask_addr(dest, self.mask) == self.dest
+    }
+}
+
+fn mask_addr(addr: Ipv4Addr, mask: Ipv4Addr) -> Ipv4Addr {
+    let masked = u32::from_be_bytes(addr.0) & u32::from_be_bytes(mask.0);
+    Ipv4Addr::from(masked.to_be_bytes())
+}
+
+static ROUTES: RwLock<Vec<Route>> = RwLock::new(Vec::new());
+
+/// Adds `route` to the routing table. Returns [`false`] if an identical route is already
+/// present.
+pub fn add(route: Route) -> bool {
+    let mut routes = ROUTES.write();
+
+    let exists = routes.iter().any(|e| {
+        e.dest == route.dest
+            && e.mask == route.mask
+            && e.gateway == route.gateway
+            && e.metric == route.metric
+            && Arc::ptr_eq(&e.device, &route.device)
+    });
+
+    if exists {
+        return false;
+    }
+
+    log::debug!(
+        "route: add {:?}/{} via {:?} dev {} metric {}",
+        route.dest,
+        route.prefix_len(),
+        route.gateway,
+        route.device.name(),
+        route.metric
+    );
+
+    routes.push(route);
+    true
+}
+
+/// Removes the first route to `dest`/`mask` that matches the provided `gateway`, `device`
+/// and `metric`, if they are specified. Returns [`false`] if there is no such route.
+pub fn remove(
+    dest: Ipv4Addr,
+    mask: Ipv4Addr,
+    gateway: Option<Ipv4Addr>,
+    device: Option<&Arc<NetworkDevice>>,
+    metric: Option<u32>,
+) -> bool {
+    let dest = mask_addr(dest, mask);
+    let mut routes = ROUTES.write();
+
+    let position = routes.iter().position(|e| {
+        e.dest == dest
+            && e.mask == mask
+            && gateway.map_or(true, |gateway| e.gateway == Some(gateway))
+            && device.map_or(true, |device| Arc::ptr_eq(&e.device, device))
+            && metric.map_or(true, |metric| e.metric == metric)
+    });
+
+    if let Some(position) = position {
+        routes.remove(position);
+        true
+    } else {
+        false
+    }
+}
+
+/// Returns the interface and the next hop used to reach `dest`. [`None`] is returned if
+/// the destination is unreachable.
+pub fn lookup(dest: Ipv4Addr) -> Option<(Arc<NetworkDevice>, Ipv4Addr)> {
+    ROUTES
+        .read()
+        .iter()
+        .filter(|route| route.device.is_up() && route.matches(dest))
+        .max_by(|a, b| {
+            // Prefer the most specific route and then the one with the lowest metric.
+            a.prefix_len()
+                .cmp(&b.prefix_len())
+                .then(b.metric.cmp(&a.metric))
+        })
+        .map(|route| (route.device.clone(), route.gateway.unwrap_or(dest)))
+}
+
+/// Returns a snapshot of the routing table.
+pub fn routes() -> Vec<Route> {
+    ROUTES.read().clone()
+}
+
+/// Replaces the route to the subnet of `device` after its address or subnet mask has
+/// changed.
+pub fn update_interface(device: &Arc<NetworkDevice>) {
+    // Local traffic never goes through the routing table.
+    if device.flags().contains(InterfaceFlags::LOOPBACK) {
+        return;
+    }
+
+    let mut routes = ROUTES.write();
+    routes.retain(|route| !(route.kernel && Arc::ptr_eq(&route.device, device)));
+
+    let mut route = Route::new(device.ip(), device.subnet_mask(), None, device.clone(), 0);
+    route.kernel = true;
+
+    routes.push(route);
+}
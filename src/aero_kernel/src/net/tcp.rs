@@ -18,18 +18,29 @@
 use alloc::collections::BTreeMap;
 use alloc::sync::Arc;
 
+use crabnet::network::Ipv4Addr;
 use crabnet::transport::{Tcp, TcpOptions};
 use spin::RwLock;
 
 use crate::socket::tcp::TcpSocket;
 
+/// Sockets bound to a local port.
 static HANDLERS: RwLock<BTreeMap<u16, Arc<TcpSocket>>> = RwLock::new(BTreeMap::new());
 
-pub fn on_packet(tcp: &Tcp, options: TcpOptions, payload: &[u8]) {
-    let handlers = HANDLERS.read();
+/// Connections created by listening sockets, keyed by the local port and the address of
+/// the remote end. As they share the local port with the listening socket, they are
+/// looked up before [`HANDLERS`].
+static CONNECTIONS: RwLock<BTreeMap<(u16, Ipv4Addr, u16), Arc<TcpSocket>>> =
+    RwLock::new(BTreeMap::new());
 
-    if let Some(handler) = handlers.get(&tcp.dest_port()) {
-        handler.on_packet(tcp, options, payload);
+pub fn on_packet(src_ip: Ipv4Addr, tcp: &Tcp, options: TcpOptions, payload: &[u8]) {
+    let key = (tcp.dest_port(), src_ip, tcp.src_port());
+    let connection = CONNECTIONS.read().get(&key).cloned();
+
+    let handler = connection.or_else(|| HANDLERS.read().get(&tcp.dest_port()).cloned());
+
+    if let Some(handler) = handler {
+        handler.on_packet(src_ip, tcp, options, payload);
     } else {
         log::warn!("tcp: no handler registered for port {}", tcp.dest_port());
     }
@@ -39,6 +50,47 @@ pub trait TcpHandler: Send + Sync {
     fn recv(&self, packet: &Tcp, payload: &[u8]);
 }
 
+/// Binds the `socket` to the local `port`. Returns [`false`] if the port is already
+/// in use. If `reuse_addr` is set (`SO_REUSEADDR`), the port can be taken over from a
+/// socket whose connection is closed or in `TIME_WAIT`.
+pub fn bind(port: u16, socket: Arc<TcpSocket>, reuse_addr: bool) -> bool {
+    let mut handlers = HANDLERS.write();
+
+    if let Some(handler) = handlers.get(&port) {
+        if !reuse_addr || !handler.is_closed() {
+            return false;
+        }
+    }
+
+    handlers.insert(port, socket);
+    true
+}
+
+/// Unbinds the `socket` from the local `port`. The port is left alone if it has been taken
+/// over by another socket (see [`bind`]).
+pub fn unbind(port: u16, socket: &TcpSocket) {
+    let mut handlers = HANDLERS.write();
+
+    if handlers
+        .get(&port)
+        .map_or(false, |handler| core::ptr::eq(handler.as_ref(), socket))
+    {
+        handlers.remove(&port);
+    }
+}
+
+/// Registers a connection accepted on the local `port` from `remote_ip:remote_port`.
+pub fn add_connection(port: u16, remote_ip: Ipv4Addr, remote_port: u16, socket: Arc<TcpSocket>) {
+    CONNECTIONS
+        .write()
+        .insert((port, remote_ip, remote_port), socket);
+}
+
+/// Removes the connection registered using [`add_connection`].
+pub fn remove_connection(port: u16, remote_ip: Ipv4Addr, remote_port: u16) {
+    CONNECTIONS.write().remove(&(port, remote_ip, remote_port));
+}
+
 pub fn alloc_ephemeral_port(socket: Arc<TcpSocket>) -> Option<u16> {
     const EPHEMERAL_START: u16 = 49152;
     const EPHEMERAL_END: u16 = u16::MAX;
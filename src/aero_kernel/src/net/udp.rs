@@ -22,15 +22,19 @@ use spin::RwLock;
 use crabnet::network::Ipv4Addr;
 use crabnet::transport::Udp;
 
-pub fn on_packet(udp: &Udp, payload: &[u8]) {
+/// Delivers the UDP datagram to the socket bound to its destination port. Returns
+/// [`false`] if no socket is bound to the port.
+pub fn on_packet(udp: &Udp, payload: &[u8]) -> bool {
     let dest_port = udp.dst_port();
 
     let handlers = HANDLERS.read();
 
     if let Some(handler) = handlers.get(&dest_port) {
         handler.recv(udp, payload);
+        true
     } else {
         log::warn!("udp: no handler registered for port {}", dest_port);
+        false
     }
 }
 
@@ -0,0 +1,381 @@
+// Copyright (C) 2021-2023 The Aero Project Developers.
+//
+// This file is part of The Aero Project.
+//
+// Aero is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Aero is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Aero. If not, see <https://www.gnu.org/licenses/>.
+
+//! Kernel random number generator.
+//!
+//! Entropy is collected from the hardware random number generator (`RDSEED` and `RDRAND`),
+//! the timing of interrupts and the jitter of the timestamp counter into the entropy pool. Once
+//! the pool has been credited with enough entropy, it is used to seed a ChaCha20 based CSPRNG
+//! which backs `/dev/urandom`, `/dev/random` and `getrandom(2)`.
+
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use crate::userland::signals::SignalResult;
+use crate::utils::sync::{Mutex, WaitQueue};
+
+/// The number of bits of entropy required to seed the CSPRNG.
+const SEED_BITS: usize = 256;
+/// The number of timestamp counter samples that are mixed into the pool on initialization.
+const JITTER_SAMPLES: usize = 256;
+
+/// "expand 32-byte k"
+const CHACHA_CONSTANTS: [u32; 4] = [0x61707865, 0x3320646e, 0x79622d32, 0x6b206574];
+
+static POOL: Mutex<EntropyPool> = Mutex::new(EntropyPool::new());
+static CRNG: Mutex<ChaCha20> = Mutex::new(ChaCha20::new());
+
+/// Set once the CSPRNG has been seeded with [`SEED_BITS`] of entropy.
+static INITIALIZED: AtomicBool = AtomicBool::new(false);
+static INIT_WQ: WaitQueue = WaitQueue::new();
+
+#[inline]
+fn quarter_round(state: &mut [u32; 16], a: usize, b: usize, c: usize, d: usize) {
+    state[a] = state[a].wrapping_add(state[b]);
+    state[d] = (state[d] ^ state[a]).rotate_left(16);
+
+    state[c] = state[c].wrapping_add(state[d]);
+    state[b] = (state[b] ^ state[c]).rotate_left(12);
+
+    state[a] = state[a].wrapping_add(state[b]);
+    state[d] = (state[d] ^ state[a]).rotate_left(8);
+
+    state[c] = state[c].wrapping_add(state[d]);
+    state[b] = (state[b] ^ state[c]).rotate_left(7);
+}
+
+/// The ChaCha20 block function (see RFC 7539 section 2.3).
+fn chacha20_block(input: &[u32; 16]) -> [u32; 16] {
+    let mut state = *input;
+
+    for _ in 0..10 {
+        // Column rounds.
+        quarter_round(&mut state, 0, 4, 8, 12);
+        quarter_round(&mut state, 1, 5, 9, 13);
+        quarter_round(&mut state, 2, 6, 10, 14);
+        quarter_round(&mut state, 3, 7, 11, 15);
+
+        // Diagonal rounds.
+        quarter_round(&mut state, 0, 5, 10, 15);
+        quarter_round(&mut state, 1, 6, 11, 12);
+        quarter_round(&mut state, 2, 7, 8, 13);
+        quarter_round(&mut state, 3, 4, 9, 14);
+    }
+
+    for (word, input) in state.iter_mut().zip(input) {
+        *word = word.wrapping_add(*input);
+    }
+
+    state
+}
+
+/// ChaCha20 keystream generator with a 64-bit block counter and a zero nonce.
+struct ChaCha20 {
+    key: [u32; 8],
+    counter: u64,
+}
+
+impl ChaCha20 {
+    const fn new() -> Self {
+        Self {
+            key: [0; 8],
+            counter: 0,
+        }
+    }
+
+    fn next_block(&mut self) -> [u32; 16] {
+        let mut input = [0; 16];
+
+        input[..4].copy_from_slice(&CHACHA_CONSTANTS);
+        input[4..12].copy_from_slice(&self.key);
+        input[12] = self.counter as u32;
+        input[13] = (self.counter >> 32) as u32;
+
+        self.counter = self.counter.wrapping_add(1);
+        chacha20_block(&input)
+    }
+
+    /// Mixes `seed` into the key.
+    fn reseed(&mut self, seed: &[u32; 8]) {
+        for (key, seed) in self.key.iter_mut().zip(seed) {
+            *key ^= *seed;
+        }
+
+        let block = self.next_block();
+        self.key.copy_from_slice(&block[..8]);
+    }
+
+    /// Returns a new generator keyed with the output of this generator. The key of this
+    /// generator is replaced as well, so the output of the returned generator cannot be
+    /// recovered from the state of this one.
+    fn fork(&mut self) -> Self {
+        let block = self.next_block();
+        self.key.copy_from_slice(&block[..8]);
+
+        let mut key = [0; 8];
+        key.copy_from_slice(&block[8..]);
+
+        Self { key, counter: 0 }
+    }
+
+    fn fill(&mut self, buffer: &mut [u8]) {
+        for chunk in buffer.chunks_mut(64) {
+            let block = self.next_block();
+            let bytes = block.iter().flat_map(|word| word.to_le_bytes());
+
+            for (byte, value) in chunk.iter_mut().zip(bytes) {
+                *byte = value;
+            }
+        }
+    }
+}
+
+struct EntropyPool {
+    words: [u32; 16],
+    cursor: usize,
+    /// The number of bits of entropy that have been credited to the pool since the
+    /// last full reseed.
+    entropy: usize,
+}
+
+impl EntropyPool {
+    const fn new() -> Self {
+        Self {
+            words: [0; 16],
+            cursor: 0,
+            entropy: 0,
+        }
+    }
+
+    /// Mixes `sample` into the pool and credits it with `bits` of entropy.
+    fn mix(&mut self, sample: u64, bits: usize) {
+        self.words[self.cursor] ^= sample as u32;
+        self.words[self.cursor + 1] ^= (sample >> 32) as u32;
+        self.cursor += 2;
+
+        if self.cursor == self.words.len() {
+            // Stir the pool, so that every sample affects the whole pool.
+            self.words = chacha20_block(&self.words);
+            self.cursor = 0;
+        }
+
+        self.entropy = core::cmp::min(self.entropy + bits, SEED_BITS);
+    }
+
+    /// Returns a seed derived from the pool. Half of the pool is replaced with the
+    /// other half of the output, so the seed cannot be recovered from the pool.
+    fn extract(&mut self) -> [u32; 8] {
+        let block = chacha20_block(&self.words);
+        self.words[..8].copy_from_slice(&block[8..]);
+        self.cursor = 0;
+
+        let mut seed = [0; 8];
+        seed.copy_from_slice(&block[..8]);
+        seed
+    }
+}
+
+/// Reseeds the CSPRNG from `pool`. The credited entropy is only consumed (and the CSPRNG
+/// marked as initialized) once the pool has [`SEED_BITS`] of entropy; until then, the CSPRNG
+/// is still reseeded so its output depends on everything that has been collected so far.
+fn reseed(pool: &mut EntropyPool) {
+    let seed = pool.extract();
+    CRNG.lock_irq().reseed(&seed);
+
+    if pool.entropy >= SEED_BITS {
+        pool.entropy = 0;
+
+        if !INITIALIZED.swap(true, Ordering::SeqCst) {
+            INIT_WQ.notify_all();
+        }
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+fn read_cycles() -> u64 {
+    unsafe { core::arch::x86_64::_rdtsc() }
+}
+
+#[cfg(target_arch = "aarch64")]
+fn read_cycles() -> u64 {
+    let value: u64;
+
+    unsafe {
+        asm!("mrs {}, cntvct_el0", out(reg) value, options(nomem, nostack));
+    }
+
+    value
+}
+
+#[cfg(target_arch = "x86_64")]
+fn rdrand() -> Option<u64> {
+    for _ in 0..10 {
+        let value: u64;
+        let ok: u8;
+
+        unsafe {
+            asm!("rdrand {}", "setc {}", out(reg) value, out(reg_byte) ok, options(nomem, nostack));
+        }
+
+        if ok != 0 {
+            return Some(value);
+        }
+    }
+
+    None
+}
+
+#[cfg(target_arch = "x86_64")]
+fn rdseed() -> Option<u64> {
+    // RDSEED fails when the entropy source has been drained, so give it a bit more time
+    // to recover than RDRAND.
+    for _ in 0..100 {
+        let value: u64;
+        let ok: u8;
+
+        unsafe {
+            asm!("rdseed {}", "setc {}", out(reg) value, out(reg_byte) ok, options(nomem, nostack));
+        }
+
+        if ok != 0 {
+            return Some(value);
+        }
+
+        core::hint::spin_loop();
+    }
+
+    None
+}
+
+/// Mixes the output of the hardware random number generator into `pool`.
+#[cfg(target_arch = "x86_64")]
+fn add_hwrng_randomness(pool: &mut EntropyPool) {
+    let cpuid = raw_cpuid::CpuId::new();
+
+    let has_rdrand = cpuid.get_feature_info().map_or(false, |f| f.has_rdrand());
+    let has_rdseed = cpuid
+        .get_extended_feature_info()
+        .map_or(false, |f| f.has_rdseed());
+
+    for _ in 0..SEED_BITS / 64 {
+        if let Some(seed) = has_rdseed.then(rdseed).flatten() {
+            pool.mix(seed, 64);
+        } else if let Some(value) = has_rdrand.then(rdrand).flatten() {
+            // RDRAND is the output of a DRBG that is only reseeded from time to time,
+            // so it is not credited with its full size.
+            pool.mix(value, 32);
+        }
+    }
+}
+
+#[cfg(target_arch = "aarch64")]
+fn add_hwrng_randomness(_pool: &mut EntropyPool) {}
+
+/// Mixes the time at which the interrupt `vector` was raised into the entropy pool. Must be
+/// called with interrupts disabled.
+pub fn add_interrupt_randomness(vector: usize) {
+    let sample = read_cycles() ^ ((vector as u64) << 56);
+    let mut pool = POOL.lock();
+
+    pool.mix(sample, 1);
+
+    if !is_initialized() && pool.entropy >= SEED_BITS {
+        reseed(&mut pool);
+    }
+}
+
+/// Mixes `data` into the entropy pool without crediting any entropy for it.
+pub fn add_device_randomness(data: &[u8]) {
+    let mut pool = POOL.lock_irq();
+
+    for chunk in data.chunks(8) {
+        let mut sample = [0; 8];
+        sample[..chunk.len()].copy_from_slice(chunk);
+
+        pool.mix(u64::from_le_bytes(sample), 0);
+    }
+}
+
+/// Returns [`true`] if the CSPRNG has been seeded with enough entropy.
+pub fn is_initialized() -> bool {
+    INITIALIZED.load(Ordering::SeqCst)
+}
+
+/// Blocks the current task until the CSPRNG has been seeded with enough entropy.
+pub fn wait_for_entropy() -> SignalResult<()> {
+    INIT_WQ.block_on(&POOL, |_| is_initialized())?;
+    Ok(())
+}
+
+/// Fills `buffer` with the output of the CSPRNG. This does not wait for the CSPRNG to be
+/// initialized (see [`wait_for_entropy`]).
+pub fn get_random_bytes(buffer: &mut [u8]) {
+    {
+        let mut pool = POOL.lock_irq();
+
+        if !is_initialized() || pool.entropy >= SEED_BITS {
+            reseed(&mut pool);
+        }
+    }
+
+    // Generate the output outside of the lock, so large reads do not keep interrupts
+    // disabled.
+    let mut rng = CRNG.lock_irq().fork();
+    rng.fill(buffer);
+}
+
+/// Seeds the entropy pool with the hardware random number generator and the jitter of the
+/// timestamp counter.
+pub fn init() {
+    let mut pool = POOL.lock_irq();
+
+    add_hwrng_randomness(&mut pool);
+
+    let mut last = read_cycles();
+
+    for i in 0..JITTER_SAMPLES {
+        // Stirring the pool takes a slightly different amount of time depending on the
+        // state of the caches and the pipeline.
+        let now = read_cycles();
+        pool.mix(now.wrapping_sub(last), (i % 8 == 0) as usize);
+        last = now;
+    }
+
+    reseed(&mut pool);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chacha20_block_test() {
+        // RFC 7539 section 2.3.2
+        let input = [
+            0x61707865, 0x3320646e, 0x79622d32, 0x6b206574, 0x03020100, 0x07060504, 0x0b0a0908,
+            0x0f0e0d0c, 0x13121110, 0x17161514, 0x1b1a1918, 0x1f1e1d1c, 0x00000001, 0x09000000,
+            0x4a000000, 0x00000000,
+        ];
+
+        let expected = [
+            0xe4e7f110, 0x15593bd1, 0x1fdd0f50, 0xc47120a3, 0xc7f4d1c7, 0x0368c033, 0x9aaa2204,
+            0x4e6cd4c3, 0x466482d2, 0x09aa9f07, 0x05d7c214, 0xa2028bd9, 0xd19c12b5, 0xb94e16de,
+            0xe883d0cb, 0x4e3c50a2,
+        ];
+
+        assert_eq!(chacha20_block(&input), expected);
+    }
+}
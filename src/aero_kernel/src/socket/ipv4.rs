@@ -14,42 +14,204 @@
 //
 // You should have received a copy of the GNU General Public License
 // along with Aero. If not, see <https://www.gnu.org/licenses/>.
-
 //! IPv4 or `AF_RAW` sockets.
+//!
+//! Raw ICMP sockets (`socket(AF_INET, SOCK_RAW, IPPROTO_ICMP)`) receive a copy of every
+//! incoming ICMP datagram, including its IP header. The data written to them is sent as
+//! the payload of an IPv4 datagram, so userland only builds the ICMP message.
 
+use aero_syscall::socket::{MessageFlags, MessageHeader, SOL_SOCKET};
+use aero_syscall::{InAddr, OpenFlags, SocketAddrInet, SocketType, AF_INET};
+use alloc::collections::VecDeque;
 use alloc::sync::Arc;
+use alloc::vec::Vec;
+use spin::Once;
 
-use aero_syscall::prelude::{IfReq, SIOCGIFINDEX};
-
-use crate::arch::user_copy::UserRef;
+use crabnet::network::Ipv4Addr;
 
-use crate::fs::inode::INodeInterface;
-use crate::fs::Result;
+use crate::fs::cache::DirCacheItem;
+use crate::fs::file_table::FileHandle;
+use crate::fs::inode::{FileType, INodeInterface, Metadata, PollFlags, PollTable};
+use crate::fs::{FileSystemError, Result};
+use crate::net;
+use crate::net::icmp::{self, IcmpHandler};
+use crate::utils::sync::{Mutex, WaitQueue};
 
-use crate::mem::paging::VirtAddr;
+use super::SocketOptions;
 
-pub struct Ipv4Socket {}
+pub struct Ipv4Socket {
+    /// The IPv4 protocol number of the socket, if it is a raw socket.
+    protocol: Option<u8>,
+    /// Datagrams that have not been received yet. Their total size is bounded by the
+    /// receive buffer size (`SO_RCVBUF`).
+    incoming: Mutex<VecDeque<Vec<u8>>>,
+    options: Mutex<SocketOptions>,
+    wq: WaitQueue,
+    handle: Once<Arc<FileHandle>>,
+}
 
 impl Ipv4Socket {
     pub fn new() -> Arc<Self> {
-        Arc::new(Self {})
+        Arc::new(Self {
+            protocol: None,
+            incoming: Mutex::new(VecDeque::new()),
+            options: Mutex::new(SocketOptions::new(AF_INET, SocketType::Dgram)),
+            wq: WaitQueue::new(),
+            handle: Once::new(),
+        })
+    }
+
+    /// Creates a raw ICMP socket.
+    pub fn new_icmp() -> Arc<Self> {
+        let socket = Arc::new(Self {
+            protocol: Some(icmp::IPV4_PROTOCOL_ICMP),
+            incoming: Mutex::new(VecDeque::new()),
+            options: Mutex::new(SocketOptions::new(AF_INET, SocketType::Raw)),
+            wq: WaitQueue::new(),
+            handle: Once::new(),
+        });
+
+        icmp::register(socket.clone());
+        socket
+    }
+
+    fn is_non_block(&self) -> bool {
+        self.handle
+            .get()
+            .map(|handle| handle.flags.read().contains(OpenFlags::O_NONBLOCK))
+            .unwrap_or(false)
     }
 }
 
 impl INodeInterface for Ipv4Socket {
+    fn open(&self, _flags: OpenFlags, handle: Arc<FileHandle>) -> Result<Option<DirCacheItem>> {
+        self.handle.call_once(|| handle);
+        Ok(None)
+    }
+
+    fn metadata(&self) -> Result<Metadata> {
+        Ok(Metadata {
+            id: 0,
+            file_type: FileType::Socket,
+            size: 0,
+            children_len: 0,
+        })
+    }
+
+    fn send(&self, message_hdr: &mut MessageHeader, _flags: MessageFlags) -> Result<usize> {
+        let protocol = self.protocol.ok_or(FileSystemError::NotSupported)?;
+
+        let dest_ip = message_hdr
+            .name_mut::<SocketAddrInet>()
+            .map(|name| Ipv4Addr::from(name.addr()))
+            .ok_or(FileSystemError::NotConnected)?;
+
+        let data = message_hdr
+            .iovecs()
+            .iter()
+            .flat_map(|e| e.as_slice())
+            .copied()
+            .collect::<Vec<_>>();
+
+        if !net::send_ipv4(dest_ip, protocol, &data) {
+            return Err(FileSystemError::NetworkUnreachable);
+        }
+
+        Ok(data.len())
+    }
+
+    fn recv(&self, message_hdr: &mut MessageHeader, _flags: MessageFlags) -> Result<usize> {
+        if self.protocol.is_none() {
+            return Err(FileSystemError::NotSupported);
+        }
+
+        if self.incoming.lock_irq().is_empty() && self.is_non_block() {
+            return Err(FileSystemError::WouldBlock);
+        }
+
+        let timeout = self.options.lock_irq().recv_timeout;
+        let mut incoming = super::timeout_result(self.wq.block_on_timeout(
+            &self.incoming,
+            timeout,
+            |e| !e.is_empty(),
+        ))?;
+        let mut data = incoming.pop_front().expect("recv: someone was greedy");
+        drop(incoming);
+
+        if let Some(name) = message_hdr.name_mut::<SocketAddrInet>() {
+            *name = SocketAddrInet {
+                family: AF_INET,
+                port: 0.into(),
+                sin_addr: InAddr {
+                    addr: u32::from_le_bytes([data[12], data[13], data[14], data[15]]),
+                },
+                padding: [0; 8],
+            };
+        }
+
+        // Raw sockets are message based, so the rest of the datagram is discarded if it
+        // does not fit in the provided buffers.
+        Ok(message_hdr
+            .iovecs_mut()
+            .iter_mut()
+            .map(|iovec| {
+                let iovec = iovec.as_slice_mut();
+                let size = core::cmp::min(iovec.len(), data.len());
+                iovec[..size].copy_from_slice(&data.drain(..size).collect::<Vec<_>>());
+                size
+            })
+            .sum::<usize>())
+    }
+
     fn ioctl(&self, command: usize, arg: usize) -> Result<usize> {
-        match command {
-            SIOCGIFINDEX => {
-                let mut ifreq = unsafe { UserRef::<IfReq>::new(VirtAddr::new(arg as _)) };
+        super::inet_ioctl(command, arg)
+    }
+
+    fn set_sockopt(&self, level: usize, name: usize, value: &[u8]) -> Result<()> {
+        match level {
+            SOL_SOCKET => self.options.lock_irq().set(name, value),
+            _ => Err(FileSystemError::NoProtocolOption),
+        }
+    }
+
+    fn get_sockopt(&self, level: usize, name: usize, value: &mut [u8]) -> Result<usize> {
+        match level {
+            SOL_SOCKET => self.options.lock_irq().get(name, value),
+            _ => Err(FileSystemError::NoProtocolOption),
+        }
+    }
 
-                let name = ifreq.name().unwrap();
-                assert!(name == "eth0");
+    fn poll(&self, table: Option<&mut PollTable>) -> Result<PollFlags> {
+        if let Some(table) = table {
+            table.insert(&self.wq);
+        }
 
-                ifreq.data.ifindex = 1; // FIXME: Fill the actual interface index
-                Ok(0)
-            }
+        let mut flags = PollFlags::OUT;
 
-            _ => unimplemented!(),
+        if !self.incoming.lock_irq().is_empty() {
+            flags |= PollFlags::IN;
         }
+
+        Ok(flags)
+    }
+}
+
+impl IcmpHandler for Ipv4Socket {
+    fn recv(&self, datagram: &[u8]) {
+        let recv_buffer_size = self.options.lock_irq().recv_buffer_size;
+        let mut incoming = self.incoming.lock_irq();
+
+        // Drop the datagram if the receive buffer is full.
+        let queued = incoming.iter().map(|e| e.len()).sum::<usize>();
+
+        if queued + datagram.len() > recv_buffer_size {
+            log::warn!("icmp: receive buffer full, dropping datagram");
+            return;
+        }
+
+        incoming.push_back(datagram.to_vec());
+        drop(incoming);
+
+        self.wq.notify_all();
     }
 }
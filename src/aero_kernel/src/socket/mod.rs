@@ -21,10 +21,23 @@ pub mod tcp;
 pub mod udp;
 pub mod unix;
 
-use aero_syscall::prelude::IfReq;
+use core::ffi::c_int;
+use core::time::Duration;
+
+use aero_syscall::prelude::*;
+use aero_syscall::socket::*;
 use aero_syscall::*;
+use alloc::sync::Arc;
+use crabnet::data_link::MacAddr;
+use crabnet::network::Ipv4Addr;
 
+use crate::arch::user_copy::UserRef;
+use crate::fs::{self, FileSystemError};
 use crate::mem::paging::VirtAddr;
+use crate::net::route::{self, Route};
+use crate::net::{self, NetworkDevice};
+use crate::userland::signals::SignalResult;
+use crate::userland::task::credentials::Credentials;
 
 #[derive(Debug)]
 pub enum SocketAddr {
@@ -72,3 +85,505 @@ impl<'a> SocketAddrRef<'a> {
         }
     }
 }
+
+/// Default size of the send and receive buffers.
+const DEFAULT_BUFFER_SIZE: usize = 212992;
+const MIN_BUFFER_SIZE: usize = 2048;
+const MAX_BUFFER_SIZE: usize = 4 * 1024 * 1024;
+
+/// Reads the value of a socket option.
+fn option_value<T: Copy>(value: &[u8]) -> fs::Result<T> {
+    if value.len() < core::mem::size_of::<T>() {
+        return Err(FileSystemError::InvalidArgument);
+    }
+
+    // SAFETY: The size of the buffer was checked above.
+    Ok(unsafe { value.as_ptr().cast::<T>().read_unaligned() })
+}
+
+/// Writes the value of a socket option to `buffer` and returns its size.
+fn set_option_value<T>(buffer: &mut [u8], value: T) -> fs::Result<usize> {
+    let size = core::mem::size_of::<T>();
+
+    if buffer.len() < size {
+        return Err(FileSystemError::InvalidArgument);
+    }
+
+    // SAFETY: The size of the buffer was checked above.
+    unsafe { buffer.as_mut_ptr().cast::<T>().write_unaligned(value) };
+    Ok(size)
+}
+
+fn timeout_from_timeval(value: &[u8]) -> fs::Result<Option<Duration>> {
+    let TimeVal { tv_sec, tv_usec } = option_value::<TimeVal>(value)?;
+
+    if tv_sec < 0 || !(0..1_000_000).contains(&tv_usec) {
+        return Err(FileSystemError::InvalidArgument);
+    }
+
+    // A zero timeout means that the operation never times out.
+    if tv_sec == 0 && tv_usec == 0 {
+        return Ok(None);
+    }
+
+    Ok(Some(
+        Duration::from_secs(tv_sec as u64) + Duration::from_micros(tv_usec as u64),
+    ))
+}
+
+fn timeval_from_timeout(timeout: Option<Duration>) -> TimeVal {
+    let timeout = timeout.unwrap_or_default();
+
+    TimeVal {
+        tv_sec: timeout.as_secs() as i64,
+        tv_usec: timeout.subsec_micros() as i64,
+    }
+}
+
+/// Converts the result of [`WaitQueue::block_on_timeout`] into the result of a blocking
+/// socket operation, which fails with `EAGAIN` if it timed out.
+///
+/// [`WaitQueue::block_on_timeout`]: crate::utils::sync::WaitQueue::block_on_timeout
+fn timeout_result<T>(result: SignalResult<Option<T>>) -> fs::Result<T> {
+    result?.ok_or(FileSystemError::WouldBlock)
+}
+
+/// Socket-level (`SOL_SOCKET`) options that are common to all of the socket types.
+#[derive(Clone)]
+pub struct SocketOptions {
+    domain: u32,
+    typ: SocketType,
+
+    pub reuse_addr: bool,
+    pub keep_alive: bool,
+    pub broadcast: bool,
+    /// Whether the credentials of the sender are received with each message
+    /// (`SCM_CREDENTIALS`).
+    pub pass_credentials: bool,
+
+    /// Timeout of the blocking receive operations.
+    pub recv_timeout: Option<Duration>,
+    /// Timeout of the blocking send operations.
+    pub send_timeout: Option<Duration>,
+
+    pub recv_buffer_size: usize,
+    pub send_buffer_size: usize,
+}
+
+impl SocketOptions {
+    pub fn new(domain: u32, typ: SocketType) -> Self {
+        Self {
+            domain,
+            typ,
+
+            reuse_addr: false,
+            keep_alive: false,
+            broadcast: false,
+            pass_credentials: false,
+
+            recv_timeout: None,
+            send_timeout: None,
+
+            recv_buffer_size: DEFAULT_BUFFER_SIZE,
+            send_buffer_size: DEFAULT_BUFFER_SIZE,
+        }
+    }
+
+    pub fn set(&mut self, name: usize, value: &[u8]) -> fs::Result<()> {
+        let buffer_size = |value: &[u8]| -> fs::Result<usize> {
+            let size = option_value::<c_int>(value)?;
+            Ok((size.max(0) as usize).clamp(MIN_BUFFER_SIZE, MAX_BUFFER_SIZE))
+        };
+
+        match name {
+            SO_REUSEADDR => self.reuse_addr = option_value::<c_int>(value)? != 0,
+            SO_KEEPALIVE => self.keep_alive = option_value::<c_int>(value)? != 0,
+            SO_BROADCAST => self.broadcast = option_value::<c_int>(value)? != 0,
+            SO_PASSCRED => self.pass_credentials = option_value::<c_int>(value)? != 0,
+
+            SO_RCVTIMEO => self.recv_timeout = timeout_from_timeval(value)?,
+            SO_SNDTIMEO => self.send_timeout = timeout_from_timeval(value)?,
+
+            SO_RCVBUF => self.recv_buffer_size = buffer_size(value)?,
+            SO_SNDBUF => self.send_buffer_size = buffer_size(value)?,
+
+            SO_RCVBUFFORCE | SO_SNDBUFFORCE => {
+                if !Credentials::current().is_superuser() {
+                    return Err(FileSystemError::PermissionDenied);
+                }
+
+                let size = buffer_size(value)?;
+
+                if name == SO_RCVBUFFORCE {
+                    self.recv_buffer_size = size;
+                } else {
+                    self.send_buffer_size = size;
+                }
+            }
+
+            _ => {
+                log::warn!("socket: unsupported option (level=SOL_SOCKET, name={name})");
+                return Err(FileSystemError::NoProtocolOption);
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn get(&self, name: usize, buffer: &mut [u8]) -> fs::Result<usize> {
+        match name {
+            SO_REUSEADDR => set_option_value(buffer, self.reuse_addr as c_int),
+            SO_KEEPALIVE => set_option_value(buffer, self.keep_alive as c_int),
+            SO_BROADCAST => set_option_value(buffer, self.broadcast as c_int),
+            SO_PASSCRED => set_option_value(buffer, self.pass_credentials as c_int),
+
+            SO_RCVTIMEO => set_option_value(buffer, timeval_from_timeout(self.recv_timeout)),
+            SO_SNDTIMEO => set_option_value(buffer, timeval_from_timeout(self.send_timeout)),
+
+            SO_RCVBUF => set_option_value(buffer, self.recv_buffer_size as c_int),
+            SO_SNDBUF => set_option_value(buffer, self.send_buffer_size as c_int),
+
+            SO_TYPE => set_option_value(buffer, self.typ as c_int),
+            SO_DOMAIN => set_option_value(buffer, self.domain as c_int),
+            // Errors are reported synchronously, so there is never a pending error.
+            SO_ERROR => set_option_value(buffer, 0 as c_int),
+
+            _ => {
+                log::warn!("socket: unsupported option (level=SOL_SOCKET, name={name})");
+                Err(FileSystemError::NoProtocolOption)
+            }
+        }
+    }
+}
+
+/// IPv4-level (`SOL_IP`) options of the inet sockets.
+#[derive(Default, Clone)]
+pub struct InetOptions {
+    /// The time-to-live of the outgoing datagrams, [`None`] to use the default.
+    pub ttl: Option<u8>,
+    pub tos: u8,
+}
+
+impl InetOptions {
+    /// Applies the options to the outgoing ethernet frame `packet`.
+    pub fn apply(&self, packet: &mut [u8]) {
+        if self.ttl.is_some() || self.tos != 0 {
+            net::set_ipv4_options(packet, self.ttl.unwrap_or(net::DEFAULT_TTL), self.tos);
+        }
+    }
+
+    pub fn set(&mut self, name: usize, value: &[u8]) -> fs::Result<()> {
+        match name {
+            IP_TTL => {
+                self.ttl = match option_value::<c_int>(value)? {
+                    -1 => None,
+                    ttl @ 1..=255 => Some(ttl as u8),
+                    _ => return Err(FileSystemError::InvalidArgument),
+                }
+            }
+
+            IP_TOS => self.tos = option_value::<c_int>(value)? as u8,
+
+            _ => {
+                log::warn!("socket: unsupported option (level=SOL_IP, name={name})");
+                return Err(FileSystemError::NoProtocolOption);
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn get(&self, name: usize, buffer: &mut [u8]) -> fs::Result<usize> {
+        match name {
+            IP_TTL => set_option_value(buffer, self.ttl.unwrap_or(net::DEFAULT_TTL) as c_int),
+            IP_TOS => set_option_value(buffer, self.tos as c_int),
+
+            _ => {
+                log::warn!("socket: unsupported option (level=SOL_IP, name={name})");
+                Err(FileSystemError::NoProtocolOption)
+            }
+        }
+    }
+}
+
+fn ifreq_device(ifreq: &IfReq) -> fs::Result<Arc<NetworkDevice>> {
+    let name = ifreq.name().ok_or(FileSystemError::InvalidPath)?;
+    net::device_by_name(name).ok_or(FileSystemError::NoDevice)
+}
+
+fn ifreq_inet(ifreq: &IfReq) -> fs::Result<Ipv4Addr> {
+    let address = SocketAddrRef::from_ifreq(ifreq)
+        .map_err(|_| FileSystemError::NotSupported)?
+        .as_inet()
+        .ok_or(FileSystemError::NotSupported)?;
+
+    Ok(Ipv4Addr::from(address.addr()))
+}
+
+const_assert_eq!(
+    core::mem::size_of::<SocketAddrInet>(),
+    core::mem::size_of::<SockAddrStorage>()
+);
+
+fn ifreq_set_inet(ifreq: &mut IfReq, ip: Ipv4Addr) {
+    let address = SocketAddrInet {
+        family: AF_INET,
+        port: 0.into(),
+        sin_addr: InAddr {
+            addr: u32::from_le_bytes(ip.0),
+        },
+        padding: [0; 8],
+    };
+
+    // SAFETY: The size of `SocketAddrInet` is equal to the size of `SockAddrStorage`.
+    unsafe {
+        let ptr = core::ptr::addr_of_mut!(ifreq.data.addr).cast::<SocketAddrInet>();
+        ptr.write_unaligned(address);
+    }
+}
+
+fn sockaddr_inet(address: &SockAddrStorage) -> fs::Result<Ipv4Addr> {
+    if address.sa_family != AF_INET {
+        return Err(FileSystemError::NotSupported);
+    }
+
+    // SAFETY: The size of `SocketAddrInet` is equal to the size of `SockAddrStorage`.
+    let address = unsafe {
+        (address as *const SockAddrStorage)
+            .cast::<SocketAddrInet>()
+            .read_unaligned()
+    };
+
+    Ok(Ipv4Addr::from(address.addr()))
+}
+
+/// A routing table entry passed to the `SIOCADDRT` and `SIOCDELRT` ioctls.
+struct RouteRequest {
+    dest: Ipv4Addr,
+    mask: Ipv4Addr,
+    gateway: Option<Ipv4Addr>,
+    device: Option<Arc<NetworkDevice>>,
+    metric: Option<u32>,
+}
+
+fn read_route_request(arg: usize) -> fs::Result<RouteRequest> {
+    let rtentry = unsafe { UserRef::<RtEntry>::new(VirtAddr::new(arg as _)) };
+    let flags = RouteFlags::from_bits_truncate(rtentry.flags);
+
+    let dest = sockaddr_inet(&rtentry.dst)?;
+
+    let mask = if flags.contains(RouteFlags::HOST) {
+        Ipv4Addr::BROADCAST
+    } else {
+        sockaddr_inet(&rtentry.genmask)?
+    };
+
+    let gateway = if flags.contains(RouteFlags::GATEWAY) {
+        Some(sockaddr_inet(&rtentry.gateway)?)
+    } else {
+        None
+    };
+
+    let device = if rtentry.dev.is_null() {
+        None
+    } else {
+        let name = VirtAddr::new(rtentry.dev as u64)
+            .read_mut::<[u8; IF_NAME_SIZE]>()
+            .map_err(|_| FileSystemError::InvalidPath)?;
+
+        let length = name.iter().position(|&x| x == 0).unwrap_or(IF_NAME_SIZE);
+        let name =
+            core::str::from_utf8(&name[..length]).map_err(|_| FileSystemError::InvalidPath)?;
+
+        Some(net::device_by_name(name).ok_or(FileSystemError::NoDevice)?)
+    };
+
+    // The metric is offset by one, zero selects the default metric.
+    let metric = (rtentry.metric > 0).then(|| rtentry.metric as u32 - 1);
+
+    Ok(RouteRequest {
+        dest,
+        mask,
+        gateway,
+        device,
+        metric,
+    })
+}
+
+/// Network interface configuration ioctls, shared by all of the inet sockets.
+fn inet_ioctl(command: usize, arg: usize) -> fs::Result<usize> {
+    let is_superuser = || {
+        if Credentials::current().is_superuser() {
+            Ok(())
+        } else {
+            Err(FileSystemError::PermissionDenied)
+        }
+    };
+
+    match command {
+        SIOCGIFCONF => {
+            let mut ifconf = unsafe { UserRef::<IfConf>::new(VirtAddr::new(arg as _)) };
+            let devices = net::devices();
+
+            let ifreq_size = core::mem::size_of::<IfReq>();
+
+            if ifconf.buffer.is_null() {
+                ifconf.len = (devices.len() * ifreq_size) as _;
+                return Ok(0);
+            }
+
+            let count = core::cmp::min(ifconf.len as usize / ifreq_size, devices.len());
+            let buffer = VirtAddr::new(ifconf.buffer as u64);
+
+            for (i, device) in devices.iter().take(count).enumerate() {
+                let address = buffer + i * ifreq_size;
+                let mut ifreq = unsafe { UserRef::<IfReq>::new(address) };
+
+                ifreq.set_name(&device.name());
+                ifreq_set_inet(&mut ifreq, device.ip());
+            }
+
+            ifconf.len = (count * ifreq_size) as _;
+            Ok(0)
+        }
+
+        SIOCGIFNAME => {
+            let mut ifreq = unsafe { UserRef::<IfReq>::new(VirtAddr::new(arg as _)) };
+
+            let index = unsafe { ifreq.data.ifindex } as usize;
+            let device = net::device_by_index(index).ok_or(FileSystemError::NoDevice)?;
+
+            ifreq.set_name(&device.name());
+            Ok(0)
+        }
+
+        SIOCGIFINDEX => {
+            let mut ifreq = unsafe { UserRef::<IfReq>::new(VirtAddr::new(arg as _)) };
+            let device = ifreq_device(&ifreq)?;
+
+            ifreq.data.ifindex = device.index() as _;
+            Ok(0)
+        }
+
+        SIOCGIFFLAGS => {
+            let mut ifreq = unsafe { UserRef::<IfReq>::new(VirtAddr::new(arg as _)) };
+            let device = ifreq_device(&ifreq)?;
+
+            ifreq.data.flags = device.flags().bits() as _;
+            Ok(0)
+        }
+
+        SIOCSIFFLAGS => {
+            is_superuser()?;
+
+            let ifreq = unsafe { UserRef::<IfReq>::new(VirtAddr::new(arg as _)) };
+            let device = ifreq_device(&ifreq)?;
+
+            let flags = InterfaceFlags::from_bits_truncate(unsafe { ifreq.data.flags } as u16);
+            device.set_flags(flags);
+            Ok(0)
+        }
+
+        SIOCGIFHWADDR => {
+            let mut ifreq = unsafe { UserRef::<IfReq>::new(VirtAddr::new(arg as _)) };
+            let device = ifreq_device(&ifreq)?;
+
+            let hwaddr = unsafe { &mut ifreq.data.addr.sa_data[..MacAddr::ADDR_SIZE] };
+            hwaddr.copy_from_slice(device.mac().0.as_slice());
+            Ok(0)
+        }
+
+        SIOCGIFADDR => {
+            let mut ifreq = unsafe { UserRef::<IfReq>::new(VirtAddr::new(arg as _)) };
+            let device = ifreq_device(&ifreq)?;
+
+            ifreq_set_inet(&mut ifreq, device.ip());
+            Ok(0)
+        }
+
+        SIOCSIFADDR => {
+            is_superuser()?;
+
+            let ifreq = unsafe { UserRef::<IfReq>::new(VirtAddr::new(arg as _)) };
+            let device = ifreq_device(&ifreq)?;
+
+            device.set_ip(ifreq_inet(&ifreq)?);
+            route::update_interface(&device);
+            Ok(0)
+        }
+
+        SIOCGIFNETMASK => {
+            let mut ifreq = unsafe { UserRef::<IfReq>::new(VirtAddr::new(arg as _)) };
+            let device = ifreq_device(&ifreq)?;
+
+            ifreq_set_inet(&mut ifreq, device.subnet_mask());
+            Ok(0)
+        }
+
+        SIOCSIFNETMASK => {
+            is_superuser()?;
+
+            let ifreq = unsafe { UserRef::<IfReq>::new(VirtAddr::new(arg as _)) };
+            let device = ifreq_device(&ifreq)?;
+
+            device.set_subnet_mask(ifreq_inet(&ifreq)?);
+            route::update_interface(&device);
+            Ok(0)
+        }
+
+        SIOCADDRT => {
+            is_superuser()?;
+
+            let request = read_route_request(arg)?;
+
+            // Select the interface that the gateway (or the destination) is reachable
+            // through, if it was not specified.
+            let device = match request.device {
+                Some(device) => device,
+                None => {
+                    net::route(request.gateway.unwrap_or(request.dest))
+                        .ok_or(FileSystemError::NetworkUnreachable)?
+                        .0
+                }
+            };
+
+            let route = Route::new(
+                request.dest,
+                request.mask,
+                request.gateway,
+                device,
+                request.metric.unwrap_or(0),
+            );
+
+            if !route::add(route) {
+                return Err(FileSystemError::EntryExists);
+            }
+
+            Ok(0)
+        }
+
+        SIOCDELRT => {
+            is_superuser()?;
+
+            let request = read_route_request(arg)?;
+            let removed = route::remove(
+                request.dest,
+                request.mask,
+                request.gateway,
+                request.device.as_ref(),
+                request.metric,
+            );
+
+            if !removed {
+                return Err(FileSystemError::EntryNotFound);
+            }
+
+            Ok(0)
+        }
+
+        _ => {
+            log::warn!("inet: unknown ioctl command (`{command:#x}`)");
+            Err(FileSystemError::NotSupported)
+        }
+    }
+}
@@ -1,5 +1,9 @@
-use aero_syscall::socket::{MessageFlags, MessageHeader};
-use aero_syscall::{InAddr, OpenFlags, SocketAddrInet, AF_INET};
+use core::ffi::c_int;
+use core::sync::atomic::{AtomicUsize, Ordering};
+
+use aero_syscall::socket::*;
+use aero_syscall::{InAddr, OpenFlags, SocketAddrInet, SocketType, SyscallError, AF_INET};
+use alloc::collections::VecDeque;
 use alloc::sync::{Arc, Weak};
 use alloc::vec::Vec;
 
@@ -7,31 +11,44 @@ use crabnet::network::Ipv4Addr;
 use spin::Once;
 
 use crabnet::data_link::{Eth, EthType, MacAddr};
-use crabnet::transport::{Tcp, TcpOptions};
+use crabnet::transport::{Tcp, TcpFlags, TcpOptions};
+use crabnet::IntoBoxedBytes;
 use crabnet_tcp::{Address, Error as TcpError, Packet as TcpPacket, State};
 
+use crate::arch::user_copy::UserRef;
 use crate::fs::file_table::FileHandle;
 use crate::fs::inode::{FileType, INodeInterface, Metadata, PollFlags, PollTable};
 use crate::fs::{self, FileSystemError};
+use crate::mem::paging::VirtAddr;
 use crate::net;
-use crate::net::shim::PacketSend;
 use crate::net::{tcp, NetworkDevice};
+use crate::utils::dma::DmaAllocator;
 use crate::utils::sync::{Mutex, WaitQueue};
 
+use super::{option_value, set_option_value, InetOptions, SocketOptions};
+
 // ./aero.py -- -netdev user,id=mynet0 -device e1000,netdev=mynet0,id=ck_nic0 -object
 // filter-dump,id=mynet0,netdev=mynet0,file=qemulog.log
 
-struct DeviceShim(Arc<NetworkDevice>);
+struct DeviceShim {
+    device: Arc<NetworkDevice>,
+    inet_options: Arc<Mutex<InetOptions>>,
+}
 
 impl crabnet_tcp::NetworkDevice for DeviceShim {
     fn ip(&self) -> Ipv4Addr {
-        self.0.ip()
+        self.device.ip()
     }
 
     fn send(&self, packet: TcpPacket, _handle: crabnet_tcp::RetransmitHandle) {
         // TODO(andypython): Handle TCP retransmission here.
-        let eth = Eth::new(MacAddr::NULL, self.0.mac(), EthType::Ip);
-        (eth / packet.ip / packet.tcp / packet.options / packet.payload).send();
+        let dest_ip = packet.ip.dest_ip();
+        let eth = Eth::new(MacAddr::NULL, self.device.mac(), EthType::Ip);
+        let mut packet = (eth / packet.ip / packet.tcp / packet.options / packet.payload)
+            .into_boxed_bytes_in(DmaAllocator);
+
+        self.inet_options.lock_irq().apply(&mut packet);
+        net::transmit(dest_ip, packet);
     }
 
     fn remove_retransmit(&self, _seq_number: u32) {
@@ -39,27 +56,143 @@ impl crabnet_tcp::NetworkDevice for DeviceShim {
     }
 }
 
+/// The default maximum segment size.
+const DEFAULT_MSS: usize = 1460;
+
+/// TCP-level (`SOL_TCP`) options.
+#[derive(Clone)]
+struct TcpLevelOptions {
+    /// Whether the segments are sent as soon as possible. Nagle's algorithm is not
+    /// implemented, so this is always the case and the option is only stored.
+    no_delay: bool,
+    max_segment_size: usize,
+
+    /// The time (in seconds) that the connection needs to remain idle before keep-alive
+    /// probes are sent.
+    keep_idle: u32,
+    /// The time (in seconds) between the keep-alive probes.
+    keep_interval: u32,
+    /// The number of keep-alive probes sent before the connection is dropped.
+    keep_count: u32,
+}
+
+impl Default for TcpLevelOptions {
+    fn default() -> Self {
+        Self {
+            no_delay: false,
+            max_segment_size: DEFAULT_MSS,
+
+            keep_idle: 7200,
+            keep_interval: 75,
+            keep_count: 9,
+        }
+    }
+}
+
+impl TcpLevelOptions {
+    fn set(&mut self, name: usize, value: &[u8]) -> fs::Result<()> {
+        let value = option_value::<c_int>(value)?;
+
+        match (name, value) {
+            (TCP_NODELAY, _) => self.no_delay = value != 0,
+            (TCP_MAXSEG, 88..=32767) => {
+                self.max_segment_size = (value as usize).min(DEFAULT_MSS);
+            }
+
+            (TCP_KEEPIDLE, 1..=32767) => self.keep_idle = value as u32,
+            (TCP_KEEPINTVL, 1..=32767) => self.keep_interval = value as u32,
+            (TCP_KEEPCNT, 1..=127) => self.keep_count = value as u32,
+
+            (TCP_MAXSEG | TCP_KEEPIDLE | TCP_KEEPINTVL | TCP_KEEPCNT, _) => {
+                return Err(FileSystemError::InvalidArgument)
+            }
+
+            _ => {
+                log::warn!("tcp: unsupported option (level=SOL_TCP, name={name})");
+                return Err(FileSystemError::NoProtocolOption);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn get(&self, name: usize, buffer: &mut [u8]) -> fs::Result<usize> {
+        let value = match name {
+            TCP_NODELAY => self.no_delay as c_int,
+            TCP_MAXSEG => self.max_segment_size as c_int,
+            TCP_KEEPIDLE => self.keep_idle as c_int,
+            TCP_KEEPINTVL => self.keep_interval as c_int,
+            TCP_KEEPCNT => self.keep_count as c_int,
+
+            _ => {
+                log::warn!("tcp: unsupported option (level=SOL_TCP, name={name})");
+                return Err(FileSystemError::NoProtocolOption);
+            }
+        };
+
+        set_option_value(buffer, value)
+    }
+}
+
+/// State of a listening socket.
+struct Listener {
+    /// The local port that the socket is listening on.
+    port: u16,
+    /// The maximum number of pending connections.
+    backlog: usize,
+    /// Connections that have not been accepted yet, oldest first. The connections are
+    /// ready to be accepted once the three-way handshake is complete.
+    pending: VecDeque<Arc<TcpSocket>>,
+}
+
 pub struct TcpSocket {
     tcp: Mutex<Option<crabnet_tcp::Socket<DeviceShim>>>,
+    listener: Mutex<Option<Listener>>,
+    /// The send and receive buffers are owned by `crabnet_tcp`, so `SO_RCVBUF` has no
+    /// effect and always reads back as the default size. See [`TcpSocket::send`] for the
+    /// send buffer size.
+    options: Mutex<SocketOptions>,
+    /// Shared with the device of the connection, which applies them to the outgoing
+    /// segments.
+    inet_options: Arc<Mutex<InetOptions>>,
+    tcp_options: Mutex<TcpLevelOptions>,
     wq: WaitQueue,
     handle: Once<Arc<FileHandle>>,
     sref: Weak<TcpSocket>,
     peer: Once<SocketAddrInet>,
+    /// The local port that the socket has been bound to.
+    port: Once<u16>,
+    /// The listening socket that created this connection.
+    parent: Once<Weak<TcpSocket>>,
+    /// The number of file handles that refer to the socket.
+    refs: AtomicUsize,
 }
 
 impl TcpSocket {
     pub fn new() -> Arc<Self> {
         Arc::new_cyclic(|sref| Self {
             tcp: Mutex::new(None),
+            listener: Mutex::new(None),
+            options: Mutex::new(SocketOptions::new(AF_INET, SocketType::Stream)),
+            inet_options: Arc::new(Mutex::new(InetOptions::default())),
+            tcp_options: Mutex::new(TcpLevelOptions::default()),
             wq: WaitQueue::new(),
             sref: sref.clone(),
             handle: Once::new(),
             peer: Once::new(),
+            port: Once::new(),
+            parent: Once::new(),
+            refs: AtomicUsize::new(0),
         })
     }
 
-    pub fn on_packet(&self, tcp: &Tcp, options: TcpOptions, payload: &[u8]) {
-        if let Some(socket) = self.tcp.lock_irq().as_mut() {
+    pub fn on_packet(&self, src_ip: Ipv4Addr, tcp: &Tcp, options: TcpOptions, payload: &[u8]) {
+        if let Some(listener) = self.listener.lock_irq().as_mut() {
+            self.on_listen_packet(listener, src_ip, tcp, options, payload);
+            return;
+        }
+
+        let state = if let Some(socket) = self.tcp.lock_irq().as_mut() {
             // Ignore any invalid TCP options.
             let options = options
                 .iter()
@@ -67,14 +200,178 @@ impl TcpSocket {
                 .collect::<Vec<_>>();
 
             socket.on_packet(tcp, &options, payload);
-            self.wq.notify_all();
+            socket.state()
+        } else {
+            return;
+        };
+
+        self.wq.notify_all();
+
+        // The connection is over, so stop routing packets to it.
+        if matches!(state, State::Closed | State::TimeWait) {
+            self.release();
+        }
+
+        // Wake up the listening socket if the connection is ready to be accepted.
+        if let Some(parent) = self.parent.get().and_then(|parent| parent.upgrade()) {
+            if self.is_established() {
+                parent.wq.notify_all();
+            }
         }
     }
 
+    /// Handles a packet received by a listening socket (passive open). A new connection
+    /// is created for each SYN and queued in the backlog.
+    fn on_listen_packet(
+        &self,
+        listener: &mut Listener,
+        src_ip: Ipv4Addr,
+        tcp: &Tcp,
+        options: TcpOptions,
+        payload: &[u8],
+    ) {
+        let flags = tcp.flags();
+
+        if !flags.contains(TcpFlags::SYN) || flags.contains(TcpFlags::ACK) {
+            return;
+        }
+
+        if listener.pending.len() >= listener.backlog {
+            // Make room by dropping the oldest half-open connection, so that SYNs which are
+            // never acknowledged (e.g. spoofed ones) cannot fill up the backlog for good.
+            let Some(index) = listener.pending.iter().position(|c| !c.is_established()) else {
+                log::warn!("tcp: backlog full, dropping SYN from {src_ip:?}");
+                return;
+            };
+
+            let stale = listener.pending.remove(index).unwrap();
+            stale.release();
+        }
+
+        let Some((device, _)) = net::route(src_ip) else {
+            return;
+        };
+
+        let connection = TcpSocket::new();
+
+        // The connection inherits the options of the listening socket.
+        *connection.options.lock_irq() = self.options.lock_irq().clone();
+        *connection.inet_options.lock_irq() = self.inet_options.lock_irq().clone();
+        *connection.tcp_options.lock_irq() = self.tcp_options.lock_irq().clone();
+
+        let addr = Address::new(listener.port, tcp.src_port(), src_ip);
+        let device = connection.device_shim(device);
+
+        let options = options
+            .iter()
+            .filter_map(|option| option.ok())
+            .collect::<Vec<_>>();
+
+        let mut socket = crabnet_tcp::Socket::listen(device, addr);
+        socket.on_packet(tcp, &options, payload); // Reply with a SYN-ACK.
+
+        *connection.tcp.lock_irq() = Some(socket);
+
+        connection.peer.call_once(|| SocketAddrInet {
+            family: AF_INET,
+            port: tcp.src_port().into(),
+            sin_addr: InAddr {
+                addr: u32::from_le_bytes(src_ip.0),
+            },
+            padding: [0; 8],
+        });
+
+        connection.port.call_once(|| listener.port);
+        connection.parent.call_once(|| self.sref.clone());
+
+        net::tcp::add_connection(listener.port, src_ip, tcp.src_port(), connection.clone());
+        listener.pending.push_back(connection);
+    }
+
     fn sref(&self) -> Arc<TcpSocket> {
         self.sref.upgrade().unwrap()
     }
 
+    /// Removes the socket from the port and connection tables of the TCP layer, which hold
+    /// a reference to the socket. The pending connections of a listening socket are
+    /// released as well.
+    fn release(&self) {
+        let Some(&port) = self.port.get() else {
+            return;
+        };
+
+        if let (Some(_), Some(peer)) = (self.parent.get(), self.peer.get()) {
+            tcp::remove_connection(port, Ipv4Addr::from(peer.addr()), peer.port());
+        } else {
+            tcp::unbind(port, self);
+        }
+
+        if let Some(listener) = self.listener.lock_irq().take() {
+            for connection in listener.pending {
+                connection.close_connection();
+            }
+        }
+    }
+
+    /// Closes the connection, which sends a FIN to the peer. The socket stays registered
+    /// with the TCP layer until the connection is over, so that the closing handshake can
+    /// complete, and is released straight away if there is no connection.
+    fn close_connection(&self) {
+        let state = self.tcp.lock_irq().as_mut().map(|socket| {
+            if !matches!(socket.state(), State::Closed | State::TimeWait) {
+                socket.close();
+            }
+
+            socket.state()
+        });
+
+        self.wq.notify_all();
+
+        if state.map_or(true, |state| {
+            matches!(state, State::Closed | State::TimeWait)
+        }) {
+            self.release();
+        }
+    }
+
+    fn device_shim(&self, device: Arc<NetworkDevice>) -> Arc<DeviceShim> {
+        Arc::new(DeviceShim {
+            device,
+            inet_options: self.inet_options.clone(),
+        })
+    }
+
+    /// Returns whether the socket is listening for connections.
+    pub fn is_listening(&self) -> bool {
+        self.listener.lock_irq().is_some()
+    }
+
+    /// Returns whether the connection of the socket is over (ie. closed or in
+    /// `TIME_WAIT`). Listening and unconnected sockets are not closed.
+    pub fn is_closed(&self) -> bool {
+        self.tcp.lock_irq().as_ref().map_or(false, |socket| {
+            matches!(socket.state(), State::Closed | State::TimeWait)
+        })
+    }
+
+    fn is_established(&self) -> bool {
+        self.tcp
+            .lock_irq()
+            .as_ref()
+            .map(|socket| socket.state() == State::Established)
+            .unwrap_or(false)
+    }
+
+    /// Binds the socket to an ephemeral port, if it was not bound already.
+    fn bind_ephemeral(&self) -> fs::Result<u16> {
+        if let Some(port) = self.port.get() {
+            return Ok(*port);
+        }
+
+        let port = tcp::alloc_ephemeral_port(self.sref()).ok_or(FileSystemError::AddressInUse)?;
+        Ok(*self.port.call_once(|| port))
+    }
+
     /// Returns whether the socket is in non-blocking mode.
     pub fn non_blocking(&self) -> bool {
         self.handle
@@ -94,11 +391,13 @@ impl TcpSocket {
             Err(TcpError::WouldBlock) => {
                 drop(tcp);
 
-                let mut socket = self.wq.block_on(&self.tcp, |tcp| {
-                    tcp.as_ref()
-                        .map(|socket| !socket.recv_queue.is_empty())
-                        .unwrap_or(true)
-                })?;
+                let timeout = self.options.lock_irq().recv_timeout;
+                let mut socket =
+                    super::timeout_result(self.wq.block_on_timeout(&self.tcp, timeout, |tcp| {
+                        tcp.as_ref()
+                            .map(|socket| !socket.recv_queue.is_empty())
+                            .unwrap_or(true)
+                    }))?;
 
                 if let Some(socket) = socket.as_mut() {
                     Ok(socket.recv(buf).unwrap())
@@ -111,41 +410,153 @@ impl TcpSocket {
         }
     }
 
+    /// Queues `buf` to be sent and returns the number of bytes queued. `crabnet_tcp` takes
+    /// all of the data without blocking, so a single call queues at most `SO_SNDBUF` bytes
+    /// and the rest is left to the caller (ie. a short write). As the call never blocks,
+    /// `SO_SNDTIMEO` only applies to connect(2).
     pub fn send(&self, buf: &[u8]) -> Result<usize, FileSystemError> {
+        let send_buffer_size = self.options.lock_irq().send_buffer_size;
+        let max_segment_size = self.tcp_options.lock_irq().max_segment_size;
+
         let mut tcp = self.tcp.lock_irq();
         let socket = tcp.as_mut().ok_or(FileSystemError::NotConnected)?;
 
-        let bytes_written = socket.send(buf).unwrap();
-        Ok(bytes_written)
+        let buf = &buf[..core::cmp::min(buf.len(), send_buffer_size)];
+
+        // TODO: handle fragmentation in crabnet_tcp
+        for chunk in buf.chunks(max_segment_size) {
+            socket.send(chunk).expect("tcp: failed to send data");
+        }
+
+        Ok(buf.len())
     }
 }
 
 impl INodeInterface for TcpSocket {
+    fn bind(&self, address: super::SocketAddrRef, _length: usize) -> fs::Result<()> {
+        let address = address.as_inet().ok_or(FileSystemError::NotSupported)?;
+
+        if self.port.get().is_some() {
+            return Err(FileSystemError::InvalidPath);
+        }
+
+        let reuse_addr = self.options.lock_irq().reuse_addr;
+
+        let port = match address.port() {
+            0 => tcp::alloc_ephemeral_port(self.sref()).ok_or(FileSystemError::AddressInUse)?,
+            port if tcp::bind(port, self.sref(), reuse_addr) => port,
+            _ => return Err(FileSystemError::AddressInUse),
+        };
+
+        self.port.call_once(|| port);
+        Ok(())
+    }
+
+    fn listen(&self, backlog: usize) -> Result<(), SyscallError> {
+        if self.tcp.lock_irq().is_some() {
+            return Err(SyscallError::EINVAL);
+        }
+
+        let port = self.bind_ephemeral()?;
+        let mut listener = self.listener.lock_irq();
+
+        if let Some(listener) = listener.as_mut() {
+            // Calling listen(2) again only updates the backlog.
+            listener.backlog = backlog.max(1);
+        } else {
+            *listener = Some(Listener {
+                port,
+                backlog: backlog.max(1),
+                pending: VecDeque::new(),
+            });
+        }
+
+        Ok(())
+    }
+
+    fn accept(&self, address: Option<(VirtAddr, &mut u32)>) -> fs::Result<Arc<dyn INodeInterface>> {
+        let is_ready = |listener: &Option<Listener>| {
+            listener
+                .as_ref()
+                .map(|listener| listener.pending.iter().any(|c| c.is_established()))
+                .unwrap_or(true)
+        };
+
+        if !is_ready(&self.listener.lock_irq()) && self.non_blocking() {
+            return Err(FileSystemError::WouldBlock);
+        }
+
+        let timeout = self.options.lock_irq().recv_timeout;
+        let mut listener = super::timeout_result(self.wq.block_on_timeout(
+            &self.listener,
+            timeout,
+            |l| is_ready(l),
+        ))?;
+        let listener = listener.as_mut().ok_or(FileSystemError::InvalidPath)?;
+
+        let index = listener
+            .pending
+            .iter()
+            .position(|connection| connection.is_established())
+            .expect("TcpSocket::accept(): no established connections");
+
+        let connection = listener.pending.remove(index).unwrap();
+
+        if let Some((address, length)) = address {
+            let mut address = unsafe { UserRef::<SocketAddrInet>::new(address) };
+
+            *address = connection.peer.get().unwrap().clone();
+            *length = core::mem::size_of::<SocketAddrInet>() as u32;
+        }
+
+        Ok(connection)
+    }
+
     fn connect(&self, address: super::SocketAddrRef, _length: usize) -> crate::fs::Result<()> {
         {
             let mut tcp = self.tcp.lock_irq();
-            assert!(tcp.is_none(), "connect: socket is already initialized");
 
-            let port = tcp::alloc_ephemeral_port(self.sref()).unwrap();
+            match tcp.as_ref().map(|socket| socket.state()) {
+                None => {}
+                Some(State::SynSent) => return Err(FileSystemError::AlreadyInProgress),
+                // The handshake of a previous attempt failed after it timed out.
+                Some(State::Closed) => *tcp = None,
+                Some(_) => return Err(FileSystemError::IsConnected),
+            }
 
             let addr = address.as_inet().ok_or(FileSystemError::NotSupported)?;
-            self.peer.call_once(|| addr.clone());
+            let dest_ip = Ipv4Addr::from(addr.addr());
 
-            if addr.addr() == Ipv4Addr::LOOPBACK.0 {
-                return Err(FileSystemError::NotSupported);
-            }
+            let (device, _) = net::route(dest_ip).ok_or(FileSystemError::NetworkUnreachable)?;
 
-            let addr = Address::new(port, addr.port(), addr.addr().into());
+            let port = self.bind_ephemeral()?;
+            self.peer.call_once(|| addr.clone());
 
-            let device = Arc::new(DeviceShim(net::default_device()));
+            let addr = Address::new(port, addr.port(), dest_ip);
+            let device = self.device_shim(device);
             let socket = crabnet_tcp::Socket::connect(device, addr);
 
             *tcp = Some(socket);
         }
 
-        let _ = self.wq.block_on(&self.tcp, |x| {
-            x.as_ref().unwrap().state() == State::Established
-        });
+        // Wait for the handshake to complete. Like on Linux, the wait is bounded by the send
+        // timeout (`SO_SNDTIMEO`) and fails with `EINPROGRESS` once it expires.
+        let timeout = self.options.lock_irq().send_timeout;
+        let mut tcp = self
+            .wq
+            .block_on_timeout(&self.tcp, timeout, |tcp| {
+                tcp.as_ref().map_or(true, |socket| {
+                    let state = socket.state();
+                    state == State::Established || state == State::Closed
+                })
+            })?
+            .ok_or(FileSystemError::InProgress)?;
+
+        if tcp.as_ref().map(|socket| socket.state()) != Some(State::Established) {
+            // Allow the connection to be retried.
+            *tcp = None;
+            return Err(FileSystemError::ConnectionRefused);
+        }
 
         Ok(())
     }
@@ -156,9 +567,17 @@ impl INodeInterface for TcpSocket {
         handle: Arc<FileHandle>,
     ) -> fs::Result<Option<fs::cache::DirCacheItem>> {
         self.handle.call_once(|| handle);
+        self.refs.fetch_add(1, Ordering::SeqCst);
+
         Ok(None)
     }
 
+    fn close(&self, _flags: OpenFlags) {
+        if self.refs.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.close_connection();
+        }
+    }
+
     #[inline]
     fn metadata(&self) -> Result<Metadata, FileSystemError> {
         Ok(Metadata::with_file_type(FileType::Socket))
@@ -182,19 +601,11 @@ impl INodeInterface for TcpSocket {
             .copied()
             .collect::<Vec<_>>();
 
-        let mut tcp = self.tcp.lock_irq();
-        let socket = tcp.as_mut().ok_or(FileSystemError::NotSupported)?;
-
-        // TODO: handle fragmentation in crabnet_tcp
-        for chunk in data.chunks(1460) {
-            socket.send(chunk).expect("failed to send data");
-        }
-
         // -netdev user,id=mynet0,net=192.168.1.0/24,dhcpstart=192.168.1.128,hostfwd=tcp::4444-:80
         // -device e1000,netdev=mynet0,id=ck_nic0 -object
         // filter-dump,id=mynet0,netdev=user,file=qemulog.log
 
-        Ok(data.len())
+        TcpSocket::send(self, &data)
     }
 
     fn get_peername(&self) -> fs::Result<super::SocketAddr> {
@@ -207,30 +618,56 @@ impl INodeInterface for TcpSocket {
     }
 
     fn get_sockname(&self) -> fs::Result<super::SocketAddr> {
-        if let Some(socket) = self.tcp.lock().as_mut() {
-            // FIXME:
-            let addr = SocketAddrInet {
-                family: AF_INET,
-                port: socket.addr.src_port.into(),
-                sin_addr: InAddr { addr: 0 },
-                padding: [0; 8],
-            };
-
-            Ok(super::SocketAddr::Inet(addr))
+        let port = if let Some(socket) = self.tcp.lock().as_mut() {
+            socket.addr.src_port
         } else {
-            Err(FileSystemError::NotConnected)
-        }
+            *self.port.get().ok_or(FileSystemError::NotConnected)?
+        };
+
+        // FIXME:
+        let addr = SocketAddrInet {
+            family: AF_INET,
+            port: port.into(),
+            sin_addr: InAddr { addr: 0 },
+            padding: [0; 8],
+        };
+
+        Ok(super::SocketAddr::Inet(addr))
     }
 
     fn recv(&self, message_hdr: &mut MessageHeader, _flags: MessageFlags) -> fs::Result<usize> {
-        Ok(message_hdr
+        message_hdr
             .iovecs_mut()
             .iter_mut()
             .map(|iovec| {
                 let iovec = iovec.as_slice_mut();
-                self.do_recv(iovec).unwrap()
+                self.do_recv(iovec)
             })
-            .sum::<usize>())
+            .sum::<fs::Result<usize>>()
+    }
+
+    fn ioctl(&self, command: usize, arg: usize) -> fs::Result<usize> {
+        super::inet_ioctl(command, arg)
+    }
+
+    fn set_sockopt(&self, level: usize, name: usize, value: &[u8]) -> fs::Result<()> {
+        match level {
+            SOL_SOCKET => self.options.lock_irq().set(name, value),
+            SOL_IP => self.inet_options.lock_irq().set(name, value),
+            SOL_TCP => self.tcp_options.lock_irq().set(name, value),
+            _ => Err(FileSystemError::NoProtocolOption),
+        }
+    }
+
+    fn get_sockopt(&self, level: usize, name: usize, value: &mut [u8]) -> fs::Result<usize> {
+        match (level, name) {
+            (SOL_SOCKET, SO_ACCEPTCONN) => set_option_value(value, self.is_listening() as c_int),
+            (SOL_SOCKET, SO_RCVBUF) => set_option_value(value, super::DEFAULT_BUFFER_SIZE as c_int),
+            (SOL_SOCKET, _) => self.options.lock_irq().get(name, value),
+            (SOL_IP, _) => self.inet_options.lock_irq().get(name, value),
+            (SOL_TCP, _) => self.tcp_options.lock_irq().get(name, value),
+            _ => Err(FileSystemError::NoProtocolOption),
+        }
     }
 
     fn poll(&self, table: Option<&mut PollTable>) -> fs::Result<PollFlags> {
@@ -239,10 +676,22 @@ impl INodeInterface for TcpSocket {
         }
 
         let mut flags = PollFlags::empty();
+
+        if let Some(listener) = self.listener.lock_irq().as_ref() {
+            if listener.pending.iter().any(|c| c.is_established()) {
+                flags |= PollFlags::IN;
+            }
+
+            return Ok(flags);
+        }
+
         let mut tcp = self.tcp.lock_irq();
 
         if let Some(socket) = tcp.as_mut() {
-            assert_ne!(socket.state(), State::Closed);
+            // The connection was reset by the peer.
+            if socket.state() == State::Closed {
+                return Ok(PollFlags::HUP | PollFlags::ERR);
+            }
 
             flags |= PollFlags::OUT;
 
@@ -15,28 +15,27 @@
 // You should have received a copy of the GNU General Public License
 // along with Aero. If not, see <https://www.gnu.org/licenses/>.
 
-use aero_syscall::prelude::{IfReq, SIOCGIFHWADDR, SIOCSIFADDR, SIOCSIFNETMASK};
-use aero_syscall::socket::{MessageFlags, MessageHeader};
-use aero_syscall::{OpenFlags, SocketAddrInet};
+use aero_syscall::socket::{MessageFlags, MessageHeader, SOL_IP, SOL_SOCKET};
+use aero_syscall::{OpenFlags, SocketAddrInet, SocketType, AF_INET};
 use alloc::sync::{Arc, Weak};
 use alloc::vec::Vec;
 use spin::Once;
 
-use crate::arch::user_copy::UserRef;
 use crate::fs::cache::DirCacheItem;
 use crate::fs::file_table::FileHandle;
 use crate::fs::inode::{FileType, INodeInterface, Metadata, PollFlags};
 use crate::fs::{self, FileSystemError};
-use crate::mem::paging::VirtAddr;
+use crate::net;
 use crate::net::udp::{self, UdpHandler};
-use crate::net::{self};
+use crate::utils::dma::DmaAllocator;
 use crate::utils::sync::{Mutex, WaitQueue};
 
-use super::SocketAddrRef;
+use super::{InetOptions, SocketOptions};
 
 use crabnet::data_link::{Eth, EthType, MacAddr};
 use crabnet::network::{Ipv4, Ipv4Addr, Ipv4Type};
 use crabnet::transport::Udp;
+use crabnet::IntoBoxedBytes;
 
 #[derive(Default)]
 enum SocketState {
@@ -56,6 +55,8 @@ struct UdpSocketInner {
 
 pub struct UdpSocket {
     inner: Mutex<UdpSocketInner>,
+    options: Mutex<SocketOptions>,
+    inet_options: Mutex<InetOptions>,
     wq: WaitQueue,
     handle: Once<Arc<FileHandle>>,
 
@@ -69,6 +70,8 @@ impl UdpSocket {
             handle: Once::new(),
 
             inner: Mutex::new(Default::default()),
+            options: Mutex::new(SocketOptions::new(AF_INET, SocketType::Dgram)),
+            inet_options: Mutex::new(InetOptions::default()),
             sref: sref.clone(),
         })
     }
@@ -172,21 +175,26 @@ impl INodeInterface for UdpSocket {
             .copied()
             .collect::<Vec<_>>();
 
-        // FIXME: loopback
-        if dest_ip == Ipv4Addr::LOOPBACK {
-            log::debug!("looback moments :)");
-            return Err(FileSystemError::NotSupported);
-            // return Ok(data.len());
+        if data.len() > self.options.lock_irq().send_buffer_size {
+            return Err(FileSystemError::MessageTooLong);
         }
 
-        use crate::net::shim::PacketSend;
+        // Broadcasts (e.g. DHCP discovery) may be sent before the interface has an address.
+        let src_ip = if dest_ip.is_broadcast() {
+            Ipv4Addr::BROADCAST
+        } else {
+            let (device, _) = net::route(dest_ip).ok_or(FileSystemError::NetworkUnreachable)?;
+            device.ip()
+        };
 
         let eth = Eth::new(MacAddr::NULL, MacAddr::NULL, EthType::Ip);
-        let ipv4 = Ipv4::new(Ipv4Addr::BROADCAST, Ipv4Addr::BROADCAST, Ipv4Type::Udp);
+        let ipv4 = Ipv4::new(src_ip, dest_ip, Ipv4Type::Udp);
         let udp = Udp::new(src_port, dest_port);
-        let packet = eth / ipv4 / udp / data.as_slice();
+        let mut packet = (eth / ipv4 / udp / data.as_slice()).into_boxed_bytes_in(DmaAllocator);
+
+        self.inet_options.lock_irq().apply(&mut packet);
 
-        packet.send();
+        net::transmit(dest_ip, packet);
         Ok(data.len())
     }
 
@@ -197,7 +205,12 @@ impl INodeInterface for UdpSocket {
             return Err(FileSystemError::WouldBlock);
         }
 
-        let mut this = self.wq.block_on(&self.inner, |e| !e.incoming.is_empty())?;
+        let timeout = self.options.lock_irq().recv_timeout;
+        let mut this = super::timeout_result(self.wq.block_on_timeout(
+            &self.inner,
+            timeout,
+            |e| !e.incoming.is_empty(),
+        ))?;
         let packet = this.incoming.pop().expect("recv: someone was greedy");
 
         let mut data = packet.as_slice().to_vec();
@@ -215,61 +228,22 @@ impl INodeInterface for UdpSocket {
     }
 
     fn ioctl(&self, command: usize, arg: usize) -> fs::Result<usize> {
-        match command {
-            SIOCGIFHWADDR => {
-                let mut ifreq = unsafe { UserRef::<IfReq>::new(VirtAddr::new(arg as _)) };
-
-                let name = ifreq.name().ok_or(FileSystemError::InvalidPath)?;
-                assert!(name == "eth0");
-
-                let hwaddr = unsafe {
-                    core::slice::from_raw_parts_mut(
-                        ifreq.data.addr.sa_data.as_mut_ptr(),
-                        MacAddr::ADDR_SIZE,
-                    )
-                };
-
-                let mac_addr = net::default_device().mac();
-                hwaddr.copy_from_slice(mac_addr.0.as_slice());
-                Ok(0)
-            }
-
-            SIOCSIFADDR => {
-                let ifreq = unsafe { UserRef::<IfReq>::new(VirtAddr::new(arg as _)) };
-                let socket = SocketAddrRef::from_ifreq(&ifreq)
-                    .map_err(|_| FileSystemError::NotSupported)?
-                    .as_inet()
-                    .ok_or(FileSystemError::NotSupported)?;
-
-                let name = ifreq.name().ok_or(FileSystemError::InvalidPath)?;
-
-                // FIXME:
-                assert!(name == "eth0");
-
-                let device = net::default_device();
-                device.set_ip(Ipv4Addr::from(socket.addr()));
-                Ok(0)
-            }
-
-            SIOCSIFNETMASK => {
-                let ifreq = unsafe { UserRef::<IfReq>::new(VirtAddr::new(arg as _)) };
-                let socket = SocketAddrRef::from_ifreq(&ifreq)
-                    .map_err(|_| FileSystemError::NotSupported)?
-                    .as_inet()
-                    .ok_or(FileSystemError::NotSupported)?;
-
-                let name = ifreq.name().ok_or(FileSystemError::InvalidPath)?;
-
-                // FIXME:
-                assert!(name == "eth0");
-
-                let device = net::default_device();
-                device.set_subnet_mask(Ipv4Addr::from(socket.addr()));
+        super::inet_ioctl(command, arg)
+    }
 
-                Ok(0)
-            }
+    fn set_sockopt(&self, level: usize, name: usize, value: &[u8]) -> fs::Result<()> {
+        match level {
+            SOL_SOCKET => self.options.lock_irq().set(name, value),
+            SOL_IP => self.inet_options.lock_irq().set(name, value),
+            _ => Err(FileSystemError::NoProtocolOption),
+        }
+    }
 
-            _ => unreachable!("inet::ioctl(): unknown command {command}"),
+    fn get_sockopt(&self, level: usize, name: usize, value: &mut [u8]) -> fs::Result<usize> {
+        match level {
+            SOL_SOCKET => self.options.lock_irq().get(name, value),
+            SOL_IP => self.inet_options.lock_irq().get(name, value),
+            _ => Err(FileSystemError::NoProtocolOption),
         }
     }
 
@@ -290,7 +264,20 @@ impl INodeInterface for UdpSocket {
 
 impl UdpHandler for UdpSocket {
     fn recv(&self, _udp: &Udp, payload: &[u8]) {
-        self.inner.lock_irq().incoming.push(payload.to_vec());
+        let recv_buffer_size = self.options.lock_irq().recv_buffer_size;
+        let mut inner = self.inner.lock_irq();
+
+        // Drop the datagram if the receive buffer is full.
+        let queued = inner.incoming.iter().map(|e| e.len()).sum::<usize>();
+
+        if queued + payload.len() > recv_buffer_size {
+            log::warn!("udp: receive buffer full, dropping datagram");
+            return;
+        }
+
+        inner.incoming.push(payload.to_vec());
+        drop(inner);
+
         self.wq.notify_all();
     }
 }
@@ -15,9 +15,11 @@
 // You should have received a copy of the GNU General Public License
 // along with Aero. If not, see <https://www.gnu.org/licenses/>.
 
-use aero_syscall::{OpenFlags, SocketAddrUnix, SyscallError, AF_UNIX};
+use core::ffi::c_int;
 
-use aero_syscall::socket::{MessageFlags, MessageHeader};
+use aero_syscall::{OpenFlags, SocketAddrUnix, SocketType, SyscallError, AF_UNIX};
+
+use aero_syscall::socket::*;
 
 use alloc::collections::VecDeque;
 use alloc::sync::{Arc, Weak};
@@ -33,15 +35,21 @@ use crate::fs::inode::*;
 use crate::fs::{FileSystemError, Path};
 
 use crate::mem::paging::VirtAddr;
-use crate::utils::sync::{Mutex, WaitQueue};
+use crate::userland::scheduler;
+use crate::utils::sync::{Mutex, MutexGuard, WaitQueue};
 
-use super::SocketAddrRef;
+use super::{set_option_value, SocketAddrRef, SocketOptions};
 
 fn path_from_unix_sock(address: &SocketAddrUnix) -> fs::Result<&Path> {
     // The abstract namespace socket allows the creation of a socket
     // connection which does not require a path to be created.
+    //
+    // TODO: Support abstract namespace sockets. An empty path is rejected as well.
     let abstract_namespaced = address.path[0] == 0;
-    assert!(!abstract_namespaced);
+
+    if abstract_namespaced {
+        return Err(FileSystemError::InvalidArgument);
+    }
 
     let path_len = address
         .path
@@ -56,21 +64,145 @@ fn path_from_unix_sock(address: &SocketAddrUnix) -> fs::Result<&Path> {
     Ok(Path::new(path_str))
 }
 
-#[derive(Debug, Default)]
+/// The maximum number of file descriptors that can be passed in a single message.
+const MAX_RIGHTS: usize = 253;
+
+/// Looks up the socket bound to `address`.
+fn lookup_socket(address: &SocketAddrUnix) -> fs::Result<Arc<UnixSocket>> {
+    let path = path_from_unix_sock(address)?;
+    let socket = fs::lookup_path(path)?;
+
+    socket
+        .inode()
+        .as_unix_socket()?
+        .downcast_arc::<UnixSocket>()
+        .ok_or(FileSystemError::NotSocket)
+}
+
+#[derive(Default)]
 pub struct Message {
     data: Vec<u8>,
-    // TODO: Keep track of the sender of the message here?
+    /// The address of the sender, if it is bound.
+    address: Option<SocketAddrUnix>,
+    /// File handles passed with the message (`SCM_RIGHTS`).
+    rights: Vec<Arc<FileHandle>>,
+    /// Credentials of the sender (`SCM_CREDENTIALS`).
+    credentials: UCred,
 }
 
 impl Message {
-    pub fn new(data: Vec<u8>) -> Self {
-        Self { data }
+    pub fn new(data: Vec<u8>, rights: Vec<Arc<FileHandle>>, credentials: UCred) -> Self {
+        Self {
+            data,
+            address: None,
+            rights,
+            credentials,
+        }
+    }
+
+    /// Copies the data of the message into `iovecs` and returns the number of bytes
+    /// copied.
+    fn copy_to(&self, iovecs: &mut [IoVec]) -> usize {
+        let mut copied = 0;
+
+        for iovec in iovecs {
+            let iovec = iovec.as_slice_mut();
+            let size = core::cmp::min(iovec.len(), self.data.len() - copied);
+
+            iovec[..size].copy_from_slice(&self.data[copied..copied + size]);
+            copied += size;
+        }
+
+        copied
+    }
+}
+
+/// Returns the credentials of the current task.
+fn current_credentials() -> UCred {
+    let task = scheduler::get_scheduler().current_task();
+    let credentials = task.credentials();
+
+    UCred {
+        pid: task.pid().as_usize() as c_int,
+        uid: credentials.euid,
+        gid: credentials.egid,
+    }
+}
+
+/// Checks that the credentials provided by the sender of a message are its own. The
+/// superuser is allowed to send any credentials.
+fn check_credentials(credentials: &UCred) -> fs::Result<()> {
+    let task = scheduler::get_scheduler().current_task();
+    let current = task.credentials();
+
+    if current.is_superuser() {
+        return Ok(());
+    }
+
+    let pid_ok = credentials.pid as usize == task.pid().as_usize();
+    let uid_ok = [current.uid, current.euid, current.suid].contains(&credentials.uid);
+    let gid_ok = [current.gid, current.egid, current.sgid].contains(&credentials.gid);
+
+    if pid_ok && uid_ok && gid_ok {
+        Ok(())
+    } else {
+        Err(FileSystemError::NotPermitted)
+    }
+}
+
+/// Parses the control messages (ancillary data) of a message that is being sent. Returns
+/// the file handles and the credentials that are passed with it.
+fn parse_control(header: &MessageHeader) -> fs::Result<(Vec<Arc<FileHandle>>, UCred)> {
+    let mut rights = Vec::new();
+    let mut credentials = None;
+
+    for message in header.control_messages() {
+        let message = message.map_err(|_| FileSystemError::InvalidArgument)?;
+
+        match (message.level, message.typ) {
+            (SOL_SOCKET, SCM_RIGHTS) => {
+                let size = core::mem::size_of::<c_int>();
+
+                if message.data.len() % size != 0
+                    || rights.len() + message.data.len() / size > MAX_RIGHTS
+                {
+                    return Err(FileSystemError::InvalidArgument);
+                }
+
+                let file_table = &scheduler::get_scheduler().current_task().file_table;
+
+                for fd in message.data.chunks_exact(size) {
+                    let fd = c_int::from_ne_bytes(fd.try_into().unwrap());
+                    let handle = file_table
+                        .get_handle(fd as usize)
+                        .ok_or(FileSystemError::BadDescriptor)?;
+
+                    rights.push(handle);
+                }
+            }
+
+            (SOL_SOCKET, SCM_CREDENTIALS) => {
+                let value = super::option_value::<UCred>(message.data)?;
+
+                check_credentials(&value)?;
+                credentials = Some(value);
+            }
+
+            (level, typ) => {
+                log::warn!("unix: unsupported control message (level={level}, type={typ})");
+                return Err(FileSystemError::InvalidArgument);
+            }
+        }
     }
+
+    Ok((rights, credentials.unwrap_or_else(current_credentials)))
 }
 
 #[derive(Default)]
 pub struct MessageQueue {
     messages: VecDeque<Message>,
+    /// The total size of the queued messages.
+    size: usize,
 }
 
 impl MessageQueue {
@@ -78,12 +210,18 @@ impl MessageQueue {
         self.messages.is_empty()
     }
 
+    /// Returns the total size of the queued messages.
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
     pub fn read(&mut self, buffer: &mut [u8]) -> usize {
         if let Some(message) = self.messages.front_mut() {
             let message_len = message.data.len();
             let size = core::cmp::min(buffer.len(), message_len);
 
             buffer[..size].copy_from_slice(&message.data[..size]);
+            self.size -= size;
 
             if size < message_len {
                 message.data.drain(..size);
@@ -97,8 +235,41 @@ impl MessageQueue {
         }
     }
 
-    pub fn write(&mut self, buffer: &[u8]) {
-        let message = Message::new(buffer.to_vec());
+    /// Removes the message at the front of the queue and returns it. Used by the sockets
+    /// that preserve message boundaries.
+    pub fn pop(&mut self) -> Option<Message> {
+        let message = self.messages.pop_front()?;
+
+        self.size -= message.data.len();
+        Some(message)
+    }
+
+    /// Returns the message at the front of the queue.
+    pub fn front(&self) -> Option<&Message> {
+        self.messages.front()
+    }
+
+    /// Returns whether the message at the front of the queue carries file handles.
+    pub fn has_rights(&self) -> bool {
+        self.messages
+            .front()
+            .map(|message| !message.rights.is_empty())
+            .unwrap_or(false)
+    }
+
+    /// Takes the file handles passed with the message at the front of the queue and returns
+    /// them along with the credentials of its sender.
+    pub fn take_ancillary(&mut self) -> (Vec<Arc<FileHandle>>, UCred) {
+        let message = self
+            .messages
+            .front_mut()
+            .expect("MessageQueue::take_ancillary() called when queue is empty");
+
+        (core::mem::take(&mut message.rights), message.credentials)
+    }
+
+    pub fn write(&mut self, message: Message) {
+        self.size += message.data.len();
         self.messages.push_back(message);
     }
 }
@@ -189,19 +360,27 @@ struct UnixSocketInner {
 }
 
 pub struct UnixSocket {
+    typ: SocketType,
     inner: Mutex<UnixSocketInner>,
     buffer: Mutex<MessageQueue>,
+    options: Mutex<SocketOptions>,
     wq: WaitQueue,
     weak: Weak<UnixSocket>,
     handle: Once<Arc<FileHandle>>,
 }
 
 impl UnixSocket {
-    pub fn new() -> Arc<Self> {
+    /// Creates a new UNIX socket of the provided type. Stream sockets (`SOCK_STREAM`)
+    /// provide a byte stream, while datagram (`SOCK_DGRAM`) and sequenced packet
+    /// (`SOCK_SEQPACKET`) sockets preserve message boundaries. Datagram sockets are
+    /// connectionless.
+    pub fn new(typ: SocketType) -> Arc<Self> {
         Arc::new_cyclic(|weak| Self {
+            typ,
             inner: Mutex::new(UnixSocketInner::default()),
 
             buffer: Mutex::new(MessageQueue::default()),
+            options: Mutex::new(SocketOptions::new(AF_UNIX, typ)),
             wq: WaitQueue::new(),
             weak: weak.clone(),
             handle: Once::new(),
@@ -236,6 +415,75 @@ impl UnixSocket {
             .read()
             .contains(OpenFlags::O_NONBLOCK)
     }
+
+    /// Returns whether the socket preserves message boundaries.
+    fn is_message_based(&self) -> bool {
+        self.typ != SocketType::Stream
+    }
+
+    fn peer(&self) -> fs::Result<Arc<UnixSocket>> {
+        match self.inner.lock_irq().state {
+            UnixSocketState::Connected(ref peer) => Ok(peer.clone()),
+            _ => Err(FileSystemError::NotConnected),
+        }
+    }
+
+    /// Queues `message` in the receive buffer of `peer`, blocking until there is enough
+    /// space in it.
+    fn write_message(
+        &self,
+        peer: &UnixSocket,
+        mut message: Message,
+        non_block: bool,
+    ) -> fs::Result<usize> {
+        let size = message.data.len();
+        let (send_buffer_size, timeout) = {
+            let options = self.options.lock_irq();
+            (options.send_buffer_size, options.send_timeout)
+        };
+
+        // Messages are never split, so they have to fit in the send buffer.
+        if self.is_message_based() && size > send_buffer_size {
+            return Err(FileSystemError::MessageTooLong);
+        }
+
+        message.address = self.inner.lock_irq().address.clone();
+
+        // A message that is larger than the send buffer is accepted once the receive buffer
+        // of the peer has been drained.
+        let has_room =
+            |queue: &MessageQueue| queue.is_empty() || queue.size() + size <= send_buffer_size;
+
+        if !has_room(&peer.buffer.lock_irq()) && non_block {
+            return Err(FileSystemError::WouldBlock);
+        }
+
+        let mut queue = super::timeout_result(peer.wq.block_on_timeout(
+            &peer.buffer,
+            timeout,
+            |e| has_room(e),
+        ))?;
+
+        queue.write(message);
+        drop(queue);
+
+        peer.wq.notify_all();
+        Ok(size)
+    }
+
+    /// Blocks until a message is available in the receive buffer or the receive timeout
+    /// (`SO_RCVTIMEO`) has elapsed.
+    fn wait_for_message(&self, non_block: bool) -> fs::Result<MutexGuard<MessageQueue>> {
+        if self.buffer.lock_irq().is_empty() && non_block {
+            return Err(FileSystemError::WouldBlock);
+        }
+
+        let timeout = self.options.lock_irq().recv_timeout;
+        super::timeout_result(
+            self.wq
+                .block_on_timeout(&self.buffer, timeout, |e| !e.is_empty()),
+        )
+    }
 }
 
 impl INodeInterface for UnixSocket {
@@ -258,30 +506,36 @@ impl INodeInterface for UnixSocket {
     }
 
     fn read_at(&self, _offset: usize, user_buffer: &mut [u8]) -> fs::Result<usize> {
-        if self.buffer.lock_irq().is_empty() && self.is_non_block() {
-            return Err(FileSystemError::WouldBlock);
-        }
+        let mut buffer = self.wait_for_message(self.is_non_block())?;
+
+        let read = if self.is_message_based() {
+            // The rest of the message is discarded if it does not fit in the buffer.
+            let message = buffer.pop().unwrap();
+            let size = core::cmp::min(user_buffer.len(), message.data.len());
+
+            user_buffer[..size].copy_from_slice(&message.data[..size]);
+            size
+        } else {
+            buffer.read(user_buffer)
+        };
 
-        let mut buffer = self.wq.block_on(&self.buffer, |e| !e.is_empty())?;
+        drop(buffer);
 
-        let read = buffer.read(user_buffer);
+        // Wake up the writers waiting for space in the buffer.
+        self.wq.notify_all();
         Ok(read)
     }
 
     fn write_at(&self, _offset: usize, buffer: &[u8]) -> fs::Result<usize> {
-        let inner = self.inner.lock_irq();
-        let peer = match inner.state {
-            UnixSocketState::Connected(ref peer) => peer,
-            _ => return Err(FileSystemError::NotConnected),
-        };
-
-        peer.buffer.lock_irq().write(buffer);
-        peer.wq.notify_all();
-
-        Ok(buffer.len())
+        let message = Message::new(buffer.to_vec(), Vec::new(), current_credentials());
+        self.write_message(&self.peer()?, message, self.is_non_block())
     }
 
     fn listen(&self, backlog: usize) -> Result<(), SyscallError> {
+        if self.typ == SocketType::Dgram {
+            return Err(SyscallError::EOPNOTSUPP);
+        }
+
         let mut inner = self.inner.lock_irq();
         let is_bound = inner.address.is_some();
 
@@ -320,14 +574,18 @@ impl INodeInterface for UnixSocket {
 
     fn connect(&self, address: SocketAddrRef, _length: usize) -> fs::Result<()> {
         let address = address.as_unix().ok_or(FileSystemError::NotSupported)?;
-        let path = path_from_unix_sock(address)?;
-        let socket = fs::lookup_path(path)?;
+        let target = lookup_socket(address)?;
 
-        let target = socket
-            .inode()
-            .as_unix_socket()?
-            .downcast_arc::<UnixSocket>()
-            .ok_or(FileSystemError::NotSocket)?;
+        if target.typ != self.typ {
+            return Err(FileSystemError::WrongProtocolType);
+        }
+
+        if self.typ == SocketType::Dgram {
+            // Connecting a datagram socket only sets the default destination of the
+            // messages sent through it.
+            self.inner.lock_irq().state = UnixSocketState::Connected(target);
+            return Ok(());
+        }
 
         let mut itarget = target.inner.lock_irq();
 
@@ -344,10 +602,16 @@ impl INodeInterface for UnixSocket {
         Ok(())
     }
 
-    fn accept(&self, address: Option<(VirtAddr, &mut u32)>) -> fs::Result<Arc<UnixSocket>> {
-        let mut inner = self.wq.block_on(&self.inner, |e| {
-            e.state.queue().map(|x| !x.is_empty()).unwrap_or(false)
-        })?;
+    fn accept(&self, address: Option<(VirtAddr, &mut u32)>) -> fs::Result<Arc<dyn INodeInterface>> {
+        if self.typ == SocketType::Dgram {
+            return Err(FileSystemError::OperationNotSupported);
+        }
+
+        let timeout = self.options.lock_irq().recv_timeout;
+        let mut inner =
+            super::timeout_result(self.wq.block_on_timeout(&self.inner, timeout, |e| {
+                e.state.queue().map(|x| !x.is_empty()).unwrap_or(false)
+            }))?;
 
         let queue = inner
             .state
@@ -355,7 +619,7 @@ impl INodeInterface for UnixSocket {
             .ok_or(FileSystemError::ConnectionRefused)?;
 
         let peer = queue.pop().expect("UnixSocket::accept(): backlog is empty");
-        let sock = Self::new();
+        let sock = Self::new(self.typ);
 
         {
             let mut sock_inner = sock.inner.lock_irq();
@@ -385,34 +649,117 @@ impl INodeInterface for UnixSocket {
     }
 
     fn recv(&self, header: &mut MessageHeader, flags: MessageFlags) -> fs::Result<usize> {
-        assert!(flags.is_empty());
+        // Datagram sockets can receive messages without being connected.
+        if self.typ != SocketType::Dgram && !self.inner.lock_irq().state.is_connected() {
+            return Err(FileSystemError::NotConnected);
+        }
 
-        let inner = self.inner.lock_irq();
+        let non_block = self.is_non_block() || flags.contains(MessageFlags::DONTWAIT);
+        let mut buffer = self.wait_for_message(non_block)?;
 
-        let peer = match &inner.state {
-            UnixSocketState::Connected(peer) => peer,
-            _ => return Err(FileSystemError::NotConnected),
-        };
+        if let Some(addr) = header.name_mut::<SocketAddrUnix>() {
+            let message = buffer.front().unwrap();
+            *addr = message.address.clone().unwrap_or_default();
+        }
 
-        if self.buffer.lock_irq().is_empty() && self.is_non_block() {
-            return Err(FileSystemError::WouldBlock);
+        let mut message_flags = MessageFlags::empty();
+        let (read, rights, credentials);
+
+        if self.is_message_based() {
+            let message = buffer.pop().unwrap();
+            let copied = message.copy_to(header.iovecs_mut());
+
+            // The rest of the message is discarded if it does not fit in the buffers.
+            if copied < message.data.len() {
+                message_flags |= MessageFlags::TRUNC;
+            }
+
+            // With `MSG_TRUNC`, the real size of the message is returned.
+            read = if flags.contains(MessageFlags::TRUNC) {
+                message.data.len()
+            } else {
+                copied
+            };
+
+            (rights, credentials) = (message.rights, message.credentials);
+        } else {
+            (rights, credentials) = buffer.take_ancillary();
+            let mut size = 0;
+
+            for iovec in header.iovecs_mut() {
+                // Stop at the next message if it carries file handles, so that they are
+                // received along with its data.
+                if buffer.is_empty() || (size != 0 && buffer.has_rights()) {
+                    break;
+                }
+
+                size += buffer.read(iovec.as_slice_mut());
+            }
+
+            read = size;
         }
 
-        let mut buffer = self.wq.block_on(&self.buffer, |e| !e.is_empty())?;
+        drop(buffer);
 
-        if let Some(addr) = header.name_mut::<SocketAddrUnix>() {
-            *addr = peer.inner.lock_irq().address.as_ref().cloned().unwrap();
+        // Wake up the writers waiting for space in the buffer.
+        self.wq.notify_all();
+
+        let mut control = header.control_writer();
+        let mut truncated = false;
+
+        if !rights.is_empty() {
+            let file_table = &scheduler::get_scheduler().current_task().file_table;
+            let cloexec = flags.contains(MessageFlags::CMSG_CLOEXEC);
+
+            // The file handles that do not fit in the control buffer are discarded.
+            let capacity = control.capacity() / core::mem::size_of::<c_int>();
+            truncated = rights.len() > capacity;
+
+            let mut fds = Vec::new();
+
+            for handle in rights.iter().take(capacity) {
+                // Like on Linux, the message has already been consumed, so the file handles
+                // that cannot be installed (e.g. the file table is full) are discarded too.
+                let Ok(fd) = file_table.install(handle, cloexec) else {
+                    truncated = true;
+                    break;
+                };
+
+                fds.extend_from_slice(&(fd as c_int).to_ne_bytes());
+            }
+
+            control.write(SOL_SOCKET, SCM_RIGHTS, &fds);
         }
 
-        Ok(header
-            .iovecs_mut()
-            .iter_mut()
-            .map(|iovec| buffer.read(iovec.as_slice_mut()))
-            .sum::<usize>())
+        if self.options.lock_irq().pass_credentials {
+            // SAFETY: `UCred` is a plain old data structure without any padding.
+            let credentials = unsafe {
+                core::slice::from_raw_parts(
+                    (&credentials as *const UCred).cast::<u8>(),
+                    core::mem::size_of::<UCred>(),
+                )
+            };
+
+            control.write(SOL_SOCKET, SCM_CREDENTIALS, credentials);
+        }
+
+        let control_len = control.bytes_written();
+        truncated |= control.is_truncated();
+
+        header.set_control_len(control_len);
+
+        if truncated {
+            message_flags |= MessageFlags::CTRUNC;
+        }
+
+        header.set_flags(message_flags);
+
+        Ok(read)
     }
 
-    fn send(&self, message_hdr: &mut MessageHeader, _flags: MessageFlags) -> fs::Result<usize> {
-        // FIXME(andyython): figure out the message header stuff...
+    fn send(&self, message_hdr: &mut MessageHeader, flags: MessageFlags) -> fs::Result<usize> {
+        let (rights, credentials) = parse_control(message_hdr)?;
+        let header_address = message_hdr.name::<SocketAddrUnix>();
         let data = message_hdr
             .iovecs()
             .iter()
@@ -420,7 +767,25 @@ impl INodeInterface for UnixSocket {
             .copied()
             .collect::<Vec<_>>();
 
-        self.write_at(0, &data)
+        // Datagrams can be sent to any bound socket, without a connection.
+        let peer = match header_address {
+            Some(address) if self.typ == SocketType::Dgram => {
+                let peer = lookup_socket(&address)?;
+
+                if peer.typ != self.typ {
+                    return Err(FileSystemError::WrongProtocolType);
+                }
+
+                peer
+            }
+
+            _ => self.peer()?,
+        };
+
+        let non_block = self.is_non_block() || flags.contains(MessageFlags::DONTWAIT);
+        let message = Message::new(data, rights, credentials);
+
+        self.write_message(&peer, message, non_block)
     }
 
     fn poll(&self, table: Option<&mut PollTable>) -> fs::Result<PollFlags> {
@@ -447,6 +812,26 @@ impl INodeInterface for UnixSocket {
         Ok(events)
     }
 
+    fn set_sockopt(&self, level: usize, name: usize, value: &[u8]) -> fs::Result<()> {
+        match level {
+            SOL_SOCKET => self.options.lock_irq().set(name, value),
+            _ => Err(FileSystemError::NoProtocolOption),
+        }
+    }
+
+    fn get_sockopt(&self, level: usize, name: usize, value: &mut [u8]) -> fs::Result<usize> {
+        match (level, name) {
+            (SOL_SOCKET, SO_ACCEPTCONN) => {
+                let listening =
+                    matches!(self.inner.lock_irq().state, UnixSocketState::Listening(_));
+                set_option_value(value, listening as c_int)
+            }
+
+            (SOL_SOCKET, _) => self.options.lock_irq().get(name, value),
+            _ => Err(FileSystemError::NoProtocolOption),
+        }
+    }
+
     fn shutdown(&self, how: usize) -> fs::Result<()> {
         log::warn!("shutdown how={how}");
         Ok(())
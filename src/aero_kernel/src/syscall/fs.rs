@@ -17,15 +17,21 @@
 
 use aero_syscall::prelude::*;
 use aero_syscall::signal::SigProcMask;
-use aero_syscall::{OpenFlags, Stat, SyscallError, TimeSpec};
+use aero_syscall::{Mode, OpenFlags, Stat, SyscallError, TimeSpec};
+use alloc::sync::Arc;
 
-use crate::fs::cache::{self, DirCacheImpl};
+use crate::drivers::pty;
+use crate::fs::cache::{self, DirCacheImpl, DirCacheItem};
 use crate::fs::epoll::EPoll;
 use crate::fs::eventfd::EventFd;
+use crate::fs::ext2::Ext2;
 use crate::fs::file_table::DuplicateHint;
-use crate::fs::inode::{DirEntry, PollTable};
+use crate::fs::inode::{self, DirEntry, FileType, PollTable, SetAttr};
 use crate::fs::pipe::Pipe;
-use crate::fs::{self, lookup_path, LookupMode};
+use crate::fs::procfs::ProcFs;
+use crate::fs::ramfs::RamFs;
+use crate::fs::shmem::{SharedMemory, TmpFs};
+use crate::fs::{self, block, lookup_path, FileSystem, LookupMode};
 use crate::userland::scheduler;
 
 use crate::fs::Path;
@@ -70,8 +76,8 @@ pub fn read(fd: usize, buffer: &mut [u8]) -> Result<usize, SyscallError> {
 }
 
 #[syscall]
-pub fn open(_fd: usize, path: &Path, mode: usize) -> Result<usize, SyscallError> {
-    let mut flags = OpenFlags::from_bits(mode).ok_or(SyscallError::EINVAL)?;
+pub fn open(_fd: usize, path: &Path, flags: usize, mode: usize) -> Result<usize, SyscallError> {
+    let mut flags = OpenFlags::from_bits(flags).ok_or(SyscallError::EINVAL)?;
 
     if !flags.intersects(OpenFlags::O_RDONLY | OpenFlags::O_RDWR | OpenFlags::O_WRONLY) {
         flags.insert(OpenFlags::O_RDONLY);
@@ -80,15 +86,32 @@ pub fn open(_fd: usize, path: &Path, mode: usize) -> Result<usize, SyscallError>
     let mut lookup_mode = LookupMode::None;
 
     if flags.contains(OpenFlags::O_CREAT) {
-        lookup_mode = LookupMode::Create;
+        lookup_mode = LookupMode::Create(Mode::from_bits_truncate(mode as u32 & 0o7777));
     }
 
     let inode = fs::lookup_path_with_mode(path, lookup_mode)?;
+    let metadata = inode.inode().metadata()?;
 
-    if flags.contains(OpenFlags::O_DIRECTORY) && !inode.inode().metadata()?.is_directory() {
+    if flags.contains(OpenFlags::O_DIRECTORY) && !metadata.is_directory() {
         return Err(SyscallError::ENOTDIR);
     }
 
+    let mut access = AccessMode::empty();
+
+    if flags.is_readable() {
+        access.insert(AccessMode::R_OK);
+    }
+
+    if flags.is_writable() || flags.contains(OpenFlags::O_TRUNC) {
+        access.insert(AccessMode::W_OK);
+
+        if metadata.is_file() {
+            fs::ensure_writable(&inode.inode())?;
+        }
+    }
+
+    fs::check_access(&inode.inode(), access)?;
+
     if flags.contains(OpenFlags::O_TRUNC) {
         inode.inode().truncate(0)?;
     }
@@ -156,7 +179,7 @@ pub fn chdir(path: &str) -> Result<usize, SyscallError> {
 }
 
 #[syscall]
-pub fn mkdirat(dfd: usize, path: &Path) -> Result<usize, SyscallError> {
+pub fn mkdirat(dfd: usize, path: &Path, mode: usize) -> Result<usize, SyscallError> {
     // NOTE: If the pathname given in pathname is relative, then it is interpreted
     // relative to the directory referred to by the file descriptor (rather than relative
     // to the current working directory of the calling task, as is done by mkdir() for a
@@ -192,7 +215,10 @@ pub fn mkdirat(dfd: usize, path: &Path) -> Result<usize, SyscallError> {
         return Err(SyscallError::EEXIST);
     }
 
-    parent_inode.mkdir(child)?;
+    fs::ensure_writable(&parent_inode)?;
+    fs::check_access(&parent_inode, AccessMode::W_OK | AccessMode::X_OK)?;
+
+    parent_inode.mkdir(child, Mode::from_bits_truncate(mode as u32 & 0o7777))?;
     Ok(0x00)
 }
 
@@ -209,6 +235,12 @@ pub fn rmdir(path: &str) -> Result<usize, SyscallError> {
         return Err(SyscallError::ENOTDIR);
     }
 
+    fs::ensure_writable(&inode.inode())?;
+
+    if let Some(parent) = inode.parent() {
+        fs::check_access(&parent.inode(), AccessMode::W_OK | AccessMode::X_OK)?;
+    }
+
     inode.inode().rmdir(child)?;
     inode.drop_from_cache();
     Ok(0x00)
@@ -292,32 +324,67 @@ pub fn pipe(fds: &mut [i32; 2], flags: usize) -> Result<usize, SyscallError> {
 }
 
 #[syscall]
-pub fn unlink(_fd: usize, _path: &Path, _flags: usize) -> Result<usize, SyscallError> {
-    // let _flags = OpenFlags::from_bits(flags).ok_or(SyscallError::EINVAL)?;
-    // let name = path.container();
+pub fn unlink(fd: usize, path: &Path, _flags: usize) -> Result<usize, SyscallError> {
+    if fd as isize != aero_syscall::AT_FDCWD {
+        // TODO: Implement atfd unlink
+        return Err(SyscallError::ENOSYS);
+    }
 
-    // if fd as isize == aero_syscall::AT_FDCWD {
-    //     let file = fs::lookup_path(path)?;
+    let (parent_path, name) = path.parent_and_basename();
 
-    //     if let Some(dir) = file.parent() {
-    //         let metadata = file.inode().metadata()?;
+    let parent = fs::lookup_path(parent_path)?;
+    let parent_inode = parent.inode();
 
-    //         if metadata.is_file() {
-    //             dir.inode().unlink(name.as_str())?;
-    //             file.drop_from_cache();
-    //         }
-    //     }
-    // } else {
-    //     unimplemented!()
-    // }
+    // NOTE: The last component is not resolved as we want to unlink the symbolic link
+    // itself rather than the file it points to.
+    let file = match inode::fetch_dir_entry(parent.clone(), String::from(name)) {
+        Some(file) => file,
+        None => parent_inode.lookup(parent.clone(), name)?,
+    };
+
+    if file.inode().metadata()?.is_directory() {
+        return Err(SyscallError::EISDIR);
+    }
+
+    fs::ensure_writable(&parent_inode)?;
+
+    let credentials = scheduler::get_scheduler().current_task().credentials();
+    fs::check_access_with(
+        &credentials,
+        &parent_inode,
+        AccessMode::W_OK | AccessMode::X_OK,
+    )?;
+
+    // In a directory with the sticky bit set, a file can only be removed by its owner,
+    // the owner of the directory or the superuser.
+    let parent_stat = parent_inode.stat()?;
+
+    if parent_stat.st_mode.contains(Mode::S_ISVTX) && !credentials.is_superuser() {
+        let file_stat = file.inode().stat()?;
+
+        if credentials.euid != file_stat.st_uid && credentials.euid != parent_stat.st_uid {
+            return Err(SyscallError::EPERM);
+        }
+    }
+
+    parent_inode.unlink(name)?;
+    file.drop_from_cache();
 
     Ok(0x00)
 }
 
 #[syscall]
-pub fn access(fd: usize, path: &Path, _mode: usize, _flags: usize) -> Result<usize, SyscallError> {
+pub fn access(fd: usize, path: &Path, mode: usize, _flags: usize) -> Result<usize, SyscallError> {
+    let mode = AccessMode::from_bits(mode).ok_or(SyscallError::EINVAL)?;
+
     if fd as isize == aero_syscall::AT_FDCWD {
-        lookup_path(path)?;
+        let inode = lookup_path(path)?.inode();
+
+        // Unlike other permission checks, `access` checks against the real user and
+        // group IDs.
+        let credentials = scheduler::get_scheduler().current_task().credentials();
+        fs::check_access_with(&credentials.as_real(), &inode, mode)?;
+
         Ok(0x00)
     } else {
         // TODO: Implement atfd access
@@ -325,6 +392,184 @@ pub fn access(fd: usize, path: &Path, _mode: usize, _flags: usize) -> Result<usi
     }
 }
 
+/// Resolves `path` relative to the directory referred to by `fd` (or relative to the current
+/// working directory if `fd` is `AT_FDCWD`). If `path` is empty and [`AtFlags::EMPTY_PATH`]
+/// is set, the file referred to by `fd` itself is returned.
+fn lookup_at(fd: usize, path: &Path, flags: AtFlags) -> Result<DirCacheItem, SyscallError> {
+    let mode = if flags.contains(AtFlags::SYMLINK_NOFOLLOW) {
+        LookupMode::NoFollow
+    } else {
+        LookupMode::None
+    };
+
+    if path.is_absolute() {
+        return Ok(fs::lookup_path_with(fs::root_dir().clone(), path, mode)?);
+    }
+
+    let task = scheduler::get_scheduler().current_task();
+
+    let at = if fd as isize == aero_syscall::AT_FDCWD {
+        task.cwd_dirent()
+    } else {
+        let handle = task.file_table.get_handle(fd).ok_or(SyscallError::EBADFD)?;
+
+        handle.inode.clone()
+    };
+
+    if path.as_str().is_empty() {
+        return if flags.contains(AtFlags::EMPTY_PATH) {
+            Ok(at)
+        } else {
+            Err(SyscallError::ENOENT)
+        };
+    }
+
+    Ok(fs::lookup_path_with(at, path, mode)?)
+}
+
+#[syscall]
+pub fn fchmodat(fd: usize, path: &Path, mode: usize, flags: usize) -> Result<usize, SyscallError> {
+    let flags = AtFlags::from_bits(flags).ok_or(SyscallError::EINVAL)?;
+
+    let inode = lookup_at(fd, path, flags)?.inode();
+    let stat = inode.stat()?;
+
+    let credentials = scheduler::get_scheduler().current_task().credentials();
+
+    // Only the owner of the file or the superuser may change its mode.
+    if !credentials.is_superuser() && credentials.euid != stat.st_uid {
+        return Err(SyscallError::EPERM);
+    }
+
+    let mut mode = Mode::from_bits_truncate(mode as u32) & !Mode::S_IFMT;
+
+    // An unprivileged task cannot set the set-group-ID bit on a file whose group it is not
+    // a member of. The bit is silently cleared instead.
+    if !credentials.is_superuser() && !credentials.in_group(stat.st_gid) {
+        mode.remove(Mode::S_ISGID);
+    }
+
+    fs::ensure_writable(&inode)?;
+    inode.set_attr(&SetAttr {
+        mode: Some(mode.bits()),
+        ..Default::default()
+    })?;
+
+    Ok(0)
+}
+
+#[syscall]
+pub fn fchownat(
+    fd: usize,
+    path: &Path,
+    uid: usize,
+    gid: usize,
+    flags: usize,
+) -> Result<usize, SyscallError> {
+    let flags = AtFlags::from_bits(flags).ok_or(SyscallError::EINVAL)?;
+
+    // An ID of -1 leaves the respective ID unchanged.
+    let uid = (uid as u32 != u32::MAX).then_some(uid as u32);
+    let gid = (gid as u32 != u32::MAX).then_some(gid as u32);
+
+    let inode = lookup_at(fd, path, flags)?.inode();
+    let stat = inode.stat()?;
+
+    let credentials = scheduler::get_scheduler().current_task().credentials();
+    let mut attr = SetAttr {
+        uid,
+        gid,
+        ..Default::default()
+    };
+
+    if !credentials.is_superuser() {
+        // Only the superuser may change the owner of a file. The owner of the file may
+        // change its group to any of the groups that it is a member of, and may always
+        // keep its current group.
+        let is_owner = credentials.euid == stat.st_uid;
+        let keeps_owner = uid.map_or(true, |uid| uid == stat.st_uid);
+        let is_member = gid.map_or(true, |gid| gid == stat.st_gid || credentials.in_group(gid));
+
+        if !is_owner || !keeps_owner || !is_member {
+            return Err(SyscallError::EPERM);
+        }
+
+        // The set-user-ID and set-group-ID bits are cleared when an unprivileged task
+        // changes the ownership of the file.
+        if stat.st_mode.intersects(Mode::S_ISUID | Mode::S_ISGID) {
+            let mode = stat.st_mode & !(Mode::S_IFMT | Mode::S_ISUID | Mode::S_ISGID);
+            attr.mode = Some(mode.bits());
+        }
+    }
+
+    fs::ensure_writable(&inode)?;
+    inode.set_attr(&attr)?;
+
+    Ok(0)
+}
+
+#[syscall]
+pub fn utimensat(
+    fd: usize,
+    path: &Path,
+    times: usize,
+    flags: usize,
+) -> Result<usize, SyscallError> {
+    let mut flags = AtFlags::from_bits(flags).ok_or(SyscallError::EINVAL)?;
+
+    // An empty path refers to the file referred to by `fd` itself (akin `futimens`).
+    if path.as_str().is_empty() {
+        flags.insert(AtFlags::EMPTY_PATH);
+    }
+
+    let inode = lookup_at(fd, path, flags)?.inode();
+    let now = crate::arch::time::get_realtime_clock();
+
+    // Whether any of the timestamps is set to an explicit value.
+    let mut explicit = false;
+
+    // If `times` is NULL, both of the timestamps are set to the current time.
+    let (atime, mtime) = if times != 0x00 {
+        let times = crate::utils::validate_slice(times as *const TimeSpec, 2)?;
+
+        let mut resolve = |time: &TimeSpec| match time.tv_nsec {
+            UTIME_NOW => Ok(Some(now.clone())),
+            UTIME_OMIT => Ok(None),
+            0..=999_999_999 => {
+                explicit = true;
+                Ok(Some(time.clone()))
+            }
+            _ => Err(SyscallError::EINVAL),
+        };
+
+        (resolve(&times[0])?, resolve(&times[1])?)
+    } else {
+        (Some(now.clone()), Some(now.clone()))
+    };
+
+    let stat = inode.stat()?;
+    let credentials = scheduler::get_scheduler().current_task().credentials();
+
+    if !credentials.is_superuser() && credentials.euid != stat.st_uid {
+        // Setting the timestamps to an explicit value requires ownership of the file, while
+        // setting them to the current time only requires write permission.
+        if explicit {
+            return Err(SyscallError::EPERM);
+        }
+
+        fs::check_access_with(&credentials, &inode, AccessMode::W_OK)?;
+    }
+
+    fs::ensure_writable(&inode)?;
+    inode.set_attr(&SetAttr {
+        atime,
+        mtime,
+        ..Default::default()
+    })?;
+
+    Ok(0)
+}
+
 const SETFL_MASK: OpenFlags = OpenFlags::from_bits_truncate(
     OpenFlags::O_APPEND.bits()
         | OpenFlags::O_NONBLOCK.bits()
@@ -410,6 +655,35 @@ pub fn fcntl(fd: usize, command: usize, arg: usize) -> Result<usize, SyscallErro
             Ok(0)
         }
 
+        // Add the seals in `arg` to the set of seals of the file. Only files created using
+        // `memfd_create(2)` support sealing.
+        aero_syscall::prelude::F_ADD_SEALS => {
+            let seals = SealFlags::from_bits(arg).ok_or(SyscallError::EINVAL)?;
+            let memory = handle
+                .inode()
+                .downcast_arc::<SharedMemory>()
+                .ok_or(SyscallError::EINVAL)?;
+
+            if !handle.flags.read().is_writable() {
+                return Err(SyscallError::EPERM);
+            }
+
+            // Fails with `EBUSY` if `F_SEAL_WRITE` is added while the file has writable
+            // shared mappings.
+            memory.add_seals(seals)?;
+            Ok(0)
+        }
+
+        // Get the set of seals of the file.
+        aero_syscall::prelude::F_GET_SEALS => {
+            let memory = handle
+                .inode()
+                .downcast_arc::<SharedMemory>()
+                .ok_or(SyscallError::EINVAL)?;
+
+            Ok(memory.seals().bits())
+        }
+
         _ => unimplemented!("fcntl: unknown command {command}"),
     }
 }
@@ -551,6 +825,39 @@ pub fn event_fd(_initval: usize, flags: usize) -> Result<usize, SyscallError> {
         .open_file(entry, OpenFlags::O_RDWR)?)
 }
 
+/// Creates an anonymous file that only lives in memory and returns a file descriptor
+/// referring to it. The `name` is only used for debugging purposes.
+#[syscall]
+pub fn memfd_create(name: &str, flags: usize) -> Result<usize, SyscallError> {
+    let flags = MemFdFlags::from_bits(flags).ok_or(SyscallError::EINVAL)?;
+
+    // The name is shown as the target of the symbolic link in `/proc/self/fd` (prefixed
+    // with `memfd:`), so it is limited to the same length as on Linux.
+    if name.len() > 249 {
+        return Err(SyscallError::EINVAL);
+    }
+
+    // Seals can only be added to the file if sealing was allowed on creation.
+    let seals = if flags.contains(MemFdFlags::MFD_ALLOW_SEALING) {
+        SealFlags::empty()
+    } else {
+        SealFlags::F_SEAL_SEAL
+    };
+
+    let memfd_file = SharedMemory::with_seals(0, seals);
+    let entry = DirEntry::from_inode(memfd_file, alloc::format!("memfd:{name}"));
+
+    let mut open_flags = OpenFlags::O_RDWR;
+
+    if flags.contains(MemFdFlags::MFD_CLOEXEC) {
+        open_flags.insert(OpenFlags::O_CLOEXEC);
+    }
+
+    let current_task = scheduler::get_scheduler().current_task();
+
+    Ok(current_task.file_table.open_file(entry, open_flags)?)
+}
+
 /// Creates a new link (also known as a hard link) to an existing
 /// file.
 #[syscall]
@@ -572,6 +879,7 @@ pub fn link(src_path: &Path, dest_path: &Path) -> Result<usize, SyscallError> {
         return Err(SyscallError::EINVAL);
     }
 
+    fs::ensure_writable(&dest_dir)?;
     dest_dir.link(dest_name, src)?;
     Ok(0)
 }
@@ -680,6 +988,7 @@ pub fn rename(src: &Path, dest: &Path) -> Result<usize, SyscallError> {
         (fs::lookup_path(dir)?, name)
     };
 
+    fs::ensure_writable(&dest.inode())?;
     dest.inode().rename(src.clone(), name)?;
 
     cache::dcache().rehash(src.clone(), || {
@@ -688,3 +997,76 @@ pub fn rename(src: &Path, dest: &Path) -> Result<usize, SyscallError> {
     });
     Ok(0)
 }
+
+#[syscall]
+pub fn mount(target: &Path, args: &MountArgs) -> Result<usize, SyscallError> {
+    if !scheduler::get_scheduler()
+        .current_task()
+        .credentials()
+        .is_superuser()
+    {
+        return Err(SyscallError::EPERM);
+    }
+
+    let source = crate::utils::validate_str(args.source, args.source_len)?;
+    let fs_type = crate::utils::validate_str(args.fs_type, args.fs_type_len)?;
+    let flags = MountFlags::from_bits(args.flags).ok_or(SyscallError::EINVAL)?;
+
+    let target = fs::lookup_path(target)?;
+
+    if !target.inode().metadata()?.is_directory() {
+        return Err(SyscallError::ENOTDIR);
+    }
+
+    let (filesystem, source): (Arc<dyn FileSystem>, String) = match fs_type {
+        "ext2" => {
+            // The source is the path to the block device (e.g. `/dev/nvme0n1p1`).
+            let device = fs::lookup_path(Path::new(source))?;
+
+            if device.inode().metadata()?.file_type() != FileType::Device {
+                return Err(SyscallError::ENOTBLK);
+            }
+
+            let block = block::find_block_device(&device.name()).ok_or(SyscallError::ENOTBLK)?;
+            let source = alloc::format!("/dev/{}", block.name());
+
+            // Each [`Ext2`] instance keeps its own view of the allocation state, so a
+            // block device cannot be mounted twice.
+            if fs::MOUNT_MANAGER
+                .mounts()
+                .iter()
+                .any(|mount| mount.source == source)
+            {
+                return Err(SyscallError::EBUSY);
+            }
+
+            (Ext2::new(block).ok_or(SyscallError::EINVAL)?, source)
+        }
+
+        "ramfs" => (RamFs::new(), String::from(fs_type)),
+        "tmpfs" => (TmpFs::new(), String::from(fs_type)),
+        "proc" => (ProcFs::new()?, String::from(fs_type)),
+        "devpts" => (pty::pts_filesystem(), String::from(fs_type)),
+
+        _ => return Err(SyscallError::ENODEV),
+    };
+
+    fs::MOUNT_MANAGER.mount_with(target, filesystem, source, flags)?;
+    Ok(0)
+}
+
+#[syscall]
+pub fn umount(target: &Path) -> Result<usize, SyscallError> {
+    if !scheduler::get_scheduler()
+        .current_task()
+        .credentials()
+        .is_superuser()
+    {
+        return Err(SyscallError::EPERM);
+    }
+
+    let target = fs::lookup_path(target)?;
+
+    fs::MOUNT_MANAGER.unmount(target)?;
+    Ok(0)
+}
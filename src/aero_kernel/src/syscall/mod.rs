@@ -26,6 +26,7 @@ mod futex;
 mod ipc;
 mod net;
 mod process;
+mod ptrace;
 mod time;
 
 use alloc::boxed::Box;
@@ -68,6 +69,19 @@ impl ExecArgs {
 
         tops
     }
+
+    /// Returns the arguments as consecutive NUL-terminated strings (the format of
+    /// `/proc/<pid>/cmdline`).
+    pub fn to_nul_separated(&self) -> Vec<u8> {
+        let mut result = Vec::new();
+
+        for arg in self.inner.iter() {
+            result.extend_from_slice(arg);
+            result.push(0);
+        }
+
+        result
+    }
 }
 
 pub fn exec_args_from_slice(args: usize, size: usize) -> ExecArgs {
@@ -176,6 +190,11 @@ pub fn generic_do_syscall(
         SYS_MMAP => process::mmap(b, c, d, e, f, g),
         SYS_MUNMAP => process::munmap(b, c),
         SYS_MPROTECT => process::mprotect(b, c, d),
+        SYS_MSYNC => process::msync(b, c, d),
+        SYS_MADVISE => process::madvise(b, c, d),
+        SYS_MLOCK => process::mlock(b, c),
+        SYS_MUNLOCK => process::munlock(b, c),
+        SYS_MINCORE => process::mincore(b, c, d, e),
         SYS_EXEC => process::exec(b, c, d, e, f, g),
         SYS_LOG => process::log(b, c),
         SYS_UNAME => process::uname(b),
@@ -186,8 +205,14 @@ pub fn generic_do_syscall(
         SYS_GETHOSTNAME => process::gethostname(b, c),
         SYS_SETHOSTNAME => process::sethostname(b, c),
         SYS_INFO => process::info(b),
+        SYS_GETRANDOM => process::getrandom(b, c, d),
         SYS_SIGACTION => process::sigaction(b, c, d, e),
         SYS_SIGPROCMASK => process::sigprocmask(b, c, d),
+        SYS_SIGALTSTACK => process::sigaltstack(b, c),
+        SYS_SIGQUEUEINFO => process::sigqueueinfo(b, c, d),
+        SYS_SIGTIMEDWAIT => process::sigtimedwait(b, c, d),
+        SYS_SIGSUSPEND => process::sigsuspend(b),
+        SYS_PTRACE => ptrace::ptrace(b, c, d, e),
         SYS_CLONE => process::clone(b, c),
         SYS_KILL => process::kill(b, c),
         SYS_BACKTRACE => process::backtrace(),
@@ -195,15 +220,22 @@ pub fn generic_do_syscall(
         SYS_SETPGID => process::setpgid(b, c),
         SYS_SETSID => process::setsid(),
         SYS_GETPGID => process::getpgid(b),
+        SYS_GETUID => process::getuid(),
+        SYS_GETEUID => process::geteuid(),
+        SYS_GETGID => process::getgid(),
+        SYS_GETEGID => process::getegid(),
+        SYS_SETUID => process::setuid(b),
+        SYS_SETGID => process::setgid(b),
+        SYS_GETGROUPS => process::getgroups(b, c),
 
         SYS_READ => fs::read(b, c, d),
-        SYS_OPEN => fs::open(b, c, d, e),
+        SYS_OPEN => fs::open(b, c, d, e, f),
         SYS_CLOSE => fs::close(b),
         SYS_WRITE => fs::write(b, c, d),
         SYS_GETDENTS => fs::getdents(b, c, d),
         SYS_GETCWD => fs::getcwd(b, c),
         SYS_CHDIR => fs::chdir(b, c),
-        SYS_MKDIR_AT => fs::mkdirat(b, c, d),
+        SYS_MKDIR_AT => fs::mkdirat(b, c, d, e),
         SYS_RMDIR => fs::rmdir(b, c),
         SYS_IOCTL => fs::ioctl(b, c, d),
         SYS_SEEK => fs::seek(b, c, d),
@@ -217,9 +249,15 @@ pub fn generic_do_syscall(
         SYS_FSTAT => fs::fstat(b, c),
         SYS_READ_LINK => fs::read_link(b, c, d, e),
         SYS_EVENT_FD => fs::event_fd(b, c),
+        SYS_MEMFD_CREATE => fs::memfd_create(b, c, d),
         SYS_LINK => fs::link(b, c, d, e),
         SYS_POLL => fs::poll(b, c, d, e),
         SYS_RENAME => fs::rename(b, c, d, e),
+        SYS_MOUNT => fs::mount(b, c, d),
+        SYS_UMOUNT => fs::umount(b, c),
+        SYS_FCHMODAT => fs::fchmodat(b, c, d, e, f),
+        SYS_FCHOWNAT => fs::fchownat(b, c, d, e, f, g),
+        SYS_UTIMENSAT => fs::utimensat(b, c, d, e, f),
 
         // epoll calls:
         SYS_EPOLL_CREATE => fs::epoll_create(b),
@@ -237,8 +275,12 @@ pub fn generic_do_syscall(
         SYS_SOCK_SHUTDOWN => net::shutdown(b, c),
         SYS_GETPEERNAME => net::get_peername(b, c, d),
         SYS_GETSOCKNAME => net::get_sockname(b, c, d),
+        SYS_SETSOCKOPT => net::setsockopt(b, c, d, e, f),
+        SYS_GETSOCKOPT => net::getsockopt(b, c, d, e, f),
 
         SYS_GETTIME => time::gettime(b, c),
+        SYS_CLOCK_GETRES => time::getres(b, c),
+        SYS_CLOCK_SETTIME => time::settime(b, c),
         SYS_SLEEP => time::sleep(b),
 
         SYS_SETITIMER => time::setitimer(b, c, d),
@@ -253,7 +295,7 @@ pub fn generic_do_syscall(
         SYS_FUTEX_WAKE => futex::wake(b),
 
         // Syscall aliases (this should be handled in aero_syscall)
-        SYS_MKDIR => fs::mkdirat(aero_syscall::AT_FDCWD as _, b, c),
+        SYS_MKDIR => fs::mkdirat(aero_syscall::AT_FDCWD as _, b, c, d),
 
         _ => {
             log::error!("invalid syscall: {:#x}", a);
@@ -16,6 +16,7 @@ use crate::socket::unix::*;
 use crate::socket::{SocketAddr, SocketAddrRef};
 
 use crate::userland::scheduler;
+use crate::userland::task::credentials::Credentials;
 
 /// Creates a [`SocketAddr`] from the provided userland socket structure address. This
 /// is done by looking at the family field present in every socket address structure.
@@ -79,6 +80,11 @@ pub fn sock_send(
 ) -> Result<usize, SyscallError> {
     let flags = MessageFlags::from_bits(flags).ok_or(SyscallError::EINVAL)?;
 
+    // The control messages are parsed straight from the user buffer.
+    if !header.control_ptr().is_null() {
+        crate::utils::validate_slice(header.control_ptr(), header.control_len())?;
+    }
+
     let current_task = scheduler::get_scheduler().current_task();
     let socket = current_task
         .file_table
@@ -96,6 +102,11 @@ pub fn sock_recv(
 ) -> Result<usize, SyscallError> {
     let flags = MessageFlags::from_bits(flags).ok_or(SyscallError::EINVAL)?;
 
+    // The control messages are written straight to the user buffer.
+    if !header.control_ptr().is_null() {
+        crate::utils::validate_slice_mut(header.control_ptr(), header.control_len())?;
+    }
+
     let current_task = scheduler::get_scheduler().current_task();
     let socket = current_task
         .file_table
@@ -128,7 +139,16 @@ fn create_socket(
     let protocol = IpProtocol::from_usize(protocol).ok_or(SyscallError::EINVAL)?;
 
     let (name, socket) = match domain as u32 {
-        AF_UNIX => ("unix", UnixSocket::new() as Arc<dyn INodeInterface>),
+        AF_UNIX => match typ {
+            SocketType::Stream | SocketType::Dgram | SocketType::SeqPacket => {
+                ("unix", UnixSocket::new(typ) as Arc<dyn INodeInterface>)
+            }
+
+            _ => {
+                log::warn!("unsupported socket type: domain={domain}, socket_type={socket_type}");
+                return Err(SyscallError::EINVAL);
+            }
+        },
         AF_INET => match (typ, protocol) {
             (SocketType::Dgram, IpProtocol::Default | IpProtocol::Udp) => {
                 ("udp", UdpSocket::new() as Arc<dyn INodeInterface>)
@@ -138,6 +158,16 @@ fn create_socket(
                 ("ipv4", Ipv4Socket::new() as Arc<dyn INodeInterface>)
             }
 
+            // Raw sockets can be used to forge packets, so they are restricted to the
+            // superuser.
+            (SocketType::Raw, IpProtocol::Icmp) => {
+                if !Credentials::current().is_superuser() {
+                    return Err(SyscallError::EPERM);
+                }
+
+                ("icmp", Ipv4Socket::new_icmp() as Arc<dyn INodeInterface>)
+            }
+
             (SocketType::Stream, IpProtocol::Default | IpProtocol::Tcp) => {
                 ("tcp", TcpSocket::new() as Arc<dyn INodeInterface>)
             }
@@ -256,6 +286,47 @@ pub fn get_sockname(fd: usize, addr: usize, len: &mut u32) -> Result<usize, Sysc
     Ok(0)
 }
 
+/// Sets the socket option `name` at the protocol `level` (e.g. `SOL_SOCKET`) to `value`.
+#[syscall]
+pub fn setsockopt(
+    fd: usize,
+    level: usize,
+    name: usize,
+    value: &[u8],
+) -> Result<usize, SyscallError> {
+    let file = scheduler::get_scheduler()
+        .current_task()
+        .file_table
+        .get_handle(fd)
+        .ok_or(SyscallError::EBADF)?;
+
+    file.inode().set_sockopt(level, name, value)?;
+    Ok(0)
+}
+
+/// Reads the value of the socket option `name` at the protocol `level` into the buffer at
+/// `value`. On entry, `length` contains the size of the buffer and on return, it contains
+/// the size of the option.
+#[syscall]
+pub fn getsockopt(
+    fd: usize,
+    level: usize,
+    name: usize,
+    value: usize,
+    length: &mut u32,
+) -> Result<usize, SyscallError> {
+    let file = scheduler::get_scheduler()
+        .current_task()
+        .file_table
+        .get_handle(fd)
+        .ok_or(SyscallError::EBADF)?;
+
+    let buffer = VirtAddr::new(value as u64).as_bytes_mut(*length as usize);
+
+    *length = file.inode().get_sockopt(level, name, buffer)? as u32;
+    Ok(0)
+}
+
 /// Create an unbound pair of connected sockets in a specified domain, of a
 /// specified type, under the protocol optionally specified by the protocol
 /// argument. The two sockets shall be identical. The file descriptors used
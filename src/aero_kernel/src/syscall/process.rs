@@ -15,18 +15,22 @@
 // You should have received a copy of the GNU General Public License
 // along with Aero. If not, see <https://www.gnu.org/licenses/>.
 
-use aero_syscall::signal::{SigAction, SigProcMask};
+use aero_syscall::consts::AccessMode;
+use aero_syscall::signal::*;
 use aero_syscall::*;
 use alloc::sync::Arc;
+use num_traits::cast::FromPrimitive;
 use spin::{Mutex, Once};
 
 use crate::acpi::aml;
 use crate::fs;
+use crate::fs::shmem::SharedMemory;
 use crate::fs::Path;
 
-use crate::mem::paging::VirtAddr;
+use crate::mem::paging::{PageSize, Size4KiB, VirtAddr};
+use crate::random;
 use crate::userland::scheduler::{self, ExitStatus};
-use crate::userland::signals::SignalEntry;
+use crate::userland::signals::{is_on_stack, SignalEntry, SIGNAL_COUNT};
 use crate::userland::task::sessions::SESSIONS;
 use crate::userland::task::{Task, TaskId};
 use crate::utils::sync::IrqGuard;
@@ -107,7 +111,15 @@ pub fn kill(pid: usize, signal: usize) -> Result<usize, SyscallError> {
             .find_task(TaskId::new(pid))
             .ok_or(SyscallError::ESRCH)?;
 
-        task.signal(signal);
+        let current_task = scheduler::get_scheduler().current_task();
+        let info = SigInfo::from_process(
+            signal,
+            SI_USER,
+            current_task.pid().as_usize(),
+            current_task.credentials().uid,
+        );
+
+        task.send_signal(signal, info);
         Ok(0)
     } else {
         unimplemented!()
@@ -128,6 +140,10 @@ pub fn exec(
         return Err(SyscallError::EISDIR);
     }
 
+    // The credentials may be changed by a set-user-ID or set-group-ID executable, so the
+    // permission to execute it has to be checked first.
+    fs::check_access(&executable.inode(), AccessMode::X_OK)?;
+
     // NOTE: Neither args nor envs should be used after this point, the kernel
     // now has owned copies in args and environment variables.
     let argv = if argc > 0 {
@@ -178,23 +194,57 @@ pub fn mmap(
     let flags = MMapFlags::from_bits(flags).ok_or(SyscallError::EINVAL)?;
 
     let mut file = None;
+    let mut max_protection = MMapProt::all();
 
     if fd as isize != -1 {
-        file = Some(
-            scheduler::get_scheduler()
-                .current_task()
-                .file_table
-                .get_handle(fd)
-                .ok_or(SyscallError::EBADF)?
-                .dirnode(),
-        );
+        let handle = scheduler::get_scheduler()
+            .current_task()
+            .file_table
+            .get_handle(fd)
+            .ok_or(SyscallError::EBADF)?;
+
+        // Writes to a shared mapping go through to the file, so it can only be made
+        // writable if the file was opened for writing and lives on a writable filesystem.
+        if flags.contains(MMapFlags::MAP_SHARED)
+            && (!handle.flags.read().is_writable() || fs::ensure_writable(&handle.inode()).is_err())
+        {
+            max_protection.remove(MMapProt::PROT_WRITE);
+        }
+
+        file = Some(handle.dirnode());
     }
 
-    if let Some(alloc) = scheduler::get_scheduler()
-        .current_task()
-        .vm()
-        .mmap(address, size, protection, flags, offset, file)
+    if !max_protection.contains(protection) {
+        return Err(SyscallError::EACCES);
+    }
+
+    // A file that is sealed against writes cannot be mapped shared and writable.
+    if let Some(memory) = file
+        .as_ref()
+        .and_then(|file| file.inode().downcast_arc::<SharedMemory>())
     {
+        if flags.contains(MMapFlags::MAP_SHARED)
+            && protection.contains(MMapProt::PROT_WRITE)
+            && memory.is_write_sealed()
+        {
+            return Err(SyscallError::EPERM);
+        }
+
+        // ... and mprotect(2) must not be able to make such a mapping writable later on.
+        if flags.contains(MMapFlags::MAP_SHARED) && memory.is_write_sealed() {
+            max_protection.remove(MMapProt::PROT_WRITE);
+        }
+    }
+
+    if let Some(alloc) = scheduler::get_scheduler().current_task().vm().mmap(
+        address,
+        size,
+        protection,
+        max_protection,
+        flags,
+        offset,
+        file,
+    ) {
         Ok(alloc.as_u64() as usize)
     } else {
         Err(SyscallError::EFAULT)
@@ -222,7 +272,73 @@ pub fn mprotect(ptr: usize, size: usize, prot: usize) -> Result<usize, SyscallEr
     let prot = MMapProt::from_bits(prot).ok_or(SyscallError::EINVAL)?;
 
     let task = scheduler::get_scheduler().current_task();
-    task.vm().mprotect(ptr, size, prot);
+    task.vm().mprotect(ptr, size, prot)?;
+
+    Ok(0)
+}
+
+#[syscall]
+pub fn msync(address: usize, size: usize, flags: usize) -> Result<usize, SyscallError> {
+    let address = VirtAddr::new(address as u64);
+    let flags = MSyncFlags::from_bits(flags).ok_or(SyscallError::EINVAL)?;
+
+    if !address.is_aligned(Size4KiB::SIZE)
+        || flags.contains(MSyncFlags::MS_SYNC | MSyncFlags::MS_ASYNC)
+    {
+        return Err(SyscallError::EINVAL);
+    }
+
+    // NOTE: The pages are written back to the page cache of the block device in both
+    // cases and reads are served from the shared pages, so `MS_INVALIDATE` has nothing
+    // left to do.
+    let task = scheduler::get_scheduler().current_task();
+
+    if task.vm().msync(address, size)? {
+        Ok(0)
+    } else {
+        Err(SyscallError::ENOMEM)
+    }
+}
+
+#[syscall]
+pub fn madvise(address: usize, size: usize, advice: usize) -> Result<usize, SyscallError> {
+    let address = VirtAddr::new(address as u64);
+    let advice = MAdvice::from_usize(advice).ok_or(SyscallError::EINVAL)?;
+
+    let task = scheduler::get_scheduler().current_task();
+    task.vm().madvise(address, size, advice)?;
+
+    Ok(0)
+}
+
+#[syscall]
+pub fn mlock(address: usize, size: usize) -> Result<usize, SyscallError> {
+    let address = VirtAddr::new(address as u64);
+
+    let task = scheduler::get_scheduler().current_task();
+    task.vm().mlock(address, size)?;
+
+    Ok(0)
+}
+
+#[syscall]
+pub fn munlock(address: usize, size: usize) -> Result<usize, SyscallError> {
+    let address = VirtAddr::new(address as u64);
+
+    let task = scheduler::get_scheduler().current_task();
+    task.vm().munlock(address, size)?;
+
+    Ok(0)
+}
+
+/// Reports whether the pages in the provided range are resident in memory. `residency`
+/// holds a byte for each page in the range.
+#[syscall]
+pub fn mincore(address: usize, size: usize, residency: &mut [u8]) -> Result<usize, SyscallError> {
+    let address = VirtAddr::new(address as u64);
+
+    let task = scheduler::get_scheduler().current_task();
+    task.vm().mincore(address, size, residency)?;
 
     Ok(0)
 }
@@ -261,6 +377,62 @@ pub fn gettid() -> Result<usize, SyscallError> {
     Ok(scheduler::get_scheduler().current_task().tid().as_usize())
 }
 
+#[syscall]
+pub fn getuid() -> Result<usize, SyscallError> {
+    Ok(scheduler::get_scheduler().current_task().credentials().uid as usize)
+}
+
+#[syscall]
+pub fn geteuid() -> Result<usize, SyscallError> {
+    Ok(scheduler::get_scheduler().current_task().credentials().euid as usize)
+}
+
+#[syscall]
+pub fn getgid() -> Result<usize, SyscallError> {
+    Ok(scheduler::get_scheduler().current_task().credentials().gid as usize)
+}
+
+#[syscall]
+pub fn getegid() -> Result<usize, SyscallError> {
+    Ok(scheduler::get_scheduler().current_task().credentials().egid as usize)
+}
+
+#[syscall]
+pub fn setuid(uid: usize) -> Result<usize, SyscallError> {
+    let task = scheduler::get_scheduler().current_task();
+    let uid = u32::try_from(uid).map_err(|_| SyscallError::EINVAL)?;
+
+    task.credentials_mut().set_uid(uid)?;
+    Ok(0)
+}
+
+#[syscall]
+pub fn setgid(gid: usize) -> Result<usize, SyscallError> {
+    let task = scheduler::get_scheduler().current_task();
+    let gid = u32::try_from(gid).map_err(|_| SyscallError::EINVAL)?;
+
+    task.credentials_mut().set_gid(gid)?;
+    Ok(0)
+}
+
+#[syscall]
+pub fn getgroups(groups: &mut [u32]) -> Result<usize, SyscallError> {
+    let credentials = scheduler::get_scheduler().current_task().credentials();
+
+    // If the size of the buffer is zero, the number of supplementary group IDs is
+    // returned without modifying the buffer.
+    if groups.is_empty() {
+        return Ok(credentials.groups.len());
+    }
+
+    if groups.len() < credentials.groups.len() {
+        return Err(SyscallError::EINVAL);
+    }
+
+    groups[..credentials.groups.len()].copy_from_slice(&credentials.groups);
+    Ok(credentials.groups.len())
+}
+
 #[syscall]
 pub fn gethostname(buffer: &mut [u8]) -> Result<usize, SyscallError> {
     let hostname = hostname().lock();
@@ -282,6 +454,29 @@ pub fn info(struc: &mut SysInfo) -> Result<usize, SyscallError> {
     Ok(0x00)
 }
 
+/// Fills `buffer` with the output of the kernel random number generator. Blocks until the
+/// generator has been seeded with enough entropy, unless `GRND_NONBLOCK` (fails with
+/// `EAGAIN` instead) or `GRND_INSECURE` is set.
+#[syscall]
+pub fn getrandom(buffer: &mut [u8], flags: usize) -> Result<usize, SyscallError> {
+    let flags = GrndFlags::from_bits(flags).ok_or(SyscallError::EINVAL)?;
+
+    if flags.contains(GrndFlags::GRND_RANDOM | GrndFlags::GRND_INSECURE) {
+        return Err(SyscallError::EINVAL);
+    }
+
+    if !flags.contains(GrndFlags::GRND_INSECURE) && !random::is_initialized() {
+        if flags.contains(GrndFlags::GRND_NONBLOCK) {
+            return Err(SyscallError::EAGAIN);
+        }
+
+        random::wait_for_entropy()?;
+    }
+
+    random::get_random_bytes(buffer);
+    Ok(buffer.len())
+}
+
 #[syscall]
 pub fn sethostname(name: &[u8]) -> Result<usize, SyscallError> {
     match core::str::from_utf8(name) {
@@ -352,6 +547,156 @@ pub fn sigaction(
     Ok(0)
 }
 
+#[syscall]
+pub fn sigaltstack(
+    stack: *const SignalStack,
+    old: *mut SignalStack,
+) -> Result<usize, SyscallError> {
+    let task = scheduler::get_scheduler().current_task();
+    let signals = task.signals();
+    let sp = task.arch_task().user_stack_pointer();
+
+    if !old.is_null() {
+        let old = crate::utils::validate_mut_ptr(old)?;
+        let current = signals.alt_stack();
+
+        *old = SignalStack {
+            ss_flags: if is_on_stack(&current, sp) {
+                SS_ONSTACK
+            } else {
+                current.ss_flags
+            },
+            ..current
+        };
+    }
+
+    if !stack.is_null() {
+        let stack = crate::utils::validate_ptr(stack)?;
+        signals.set_alt_stack(*stack, sp)?;
+    }
+
+    Ok(0)
+}
+
+/// Sends `signal` along with the provided `siginfo_t` to the process `pid`. Only the kernel
+/// and `kill(2)` may report a non-negative `si_code`, so it can only be used to impersonate
+/// them when the target is the calling process.
+#[syscall]
+pub fn sigqueueinfo(
+    pid: usize,
+    signal: usize,
+    info: *const SigInfo,
+) -> Result<usize, SyscallError> {
+    if signal >= SIGNAL_COUNT {
+        return Err(SyscallError::EINVAL);
+    }
+
+    let current_task = scheduler::get_scheduler().current_task();
+    let task = find_task_by_pid(pid)?;
+
+    let mut info = *crate::utils::validate_ptr(info)?;
+
+    if info.si_code >= 0 && !Arc::ptr_eq(&task, &current_task) {
+        return Err(SyscallError::EPERM);
+    }
+
+    info.si_signo = signal as i32;
+
+    if signal != 0 {
+        task.send_signal(signal, info);
+    }
+
+    Ok(0)
+}
+
+/// Waits for one of the signals in `set` to become pending and accepts it. Returns `EAGAIN`
+/// if `timeout` elapses first and `EINTR` if the wait is interrupted by another signal.
+#[syscall]
+pub fn sigtimedwait(
+    set: *const u64,
+    info: *mut SigInfo,
+    timeout: *const TimeSpec,
+) -> Result<usize, SyscallError> {
+    let set = *crate::utils::validate_ptr(set)? & !((1u64 << SIGKILL) | (1u64 << SIGSTOP));
+
+    let timeout = if timeout.is_null() {
+        None
+    } else {
+        let timeout = crate::utils::validate_ptr(timeout)?;
+        Some((timeout.tv_nsec as usize).div_ceil(1000000000) + timeout.tv_sec as usize)
+    };
+
+    let info = if info.is_null() {
+        None
+    } else {
+        Some(crate::utils::validate_mut_ptr(info)?)
+    };
+
+    let scheduler = scheduler::get_scheduler();
+    let task = scheduler.current_task();
+    let signals = task.signals();
+
+    // The signals in the set are delivered to this thread instead of being ignored or
+    // handled while it is waiting for them.
+    signals.set_waiting(set);
+
+    let result = loop {
+        if let Some((signal, accepted)) = signals.dequeue_any(set) {
+            if let Some(info) = info {
+                *info = accepted;
+            }
+
+            break Ok(signal);
+        }
+
+        match timeout {
+            Some(0) => break Err(SyscallError::EAGAIN),
+            Some(duration) => match scheduler.inner.sleep(Some(duration)) {
+                // Check if we were woken up by one of the signals in the set.
+                Ok(()) => match signals.dequeue_any(set) {
+                    Some((signal, accepted)) => {
+                        if let Some(info) = info {
+                            *info = accepted;
+                        }
+
+                        break Ok(signal);
+                    }
+
+                    None => break Err(SyscallError::EAGAIN),
+                },
+
+                Err(_) if signals.pending() & set != 0 => continue,
+                Err(_) => break Err(SyscallError::EINTR),
+            },
+
+            None => match scheduler.inner.sleep(None) {
+                Ok(()) => continue,
+                Err(_) if signals.pending() & set != 0 => continue,
+                Err(_) => break Err(SyscallError::EINTR),
+            },
+        }
+    };
+
+    signals.set_waiting(0);
+    result
+}
+
+/// Replaces the blocked mask with `mask` and waits until a signal is delivered. The
+/// original mask is restored once the signal handler returns.
+#[syscall]
+pub fn sigsuspend(mask: *const u64) -> Result<usize, SyscallError> {
+    let mask = *crate::utils::validate_ptr(mask)? & !((1u64 << SIGKILL) | (1u64 << SIGSTOP));
+
+    let scheduler = scheduler::get_scheduler();
+    let task = scheduler.current_task();
+
+    task.signals().suspend_mask(mask);
+
+    while scheduler.inner.sleep(None).is_ok() {}
+
+    Err(SyscallError::EINTR)
+}
+
 #[syscall(no_return)]
 pub fn shutdown() -> Result<usize, SyscallError> {
     fs::cache::dcache().log();
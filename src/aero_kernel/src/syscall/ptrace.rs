@@ -0,0 +1,237 @@
+// Copyright (C) 2021-2023 The Aero Project Developers.
+//
+// This file is part of The Aero Project.
+//
+// Aero is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Aero is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Aero. If not, see <https://www.gnu.org/licenses/>.
+
+use aero_syscall::ptrace::*;
+use aero_syscall::signal::{SigInfo, SIGKILL};
+use aero_syscall::SyscallError;
+use alloc::sync::Arc;
+use num_traits::cast::FromPrimitive;
+
+use crate::mem::paging::VirtAddr;
+use crate::userland::scheduler;
+use crate::userland::task::ptrace::{self, Resume};
+use crate::userland::task::{Task, TaskId};
+use crate::utils::{validate_mut_ptr, validate_ptr};
+
+/// Size of `struct user`. The general purpose registers are at the start of the structure;
+/// the rest of it reads as zeros and cannot be written.
+const USER_AREA_SIZE: usize = 912;
+
+#[syscall]
+pub fn ptrace(request: usize, pid: usize, addr: usize, data: usize) -> Result<usize, SyscallError> {
+    let request = PtraceRequest::from_usize(request).ok_or(SyscallError::EIO)?;
+
+    let current_task = scheduler::get_scheduler().current_task();
+
+    if request == PtraceRequest::TraceMe {
+        ptrace::trace_me(&current_task)?;
+        return Ok(0);
+    }
+
+    let tracee = scheduler::get_scheduler()
+        .find_task(TaskId::new(pid))
+        .ok_or(SyscallError::ESRCH)?;
+
+    match request {
+        PtraceRequest::Attach => {
+            ptrace::attach(&current_task, &tracee, false, PtraceOptions::empty())?
+        }
+
+        PtraceRequest::Seize => {
+            let options = PtraceOptions::from_bits(data).ok_or(SyscallError::EINVAL)?;
+            ptrace::attach(&current_task, &tracee, true, options)?;
+        }
+
+        PtraceRequest::PeekText | PtraceRequest::PeekData => {
+            ptrace::check_stopped(&current_task, &tracee)?;
+
+            let mut word = [0; 8];
+            read_memory(&tracee, addr, &mut word)?;
+
+            *validate_mut_ptr(data as *mut u64)? = u64::from_ne_bytes(word);
+        }
+
+        PtraceRequest::PokeText | PtraceRequest::PokeData => {
+            ptrace::check_stopped(&current_task, &tracee)?;
+            write_memory(&tracee, addr, &(data as u64).to_ne_bytes())?;
+        }
+
+        PtraceRequest::PeekUser => {
+            let word = peek_user(&current_task, &tracee, addr)?;
+            *validate_mut_ptr(data as *mut u64)? = word;
+        }
+
+        PtraceRequest::PokeUser => poke_user(&current_task, &tracee, addr, data as u64)?,
+
+        PtraceRequest::Cont => ptrace::resume(&current_task, &tracee, Resume::Continue, data)?,
+        PtraceRequest::Syscall => ptrace::resume(&current_task, &tracee, Resume::Syscall, data)?,
+
+        PtraceRequest::SingleStep => {
+            ptrace::resume(&current_task, &tracee, Resume::SingleStep, data)?
+        }
+
+        PtraceRequest::Kill => {
+            if tracee
+                .ptrace()
+                .tracer()
+                .map_or(true, |t| !Arc::ptr_eq(&t, &current_task))
+            {
+                return Err(SyscallError::ESRCH);
+            }
+
+            tracee.signal(SIGKILL);
+
+            // The tracee only exits once it is resumed, which fails if it is not stopped.
+            let _ = ptrace::resume(&current_task, &tracee, Resume::Continue, 0);
+        }
+
+        PtraceRequest::GetRegs => {
+            let regs = get_regs(&current_task, &tracee)?;
+            *validate_mut_ptr(data as *mut UserRegs)? = regs;
+        }
+
+        PtraceRequest::SetRegs => {
+            let regs = *validate_ptr(data as *const UserRegs)?;
+            set_regs(&current_task, &tracee, &regs)?;
+        }
+
+        PtraceRequest::GetFpRegs => {
+            ptrace::check_stopped(&current_task, &tracee)?;
+            *validate_mut_ptr(data as *mut [u8; 512])? = tracee.arch_task().fp_regs();
+        }
+
+        PtraceRequest::SetFpRegs => {
+            ptrace::check_stopped(&current_task, &tracee)?;
+
+            let regs = validate_ptr(data as *const [u8; 512])?;
+            tracee.arch_task_mut().set_fp_regs(regs);
+        }
+
+        PtraceRequest::Detach => ptrace::detach(&current_task, &tracee, data)?,
+
+        PtraceRequest::SetOptions => {
+            let options = PtraceOptions::from_bits(data).ok_or(SyscallError::EINVAL)?;
+            ptrace::set_options(&current_task, &tracee, options)?;
+        }
+
+        PtraceRequest::GetEventMsg => {
+            *validate_mut_ptr(data as *mut u64)? = ptrace::event_msg(&current_task, &tracee)?;
+        }
+
+        PtraceRequest::GetSigInfo => {
+            *validate_mut_ptr(data as *mut SigInfo)? = ptrace::siginfo(&current_task, &tracee)?;
+        }
+
+        PtraceRequest::SetSigInfo => {
+            let info = *validate_ptr(data as *const SigInfo)?;
+            ptrace::set_siginfo(&current_task, &tracee, info)?;
+        }
+
+        PtraceRequest::Interrupt => ptrace::interrupt(&current_task, &tracee)?,
+        PtraceRequest::TraceMe => unreachable!(),
+    }
+
+    Ok(0)
+}
+
+fn read_memory(tracee: &Task, address: usize, buffer: &mut [u8]) -> Result<(), SyscallError> {
+    let address_space = tracee.arch_task_mut().address_space();
+
+    if tracee
+        .vm()
+        .read_remote(address_space, VirtAddr::new(address as u64), buffer)
+    {
+        Ok(())
+    } else {
+        Err(SyscallError::EIO)
+    }
+}
+
+fn write_memory(tracee: &Task, address: usize, buffer: &[u8]) -> Result<(), SyscallError> {
+    let address_space = tracee.arch_task_mut().address_space();
+
+    if tracee
+        .vm()
+        .write_remote(address_space, VirtAddr::new(address as u64), buffer)
+    {
+        Ok(())
+    } else {
+        Err(SyscallError::EIO)
+    }
+}
+
+fn get_regs(tracer: &Task, tracee: &Task) -> Result<UserRegs, SyscallError> {
+    let orig_rax = ptrace::syscall_number(tracer, tracee)?;
+
+    Ok(UserRegs {
+        orig_rax,
+        ..tracee.arch_task().user_regs()
+    })
+}
+
+fn set_regs(tracer: &Task, tracee: &Task, regs: &UserRegs) -> Result<(), SyscallError> {
+    ptrace::set_syscall_number(tracer, tracee, regs.orig_rax)?;
+
+    if tracee.arch_task_mut().set_user_regs(regs) {
+        Ok(())
+    } else {
+        Err(SyscallError::EIO)
+    }
+}
+
+/// Validates the `offset` in `struct user` and returns the index of the respective register
+/// in [`UserRegs`] (if any).
+fn user_area_index(offset: usize) -> Result<Option<usize>, SyscallError> {
+    if offset % core::mem::size_of::<u64>() != 0 || offset >= USER_AREA_SIZE {
+        return Err(SyscallError::EIO);
+    }
+
+    if offset < core::mem::size_of::<UserRegs>() {
+        Ok(Some(offset / core::mem::size_of::<u64>()))
+    } else {
+        Ok(None)
+    }
+}
+
+fn regs_as_words(regs: &mut UserRegs) -> &mut [u64] {
+    const WORDS: usize = core::mem::size_of::<UserRegs>() / core::mem::size_of::<u64>();
+
+    // SAFETY: `UserRegs` only consists of `u64` fields.
+    unsafe { core::slice::from_raw_parts_mut(regs as *mut UserRegs as *mut u64, WORDS) }
+}
+
+fn peek_user(tracer: &Task, tracee: &Task, offset: usize) -> Result<u64, SyscallError> {
+    let mut regs = get_regs(tracer, tracee)?;
+
+    match user_area_index(offset)? {
+        Some(index) => Ok(regs_as_words(&mut regs)[index]),
+        None => Ok(0),
+    }
+}
+
+fn poke_user(tracer: &Task, tracee: &Task, offset: usize, value: u64) -> Result<(), SyscallError> {
+    let mut regs = get_regs(tracer, tracee)?;
+
+    match user_area_index(offset)? {
+        Some(index) => {
+            regs_as_words(&mut regs)[index] = value;
+            set_regs(tracer, tracee, &regs)
+        }
+
+        None => Err(SyscallError::EIO),
+    }
+}
@@ -15,18 +15,16 @@
 // You should have received a copy of the GNU General Public License
 // along with Aero. If not, see <https://www.gnu.org/licenses/>.
 
-use aero_syscall::time::{ITimerVal, ITIMER_REAL};
+use aero_syscall::time::*;
 use aero_syscall::{SyscallError, TimeSpec};
 use alloc::sync::Arc;
 use alloc::vec::Vec;
 
 use crate::userland::scheduler;
+use crate::userland::task::credentials::Credentials;
 use crate::userland::task::Task;
 use crate::utils::sync::{IrqGuard, Mutex};
 
-const CLOCK_TYPE_REALTIME: usize = 0;
-const CLOCK_TYPE_MONOTONIC: usize = 1;
-
 #[syscall]
 pub fn sleep(timespec: &TimeSpec) -> Result<usize, SyscallError> {
     let duration = (timespec.tv_nsec as usize).div_ceil(1000000000) + timespec.tv_sec as usize;
@@ -38,28 +36,61 @@ pub fn sleep(timespec: &TimeSpec) -> Result<usize, SyscallError> {
 
 #[syscall]
 pub fn gettime(clock: usize, timespec: &mut TimeSpec) -> Result<usize, SyscallError> {
-    match clock {
-        CLOCK_TYPE_REALTIME => {
-            let clock = crate::arch::time::get_realtime_clock();
+    *timespec = match clock {
+        CLOCK_REALTIME | CLOCK_REALTIME_COARSE => crate::arch::time::get_realtime_clock(),
 
-            timespec.tv_sec = clock.tv_sec;
-            timespec.tv_nsec = clock.tv_nsec;
+        // NOTE: The system is never suspended, so the boot time clock is the same as the
+        // monotonic clock.
+        CLOCK_MONOTONIC | CLOCK_MONOTONIC_RAW | CLOCK_MONOTONIC_COARSE | CLOCK_BOOTTIME => {
+            crate::arch::time::get_monotonic_clock()
+        }
 
-            Ok(0x00)
+        CLOCK_PROCESS_CPUTIME_ID => {
+            TimeSpec::from_nanos(scheduler::current_thread().process_cpu_time())
         }
 
-        CLOCK_TYPE_MONOTONIC => {
-            // FIXME: implement
-            let clock = crate::arch::time::get_realtime_clock();
+        CLOCK_THREAD_CPUTIME_ID => {
+            TimeSpec::from_nanos(scheduler::current_thread().thread_cpu_time())
+        }
 
-            timespec.tv_sec = clock.tv_sec;
-            timespec.tv_nsec = clock.tv_nsec;
+        _ => return Err(SyscallError::EINVAL),
+    };
 
-            Ok(0x00)
-        }
+    Ok(0x00)
+}
+
+#[syscall]
+pub fn getres(clock: usize, res: usize) -> Result<usize, SyscallError> {
+    // NOTE: All of the clocks are backed by the same clocksource, the coarse clocks are not
+    // any faster to read.
+    let resolution = match clock {
+        CLOCK_REALTIME..=CLOCK_BOOTTIME => crate::arch::time::get_clock_resolution(),
+        _ => return Err(SyscallError::EINVAL),
+    };
+
+    // The resolution can be NULL.
+    if res != 0x00 {
+        *crate::utils::validate_mut_ptr(res as *mut TimeSpec)? = resolution;
+    }
 
-        _ => Err(SyscallError::EINVAL),
+    Ok(0x00)
+}
+
+#[syscall]
+pub fn settime(clock: usize, timespec: &TimeSpec) -> Result<usize, SyscallError> {
+    // Only the realtime clock can be set.
+    if clock != CLOCK_REALTIME {
+        return Err(SyscallError::EINVAL);
     }
+
+    let time = timespec.as_nanos().ok_or(SyscallError::EINVAL)?;
+
+    if !Credentials::current().is_superuser() {
+        return Err(SyscallError::EPERM);
+    }
+
+    crate::arch::time::set_realtime_clock(time);
+    Ok(0x00)
 }
 
 static TIMERS: Mutex<Vec<Arc<Task>>> = Mutex::new(Vec::new());
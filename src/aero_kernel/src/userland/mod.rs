@@ -22,6 +22,8 @@ pub mod scheduler;
 pub mod signals;
 pub mod task;
 pub mod terminal;
+#[cfg(target_arch = "x86_64")]
+pub mod vdso;
 pub mod vm;
 
 pub fn run() -> fs::Result<()> {
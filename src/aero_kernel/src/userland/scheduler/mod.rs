@@ -30,7 +30,7 @@ use spin::Once;
 use self::round_robin::RoundRobin;
 use super::signals::SignalResult;
 use super::task::sessions::SESSIONS;
-use super::task::{Task, TaskId};
+use super::task::{ptrace, Task, TaskId};
 
 static SCHEDULER: Once<Scheduler> = Once::new();
 
@@ -46,6 +46,8 @@ pub trait SchedulerInterface: Send + Sync {
 
     fn current_task_optional(&self) -> Option<Arc<Task>>;
 
+    /// Initializes the scheduler on the current CPU. This is called once by the BSP and
+    /// then once by each of the APs, after which the CPU starts accepting tasks.
     fn init(&self);
     fn wake_up(&self, task: Arc<Task>);
 
@@ -84,6 +86,17 @@ pub enum ExitStatus {
     Signal(usize),
 }
 
+impl ExitStatus {
+    /// Returns the status reported by `waitpid(2)`.
+    pub fn wait_status(&self) -> u32 {
+        // mlibc/abis/linux/wait.h (`W_EXITCODE`)
+        match self {
+            ExitStatus::Normal(code) => (*code as u32) << 8,
+            ExitStatus::Signal(signal) => *signal as u32,
+        }
+    }
+}
+
 pub struct Scheduler {
     tasks: TaskContainer,
     pub inner: Arc<dyn SchedulerInterface>,
@@ -124,6 +137,8 @@ impl Scheduler {
 
     pub fn exit(&self, status: ExitStatus) -> ! {
         let current_task = self.inner.current_task();
+        ptrace::exit_stop(&current_task, &status);
+
         SESSIONS.remove_task(current_task.clone());
         self.tasks.remove_task(current_task);
         self.inner.exit(status)
@@ -139,6 +154,11 @@ impl Scheduler {
         });
     }
 
+    /// Calls the provided closure for every registered task.
+    pub fn for_each_task(&self, f: impl FnMut(&Arc<Task>)) {
+        self.tasks.0.lock().values().for_each(f);
+    }
+
     /// Lookup a task by ID
     #[inline]
     pub fn find_task(&self, task_id: TaskId) -> Option<Arc<Task>> {
@@ -189,3 +209,67 @@ pub fn init() {
     crate::arch::apic::get_local_apic().timer_oneshot(scheduler_vector, SCHEDULER_TIMER_US);
     SCHEDULER_VECTOR.call_once(|| scheduler_vector);
 }
+
+/// Initialize the scheduler on the current application processor and start its
+/// scheduler timer.
+///
+/// ## Panics
+/// * If the scheduler has not been initialized by the BSP.
+pub fn init_ap() {
+    get_scheduler().inner.init();
+
+    #[cfg(target_arch = "x86_64")]
+    crate::arch::apic::get_local_apic().timer_oneshot(
+        *SCHEDULER_VECTOR
+            .get()
+            .expect("init_ap: scheduler was not initialized by the BSP"),
+        SCHEDULER_TIMER_US,
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use core::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+
+    static FINISHED: AtomicUsize = AtomicUsize::new(0);
+    static USED_CPUS: AtomicUsize = AtomicUsize::new(0);
+
+    fn busy_task() {
+        for _ in 0..16 {
+            get_scheduler().inner.preempt();
+        }
+
+        USED_CPUS.fetch_or(1 << crate::arch::tls::get_cpuid(), Ordering::SeqCst);
+        FINISHED.fetch_add(1, Ordering::SeqCst);
+
+        get_scheduler().exit(ExitStatus::Normal(0));
+    }
+
+    #[test]
+    fn more_tasks_than_cpus() {
+        let scheduler = get_scheduler();
+        let online_cpus = scheduler
+            .inner
+            .downcast_arc::<RoundRobin>()
+            .unwrap()
+            .online_cpus();
+
+        let task_count = online_cpus * 4;
+
+        for _ in 0..task_count {
+            scheduler.register_task(Task::new_kernel(busy_task, true));
+        }
+
+        while FINISHED.load(Ordering::SeqCst) != task_count {
+            scheduler.inner.preempt();
+        }
+
+        let used_cpus = USED_CPUS.load(Ordering::SeqCst).count_ones() as usize;
+
+        // Every CPU that was online should have been handed at least one of the tasks. An AP
+        // that came online while the tasks were being registered may have been handed some too.
+        assert!(used_cpus >= online_cpus);
+    }
+}
@@ -16,6 +16,9 @@
 // along with Aero. If not, see <https://www.gnu.org/licenses/>.
 
 use alloc::sync::Arc;
+use alloc::vec::Vec;
+
+use core::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
 
 use intrusive_collections::LinkedList;
 
@@ -23,7 +26,7 @@ use crate::arch;
 use crate::userland::signals::{SignalError, SignalResult};
 use crate::userland::task::{SchedTaskAdapter, Task, TaskState};
 
-use crate::utils::sync::{IrqGuard, WaitQueue};
+use crate::utils::sync::{IrqGuard, Mutex, WaitQueue};
 use crate::utils::PerCpu;
 
 use super::{ExitStatus, SchedulerInterface};
@@ -92,6 +95,38 @@ impl TaskQueue {
         task.update_state(TaskState::AwaitingIo);
         self.awaiting.push_back(task);
     }
+
+    /// Moves the provided task from one of the awaiting queues into the runnable
+    /// queue. If the task is not awaiting, the wake up is recorded as pending I/O
+    /// so that the next sleep returns immediately.
+    fn wake_up(&mut self, task: Arc<Task>) {
+        if task.state() == TaskState::AwaitingIo && task.link.is_linked() {
+            let awaiting = if task.load_sleep_duration() != 0 {
+                &mut self.deadline_awaiting
+            } else {
+                &mut self.awaiting
+            };
+
+            let mut cursor = unsafe { awaiting.cursor_mut_from_ptr(task.as_ref()) };
+
+            if let Some(task) = cursor.remove() {
+                task.set_sleep_duration(0);
+                self.push_runnable(task);
+            }
+        } else {
+            task.set_pending_io(true)
+        }
+    }
+}
+
+/// Requests sent to a CPU by another CPU. A CPU's task queue is only ever modified
+/// by the CPU itself, so other CPUs post their requests here and the owning CPU
+/// applies them the next time it schedules.
+enum Request {
+    /// Enqueue a newly registered task.
+    Spawn(Arc<Task>),
+    /// Wake up a task that is (possibly) awaiting.
+    WakeUp(Arc<Task>),
 }
 
 /// Round Robin is the simplest algorithm for a preemptive scheduler. When the
@@ -103,6 +138,12 @@ impl TaskQueue {
 pub struct RoundRobin {
     /// The per-cpu scheduler queues.
     queue: PerCpu<TaskQueue>,
+    /// The per-cpu queues of requests posted by other CPUs.
+    requests: PerCpu<Mutex<Vec<Request>>>,
+    /// The number of tasks assigned to each CPU that have not been sweeped yet.
+    load: PerCpu<AtomicUsize>,
+    /// Whether the CPU has initialized its scheduler and is accepting tasks.
+    online: PerCpu<AtomicBool>,
 }
 
 impl RoundRobin {
@@ -111,9 +152,46 @@ impl RoundRobin {
     pub fn new() -> Arc<Self> {
         Arc::new(Self {
             queue: PerCpu::new(TaskQueue::new),
+            requests: PerCpu::new(|| Mutex::new(Vec::new())),
+            load: PerCpu::new(|| AtomicUsize::new(0)),
+            online: PerCpu::new(|| AtomicBool::new(false)),
         })
     }
 
+    /// Returns the number of CPUs that are accepting tasks.
+    pub fn online_cpus(&self) -> usize {
+        (0..self.online.cpu_count())
+            .filter(|cpu| self.online.get_for(*cpu).load(Ordering::SeqCst))
+            .count()
+    }
+
+    /// Returns the online CPU with the least amount of tasks assigned to it.
+    fn least_loaded_cpu(&self) -> usize {
+        (0..self.online.cpu_count())
+            .filter(|cpu| self.online.get_for(*cpu).load(Ordering::SeqCst))
+            .min_by_key(|cpu| self.load.get_for(*cpu).load(Ordering::SeqCst))
+            .unwrap_or_else(arch::tls::get_cpuid)
+    }
+
+    fn post_request(&self, cpu: usize, request: Request) {
+        self.requests.get_for(cpu).lock_irq().push(request);
+    }
+
+    /// Applies the requests posted to the current CPU by other CPUs.
+    fn handle_requests(&self) {
+        let _guard = IrqGuard::new();
+        let queue = self.queue.get_mut();
+
+        let requests = core::mem::take(&mut *self.requests.get().lock());
+
+        for request in requests {
+            match request {
+                Request::Spawn(task) => queue.push_runnable(task),
+                Request::WakeUp(task) => queue.wake_up(task),
+            }
+        }
+    }
+
     fn sweep_dead(&self) {
         let _guard = IrqGuard::new();
         let queue = self.queue.get_mut();
@@ -124,6 +202,8 @@ impl RoundRobin {
         } else if let Some(task) = queue.dead.pop_front() {
             task.update_state(TaskState::Zombie);
             task.make_zombie();
+
+            self.load.get().fetch_sub(1, Ordering::SeqCst);
         }
     }
 
@@ -155,8 +235,15 @@ impl RoundRobin {
         let guard = IrqGuard::new();
         let queue = self.queue.get_mut();
 
+        self.handle_requests();
         self.schedule_check_deadline();
 
+        let now = crate::arch::time::get_monotonic_nanos();
+
+        if let Some(current_task) = queue.current_task.as_ref() {
+            current_task.stop_cpu_time(now);
+        }
+
         // Switch to the next runnable task in the runnable queue, and put
         // the preempted task back into the runnable queue.
         if let Some(task) = queue.runnable.pop_front() {
@@ -166,12 +253,14 @@ impl RoundRobin {
                 }
             }
 
+            task.start_cpu_time(now);
             queue.current_task = Some(task.clone());
             core::mem::drop(guard);
             arch::task::arch_task_spinup(queue.preempt_task.arch_task_mut(), task.arch_task());
         } else {
             if let Some(current) = queue.current_task.as_ref() {
                 if current.state() == TaskState::Runnable {
+                    current.start_cpu_time(now);
                     core::mem::drop(guard);
                     arch::task::arch_task_spinup(
                         queue.preempt_task.arch_task_mut(),
@@ -193,9 +282,20 @@ impl RoundRobin {
 
 impl SchedulerInterface for RoundRobin {
     fn register_task(&self, task: Arc<Task>) {
-        let queue = self.queue.get_mut();
+        let cpu = task.cpu().unwrap_or_else(|| {
+            let cpu = self.least_loaded_cpu();
+            task.set_cpu(cpu);
+            cpu
+        });
+
+        self.load.get_for(cpu).fetch_add(1, Ordering::SeqCst);
 
-        queue.push_runnable(task);
+        if cpu == arch::tls::get_cpuid() {
+            let _guard = IrqGuard::new();
+            self.queue.get_mut().push_runnable(task);
+        } else {
+            self.post_request(cpu, Request::Spawn(task));
+        }
     }
 
     fn current_task_optional(&self) -> Option<Arc<Task>> {
@@ -203,22 +303,27 @@ impl SchedulerInterface for RoundRobin {
     }
 
     fn init(&self) {
-        // Register the sweeper task in the scheduler's queue.
-        super::get_scheduler().register_task(Task::new_kernel(sweeper, true));
+        let cpu = arch::tls::get_cpuid();
+
+        // Register the sweeper task of this CPU in the scheduler's queue. The dead
+        // queues are per-cpu, so the sweeper must be pinned to this CPU.
+        let sweeper = Task::new_kernel(sweeper, true);
+        sweeper.set_cpu(cpu);
+
+        super::get_scheduler().register_task(sweeper);
+        self.online.get().store(true, Ordering::SeqCst);
     }
 
     fn wake_up(&self, task: Arc<Task>) {
-        let _guard = IrqGuard::new();
-        let queue = self.queue.get_mut();
-
-        if task.state() == TaskState::AwaitingIo {
-            let mut cursor = unsafe { queue.awaiting.cursor_mut_from_ptr(task.as_ref()) };
+        // NOTE: A task that was never registered is not in any of the queues, so it
+        // is treated as if it belonged to the current CPU.
+        let cpu = task.cpu().unwrap_or_else(arch::tls::get_cpuid);
 
-            if let Some(task) = cursor.remove() {
-                queue.push_runnable(task);
-            }
+        if cpu == arch::tls::get_cpuid() {
+            let _guard = IrqGuard::new();
+            self.queue.get_mut().wake_up(task);
         } else {
-            task.set_pending_io(true)
+            self.post_request(cpu, Request::WakeUp(task));
         }
     }
 
@@ -27,12 +27,15 @@ use bit_field::BitField;
 use aero_syscall::SyscallError;
 
 use super::scheduler::{self, ExitStatus};
+use super::task::ptrace;
+use crate::arch::task::userland_last_address;
 use crate::fs::FileSystemError;
 use crate::utils::sync::{Mutex, MutexGuard};
 
 mod default {
     use crate::userland::scheduler;
     use crate::userland::scheduler::ExitStatus;
+    use crate::userland::task::ptrace;
 
     #[derive(Copy, Clone, PartialEq)]
     pub enum Action {
@@ -47,7 +50,7 @@ mod default {
         Action::Handle(terminate),        // SIGINT
         Action::Handle(terminate),        // SIGQUIT
         Action::Handle(terminate),        // SIGILL
-        Action::Ignore,                   // UNUSED
+        Action::Handle(terminate),        // SIGTRAP
         Action::Handle(terminate),        // SIGABRT
         Action::Handle(terminate),        // SIGBUS
         Action::Handle(terminate),        // SIGFPE
@@ -87,8 +90,15 @@ mod default {
         unimplemented!()
     }
 
-    fn stop(_signal: usize) {
-        unimplemented!()
+    fn stop(signal: usize) {
+        let task = scheduler::get_scheduler().current_task();
+
+        // Job control is not supported yet, only traced tasks can be stopped. The signal
+        // is ignored otherwise, as it can still arrive after the tracer detached or exited
+        // (e.g. the `SIGSTOP` queued by `PTRACE_ATTACH`).
+        if !ptrace::group_stop(&task, signal) {
+            log::warn!("signals: ignoring stop signal {signal} for an untraced task");
+        }
     }
 
     /// Get the default action for the provided `signal`.
@@ -150,9 +160,19 @@ impl SignalEntry {
         sigaction: SigAction,
         sigreturn: usize,
     ) -> Result<SignalEntry, SyscallError> {
+        let flags = SignalFlags::from_bits(sigaction.sa_flags).ok_or(SyscallError::EINVAL)?;
+
+        // The handler is stored in `sa_sigaction` if `SA_SIGINFO` is set. It is a union
+        // with `sa_handler` in C, so fall back to it if `sa_sigaction` was not provided.
+        let handler = if flags.contains(SignalFlags::SA_SIGINFO) && sigaction.sa_sigaction != 0 {
+            sigaction.sa_sigaction
+        } else {
+            sigaction.sa_handler
+        };
+
         Ok(SignalEntry {
-            handler: SignalHandler::from(sigaction.sa_handler),
-            flags: SignalFlags::from_bits(sigaction.sa_flags).ok_or(SyscallError::EINVAL)?,
+            handler: SignalHandler::from(handler),
+            flags,
             mask: sigaction.sa_mask,
             sigreturn,
         })
@@ -165,13 +185,22 @@ impl SignalEntry {
             sa_handler: handler as u64,
             sa_mask: self.mask,
             sa_flags: self.flags.bits(),
-            sa_sigaction: 0,
+            sa_sigaction: if self.flags.contains(SignalFlags::SA_SIGINFO) {
+                handler as u64
+            } else {
+                0
+            },
         }
     }
 
     pub fn flags(&self) -> SignalFlags {
         self.flags
     }
+
+    /// Returns the signals that are blocked while the handler is running.
+    pub fn mask(&self) -> u64 {
+        self.mask
+    }
 }
 
 impl SignalEntry {
@@ -185,12 +214,14 @@ impl SignalEntry {
     }
 }
 
-const SIGNAL_COUNT: usize = 35;
+pub const SIGNAL_COUNT: usize = 35;
 
 #[derive(Copy, Clone)]
 pub struct Entries {
     entries: [SignalEntry; SIGNAL_COUNT],
     pending_mask: u64,
+    /// The information for the pending process-directed signals.
+    info: [SigInfo; SIGNAL_COUNT],
 }
 
 impl Default for Entries {
@@ -198,6 +229,7 @@ impl Default for Entries {
         Entries {
             entries: [SignalEntry::default(); SIGNAL_COUNT],
             pending_mask: 0,
+            info: [SigInfo::default(); SIGNAL_COUNT],
         }
     }
 }
@@ -228,8 +260,9 @@ impl Entries {
     }
 
     /// Sets the provided `signal` to be pending.
-    pub fn set_pending(&mut self, signal: u64) {
+    pub fn set_pending(&mut self, signal: u64, info: SigInfo) {
         self.pending_mask.set_bit(signal as usize, true);
+        self.info[signal as usize] = info;
     }
 }
 
@@ -237,6 +270,14 @@ pub struct Signals {
     entries: Arc<Mutex<Entries>>,
     blocked_mask: AtomicU64,
     thread_pending_mask: AtomicU64,
+    /// The information for the pending thread-directed signals.
+    thread_info: Mutex<[SigInfo; SIGNAL_COUNT]>,
+    /// The signals that the thread is waiting for in `sigtimedwait(2)`.
+    waiting_mask: AtomicU64,
+    /// The blocked mask to restore once a signal handler returns, if the mask was
+    /// temporarily replaced by `sigsuspend(2)`.
+    saved_mask: Mutex<Option<u64>>,
+    alt_stack: Mutex<SignalStack>,
 }
 
 impl Signals {
@@ -245,16 +286,31 @@ impl Signals {
             entries: Arc::new(Mutex::new(Default::default())),
             blocked_mask: AtomicU64::new(0),
             thread_pending_mask: AtomicU64::new(0),
+            thread_info: Mutex::new([SigInfo::default(); SIGNAL_COUNT]),
+            waiting_mask: AtomicU64::new(0),
+            saved_mask: Mutex::new(None),
+            alt_stack: Mutex::new(disabled_alt_stack()),
         }
     }
 }
 
+fn disabled_alt_stack() -> SignalStack {
+    SignalStack {
+        ss_flags: SS_DISABLE,
+        ..Default::default()
+    }
+}
+
 impl Clone for Signals {
     fn clone(&self) -> Self {
         Signals {
             entries: self.entries.clone(),
             blocked_mask: AtomicU64::new(self.blocked_mask.load(Ordering::SeqCst)),
             thread_pending_mask: AtomicU64::new(0),
+            thread_info: Mutex::new([SigInfo::default(); SIGNAL_COUNT]),
+            waiting_mask: AtomicU64::new(0),
+            saved_mask: Mutex::new(None),
+            alt_stack: Mutex::new(*self.alt_stack.lock_irq()),
         }
     }
 }
@@ -291,15 +347,44 @@ impl Signals {
         }
     }
 
-    pub fn set_pending(&self, signal: u64, thread_scope: bool) {
+    pub fn set_pending(&self, signal: u64, info: SigInfo, thread_scope: bool) {
         if thread_scope {
+            self.thread_info.lock_irq()[signal as usize] = info;
             self.thread_pending_mask
                 .fetch_or(1u64 << signal, Ordering::SeqCst);
         } else {
-            self.entries().set_pending(signal);
+            self.entries().set_pending(signal, info);
         }
     }
 
+    /// Marks the pending `signal` as not pending and returns its information.
+    pub fn dequeue(&self, signal: usize) -> SigInfo {
+        if self.thread_pending().get_bit(signal) {
+            self.thread_pending_mask
+                .fetch_and(!(1u64 << signal), Ordering::SeqCst);
+
+            core::mem::take(&mut self.thread_info.lock_irq()[signal])
+        } else {
+            let mut entries = self.entries();
+            entries.clear_pending(signal as u64);
+
+            core::mem::take(&mut entries.info[signal])
+        }
+    }
+
+    /// Dequeues the lowest pending signal in `set` and returns it along with its
+    /// information.
+    pub fn dequeue_any(&self, set: u64) -> Option<(usize, SigInfo)> {
+        let pending = self.pending() & set;
+
+        if pending == 0 {
+            return None;
+        }
+
+        let signal = pending.trailing_zeros() as usize;
+        Some((signal, self.dequeue(signal)))
+    }
+
     /// Returns [`true`] if has pending signals.
     pub fn has_pending(&self) -> bool {
         (self.entries().pending() | self.thread_pending()) & !self.blocked_mask() > 0
@@ -315,28 +400,99 @@ impl Signals {
         self.blocked_mask().get_bit(signal)
     }
 
-    pub fn trigger(&self, signal: usize, this_thread: bool) -> TriggerResult {
+    /// Returns [`true`] if the thread is waiting for `signal` in `sigtimedwait(2)`.
+    pub fn is_waiting(&self, signal: usize) -> bool {
+        self.waiting_mask.load(Ordering::SeqCst).get_bit(signal)
+    }
+
+    pub fn set_waiting(&self, set: u64) {
+        self.waiting_mask.store(set, Ordering::SeqCst);
+    }
+
+    /// Replaces the blocked mask with `mask` until the next signal handler returns.
+    pub fn suspend_mask(&self, mask: u64) {
+        *self.saved_mask.lock_irq() = Some(self.blocked_mask());
+        self.set_mask(SigProcMask::Set, Some(mask), None);
+    }
+
+    /// Returns the blocked mask that was replaced by [`Signals::suspend_mask`], if any.
+    pub fn take_saved_mask(&self) -> Option<u64> {
+        self.saved_mask.lock_irq().take()
+    }
+
+    pub fn alt_stack(&self) -> SignalStack {
+        *self.alt_stack.lock_irq()
+    }
+
+    /// Sets the alternate signal stack. `sp` is the current stack pointer of the thread.
+    ///
+    /// ## Errors
+    /// * `EPERM` - The thread is currently executing on the alternate signal stack.
+    /// * `EINVAL` - `stack.ss_flags` is invalid.
+    /// * `ENOMEM` - `stack.ss_size` is less than `MINSIGSTKSZ` or the stack is not within the
+    ///   userland address space.
+    pub fn set_alt_stack(&self, stack: SignalStack, sp: u64) -> Result<(), SyscallError> {
+        let mut alt_stack = self.alt_stack.lock_irq();
+
+        let in_userland = stack
+            .ss_sp
+            .checked_add(stack.ss_size as u64)
+            .map_or(false, |end| end <= userland_last_address().as_u64());
+
+        if is_on_stack(&alt_stack, sp) {
+            return Err(SyscallError::EPERM);
+        }
+
+        match stack.ss_flags {
+            // `SS_ONSTACK` is accepted for compatibility and has the same meaning as 0.
+            0 | SS_ONSTACK if stack.ss_size < MINSIGSTKSZ || !in_userland => {
+                Err(SyscallError::ENOMEM)
+            }
+
+            0 | SS_ONSTACK => {
+                *alt_stack = SignalStack {
+                    ss_flags: 0,
+                    ..stack
+                };
+                Ok(())
+            }
+
+            SS_DISABLE => {
+                *alt_stack = disabled_alt_stack();
+                Ok(())
+            }
+
+            _ => Err(SyscallError::EINVAL),
+        }
+    }
+
+    pub fn trigger(&self, signal: usize, info: SigInfo, this_thread: bool) -> TriggerResult {
         assert!(signal < SIGNAL_COUNT);
 
         let sigs = self.entries();
         let handler = sigs[signal].handler();
 
-        if match handler {
-            SignalHandler::Ignore => false,
+        // Blocked signals are never ignored, since the action may change by the time the
+        // signal is unblocked or it may be accepted with `sigtimedwait(2)`.
+        if self.is_blocked(signal)
+            || self.is_waiting(signal)
+            || match handler {
+                SignalHandler::Ignore => false,
 
-            SignalHandler::Default => {
-                let action = default::action(signal);
+                SignalHandler::Default => {
+                    let action = default::action(signal);
 
-                match action {
-                    default::Action::Ignore => false,
-                    default::Action::Handle(_) => true,
+                    match action {
+                        default::Action::Ignore => false,
+                        default::Action::Handle(_) => true,
+                    }
                 }
-            }
 
-            SignalHandler::Handle(_) => true,
-        } {
+                SignalHandler::Handle(_) => true,
+            }
+        {
             core::mem::drop(sigs); // drop the lock
-            self.set_pending(signal as u64, this_thread);
+            self.set_pending(signal as u64, info, this_thread);
 
             if self.is_blocked(signal) {
                 TriggerResult::Blocked
@@ -348,10 +504,31 @@ impl Signals {
         }
     }
 
+    /// Sends the synchronous `signal` caused by a CPU exception to this thread.
+    ///
+    /// Returning to the faulting instruction would raise the same exception again, so if
+    /// the signal is ignored or blocked, its action is reset to the default one and it is
+    /// unblocked.
+    pub fn force(&self, signal: usize, info: SigInfo) {
+        assert!(signal < SIGNAL_COUNT);
+
+        let mut entries = self.entries();
+
+        if self.is_blocked(signal) || entries[signal].handler() == SignalHandler::Ignore {
+            entries[signal] = SignalEntry::default();
+            self.blocked_mask
+                .fetch_and(!(1u64 << signal), Ordering::SeqCst);
+        }
+
+        core::mem::drop(entries);
+        self.set_pending(signal as u64, info, true);
+    }
+
     /// Clear the signal entries and blocked mask.
     pub fn clear(&self) {
         *self.entries.lock_irq() = Entries::default();
         self.blocked_mask.store(0, Ordering::SeqCst);
+        *self.alt_stack.lock_irq() = disabled_alt_stack();
     }
 
     pub fn set_signal(
@@ -387,6 +564,9 @@ impl Signals {
             signals.blocked_mask.load(Ordering::SeqCst),
             Ordering::SeqCst,
         );
+
+        // Copy over the alternate signal stack.
+        *self.alt_stack.lock_irq() = signals.alt_stack();
     }
 
     /// Used to update or read the signal mask of a task.
@@ -423,10 +603,17 @@ impl Signals {
     }
 }
 
-pub fn check_for_signals() -> Option<(usize, SignalEntry)> {
+/// Returns [`true`] if `sp` is within the alternate signal `stack`.
+pub fn is_on_stack(stack: &SignalStack, sp: u64) -> bool {
+    stack.ss_flags & SS_DISABLE == 0 && sp > stack.ss_sp && sp - stack.ss_sp <= stack.ss_size as u64
+}
+
+pub fn check_for_signals() -> Option<(usize, SignalEntry, SigInfo)> {
     let task = scheduler::get_scheduler().current_task();
     let signals = task.signals();
 
+    ptrace::check_interrupt(&task);
+
     // Check if there are any pending signals.
     if !signals.has_pending() {
         return None;
@@ -440,24 +627,37 @@ pub fn check_for_signals() -> Option<(usize, SignalEntry)> {
 
     for i in 0..SIGNAL_COUNT {
         if !signals.is_blocked(i) && signals.is_pending(i as u64) {
-            signals.clear_pending(i as u64);
+            let info = signals.dequeue(i);
+
+            // The tracer may suppress the signal or replace it with another one.
+            let Some((signal, info)) = ptrace::signal_stop(&task, i, info) else {
+                continue;
+            };
 
-            let entries = signals.entries();
-            let entry = entries[i];
+            if signals.is_blocked(signal) {
+                signals.set_pending(signal as u64, info, true);
+                continue;
+            }
+
+            let mut entries = signals.entries();
+            let entry = entries[signal];
 
             match entry.handler() {
                 SignalHandler::Default => {
                     drop(entries);
-                    default::handle_default(i);
+                    default::handle_default(signal);
                 }
 
                 SignalHandler::Handle(_) => {
-                    return Some((i, entry));
-                }
+                    if entry.flags().contains(SignalFlags::SA_RESETHAND) {
+                        entries[signal] = SignalEntry::default();
+                    }
 
-                SignalHandler::Ignore => {
-                    unreachable!()
+                    return Some((signal, entry, info));
                 }
+
+                // The signal was ignored after it was queued while it was blocked.
+                SignalHandler::Ignore => {}
             }
         }
     }
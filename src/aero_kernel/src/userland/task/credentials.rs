@@ -0,0 +1,267 @@
+// Copyright (C) 2021-2023 The Aero Project Developers.
+//
+// This file is part of The Aero Project.
+//
+// Aero is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Aero is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Aero. If not, see <https://www.gnu.org/licenses/>.
+
+use aero_syscall::prelude::AccessMode;
+use aero_syscall::{Mode, Stat, SyscallError};
+use alloc::vec::Vec;
+
+use crate::userland::scheduler;
+
+/// The user and group identity of a task.
+///
+/// Permission checks are performed against the *effective* IDs, while the *real* IDs
+/// identify the user that started the task. The *saved* IDs allow an unprivileged task
+/// to switch its effective IDs back and forth between the real and saved IDs.
+#[derive(Debug, Clone)]
+pub struct Credentials {
+    pub uid: u32,
+    pub euid: u32,
+    pub suid: u32,
+
+    pub gid: u32,
+    pub egid: u32,
+    pub sgid: u32,
+
+    /// Supplementary group IDs.
+    pub groups: Vec<u32>,
+}
+
+impl Credentials {
+    const ROOT_ID: u32 = 0;
+
+    /// Returns the credentials of the superuser.
+    pub const fn root() -> Self {
+        Self {
+            uid: Self::ROOT_ID,
+            euid: Self::ROOT_ID,
+            suid: Self::ROOT_ID,
+
+            gid: Self::ROOT_ID,
+            egid: Self::ROOT_ID,
+            sgid: Self::ROOT_ID,
+
+            groups: Vec::new(),
+        }
+    }
+
+    /// Returns the credentials of the current task. The superuser's credentials are
+    /// returned if there is no current task (eg. during early boot).
+    pub fn current() -> Self {
+        if !scheduler::is_initialized() {
+            return Self::root();
+        }
+
+        scheduler::get_scheduler()
+            .inner
+            .current_task_optional()
+            .map(|task| task.credentials())
+            .unwrap_or_else(Self::root)
+    }
+
+    /// Returns [`true`] if the effective user ID is the superuser.
+    pub fn is_superuser(&self) -> bool {
+        self.euid == Self::ROOT_ID
+    }
+
+    /// Returns [`true`] if `gid` is the effective group ID or one of the supplementary
+    /// group IDs.
+    pub fn in_group(&self, gid: u32) -> bool {
+        self.egid == gid || self.groups.contains(&gid)
+    }
+
+    /// Returns a copy of the credentials where the effective IDs are replaced with the
+    /// real IDs. This is used by `access` which checks permissions against the real IDs.
+    pub fn as_real(&self) -> Self {
+        Self {
+            euid: self.uid,
+            egid: self.gid,
+            ..self.clone()
+        }
+    }
+
+    /// Sets the user ID (akin `setuid`). The superuser sets all of the user IDs, while an
+    /// unprivileged task can only set the effective user ID to its real or saved user ID.
+    pub fn set_uid(&mut self, uid: u32) -> Result<(), SyscallError> {
+        if self.is_superuser() {
+            self.uid = uid;
+            self.euid = uid;
+            self.suid = uid;
+        } else if uid == self.uid || uid == self.suid {
+            self.euid = uid;
+        } else {
+            return Err(SyscallError::EPERM);
+        }
+
+        Ok(())
+    }
+
+    /// Sets the group ID (akin `setgid`). See [`Credentials::set_uid`] for more information.
+    pub fn set_gid(&mut self, gid: u32) -> Result<(), SyscallError> {
+        if self.is_superuser() {
+            self.gid = gid;
+            self.egid = gid;
+            self.sgid = gid;
+        } else if gid == self.gid || gid == self.sgid {
+            self.egid = gid;
+        } else {
+            return Err(SyscallError::EPERM);
+        }
+
+        Ok(())
+    }
+
+    /// Updates the credentials on exec of the file described by `stat`. If the file has
+    /// the set-user-ID (or set-group-ID) bit set and `set_id` is [`true`], the effective
+    /// user (or group) ID is changed to the owner (or group) of the file. The saved IDs
+    /// are then set to the effective IDs.
+    pub fn exec(&mut self, stat: &Stat, set_id: bool) {
+        if set_id && stat.st_mode.contains(Mode::S_ISUID) {
+            self.euid = stat.st_uid;
+        }
+
+        if set_id && stat.st_mode.contains(Mode::S_ISGID) {
+            self.egid = stat.st_gid;
+        }
+
+        self.suid = self.euid;
+        self.sgid = self.egid;
+    }
+
+    /// Returns [`true`] if the credentials allow inspecting a task running with the
+    /// `target` credentials (e.g. tracing it or reading its private procfs entries). The
+    /// real, effective and saved user and group IDs of the target all have to match the
+    /// real IDs, so a task that only temporarily dropped its privileges is not exposed.
+    pub fn may_inspect(&self, target: &Credentials) -> bool {
+        self.is_superuser()
+            || (self.uid == target.uid
+                && self.uid == target.euid
+                && self.uid == target.suid
+                && self.gid == target.gid
+                && self.gid == target.egid
+                && self.gid == target.sgid)
+    }
+
+    /// Returns [`true`] if the credentials grant the `access` to the file described by
+    /// `stat` based on its permission bits and ownership.
+    pub fn may_access(&self, stat: &Stat, access: AccessMode) -> bool {
+        let permissions = stat.st_mode.bits();
+
+        if self.is_superuser() {
+            // The superuser bypasses the read and write permission checks. Execute
+            // permission is granted if any of the execute bits are set or if the file
+            // is a directory (ie. search permission).
+            let is_directory = (stat.st_mode & Mode::S_IFMT) == Mode::S_IFDIR;
+
+            return !access.contains(AccessMode::X_OK) || is_directory || permissions & 0o111 != 0;
+        }
+
+        let class = if stat.st_uid == self.euid {
+            permissions >> 6
+        } else if self.in_group(stat.st_gid) {
+            permissions >> 3
+        } else {
+            permissions
+        };
+
+        (access.bits() as u32) & !(class & 0o7) == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn user(uid: u32, gid: u32) -> Credentials {
+        Credentials {
+            uid,
+            euid: uid,
+            suid: uid,
+            gid,
+            egid: gid,
+            sgid: gid,
+            groups: Vec::new(),
+        }
+    }
+
+    fn file(uid: u32, gid: u32, permissions: u32) -> Stat {
+        Stat {
+            st_uid: uid,
+            st_gid: gid,
+            st_mode: Mode::S_IFREG | Mode::from_bits_truncate(permissions),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn permission_classes() {
+        let stat = file(1000, 100, 0o640);
+
+        let owner = user(1000, 1000);
+        assert!(owner.may_access(&stat, AccessMode::R_OK | AccessMode::W_OK));
+        assert!(!owner.may_access(&stat, AccessMode::X_OK));
+
+        let mut member = user(1001, 1001);
+        assert!(!member.may_access(&stat, AccessMode::R_OK));
+        member.groups.push(100);
+        assert!(member.may_access(&stat, AccessMode::R_OK));
+        assert!(!member.may_access(&stat, AccessMode::W_OK));
+
+        let root = Credentials::root();
+        assert!(root.may_access(&stat, AccessMode::R_OK | AccessMode::W_OK));
+        assert!(!root.may_access(&stat, AccessMode::X_OK));
+    }
+
+    #[test]
+    fn setuid_exec() {
+        let mut credentials = user(1000, 1000);
+        let mut stat = file(2000, 2000, 0o755);
+        stat.st_mode.insert(Mode::S_ISUID);
+
+        // The set-user-ID bit is ignored if the task is traced by an unprivileged tracer.
+        credentials.exec(&stat, false);
+        assert_eq!(credentials.euid, 1000);
+
+        credentials.exec(&stat, true);
+        assert_eq!(
+            (credentials.uid, credentials.euid, credentials.suid),
+            (1000, 2000, 2000)
+        );
+        assert_eq!(credentials.egid, 1000);
+
+        // The effective user ID can be switched between the real and saved user IDs.
+        credentials.set_uid(1000).unwrap();
+        assert_eq!(credentials.euid, 1000);
+        credentials.set_uid(2000).unwrap();
+        assert_eq!(credentials.euid, 2000);
+
+        assert_eq!(credentials.set_uid(0), Err(SyscallError::EPERM));
+    }
+
+    #[test]
+    fn inspect() {
+        let owner = user(1000, 1000);
+        assert!(owner.may_inspect(&user(1000, 1000)));
+        assert!(!owner.may_inspect(&user(1001, 1000)));
+
+        // A privileged task that switched its effective user ID to the owner.
+        let mut daemon = Credentials::root();
+        daemon.euid = 1000;
+        assert!(!owner.may_inspect(&daemon));
+
+        assert!(Credentials::root().may_inspect(&owner));
+    }
+}
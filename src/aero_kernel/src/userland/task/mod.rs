@@ -15,15 +15,19 @@
 // You should have received a copy of the GNU General Public License
 // along with Aero. If not, see <https://www.gnu.org/licenses/>.
 
+pub mod credentials;
+pub mod ptrace;
 pub mod sessions;
 
+use aero_syscall::signal::{SigInfo, SI_KERNEL};
 use aero_syscall::WaitPidFlags;
 use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
 
-use spin::{Once, RwLock};
+use spin::{Once, RwLock, RwLockWriteGuard};
 
 use core::cell::UnsafeCell;
-use core::sync::atomic::{AtomicBool, AtomicU8, AtomicUsize, Ordering};
+use core::sync::atomic::{AtomicBool, AtomicU64, AtomicU8, AtomicUsize, Ordering};
 
 use crate::fs::cache::{DirCacheImpl, DirCacheItem};
 use crate::fs::{self, FileSystem};
@@ -43,6 +47,9 @@ use super::signals::{SignalResult, TriggerResult};
 use super::terminal::TerminalDevice;
 use super::vm::Vm;
 
+use self::credentials::Credentials;
+use self::ptrace::Ptrace;
+
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
 #[repr(transparent)]
 pub struct TaskId(usize);
@@ -131,11 +138,14 @@ impl Zombies {
         self.block.notify_all();
     }
 
+    /// Waits for one of the children in `pids` to exit. The stops and the exits of the
+    /// tasks traced by the waiting task (see `ptrace`) are reported as well.
     fn waitpid(
         &self,
         pids: &[usize],
         status: &mut u32,
         flags: WaitPidFlags,
+        ptrace: &Ptrace,
     ) -> SignalResult<usize> {
         let mut captured = None;
 
@@ -145,7 +155,7 @@ impl Zombies {
             while let Some(t) = cursor.get() {
                 for pid in pids {
                     if t.pid().as_usize() == *pid {
-                        captured = Some((t.pid(), t.exit_status().clone()));
+                        captured = Some((t.pid().as_usize(), t.exit_status().wait_status()));
                         cursor.remove();
 
                         return true;
@@ -155,6 +165,13 @@ impl Zombies {
                 cursor.move_next();
             }
 
+            for pid in pids {
+                if let Some(wait_status) = ptrace.take_report(*pid) {
+                    captured = Some((*pid, wait_status));
+                    return true;
+                }
+            }
+
             if flags.contains(WaitPidFlags::WNOHANG) {
                 return true;
             }
@@ -162,19 +179,9 @@ impl Zombies {
             false
         })?;
 
-        if let Some((tid, exit_status)) = captured {
-            // mlibc/abis/linux/wait.h (`W_EXITCODE`)
-            match exit_status {
-                ExitStatus::Normal(code) => {
-                    *status = (code as u32) << 8;
-                }
-
-                ExitStatus::Signal(signal) => {
-                    *status = signal as u32;
-                }
-            }
-
-            Ok(tid.as_usize())
+        if let Some((pid, wait_status)) = captured {
+            *status = wait_status;
+            Ok(pid)
         } else {
             // If `WNOHANG` was specified in flags and there were no children in a waitable
             // state, then waipid() returns 0 immediately.
@@ -184,6 +191,43 @@ impl Zombies {
     }
 }
 
+/// The CPU time consumed by a task, in nanoseconds.
+struct CpuTime {
+    /// CPU time consumed by the task itself.
+    thread: AtomicU64,
+    /// CPU time consumed by all of the threads of the process. Shared between the threads.
+    process: Arc<AtomicU64>,
+    /// Value of the monotonic clock when the task was last scheduled in or zero if the
+    /// task is not running.
+    scheduled_at: AtomicU64,
+}
+
+impl CpuTime {
+    fn new() -> Self {
+        Self {
+            thread: AtomicU64::new(0),
+            process: Arc::new(AtomicU64::new(0)),
+            scheduled_at: AtomicU64::new(0),
+        }
+    }
+
+    /// Creates the CPU time of a new thread in the same process.
+    fn new_thread(&self) -> Self {
+        Self {
+            process: self.process.clone(),
+            ..Self::new()
+        }
+    }
+
+    /// Returns the CPU time consumed since the task was last scheduled in.
+    fn running(&self, now: u64) -> u64 {
+        match self.scheduled_at.load(Ordering::SeqCst) {
+            0 => 0,
+            scheduled_at => now.saturating_sub(scheduled_at),
+        }
+    }
+}
+
 pub struct Task {
     sref: Weak<Task>,
 
@@ -202,9 +246,18 @@ pub struct Task {
     zombies: Zombies,
 
     sleep_duration: AtomicUsize,
+    cpu_time: CpuTime,
     signals: Signals,
 
+    /// The logical ID of the CPU this task is scheduled on or [`usize::MAX`] if the task
+    /// has not been assigned to a CPU yet.
+    cpu: AtomicUsize,
+
     executable: Mutex<Option<DirCacheItem>>,
+    /// The arguments and the environment the executable was started with, as consecutive
+    /// NUL-terminated strings.
+    cmdline: Mutex<Vec<u8>>,
+    environ: Mutex<Vec<u8>>,
     pending_io: AtomicBool,
 
     pub(super) link: intrusive_collections::LinkedListLink,
@@ -216,11 +269,13 @@ pub struct Task {
     pub message_queue: MessageQueue,
 
     cwd: RwLock<Option<Cwd>>,
+    credentials: RwLock<Credentials>,
 
     pub(super) exit_status: Once<ExitStatus>,
 
     controlling_terminal: Mutex<Option<Arc<dyn TerminalDevice>>>,
     systrace: AtomicBool,
+    ptrace: Ptrace,
 }
 
 impl Task {
@@ -245,6 +300,8 @@ impl Task {
             pid,
 
             executable: Mutex::new(None),
+            cmdline: Mutex::new(Vec::new()),
+            environ: Mutex::new(Vec::new()),
 
             vm: Arc::new(Vm::new()),
             state: AtomicU8::new(TaskState::Runnable as _),
@@ -255,6 +312,8 @@ impl Task {
             pending_io: AtomicBool::new(false),
 
             sleep_duration: AtomicUsize::new(0),
+            cpu_time: CpuTime::new(),
+            cpu: AtomicUsize::new(usize::MAX),
             exit_status: Once::new(),
 
             children: Mutex::new(Default::default()),
@@ -262,8 +321,10 @@ impl Task {
 
             signals: Signals::new(),
             cwd: RwLock::new(None),
+            credentials: RwLock::new(Credentials::root()),
 
             systrace: AtomicBool::new(false),
+            ptrace: Ptrace::new(),
             controlling_terminal: Mutex::new(None),
         })
     }
@@ -294,9 +355,13 @@ impl Task {
             clink: Default::default(),
 
             sleep_duration: AtomicUsize::new(0),
+            cpu_time: CpuTime::new(),
+            cpu: AtomicUsize::new(usize::MAX),
             exit_status: Once::new(),
 
             executable: Mutex::new(None),
+            cmdline: Mutex::new(Vec::new()),
+            environ: Mutex::new(Vec::new()),
             pending_io: AtomicBool::new(false),
 
             children: Mutex::new(Default::default()),
@@ -304,8 +369,10 @@ impl Task {
 
             signals: Signals::new(),
             cwd: RwLock::new(None),
+            credentials: RwLock::new(Credentials::root()),
 
             systrace: AtomicBool::new(false),
+            ptrace: Ptrace::new(),
             controlling_terminal: Mutex::new(None),
         })
     }
@@ -327,6 +394,8 @@ impl Task {
             clink: Default::default(),
 
             sleep_duration: AtomicUsize::new(0),
+            cpu_time: CpuTime::new(),
+            cpu: AtomicUsize::new(usize::MAX),
             exit_status: Once::new(),
 
             tid: pid,
@@ -335,15 +404,19 @@ impl Task {
             pid,
 
             executable: Mutex::new(self.executable.lock().clone()),
+            cmdline: Mutex::new(self.cmdline.lock().clone()),
+            environ: Mutex::new(self.environ.lock().clone()),
             pending_io: AtomicBool::new(false),
 
             children: Mutex::new(Default::default()),
             parent: Mutex::new(None),
 
             cwd: RwLock::new(Some(self.cwd.read().as_ref().unwrap().fork())),
+            credentials: RwLock::new(self.credentials()),
             signals: Signals::new(),
 
             systrace: AtomicBool::new(self.systrace()),
+            ptrace: Ptrace::new(),
             controlling_terminal: Mutex::new(self.controlling_terminal.lock_irq().clone()),
         });
 
@@ -366,6 +439,10 @@ impl Task {
         &self.signals
     }
 
+    pub fn ptrace(&self) -> &Ptrace {
+        &self.ptrace
+    }
+
     pub fn clone_process(&self, entry: usize, stack: usize) -> Arc<Task> {
         let arch_task = UnsafeCell::new(
             self.arch_task_mut()
@@ -389,6 +466,10 @@ impl Task {
             clink: Default::default(),
 
             sleep_duration: AtomicUsize::new(0),
+            cpu_time: self.cpu_time.new_thread(),
+            // Threads share the address space with the process leader and there is no TLB
+            // shootdown yet, so they are kept on the same CPU as their parent.
+            cpu: AtomicUsize::new(self.cpu.load(Ordering::SeqCst)),
             exit_status: Once::new(),
 
             tid: pid,
@@ -397,15 +478,19 @@ impl Task {
             pid,
 
             executable: Mutex::new(self.executable.lock().clone()),
+            cmdline: Mutex::new(self.cmdline.lock().clone()),
+            environ: Mutex::new(self.environ.lock().clone()),
             pending_io: AtomicBool::new(false),
 
             children: Mutex::new(Default::default()),
             parent: Mutex::new(None),
 
             cwd: RwLock::new(Some(self.cwd.read().as_ref().unwrap().fork())),
+            credentials: RwLock::new(self.credentials()),
             signals: Signals::new(),
 
             systrace: AtomicBool::new(self.process_leader().systrace()),
+            ptrace: Ptrace::new(),
             controlling_terminal: Mutex::new(
                 self.process_leader()
                     .controlling_terminal
@@ -468,6 +553,20 @@ impl Task {
         self.sleep_duration.load(Ordering::SeqCst)
     }
 
+    /// Returns the logical ID of the CPU this task is scheduled on.
+    pub fn cpu(&self) -> Option<usize> {
+        match self.cpu.load(Ordering::SeqCst) {
+            usize::MAX => None,
+            cpu => Some(cpu),
+        }
+    }
+
+    /// Pins the task to the CPU with the provided logical ID (`cpu`). This must be
+    /// done before the task is registered in the scheduler.
+    pub fn set_cpu(&self, cpu: usize) {
+        self.cpu.store(cpu, Ordering::SeqCst);
+    }
+
     pub fn waitpid(
         &self,
         pid: isize,
@@ -489,9 +588,12 @@ impl Task {
                 .collect::<alloc::vec::Vec<_>>();
 
             pids.extend(self.children.lock_irq().iter().map(|e| e.pid().as_usize()));
-            self.zombies.waitpid(&pids, status, flags)
+            pids.extend(self.ptrace.tracee_pids());
+
+            self.zombies.waitpid(&pids, status, flags, &self.ptrace)
         } else {
-            self.zombies.waitpid(&[pid as _], status, flags)
+            self.zombies
+                .waitpid(&[pid as _], status, flags, &self.ptrace)
         }
     }
 
@@ -502,6 +604,19 @@ impl Task {
             .map(|e| e.absolute_path_str())
     }
 
+    /// Returns the executable of the task (if any).
+    pub fn executable(&self) -> Option<DirCacheItem> {
+        self.executable.lock().clone()
+    }
+
+    pub fn cmdline(&self) -> Vec<u8> {
+        self.cmdline.lock().clone()
+    }
+
+    pub fn environ(&self) -> Vec<u8> {
+        self.environ.lock().clone()
+    }
+
     pub fn exec(
         &self,
         executable: DirCacheItem,
@@ -517,13 +632,29 @@ impl Task {
 
         self.file_table.log();
 
+        // Set-user-ID and set-group-ID executables change the effective IDs of the task,
+        // unless the task is traced by an unprivileged tracer which could then take control
+        // of the privileged program.
+        let set_id = self
+            .ptrace
+            .tracer()
+            .map_or(true, |tracer| tracer.credentials().is_superuser());
+
+        if let Ok(stat) = executable.inode().stat() {
+            self.credentials.write().exec(&stat, set_id);
+        }
+
         *self.executable.lock() = Some(executable.clone());
 
+        *self.cmdline.lock() = argv.as_ref().map_or(Vec::new(), ExecArgs::to_nul_separated);
+        *self.environ.lock() = envv.as_ref().map_or(Vec::new(), ExecArgs::to_nul_separated);
+
         let vm = self.vm();
         vm.clear();
 
         // Clear the signals that are pending for this task on exec.
         self.signals().clear();
+        ptrace::exec(self);
 
         self.arch_task_mut().exec(vm, executable, argv, envv)
     }
@@ -581,10 +712,63 @@ impl Task {
         self.tid
     }
 
+    /// Called by the scheduler when the task is switched to at the monotonic time `now`.
+    pub fn start_cpu_time(&self, now: u64) {
+        self.cpu_time.scheduled_at.store(now, Ordering::SeqCst);
+    }
+
+    /// Called by the scheduler when the task is switched away from at the monotonic time
+    /// `now`. The time since the task was switched to is accounted as CPU time.
+    pub fn stop_cpu_time(&self, now: u64) {
+        let elapsed = self.cpu_time.running(now);
+
+        self.cpu_time.thread.fetch_add(elapsed, Ordering::SeqCst);
+        self.cpu_time.process.fetch_add(elapsed, Ordering::SeqCst);
+        self.cpu_time.scheduled_at.store(0, Ordering::SeqCst);
+    }
+
+    /// Returns the CPU time consumed by this thread in nanoseconds.
+    pub fn thread_cpu_time(&self) -> u64 {
+        let now = crate::arch::time::get_monotonic_nanos();
+        self.cpu_time.thread.load(Ordering::SeqCst) + self.cpu_time.running(now)
+    }
+
+    /// Returns the CPU time consumed by all of the threads of the process in nanoseconds.
+    ///
+    /// **Note**: The current time slice of the other threads that are running is not
+    /// included.
+    pub fn process_cpu_time(&self) -> u64 {
+        let now = crate::arch::time::get_monotonic_nanos();
+        self.cpu_time.process.load(Ordering::SeqCst) + self.cpu_time.running(now)
+    }
+
+    /// Returns a copy of the user and group identity of the task.
+    pub fn credentials(&self) -> Credentials {
+        self.credentials.read().clone()
+    }
+
+    pub fn credentials_mut(&self) -> RwLockWriteGuard<'_, Credentials> {
+        self.credentials.write()
+    }
+
     pub fn cwd_dirent(&self) -> DirCacheItem {
         self.cwd.read().as_ref().unwrap().inode.clone()
     }
 
+    /// Returns whether the task has a file open or mapped on the provided `filesystem` or
+    /// its current working directory is on it.
+    pub fn is_using_filesystem(&self, filesystem: &Arc<dyn FileSystem>) -> bool {
+        let cwd_busy = self
+            .cwd
+            .read()
+            .as_ref()
+            .map_or(false, |cwd| cwd.inode.is_on_filesystem(filesystem));
+
+        cwd_busy
+            || self.file_table.is_using_filesystem(filesystem)
+            || self.vm.is_using_filesystem(filesystem)
+    }
+
     pub fn get_cwd(&self) -> String {
         self.cwd.read().as_ref().unwrap().inode.absolute_path_str()
     }
@@ -620,8 +804,13 @@ impl Task {
         }
     }
 
+    /// Sends the kernel generated `signal` to the task.
     pub fn signal(&self, signal: usize) -> bool {
-        match self.signals().trigger(signal, false) {
+        self.send_signal(signal, SigInfo::new(signal, SI_KERNEL))
+    }
+
+    pub fn send_signal(&self, signal: usize, info: SigInfo) -> bool {
+        match self.signals().trigger(signal, info, false) {
             TriggerResult::Triggered => {
                 self.wake_up();
                 true
@@ -632,8 +821,11 @@ impl Task {
             TriggerResult::Blocked => {
                 // Find other thread in process to notify
                 let process_leader = self.process_leader();
+                let can_accept = |task: &Task| {
+                    !task.signals().is_blocked(signal) || task.signals().is_waiting(signal)
+                };
 
-                if !process_leader.signals().is_blocked(signal) {
+                if can_accept(&process_leader) {
                     process_leader.wake_up();
 
                     return true;
@@ -645,7 +837,7 @@ impl Task {
                     .iter()
                     .filter(|t| t.pid() == self.pid())
                 {
-                    if !c.signals().is_blocked(signal) {
+                    if can_accept(c) {
                         c.wake_up();
 
                         return true;
@@ -659,6 +851,7 @@ impl Task {
 
     pub(super) fn make_zombie(&self) {
         self.detach();
+        ptrace::release(self);
         self.arch_task_mut().dealloc();
 
         if let Some(parent) = self.get_parent() {
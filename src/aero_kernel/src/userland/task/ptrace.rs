@@ -0,0 +1,635 @@
+// Copyright (C) 2021-2023 The Aero Project Developers.
+//
+// This file is part of The Aero Project.
+//
+// Aero is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Aero is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Aero. If not, see <https://www.gnu.org/licenses/>.
+
+//! Process tracing (`ptrace(2)`).
+//!
+//! A tracer attaches to a tracee with `PTRACE_ATTACH` or `PTRACE_SEIZE`, or the tracee asks
+//! to be traced by its parent with `PTRACE_TRACEME`. The tracee stops whenever it is about
+//! to receive a signal and, if requested by the tracer, when it enters or leaves a syscall.
+//! The stops are reported to the tracer by `waitpid(2)` and the tracee stays stopped until
+//! the tracer resumes it. In the meantime, the tracer can access the memory and the
+//! registers of the tracee.
+//!
+//! All of the functions that stop a task must be called in the context of that task.
+
+use core::sync::atomic::{AtomicBool, Ordering};
+
+use aero_syscall::ptrace::*;
+use aero_syscall::signal::*;
+use aero_syscall::SyscallError;
+use alloc::sync::{Arc, Weak};
+use alloc::vec::Vec;
+
+use crate::userland::scheduler::{self, ExitStatus};
+use crate::utils::sync::Mutex;
+
+use super::{Task, TaskId};
+
+/// The reason a tracee is stopped.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Stop {
+    /// The tracee is about to receive the signal.
+    Signal(usize),
+    /// The tracee was stopped by the default action of the signal.
+    Group(usize),
+    SyscallEntry,
+    SyscallExit,
+    /// A `PTRACE_EVENT_*` event occurred.
+    Event(usize),
+}
+
+/// How a stopped tracee is resumed.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Resume {
+    Continue,
+    /// Stop at the next syscall entry or exit.
+    Syscall,
+    /// Stop after the next instruction.
+    SingleStep,
+}
+
+struct Tracee {
+    tracer: Arc<Task>,
+    options: PtraceOptions,
+    /// Whether the tracee was attached using `PTRACE_SEIZE`.
+    seized: bool,
+    stop: Option<Stop>,
+    /// Whether the current stop has been reported to the tracer.
+    reported: bool,
+    /// The signal delivered to the tracee once it is resumed from a signal-delivery-stop
+    /// and its information.
+    signal: usize,
+    siginfo: SigInfo,
+    event_msg: u64,
+    /// The number of the syscall the tracee is in or [`u64::MAX`] otherwise.
+    syscall: u64,
+}
+
+impl Tracee {
+    /// Returns the status reported to the tracer by `waitpid(2)` for the provided `stop`.
+    fn wait_status(&self, stop: Stop) -> u32 {
+        let signal = match stop {
+            Stop::Signal(signal) => signal,
+            Stop::Group(signal) if self.seized => signal | (PTRACE_EVENT_STOP << 8),
+            Stop::Group(signal) => signal,
+
+            Stop::SyscallEntry | Stop::SyscallExit
+                if self.options.contains(PtraceOptions::PTRACE_O_TRACESYSGOOD) =>
+            {
+                SIGTRAP | 0x80
+            }
+
+            Stop::SyscallEntry | Stop::SyscallExit => SIGTRAP,
+            Stop::Event(event) => SIGTRAP | (event << 8),
+        };
+
+        // mlibc/abis/linux/wait.h (`W_STOPCODE`)
+        ((signal as u32) << 8) | 0x7f
+    }
+
+    fn is_traced_by(&self, tracer: &Task) -> bool {
+        core::ptr::eq(Arc::as_ptr(&self.tracer), tracer)
+    }
+}
+
+pub struct Ptrace {
+    /// The state of the task if it is traced.
+    tracee: Mutex<Option<Tracee>>,
+    /// The tasks traced by this task.
+    tracees: Mutex<Vec<Weak<Task>>>,
+    /// The wait statuses of the tracees that exited and are not children of this task.
+    exited: Mutex<Vec<(TaskId, u32)>>,
+
+    /// Whether the task stops at the next syscall entry or exit.
+    syscall_trace: AtomicBool,
+    /// Set by `PTRACE_INTERRUPT` to stop the task at the next return to userland.
+    interrupt: AtomicBool,
+}
+
+impl Ptrace {
+    pub fn new() -> Self {
+        Self {
+            tracee: Mutex::new(None),
+            tracees: Mutex::new(Vec::new()),
+            exited: Mutex::new(Vec::new()),
+
+            syscall_trace: AtomicBool::new(false),
+            interrupt: AtomicBool::new(false),
+        }
+    }
+
+    pub fn is_traced(&self) -> bool {
+        self.tracee.lock_irq().is_some()
+    }
+
+    /// Returns the task tracing this task, if any.
+    pub fn tracer(&self) -> Option<Arc<Task>> {
+        self.tracee.lock_irq().as_ref().map(|t| t.tracer.clone())
+    }
+
+    /// Returns the process IDs of the tasks whose stops or exits are reported to this task.
+    pub(super) fn tracee_pids(&self) -> Vec<usize> {
+        let mut pids = self
+            .exited
+            .lock_irq()
+            .iter()
+            .map(|(pid, _)| pid.as_usize())
+            .collect::<Vec<_>>();
+
+        pids.extend(
+            self.tracees
+                .lock_irq()
+                .iter()
+                .filter_map(Weak::upgrade)
+                .map(|t| t.pid().as_usize()),
+        );
+
+        pids
+    }
+
+    /// Returns the wait status of the tracee with the provided `pid` if it is stopped and
+    /// the stop has not been reported yet or if it has exited.
+    pub(super) fn take_report(&self, pid: usize) -> Option<u32> {
+        let mut exited = self.exited.lock_irq();
+
+        if let Some(i) = exited.iter().position(|(p, _)| p.as_usize() == pid) {
+            return Some(exited.remove(i).1);
+        }
+
+        core::mem::drop(exited);
+
+        let tracee = self
+            .tracees
+            .lock_irq()
+            .iter()
+            .filter_map(Weak::upgrade)
+            .find(|t| t.pid().as_usize() == pid)?;
+
+        let mut state = tracee.ptrace.tracee.lock_irq();
+        let state = state.as_mut()?;
+
+        match state.stop {
+            Some(stop) if !state.reported => {
+                state.reported = true;
+                Some(state.wait_status(stop))
+            }
+
+            _ => None,
+        }
+    }
+
+    fn remove_tracee(&self, tracee: &Task) {
+        self.tracees
+            .lock_irq()
+            .retain(|t| t.strong_count() != 0 && !core::ptr::eq(t.as_ptr(), tracee));
+    }
+}
+
+/// Stops the current task and reports the stop to its tracer. Returns the signal injected
+/// by the tracer (zero if none) and its information once the task is resumed.
+fn stop(task: &Task, stop: Stop, siginfo: SigInfo) -> (usize, SigInfo) {
+    let tracer = {
+        let mut state = task.ptrace.tracee.lock_irq();
+
+        let Some(state) = state.as_mut() else {
+            return (0, siginfo);
+        };
+
+        state.stop = Some(stop);
+        state.reported = false;
+        state.signal = 0;
+        state.siginfo = siginfo;
+        state.tracer.clone()
+    };
+
+    tracer.zombies.block.notify_all();
+    tracer.signal(SIGCHLD);
+
+    let scheduler = scheduler::get_scheduler();
+
+    loop {
+        match task.ptrace.tracee.lock_irq().as_ref() {
+            Some(state) if state.stop.is_some() => {}
+            Some(state) => return (state.signal, state.siginfo),
+
+            // The tracer detached while the task was stopped.
+            None => return (0, siginfo),
+        }
+
+        // Other signals are only checked once the task is resumed, but SIGKILL always
+        // resumes the task so that it can exit.
+        if task.signals().is_pending(SIGKILL as u64) {
+            return (0, siginfo);
+        }
+
+        // NOTE: The tracer wakes up the task after it resumes it, so if it does that before
+        // the task goes to sleep, the wake up is recorded as pending I/O and the sleep
+        // returns immediately.
+        let _ = scheduler.inner.sleep(None);
+    }
+}
+
+/// Called before `signal` is delivered to the current task. Returns the signal to deliver
+/// instead, which the tracer may have changed or suppressed.
+pub fn signal_stop(task: &Task, signal: usize, info: SigInfo) -> Option<(usize, SigInfo)> {
+    if signal == SIGKILL || !task.ptrace.is_traced() {
+        return Some((signal, info));
+    }
+
+    match stop(task, Stop::Signal(signal), info) {
+        (0, _) => None,
+        result => Some(result),
+    }
+}
+
+/// Called when the default action of `signal` stops the current task. Returns [`false`] if
+/// the task is not traced.
+pub fn group_stop(task: &Task, signal: usize) -> bool {
+    if !task.ptrace.is_traced() {
+        return false;
+    }
+
+    stop(task, Stop::Group(signal), SigInfo::new(signal, SI_KERNEL));
+    true
+}
+
+/// Stops the current task if the tracer interrupted it with `PTRACE_INTERRUPT`.
+pub fn check_interrupt(task: &Task) {
+    if task.ptrace.interrupt.swap(false, Ordering::SeqCst) {
+        let info = SigInfo::new(SIGTRAP, SI_KERNEL);
+        stop(task, Stop::Event(PTRACE_EVENT_STOP), info);
+    }
+}
+
+/// Returns [`true`] if the current task has to stop at the entry and the exit of syscalls.
+pub fn is_syscall_traced(task: &Task) -> bool {
+    task.ptrace.syscall_trace.load(Ordering::SeqCst)
+}
+
+/// Stops the current task at the entry of the syscall `number`. Returns the number of the
+/// syscall to execute, which may have been changed by the tracer, or [`usize::MAX`] if the
+/// syscall has to be skipped.
+pub fn syscall_entry(task: &Task, number: usize) -> usize {
+    if let Some(state) = task.ptrace.tracee.lock_irq().as_mut() {
+        state.syscall = number as u64;
+    }
+
+    inject_signal(task, stop(task, Stop::SyscallEntry, syscall_siginfo()));
+
+    match task.ptrace.tracee.lock_irq().as_ref() {
+        Some(state) => state.syscall as usize,
+        None => number,
+    }
+}
+
+/// Stops the current task at the exit of the syscall it is in.
+pub fn syscall_exit(task: &Task) {
+    if is_syscall_traced(task) {
+        inject_signal(task, stop(task, Stop::SyscallExit, syscall_siginfo()));
+    }
+
+    if let Some(state) = task.ptrace.tracee.lock_irq().as_mut() {
+        state.syscall = u64::MAX;
+    }
+}
+
+fn syscall_siginfo() -> SigInfo {
+    SigInfo::new(SIGTRAP, SI_KERNEL)
+}
+
+/// The signal injected by the tracer when it resumes a task from a stop which is not a
+/// signal-delivery-stop is sent to the task.
+fn inject_signal(task: &Task, (signal, info): (usize, SigInfo)) {
+    if signal != 0 {
+        task.send_signal(signal, info);
+    }
+}
+
+/// Called before the current task exits with `status`.
+pub fn exit_stop(task: &Task, status: &ExitStatus) {
+    let trace_exit = match task.ptrace.tracee.lock_irq().as_mut() {
+        Some(state) if state.options.contains(PtraceOptions::PTRACE_O_TRACEEXIT) => {
+            state.event_msg = status.wait_status() as u64;
+            true
+        }
+
+        _ => false,
+    };
+
+    if trace_exit {
+        let info = SigInfo::new(SIGTRAP, SI_KERNEL);
+        stop(task, Stop::Event(PTRACE_EVENT_EXIT), info);
+    }
+}
+
+/// Called after the current task successfully executed a new program.
+pub(super) fn exec(task: &Task) {
+    if task.ptrace.is_traced() {
+        // `PTRACE_O_TRACEEXEC` is not supported, so the tracee receives a SIGTRAP like it
+        // does on Linux without the option.
+        task.signal(SIGTRAP);
+    }
+}
+
+/// Detaches the task from its tracer and from its tracees once it has exited.
+pub(super) fn release(task: &Task) {
+    let state = task.ptrace.tracee.lock_irq().take();
+
+    if let Some(state) = state {
+        let tracer = state.tracer;
+        tracer.ptrace.remove_tracee(task);
+
+        // The exit of a child is already reported to the tracer.
+        let is_child = task
+            .get_parent()
+            .map_or(false, |parent| Arc::ptr_eq(&parent, &tracer));
+
+        if !is_child {
+            let status = task.exit_status().wait_status();
+
+            tracer.ptrace.exited.lock_irq().push((task.pid(), status));
+            tracer.zombies.block.notify_all();
+            tracer.signal(SIGCHLD);
+        }
+    }
+
+    let tracees = core::mem::take(&mut *task.ptrace.tracees.lock_irq());
+
+    for tracee in tracees.iter().filter_map(Weak::upgrade) {
+        let Some(state) = tracee.ptrace.tracee.lock_irq().take() else {
+            continue;
+        };
+
+        if state.options.contains(PtraceOptions::PTRACE_O_EXITKILL) {
+            tracee.signal(SIGKILL);
+        }
+
+        if state.stop.is_some() {
+            tracee.arch_task_mut().set_single_step(false);
+        }
+
+        tracee.ptrace.syscall_trace.store(false, Ordering::SeqCst);
+        tracee.wake_up();
+    }
+}
+
+/// Makes `tracer` the tracer of `tracee`. If `seize` is not set, the tracee is sent a
+/// SIGSTOP.
+///
+/// ## Errors
+/// * `EPERM` - The tracee is already traced, is a thread of the tracer, is a kernel task or the
+///   tracer is not privileged and does not have the same user and group IDs.
+/// * `EINVAL` - `options` contains an unsupported option.
+pub fn attach(
+    tracer: &Arc<Task>,
+    tracee: &Arc<Task>,
+    seize: bool,
+    options: PtraceOptions,
+) -> Result<(), SyscallError> {
+    validate_options(options)?;
+
+    if Arc::ptr_eq(&tracer.process_leader(), &tracee.process_leader())
+        || !tracee.arch_task().is_user()
+    {
+        return Err(SyscallError::EPERM);
+    }
+
+    if !tracer.credentials().may_inspect(&tracee.credentials()) {
+        return Err(SyscallError::EPERM);
+    }
+
+    let mut state = tracee.ptrace.tracee.lock_irq();
+
+    if state.is_some() {
+        return Err(SyscallError::EPERM);
+    }
+
+    *state = Some(Tracee {
+        tracer: tracer.clone(),
+        options,
+        seized: seize,
+        stop: None,
+        reported: false,
+        signal: 0,
+        siginfo: SigInfo::default(),
+        event_msg: 0,
+        syscall: u64::MAX,
+    });
+
+    core::mem::drop(state);
+    tracer
+        .ptrace
+        .tracees
+        .lock_irq()
+        .push(Arc::downgrade(tracee));
+
+    if !seize {
+        tracee.send_signal(
+            SIGSTOP,
+            SigInfo::from_process(SIGSTOP, SI_USER, tracer.pid().as_usize(), tracer_creds.uid),
+        );
+    }
+
+    Ok(())
+}
+
+/// Makes the parent of the current task its tracer.
+pub fn trace_me(task: &Arc<Task>) -> Result<(), SyscallError> {
+    let parent = task
+        .process_leader()
+        .get_parent()
+        .ok_or(SyscallError::EPERM)?;
+    let mut state = task.ptrace.tracee.lock_irq();
+
+    if state.is_some() {
+        return Err(SyscallError::EPERM);
+    }
+
+    *state = Some(Tracee {
+        tracer: parent.clone(),
+        options: PtraceOptions::empty(),
+        seized: false,
+        stop: None,
+        reported: false,
+        signal: 0,
+        siginfo: SigInfo::default(),
+        event_msg: 0,
+        syscall: u64::MAX,
+    });
+
+    core::mem::drop(state);
+    parent.ptrace.tracees.lock_irq().push(Arc::downgrade(task));
+
+    Ok(())
+}
+
+fn validate_options(options: PtraceOptions) -> Result<(), SyscallError> {
+    let supported = PtraceOptions::PTRACE_O_TRACESYSGOOD
+        | PtraceOptions::PTRACE_O_TRACEEXIT
+        | PtraceOptions::PTRACE_O_EXITKILL;
+
+    if supported.contains(options) {
+        Ok(())
+    } else {
+        Err(SyscallError::EINVAL)
+    }
+}
+
+/// Calls `f` with the state of `tracee` if it is traced by `tracer` and it is stopped.
+///
+/// ## Errors
+/// * `ESRCH` - The tracee is not traced by `tracer` or it is not stopped.
+fn with_stopped<R, F>(tracer: &Task, tracee: &Task, f: F) -> Result<R, SyscallError>
+where
+    F: FnOnce(&mut Tracee) -> Result<R, SyscallError>,
+{
+    let mut state = tracee.ptrace.tracee.lock_irq();
+
+    match state.as_mut() {
+        Some(state) if state.is_traced_by(tracer) && state.stop.is_some() => f(state),
+        _ => Err(SyscallError::ESRCH),
+    }
+}
+
+/// Checks that `tracee` is traced by `tracer` and is stopped, which is required to access
+/// its memory and registers.
+pub fn check_stopped(tracer: &Task, tracee: &Task) -> Result<(), SyscallError> {
+    with_stopped(tracer, tracee, |_| Ok(()))
+}
+
+/// Resumes the stopped `tracee`. If the tracee is stopped at a signal-delivery-stop,
+/// `signal` is delivered to it instead of the original signal.
+pub fn resume(
+    tracer: &Task,
+    tracee: &Task,
+    resume: Resume,
+    signal: usize,
+) -> Result<(), SyscallError> {
+    if signal >= crate::userland::signals::SIGNAL_COUNT {
+        return Err(SyscallError::EIO);
+    }
+
+    with_stopped(tracer, tracee, |state| {
+        if signal != 0 && state.siginfo.si_signo != signal as i32 {
+            let uid = tracer.credentials().uid;
+            state.siginfo = SigInfo::from_process(signal, SI_USER, tracer.pid().as_usize(), uid);
+        }
+
+        state.signal = signal;
+        state.stop = None;
+
+        Ok(())
+    })?;
+
+    tracee
+        .ptrace
+        .syscall_trace
+        .store(resume == Resume::Syscall, Ordering::SeqCst);
+
+    tracee
+        .arch_task_mut()
+        .set_single_step(resume == Resume::SingleStep);
+
+    tracee.wake_up();
+    Ok(())
+}
+
+/// Detaches `tracer` from the stopped `tracee` and resumes it. `signal` is sent to the
+/// tracee if it is not zero.
+pub fn detach(tracer: &Task, tracee: &Arc<Task>, signal: usize) -> Result<(), SyscallError> {
+    if signal >= crate::userland::signals::SIGNAL_COUNT {
+        return Err(SyscallError::EIO);
+    }
+
+    check_stopped(tracer, tracee)?;
+
+    tracee.ptrace.tracee.lock_irq().take();
+    tracer.ptrace.remove_tracee(tracee);
+
+    tracee.ptrace.syscall_trace.store(false, Ordering::SeqCst);
+    tracee.ptrace.interrupt.store(false, Ordering::SeqCst);
+    tracee.arch_task_mut().set_single_step(false);
+
+    if signal != 0 {
+        tracee.signal(signal);
+    }
+
+    tracee.wake_up();
+    Ok(())
+}
+
+/// Stops the running `tracee` the next time it returns to userland.
+///
+/// ## Errors
+/// * `ESRCH` - The tracee is not traced by `tracer`.
+/// * `EIO` - The tracee was not attached using `PTRACE_SEIZE`.
+pub fn interrupt(tracer: &Task, tracee: &Task) -> Result<(), SyscallError> {
+    match tracee.ptrace.tracee.lock_irq().as_ref() {
+        Some(state) if state.is_traced_by(tracer) && state.seized => {}
+        Some(state) if state.is_traced_by(tracer) => return Err(SyscallError::EIO),
+        _ => return Err(SyscallError::ESRCH),
+    }
+
+    tracee.ptrace.interrupt.store(true, Ordering::SeqCst);
+    tracee.wake_up();
+
+    Ok(())
+}
+
+pub fn set_options(
+    tracer: &Task,
+    tracee: &Task,
+    options: PtraceOptions,
+) -> Result<(), SyscallError> {
+    validate_options(options)?;
+
+    with_stopped(tracer, tracee, |state| {
+        state.options = options;
+        Ok(())
+    })
+}
+
+pub fn event_msg(tracer: &Task, tracee: &Task) -> Result<u64, SyscallError> {
+    with_stopped(tracer, tracee, |state| Ok(state.event_msg))
+}
+
+pub fn siginfo(tracer: &Task, tracee: &Task) -> Result<SigInfo, SyscallError> {
+    with_stopped(tracer, tracee, |state| Ok(state.siginfo))
+}
+
+pub fn set_siginfo(tracer: &Task, tracee: &Task, info: SigInfo) -> Result<(), SyscallError> {
+    with_stopped(tracer, tracee, |state| {
+        state.siginfo = info;
+        Ok(())
+    })
+}
+
+/// Returns the number of the syscall the stopped `tracee` is in or [`u64::MAX`].
+pub fn syscall_number(tracer: &Task, tracee: &Task) -> Result<u64, SyscallError> {
+    with_stopped(tracer, tracee, |state| Ok(state.syscall))
+}
+
+/// Changes the number of the syscall the stopped `tracee` is about to execute.
+pub fn set_syscall_number(tracer: &Task, tracee: &Task, number: u64) -> Result<(), SyscallError> {
+    with_stopped(tracer, tracee, |state| {
+        if state.syscall != u64::MAX {
+            state.syscall = number;
+        }
+
+        Ok(())
+    })
+}
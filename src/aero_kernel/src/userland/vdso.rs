@@ -0,0 +1,190 @@
+// Copyright (C) 2021-2023 The Aero Project Developers.
+//
+// This file is part of The Aero Project.
+//
+// Aero is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Aero is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Aero. If not, see <https://www.gnu.org/licenses/>.
+
+//! The vDSO (virtual dynamic shared object) is a small shared library (see
+//! `arch/x86_64/vdso.real`) that is mapped into every process. Its address is passed to
+//! userland in the `AT_SYSINFO_EHDR` auxiliary vector entry.
+//!
+//! The vDSO is mapped as follows:
+//! ```text
+//! +------------------+ <- base
+//! | vDSO data page   | (read-only, shared with the kernel)
+//! +------------------+ <- base + 4096 (AT_SYSINFO_EHDR)
+//! | vDSO image       | (read-only, executable)
+//! +------------------+
+//! ```
+//!
+//! The data page contains the parameters required to read the monotonic and realtime
+//! clocks from userland. It is protected by a sequence lock; the sequence number is odd
+//! while the kernel is updating the data and userland retries the read if it changed.
+
+use core::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+
+use aero_syscall::MMapFlags;
+use alloc::string::String;
+use alloc::sync::Arc;
+use alloc::vec::Vec;
+use spin::Once;
+
+use crate::fs::cache::DirCacheItem;
+use crate::fs::inode::{DirEntry, INodeInterface};
+use crate::fs::{FileSystemError, Result};
+use crate::mem::paging::*;
+use crate::utils::sync::Mutex;
+
+static VDSO_IMAGE: &[u8] = include_bytes!(concat!(env!("OUT_DIR"), "/vdso.bin"));
+
+/// The clock cannot be read in userland, so the vDSO falls back to the system call.
+pub const VDSO_MODE_FALLBACK: u64 = 0;
+/// The clock is read using the invariant TSC.
+pub const VDSO_MODE_TSC: u64 = 1;
+
+/// The layout of the vDSO data page. Keep this in sync with `arch/x86_64/vdso.real`.
+#[repr(C)]
+struct VdsoData {
+    seq: AtomicU64,
+    mode: AtomicU64,
+    /// The value of the TSC when the monotonic clock was started.
+    tsc_base: AtomicU64,
+    /// The TSC ticks to nanoseconds multiplier (as a 32.32 fixed point number).
+    tsc_mult: AtomicU64,
+    /// The offset of the realtime clock from the monotonic clock in nanoseconds.
+    realtime_offset: AtomicI64,
+}
+
+struct Vdso {
+    data: PhysFrame,
+    image: Vec<PhysFrame>,
+
+    /// Serializes the writers of the vDSO data.
+    lock: Mutex<()>,
+}
+
+impl Vdso {
+    fn allocate_frame() -> PhysFrame {
+        let frame: PhysFrame = FRAME_ALLOCATOR
+            .allocate_frame()
+            .expect("vdso: failed to allocate frame");
+
+        frame.as_slice_mut::<u8>().fill(0);
+
+        // Hold a reference to the frame so it is never deallocated when it is unmapped
+        // from a process.
+        frame.start_address().as_vm_frame().unwrap().inc_ref_count();
+        frame
+    }
+
+    fn new() -> Self {
+        let data = Self::allocate_frame();
+        let image = VDSO_IMAGE
+            .chunks(Size4KiB::SIZE as usize)
+            .map(|chunk| {
+                let frame = Self::allocate_frame();
+                frame.as_slice_mut::<u8>()[..chunk.len()].copy_from_slice(chunk);
+                frame
+            })
+            .collect();
+
+        Self {
+            data,
+            image,
+            lock: Mutex::new(()),
+        }
+    }
+
+    fn data(&self) -> &VdsoData {
+        let ptr = self
+            .data
+            .start_address()
+            .as_hhdm_virt()
+            .as_ptr::<VdsoData>();
+        // SAFETY: The data frame is never deallocated and is only written to atomically.
+        unsafe { &*ptr }
+    }
+
+    /// Updates the vDSO data under the sequence lock.
+    fn update(&self, f: impl FnOnce(&VdsoData)) {
+        let _guard = self.lock.lock_irq();
+        let data = self.data();
+
+        data.seq.fetch_add(1, Ordering::SeqCst);
+        f(data);
+        data.seq.fetch_add(1, Ordering::SeqCst);
+    }
+
+    /// Returns the size of the vDSO mapping, including the data page.
+    fn size(&self) -> usize {
+        (self.image.len() + 1) * Size4KiB::SIZE as usize
+    }
+}
+
+static VDSO: Once<Vdso> = Once::new();
+
+fn get() -> &'static Vdso {
+    VDSO.call_once(Vdso::new)
+}
+
+struct VdsoINode;
+
+impl INodeInterface for VdsoINode {
+    fn mmap(&self, offset: usize, _size: usize, flags: MMapFlags) -> Result<PhysFrame> {
+        let vdso = get();
+
+        // The vDSO is shared between all of the processes.
+        if !flags.contains(MMapFlags::MAP_SHARED) {
+            return Err(FileSystemError::NotSupported);
+        }
+
+        match offset / Size4KiB::SIZE as usize {
+            0 => Ok(vdso.data),
+            page => vdso
+                .image
+                .get(page - 1)
+                .copied()
+                .ok_or(FileSystemError::NotSupported),
+        }
+    }
+}
+
+static VDSO_FILE: Once<DirCacheItem> = Once::new();
+
+/// Returns the file backing the vDSO mapping. The data page is at offset zero and is
+/// followed by the vDSO image.
+pub fn file() -> DirCacheItem {
+    VDSO_FILE
+        .call_once(|| DirEntry::from_inode(Arc::new(VdsoINode), String::from("<vdso>")))
+        .clone()
+}
+
+/// Publishes the TSC parameters used to read the clocks in userland.
+pub fn set_clock(mode: u64, tsc_base: u64, tsc_mult: u64) {
+    get().update(|data| {
+        data.mode.store(mode, Ordering::SeqCst);
+        data.tsc_base.store(tsc_base, Ordering::SeqCst);
+        data.tsc_mult.store(tsc_mult, Ordering::SeqCst);
+    });
+}
+
+/// Publishes the offset of the realtime clock from the monotonic clock.
+pub fn set_realtime_offset(offset: i64) {
+    get().update(|data| data.realtime_offset.store(offset, Ordering::SeqCst));
+}
+
+/// Returns the size of the vDSO mapping in bytes, including the data page.
+pub fn size() -> usize {
+    get().size()
+}
@@ -16,12 +16,14 @@
 // along with Aero. If not, see <https://www.gnu.org/licenses/>.
 
 use core::fmt::Write;
+use core::ops::Range;
 
-use aero_syscall::{MMapFlags, MMapProt};
+use aero_syscall::{MAdvice, MMapFlags, MMapProt, SyscallError};
 
 use alloc::boxed::Box;
 use alloc::collections::linked_list::CursorMut;
 use alloc::collections::LinkedList;
+use alloc::sync::Arc;
 
 use xmas_elf::header::*;
 use xmas_elf::program::*;
@@ -29,7 +31,8 @@ use xmas_elf::*;
 
 use crate::arch::task::userland_last_address;
 use crate::fs::cache::{DirCacheImpl, DirCacheItem};
-use crate::fs::{FileSystemError, Path};
+use crate::fs::shmem::{SharedMemory, WritableMapping};
+use crate::fs::{FileSystem, FileSystemError, Path};
 use crate::mem::paging::*;
 use crate::mem::AddressSpace;
 use crate::{fs, mem};
@@ -317,6 +320,9 @@ pub struct LoadedBinary<'header> {
 
     pub argv: Option<ExecArgs>,
     pub envv: Option<ExecArgs>,
+
+    /// The address of the vDSO's ELF header.
+    pub vdso_base: VirtAddr,
 }
 
 #[derive(Clone)]
@@ -324,18 +330,39 @@ pub struct MMapFile {
     offset: usize,
     file: DirCacheItem,
     size: usize,
+    /// Keeps a sealable file from being sealed against writes while it is mapped shared
+    /// and the mapping can be made writable.
+    writable: Option<WritableMapping>,
 }
 
 impl MMapFile {
     #[inline]
     fn new(file: DirCacheItem, offset: usize, size: usize) -> Self {
-        Self { file, offset, size }
+        Self {
+            file,
+            offset,
+            size,
+            writable: None,
+        }
     }
 }
 
+/// A mapping in the VM, as shown in `/proc/<pid>/maps`.
+pub struct MappingInfo<'a> {
+    pub start: VirtAddr,
+    pub end: VirtAddr,
+    pub protection: MMapProt,
+    pub flags: MMapFlags,
+    /// The mapped file and the offset in the file that the mapping starts at.
+    pub file: Option<(&'a DirCacheItem, usize)>,
+}
+
 #[derive(Clone)]
 struct Mapping {
     protection: MMapProt,
+    /// The protection that the mapping can be changed to using mprotect(2). A shared
+    /// mapping of a file that was not opened for writing can never become writable.
+    max_protection: MMapProt,
     flags: MMapFlags,
 
     start_addr: VirtAddr,
@@ -343,6 +370,8 @@ struct Mapping {
 
     file: Option<MMapFile>,
     refresh_flags: bool,
+    /// Whether the pages of the mapping are locked in memory using mlock(2).
+    locked: bool,
 }
 
 impl Mapping {
@@ -446,7 +475,25 @@ impl Mapping {
 
                 true
             } else if reason.contains(PageFaultErrorCode::CAUSED_BY_WRITE) {
-                self.handle_cow(offset_table, address, true)
+                if !self.flags.contains(MMapFlags::MAP_SHARED) {
+                    return self.handle_cow(offset_table, address, true);
+                }
+
+                // The page is shared with the file, so writes must go to the same frame. The
+                // writable flag was removed on fork or the mapping was made writable using
+                // mprotect(2).
+                unsafe {
+                    offset_table.update_flags(
+                        Page::<Size4KiB>::containing_address(address),
+                        PageTableFlags::PRESENT
+                            | PageTableFlags::USER_ACCESSIBLE
+                            | self.protection.into(),
+                    )
+                }
+                .unwrap()
+                .flush();
+
+                true
             } else {
                 log::error!("    - present page read failed");
                 false
@@ -543,6 +590,30 @@ impl Mapping {
         }
     }
 
+    /// Writes back the pages of a shared file mapping that overlap `start..end`.
+    fn sync(&self, start: VirtAddr, end: VirtAddr) -> fs::Result<()> {
+        // Pages of a mapping that could never be written cannot be dirty.
+        if !self.flags.contains(MMapFlags::MAP_SHARED)
+            || !self.max_protection.contains(MMapProt::PROT_WRITE)
+        {
+            return Ok(());
+        }
+
+        if let Some(mmap_file) = self.file.as_ref() {
+            let start = core::cmp::max(start, self.start_addr);
+            let end = core::cmp::min(end, self.end_addr);
+
+            if start < end {
+                let offset = mmap_file.offset + (start - self.start_addr) as usize;
+                let size = (end - start) as usize;
+
+                mmap_file.file.inode().msync(offset, size)?;
+            }
+        }
+
+        Ok(())
+    }
+
     fn unmap(
         &mut self,
         offset_table: &mut OffsetPageTable,
@@ -572,16 +643,22 @@ impl Mapping {
                 let offset = file.offset + (end - self.start_addr) as usize;
                 let size = file.size - (offset - file.offset);
 
-                MMapFile::new(file.file.clone(), offset, size)
+                MMapFile {
+                    offset,
+                    size,
+                    ..file.clone()
+                }
             });
 
             let new_mapping = Mapping {
                 protection: self.protection,
+                max_protection: self.max_protection,
                 flags: self.flags,
                 start_addr: end,
                 end_addr: end + (self.end_addr - end),
                 file: new_file,
                 refresh_flags: true,
+                locked: self.locked,
             };
 
             self.end_addr = end;
@@ -642,6 +719,72 @@ impl Mapping {
 
         (left, mid, right)
     }
+
+    /// Splits the mapping at `address`. The mapping is shrunk to end at `address` and the
+    /// rest of it is returned.
+    fn split_at(&mut self, address: VirtAddr) -> Mapping {
+        assert!(address > self.start_addr && address < self.end_addr);
+
+        let mut right = self.clone();
+        right.start_addr = address;
+        self.end_addr = address;
+
+        if let Some(file) = self.file.as_mut() {
+            let left_size = self.end_addr - self.start_addr;
+            let right_file = right.file.as_mut().unwrap();
+
+            right_file.offset += left_size as usize;
+            right_file.size = right_file.size.saturating_sub(left_size as usize);
+            file.size = core::cmp::min(file.size, left_size as usize);
+        }
+
+        right
+    }
+
+    /// Faults in the pages of the mapping in `start..end` that are not present.
+    fn populate(&mut self, offset_table: &mut OffsetPageTable, start: VirtAddr, end: VirtAddr) {
+        if self.protection.is_empty() {
+            return;
+        }
+
+        let start = core::cmp::max(start, self.start_addr);
+        let end = core::cmp::min(end, self.end_addr);
+
+        for address in (start..end).step_by(Size4KiB::SIZE as usize) {
+            if let TranslateResult::Mapped { .. } = offset_table.translate(address) {
+                continue;
+            }
+
+            let reason = PageFaultErrorCode::empty();
+
+            if self.file.is_some() {
+                self.handle_pf_file(offset_table, reason, address);
+            } else {
+                self.handle_pf_private_anon(offset_table, reason, address);
+            }
+        }
+    }
+
+    /// Unmaps the pages of the mapping in `start..end`. The pages are faulted in again
+    /// on the next access; private anonymous pages are zero filled and the rest of the
+    /// pages are read from the backing file again.
+    ///
+    /// Unmapping a page drops the reference of the page table to its frame (see
+    /// [`PageTableEntry::unref_vm_frame`]), so private frames are deallocated once no
+    /// address space maps them. The frames of shared mappings stay alive, as the shared
+    /// pages of the object hold a reference to them.
+    fn discard(&self, offset_table: &mut OffsetPageTable, start: VirtAddr, end: VirtAddr) {
+        let start = core::cmp::max(start, self.start_addr);
+        let end = core::cmp::min(end, self.end_addr);
+
+        for address in (start..end).step_by(Size4KiB::SIZE as usize) {
+            match offset_table.unmap(Page::<Size4KiB>::containing_address(address)) {
+                Ok((_, flush)) => flush.flush(),
+                Err(UnmapError::PageNotMapped) => {}
+                Err(err) => panic!("vm: failed to discard page {address:?}: {err:?}"),
+            }
+        }
+    }
 }
 
 struct VmProtected {
@@ -692,11 +835,11 @@ impl VmProtected {
                     map.handle_pf_private_anon(&mut offset_table, reason, accessed_address)
                 }
 
-                (true, false) | (false, false) => {
+                // Shared anonymous mappings are backed by a shared memory object (see
+                // `fs/shmem.rs`).
+                (true, false) | (false, false) | (false, true) => {
                     map.handle_pf_file(&mut offset_table, reason, accessed_address)
                 }
-
-                (false, true) => unreachable!("shared and anonymous mapping"),
             }
         } else {
             log::trace!("mapping not found for address: {:#x}", accessed_address);
@@ -778,9 +921,10 @@ impl VmProtected {
         address: VirtAddr,
         size: usize,
         protection: MMapProt,
+        max_protection: MMapProt,
         flags: MMapFlags,
         offset: usize,
-        file: Option<DirCacheItem>,
+        mut file: Option<DirCacheItem>,
     ) -> Option<VirtAddr> {
         // Offset is required to be a multiple of page size.
         if (offset as u64 & (Size4KiB::SIZE - 1)) != 0 {
@@ -803,9 +947,21 @@ impl VmProtected {
                 return None;
             }
 
-            // SAFETY: We cannot have a shared and an anonymous mapping.
+            // Shared anonymous mappings are backed by a shared memory object, so the
+            // children keep sharing the pages with the parent after fork.
             if flags.contains(MMapFlags::MAP_SHARED) {
-                return None;
+                file = Some(fs::shmem::anonymous(size));
+            }
+        }
+
+        let mut file = file.map(|f| MMapFile::new(f, offset, size));
+
+        if flags.contains(MMapFlags::MAP_SHARED) && max_protection.contains(MMapProt::PROT_WRITE) {
+            if let Some(file) = file.as_mut() {
+                if let Some(memory) = file.file.inode().downcast_arc::<SharedMemory>() {
+                    // The file may have been sealed against writes since the caller checked.
+                    file.writable = Some(memory.map_writable().ok()?);
+                }
             }
         }
 
@@ -837,7 +993,9 @@ impl VmProtected {
                 if prev.end_addr == addr
                     && prev.flags == flags
                     && prev.protection == protection
+                    && prev.max_protection == max_protection
                     && prev.file.is_none()
+                    && !prev.locked
                 {
                     prev.end_addr = addr + size_aligned;
 
@@ -847,13 +1005,15 @@ impl VmProtected {
 
             cursor.insert_before(Mapping {
                 protection,
+                max_protection,
                 flags,
 
                 start_addr: addr,
                 end_addr: addr + size_aligned,
 
-                file: file.map(|f| MMapFile::new(f, offset, size)),
+                file,
                 refresh_flags: true,
+                locked: false,
             });
 
             addr
@@ -979,6 +1139,7 @@ impl VmProtected {
                         virtual_start,
                         data_size as usize,
                         prot,
+                        MMapProt::all(),
                         MMapFlags::MAP_PRIVATE | MMapFlags::MAP_FIXED,
                         file_offset as usize,
                         Some(bin.clone()),
@@ -994,6 +1155,7 @@ impl VmProtected {
                         virtual_fend,
                         bss_size as usize,
                         prot,
+                        MMapProt::all(),
                         MMapFlags::MAP_PRIVATE | MMapFlags::MAP_ANONYOMUS | MMapFlags::MAP_FIXED,
                         0,
                         None,
@@ -1017,15 +1179,357 @@ impl VmProtected {
 
             argv,
             envv,
+
+            vdso_base: VirtAddr::zero(),
         })
     }
 
+    /// Maps the vDSO (see `userland/vdso.rs`) and returns the address of its ELF header.
+    #[cfg(target_arch = "x86_64")]
+    fn map_vdso(&mut self) -> Option<VirtAddr> {
+        use super::vdso;
+
+        let size = vdso::size();
+
+        // Reserve the whole range first, so the image is placed right after the data page.
+        // The pages are shared by all processes, so they can never be made writable.
+        let base = self.mmap(
+            VirtAddr::zero(),
+            size,
+            MMapProt::PROT_READ,
+            MMapProt::PROT_READ,
+            MMapFlags::MAP_SHARED,
+            0,
+            Some(vdso::file()),
+        )?;
+
+        self.mmap(
+            base + Size4KiB::SIZE,
+            size - Size4KiB::SIZE as usize,
+            MMapProt::PROT_READ | MMapProt::PROT_EXEC,
+            MMapProt::PROT_READ | MMapProt::PROT_EXEC,
+            MMapFlags::MAP_SHARED | MMapFlags::MAP_FIXED,
+            Size4KiB::SIZE as usize,
+            Some(vdso::file()),
+        )
+    }
+
     /// Clears all of the mappings without unmapping them. The caller is responsible
     /// for going through the page table and unmapping all of the pages.
     fn clear(&mut self) {
+        self.sync_all();
         self.mappings.clear()
     }
 
+    /// Writes back all of the shared file mappings.
+    fn sync_all(&self) {
+        for map in self.mappings.iter() {
+            if let Err(err) = map.sync(map.start_addr, map.end_addr) {
+                log::warn!("vm: failed to write back a shared mapping: {err:?}");
+            }
+        }
+    }
+
+    /// Writes back the shared file mappings in the provided range. Returns [`false`] if
+    /// part of the range is not mapped.
+    fn msync(&self, address: VirtAddr, size: usize) -> fs::Result<bool> {
+        let end = (address + size).align_up(Size4KiB::SIZE);
+        let mut mapped = address;
+
+        for map in self.mappings.iter() {
+            if map.end_addr <= address {
+                continue;
+            } else if map.start_addr >= end {
+                break;
+            }
+
+            if map.start_addr > mapped {
+                // There is a hole in the range.
+                return Ok(false);
+            }
+
+            map.sync(address, end)?;
+            mapped = map.end_addr;
+        }
+
+        Ok(mapped >= end)
+    }
+
+    /// Returns [`true`] if every page in `start..end` is mapped.
+    fn is_mapped(&self, start: VirtAddr, end: VirtAddr) -> bool {
+        let mut mapped = start;
+
+        for map in self.mappings.iter() {
+            if map.end_addr <= mapped {
+                continue;
+            } else if map.start_addr > mapped || mapped >= end {
+                break;
+            }
+
+            mapped = map.end_addr;
+        }
+
+        mapped >= end
+    }
+
+    /// Splits the mapping that contains `address` (if any), so that a mapping starts at
+    /// `address`.
+    fn split_mapping_at(&mut self, address: VirtAddr) {
+        let mut cursor = self.mappings.cursor_front_mut();
+
+        while let Some(map) = cursor.current() {
+            if map.start_addr < address && map.end_addr > address {
+                let right = map.split_at(address);
+                cursor.insert_after(right);
+                return;
+            } else if map.start_addr >= address {
+                return;
+            }
+
+            cursor.move_next();
+        }
+    }
+
+    fn madvise(
+        &mut self,
+        address: VirtAddr,
+        size: usize,
+        advice: MAdvice,
+    ) -> Result<(), SyscallError> {
+        if !address.is_aligned(Size4KiB::SIZE) {
+            return Err(SyscallError::EINVAL);
+        }
+
+        let start = address;
+        let end = (address + size).align_up(Size4KiB::SIZE);
+
+        let mut address_space = AddressSpace::this();
+        let mut offset_table = address_space.offset_page_table();
+
+        for map in self
+            .mappings
+            .iter_mut()
+            .filter(|map| map.end_addr > start && map.start_addr < end)
+        {
+            match advice {
+                // The advice only affects the read-ahead of the pages, which is not
+                // performed.
+                MAdvice::Normal | MAdvice::Random | MAdvice::Sequential => {}
+                MAdvice::WillNeed => map.populate(&mut offset_table, start, end),
+
+                MAdvice::DontNeed => {
+                    if map.locked {
+                        return Err(SyscallError::EINVAL);
+                    }
+
+                    map.discard(&mut offset_table, start, end);
+                }
+
+                // The pages are freed right away instead of when there is memory pressure,
+                // which is allowed since their contents are undefined until they are
+                // written to again.
+                MAdvice::Free => {
+                    let is_private_anon = map.flags.contains(MMapFlags::MAP_PRIVATE)
+                        && map.flags.contains(MMapFlags::MAP_ANONYOMUS);
+
+                    if !is_private_anon || map.locked {
+                        return Err(SyscallError::EINVAL);
+                    }
+
+                    map.discard(&mut offset_table, start, end);
+                }
+            }
+        }
+
+        if self.is_mapped(start, end) {
+            Ok(())
+        } else {
+            Err(SyscallError::ENOMEM)
+        }
+    }
+
+    /// Locks (or unlocks if `locked` is [`false`]) the pages in the provided range in
+    /// memory. The pages are faulted in when they are locked.
+    fn mlock(&mut self, address: VirtAddr, size: usize, locked: bool) -> Result<(), SyscallError> {
+        let start = address.align_down(Size4KiB::SIZE);
+        let end = (address + size).align_up(Size4KiB::SIZE);
+
+        if !self.is_mapped(start, end) {
+            return Err(SyscallError::ENOMEM);
+        }
+
+        self.split_mapping_at(start);
+        self.split_mapping_at(end);
+
+        let mut address_space = AddressSpace::this();
+        let mut offset_table = address_space.offset_page_table();
+
+        for map in self
+            .mappings
+            .iter_mut()
+            .filter(|map| map.start_addr >= start && map.end_addr <= end)
+        {
+            map.locked = locked;
+
+            if locked {
+                map.populate(&mut offset_table, start, end);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Reports whether the pages in the provided range are resident in memory. Each byte
+    /// of `residency` is set to 1 if the respective page is mapped and to 0 otherwise.
+    fn mincore(
+        &self,
+        address: VirtAddr,
+        size: usize,
+        residency: &mut [u8],
+    ) -> Result<(), SyscallError> {
+        if !address.is_aligned(Size4KiB::SIZE) {
+            return Err(SyscallError::EINVAL);
+        }
+
+        let end = (address + size).align_up(Size4KiB::SIZE);
+        let pages = ((end - address) / Size4KiB::SIZE) as usize;
+
+        if residency.len() < pages {
+            return Err(SyscallError::EFAULT);
+        }
+
+        if !self.is_mapped(address, end) {
+            return Err(SyscallError::ENOMEM);
+        }
+
+        let mut address_space = AddressSpace::this();
+        let offset_table = address_space.offset_page_table();
+
+        for (i, page) in (address..end).step_by(Size4KiB::SIZE as usize).enumerate() {
+            let resident = matches!(offset_table.translate(page), TranslateResult::Mapped { .. });
+            residency[i] = resident as u8;
+        }
+
+        Ok(())
+    }
+
+    /// Calls `f` with the part of every page in `address..address + size` of the (inactive)
+    /// address space `address_space` and the range of the buffer that it corresponds to.
+    /// The pages that are not resident are faulted in. Returns [`false`] if part of the
+    /// range is not mapped.
+    ///
+    /// If `write` is set, the pages of private mappings are copied before they are handed
+    /// to `f`, even if the mapping is not writable. This allows a tracer to insert
+    /// breakpoints in the text of a program without modifying the file it was loaded from.
+    fn access_remote<F>(
+        &mut self,
+        address_space: &mut AddressSpace,
+        address: VirtAddr,
+        size: usize,
+        write: bool,
+        mut f: F,
+    ) -> bool
+    where
+        F: FnMut(&mut [u8], Range<usize>),
+    {
+        let mut offset_table = address_space.offset_page_table();
+        let mut progress = 0;
+
+        while progress < size {
+            let addr = address + progress;
+            let page = Page::<Size4KiB>::containing_address(addr);
+            let offset = (addr - page.start_address()) as usize;
+            let chunk = core::cmp::min(size - progress, Size4KiB::SIZE as usize - offset);
+
+            let Some(map) = self
+                .mappings
+                .iter_mut()
+                .find(|e| addr >= e.start_addr && addr < e.end_addr)
+            else {
+                return false;
+            };
+
+            if map.protection.is_empty() {
+                return false;
+            }
+
+            // Writes to a private mapping are copied below, but writes to a shared mapping
+            // reach the file, so they must not bypass the permissions of the file.
+            if write
+                && map.flags.contains(MMapFlags::MAP_SHARED)
+                && !map.max_protection.contains(MMapProt::PROT_WRITE)
+            {
+                return false;
+            }
+
+            if !matches!(offset_table.translate(addr), TranslateResult::Mapped { .. }) {
+                let is_private = map.flags.contains(MMapFlags::MAP_PRIVATE);
+                let is_annon = map.flags.contains(MMapFlags::MAP_ANONYOMUS);
+
+                let resident = if is_private && is_annon {
+                    map.handle_pf_private_anon(&mut offset_table, PageFaultErrorCode::empty(), addr)
+                } else {
+                    map.handle_pf_file(&mut offset_table, PageFaultErrorCode::empty(), addr)
+                };
+
+                if !resident {
+                    return false;
+                }
+            }
+
+            let TranslateResult::Mapped { frame, flags, .. } = offset_table.translate(addr) else {
+                unreachable!()
+            };
+
+            let mut frame = PhysFrame::<Size4KiB>::containing_address(frame.start_address());
+
+            if write && map.flags.contains(MMapFlags::MAP_PRIVATE) {
+                // A writable page of a private mapping has already been copied (or is
+                // anonymous and not shared with another process), so it can be written in
+                // place. Otherwise it is still shared with the file or with another process.
+                let is_shared = if flags.contains(PageTableFlags::WRITABLE) {
+                    false
+                } else if map.file.is_some() {
+                    true
+                } else {
+                    frame
+                        .start_address()
+                        .as_vm_frame()
+                        .map_or(true, |vm_frame| vm_frame.ref_count() > 1)
+                };
+
+                if is_shared {
+                    let new_frame: PhysFrame = FRAME_ALLOCATOR
+                        .allocate_frame()
+                        .expect("access_remote: failed to allocate frame");
+
+                    new_frame
+                        .as_slice_mut::<u8>()
+                        .copy_from_slice(frame.as_slice_mut::<u8>());
+
+                    offset_table.unmap(page).unwrap().1.ignore();
+
+                    // NOTE: The address space is not active, so there is nothing to flush. The
+                    // flags are kept, so a copied page of the text is still not writable.
+                    unsafe { offset_table.map_to(page, new_frame, flags) }
+                        .expect("access_remote: failed to map the copied frame")
+                        .ignore();
+
+                    frame = new_frame;
+                }
+            }
+
+            f(
+                &mut frame.as_slice_mut::<u8>()[offset..offset + chunk],
+                progress..progress + chunk,
+            );
+
+            progress += chunk;
+        }
+
+        true
+    }
+
     fn munmap(&mut self, address: VirtAddr, size: usize) -> bool {
         let start = address.align_up(Size4KiB::SIZE);
         let end = (address + size).align_up(Size4KiB::SIZE);
@@ -1040,6 +1544,10 @@ impl VmProtected {
             if map.end_addr <= start {
                 cursor.move_next();
             } else {
+                if let Err(err) = map.sync(start, end) {
+                    log::warn!("vm: failed to write back a shared mapping: {err:?}");
+                }
+
                 match map.unmap(&mut offset_table, start, end) {
                     Ok(result) => match result {
                         UnmapResult::None => return success,
@@ -1069,10 +1577,27 @@ impl VmProtected {
         success
     }
 
-    fn mprotect(&mut self, addr: VirtAddr, size: usize, prot: MMapProt) -> bool {
+    fn mprotect(
+        &mut self,
+        addr: VirtAddr,
+        size: usize,
+        prot: MMapProt,
+    ) -> Result<(), SyscallError> {
         let start = addr.align_up(Size4KiB::SIZE);
         let end = (addr + size).align_up(Size4KiB::SIZE);
 
+        // Check all of the affected mappings before changing any of them, so a failed
+        // call leaves the protection of the range untouched.
+        let exceeds_max = self
+            .mappings
+            .iter()
+            .filter(|map| map.start_addr < end && map.end_addr > start)
+            .any(|map| !map.max_protection.contains(prot));
+
+        if exceeds_max {
+            return Err(SyscallError::EACCES);
+        }
+
         let mut cursor = self.mappings.cursor_front_mut();
 
         while let Some(map) = cursor.current() {
@@ -1116,7 +1641,7 @@ impl VmProtected {
             }
         }
 
-        true
+        Ok(())
     }
 
     fn fork_from(&mut self, parent: &Vm) {
@@ -1124,6 +1649,17 @@ impl VmProtected {
 
         // Copy over all of the mappings from the parent into the child.
         self.mappings = data.mappings.clone();
+
+        // Memory locks are not inherited by the child.
+        for map in self.mappings.iter_mut() {
+            map.locked = false;
+        }
+    }
+}
+
+impl Drop for VmProtected {
+    fn drop(&mut self) {
+        self.sync_all();
     }
 }
 
@@ -1144,21 +1680,123 @@ impl Vm {
         address: VirtAddr,
         size: usize,
         protection: MMapProt,
+        max_protection: MMapProt,
         flags: MMapFlags,
         offset: usize,
         file: Option<DirCacheItem>,
     ) -> Option<VirtAddr> {
-        self.inner
-            .lock()
-            .mmap(address, size, protection, flags, offset, file)
+        self.inner.lock().mmap(
+            address,
+            size,
+            protection,
+            max_protection,
+            flags,
+            offset,
+            file,
+        )
     }
 
     pub fn munmap(&self, address: VirtAddr, size: usize) -> bool {
         self.inner.lock().munmap(address, size)
     }
 
-    pub fn mprotect(&self, ptr: VirtAddr, size: usize, prot: MMapProt) {
-        assert!(self.inner.lock().mprotect(ptr, size, prot))
+    pub fn mprotect(&self, ptr: VirtAddr, size: usize, prot: MMapProt) -> Result<(), SyscallError> {
+        self.inner.lock().mprotect(ptr, size, prot)
+    }
+
+    /// Writes back the shared file mappings in the provided range. Returns [`false`] if
+    /// part of the range is not mapped.
+    pub fn msync(&self, address: VirtAddr, size: usize) -> fs::Result<bool> {
+        self.inner.lock().msync(address, size)
+    }
+
+    pub fn madvise(
+        &self,
+        address: VirtAddr,
+        size: usize,
+        advice: MAdvice,
+    ) -> Result<(), SyscallError> {
+        self.inner.lock().madvise(address, size, advice)
+    }
+
+    pub fn mlock(&self, address: VirtAddr, size: usize) -> Result<(), SyscallError> {
+        self.inner.lock().mlock(address, size, true)
+    }
+
+    pub fn munlock(&self, address: VirtAddr, size: usize) -> Result<(), SyscallError> {
+        self.inner.lock().mlock(address, size, false)
+    }
+
+    /// Returns whether a file on the provided `filesystem` is mapped.
+    pub fn is_using_filesystem(&self, filesystem: &Arc<dyn FileSystem>) -> bool {
+        self.inner
+            .lock()
+            .mappings
+            .iter()
+            .filter_map(|mapping| mapping.file.as_ref())
+            .any(|file| file.file.is_on_filesystem(filesystem))
+    }
+
+    pub fn mincore(
+        &self,
+        address: VirtAddr,
+        size: usize,
+        residency: &mut [u8],
+    ) -> Result<(), SyscallError> {
+        self.inner.lock().mincore(address, size, residency)
+    }
+
+    /// Reads the memory at `address` in `address_space` into `buffer`. `address_space`
+    /// must belong to this VM and must not be active (ie. the task is stopped). Returns
+    /// [`false`] if part of the range is not mapped.
+    pub fn read_remote(
+        &self,
+        address_space: &mut AddressSpace,
+        address: VirtAddr,
+        buffer: &mut [u8],
+    ) -> bool {
+        self.inner.lock().access_remote(
+            address_space,
+            address,
+            buffer.len(),
+            false,
+            |page, range| buffer[range].copy_from_slice(page),
+        )
+    }
+
+    /// Writes `buffer` to the memory at `address` in `address_space`. See
+    /// [`Vm::read_remote`] for more information.
+    pub fn write_remote(
+        &self,
+        address_space: &mut AddressSpace,
+        address: VirtAddr,
+        buffer: &[u8],
+    ) -> bool {
+        self.inner.lock().access_remote(
+            address_space,
+            address,
+            buffer.len(),
+            true,
+            |page, range| page.copy_from_slice(&buffer[range]),
+        )
+    }
+
+    /// Calls the provided closure for every mapping in the VM.
+    pub fn for_each_mapping<F>(&self, mut f: F)
+    where
+        F: FnMut(MappingInfo),
+    {
+        let this = self.inner.lock();
+
+        for mapping in this.mappings.iter() {
+            f(MappingInfo {
+                start: mapping.start_addr,
+                end: mapping.end_addr,
+                protection: mapping.protection,
+                flags: mapping.flags,
+                file: mapping.file.as_ref().map(|file| (&file.file, file.offset)),
+            });
+        }
     }
 
     pub(super) fn fork_from(&self, parent: &Vm) {
@@ -1172,7 +1810,15 @@ impl Vm {
         argv: Option<ExecArgs>,
         envv: Option<ExecArgs>,
     ) -> Result<LoadedBinary, ElfLoadError> {
-        self.inner.lock().load_bin(bin, argv, envv)
+        let mut this = self.inner.lock();
+        let mut loaded_binary = this.load_bin(bin, argv, envv)?;
+
+        #[cfg(target_arch = "x86_64")]
+        {
+            loaded_binary.vdso_base = this.map_vdso().ok_or(ElfLoadError::MemoryMapError)?;
+        }
+
+        Ok(loaded_binary)
     }
 
     /// Clears and unmaps all of the mappings in the VM.
@@ -1193,3 +1839,58 @@ impl Vm {
             .handle_page_fault(reason, accessed_address)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use aero_syscall::Mode;
+
+    use super::*;
+
+    #[test]
+    fn write_remote_shared_read_only() {
+        let root = fs::lookup_path(Path::new("/")).unwrap();
+        let file = root
+            .inode()
+            .touch(
+                root.clone(),
+                "vm_shared_read_only",
+                Mode::from_bits_truncate(0o644),
+            )
+            .unwrap();
+
+        let contents = [0xaa; Size4KiB::SIZE as usize];
+        file.inode().write_at(0, &contents).unwrap();
+
+        let vm = Vm::new();
+        let mut address_space = AddressSpace::new().unwrap();
+
+        let address = vm
+            .mmap(
+                VirtAddr::zero(),
+                contents.len(),
+                MMapProt::PROT_READ,
+                MMapProt::PROT_READ,
+                MMapFlags::MAP_SHARED,
+                0,
+                Some(file.clone()),
+            )
+            .unwrap();
+
+        // `PTRACE_POKEDATA` fails with `EIO` when the write is refused.
+        assert!(!vm.write_remote(&mut address_space, address, &[0x55; 8]));
+
+        let mut buffer = [0; 8];
+        assert!(vm.read_remote(&mut address_space, address, &mut buffer));
+        assert_eq!(buffer, [0xaa; 8]);
+
+        vm.clear();
+        drop(address_space);
+
+        let mut buffer = [0; 8];
+        file.inode().read_at(0, &mut buffer).unwrap();
+        assert_eq!(buffer, [0xaa; 8]);
+
+        root.inode().unlink("vm_shared_read_only").unwrap();
+        file.drop_from_cache();
+    }
+}
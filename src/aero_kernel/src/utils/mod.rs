@@ -28,11 +28,19 @@ use crate::mem::paging::{align_down, ReadErr, VirtAddr};
 #[cfg(target_arch = "x86_64")]
 use crate::arch::apic::get_cpu_count;
 
+#[cfg(target_arch = "x86_64")]
+use crate::arch::tls::get_cpuid;
+
 #[cfg(target_arch = "aarch64")]
 fn get_cpu_count() -> usize {
     1
 }
 
+#[cfg(target_arch = "aarch64")]
+fn get_cpuid() -> usize {
+    0
+}
+
 pub mod bitmap;
 pub mod buffer;
 pub mod dma;
@@ -116,6 +124,7 @@ impl<T: Copy> VolatileCell<T> {
 
 pub struct PerCpu<T> {
     data: UnsafeCell<Unique<T>>,
+    cpu_count: usize,
 }
 
 impl<T> PerCpu<T> {
@@ -123,6 +132,7 @@ impl<T> PerCpu<T> {
     pub const fn new_uninit() -> PerCpu<T> {
         PerCpu::<T> {
             data: UnsafeCell::new(Unique::dangling()),
+            cpu_count: 0,
         }
     }
 
@@ -142,6 +152,7 @@ impl<T> PerCpu<T> {
             this.data = UnsafeCell::new(Unique::new_unchecked(raw));
         }
 
+        this.cpu_count = cpu_count;
         this
     }
 
@@ -150,14 +161,37 @@ impl<T> PerCpu<T> {
         unsafe { (*self.data.get()).as_mut() }
     }
 
+    /// Returns the number of CPUs this per-cpu variable was allocated for.
+    #[inline]
+    pub fn cpu_count(&self) -> usize {
+        self.cpu_count
+    }
+
+    /// Returns a reference to the instance that belongs to the current CPU.
     #[inline]
     pub fn get(&self) -> &T {
-        unsafe { &*self.as_mut_ptr().offset(0) }
+        self.get_for(get_cpuid())
     }
 
+    /// Returns a mutable reference to the instance that belongs to the current CPU.
     #[inline]
     pub fn get_mut(&self) -> &mut T {
-        unsafe { &mut *self.as_mut_ptr().offset(0) }
+        let cpu_id = get_cpuid();
+        assert!(cpu_id < self.cpu_count);
+
+        unsafe { &mut *self.as_mut_ptr().add(cpu_id) }
+    }
+
+    /// Returns a reference to the instance that belongs to the CPU with the provided
+    /// logical ID (`cpu_id`).
+    ///
+    /// ## Notes
+    /// Other CPUs can access their instance concurrently, so `T` is required to
+    /// provide its own synchronization if it is mutated through this reference.
+    #[inline]
+    pub fn get_for(&self, cpu_id: usize) -> &T {
+        assert!(cpu_id < self.cpu_count);
+        unsafe { &*self.as_mut_ptr().add(cpu_id) }
     }
 }
 
@@ -1,7 +1,10 @@
+use core::time::Duration;
+
+use aero_syscall::TimeSpec;
 use alloc::sync::Arc;
 use alloc::vec::Vec;
 
-use crate::arch::interrupts;
+use crate::arch::{interrupts, time};
 use crate::userland::scheduler;
 use crate::userland::signals::SignalResult;
 use crate::userland::task::Task;
@@ -51,6 +54,61 @@ impl WaitQueue {
         Ok(lock)
     }
 
+    /// Same as [`WaitQueue::block_on`], except that the caller gives up waiting once the
+    /// `timeout` has elapsed, in which case [`None`] is returned. The caller waits forever if
+    /// `timeout` is [`None`].
+    ///
+    /// ## Notes
+    /// * The scheduler sleeps with a granularity of one second, so the caller may be woken up
+    /// up to a second after the timeout has elapsed.
+    pub fn block_on_timeout<'future, T, F: FnMut(&mut MutexGuard<T>) -> bool>(
+        &self,
+        mutex: &'future Mutex<T>,
+        timeout: Option<Duration>,
+        mut future: F,
+    ) -> SignalResult<Option<MutexGuard<'future, T>>> {
+        let Some(timeout) = timeout else {
+            return self.block_on(mutex, future).map(Some);
+        };
+
+        let mut lock = mutex.lock_irq();
+
+        // Check if the future was already completed.
+        if future(&mut lock) {
+            return Ok(Some(lock));
+        }
+
+        let deadline = time::get_monotonic_nanos() + timeout.as_nanos() as u64;
+
+        let scheduler = scheduler::get_scheduler();
+        let task = scheduler.current_task();
+
+        self.queue.lock_irq().push(task.clone());
+
+        while !future(&mut lock) {
+            core::mem::drop(lock);
+
+            let now = time::get_monotonic_nanos();
+
+            if now >= deadline {
+                self.remove(task);
+                return Ok(None);
+            }
+
+            let seconds = (deadline - now).div_ceil(TimeSpec::NANOS_PER_SEC);
+
+            if let Err(err) = scheduler.inner.sleep(Some(seconds as usize)) {
+                self.remove(task);
+                return Err(err);
+            }
+
+            lock = mutex.lock_irq();
+        }
+
+        self.remove(task);
+        Ok(Some(lock))
+    }
+
     pub fn insert(&self, task: Arc<Task>) {
         self.queue.lock_irq().push(task);
     }
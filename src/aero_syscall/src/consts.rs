@@ -98,6 +98,34 @@ pub const SYS_GETPGID: usize = 74;
 pub const SYS_SOCK_SHUTDOWN: usize = 75;
 pub const SYS_GETPEERNAME: usize = 76;
 pub const SYS_GETSOCKNAME: usize = 77;
+pub const SYS_MOUNT: usize = 78;
+pub const SYS_UMOUNT: usize = 79;
+pub const SYS_GETUID: usize = 80;
+pub const SYS_GETEUID: usize = 81;
+pub const SYS_GETGID: usize = 82;
+pub const SYS_GETEGID: usize = 83;
+pub const SYS_SETUID: usize = 84;
+pub const SYS_SETGID: usize = 85;
+pub const SYS_GETGROUPS: usize = 86;
+pub const SYS_FCHMODAT: usize = 87;
+pub const SYS_FCHOWNAT: usize = 88;
+pub const SYS_UTIMENSAT: usize = 89;
+pub const SYS_CLOCK_GETRES: usize = 90;
+pub const SYS_CLOCK_SETTIME: usize = 91;
+pub const SYS_SETSOCKOPT: usize = 92;
+pub const SYS_GETSOCKOPT: usize = 93;
+pub const SYS_MSYNC: usize = 94;
+pub const SYS_MEMFD_CREATE: usize = 95;
+pub const SYS_MADVISE: usize = 96;
+pub const SYS_MLOCK: usize = 97;
+pub const SYS_MUNLOCK: usize = 98;
+pub const SYS_MINCORE: usize = 99;
+pub const SYS_GETRANDOM: usize = 100;
+pub const SYS_SIGALTSTACK: usize = 101;
+pub const SYS_SIGQUEUEINFO: usize = 102;
+pub const SYS_SIGTIMEDWAIT: usize = 103;
+pub const SYS_SIGSUSPEND: usize = 104;
+pub const SYS_PTRACE: usize = 105;
 
 // constants for fcntl()'s command argument:
 pub const F_DUPFD: usize = 1;
@@ -111,6 +139,19 @@ pub const F_SETLK: usize = 8;
 pub const F_SETLKW: usize = 9;
 pub const F_GETOWN: usize = 10;
 pub const F_SETOWN: usize = 11;
+pub const F_ADD_SEALS: usize = 1033;
+pub const F_GET_SEALS: usize = 1034;
+
+// constants for the seals of F_ADD_SEALS and F_GET_SEALS:
+bitflags::bitflags! {
+    pub struct SealFlags: usize {
+        const F_SEAL_SEAL = 0x1;
+        const F_SEAL_SHRINK = 0x2;
+        const F_SEAL_GROW = 0x4;
+        const F_SEAL_WRITE = 0x8;
+        const F_SEAL_FUTURE_WRITE = 0x10;
+    }
+}
 
 // constants for fcntl()'s additional argument of F_GETFD and F_SETFD:
 bitflags::bitflags! {
@@ -119,6 +160,51 @@ bitflags::bitflags! {
     }
 }
 
+// constants for access()'s mode argument:
+bitflags::bitflags! {
+    pub struct AccessMode: usize {
+        const X_OK = 1;
+        const W_OK = 2;
+        const R_OK = 4;
+    }
+}
+
+// constants for the flags argument of the *at() family of syscalls:
+bitflags::bitflags! {
+    pub struct AtFlags: usize {
+        const EMPTY_PATH       = 1;
+        const SYMLINK_FOLLOW   = 2;
+        const SYMLINK_NOFOLLOW = 4;
+        const REMOVEDIR        = 8;
+        const EACCESS          = 512;
+    }
+}
+
+// special values for the tv_nsec field of utimensat()'s times argument:
+pub const UTIME_NOW: isize = (1 << 30) - 1;
+pub const UTIME_OMIT: isize = (1 << 30) - 2;
+
+// constants for mount()'s flags argument:
+bitflags::bitflags! {
+    pub struct MountFlags: usize {
+        const RDONLY = 1;
+    }
+}
+
+// structures for the mount API:
+//
+// The source, filesystem type and flags do not fit into the syscall registers along
+// with the target path, so they are passed by reference.
+#[derive(Debug)]
+#[repr(C)]
+pub struct MountArgs {
+    pub source: *const u8,
+    pub source_len: usize,
+    pub fs_type: *const u8,
+    pub fs_type_len: usize,
+    pub flags: usize,
+}
+
 // constants for the epoll API:
 bitflags::bitflags! {
     pub struct EPollFlags: usize {
@@ -358,12 +444,37 @@ pub struct FramebufferFScreenInfo {
 }
 
 // networking ioctls:
+pub const SIOCGIFNAME: usize = 0x8910; // get iface name
+pub const SIOCGIFCONF: usize = 0x8912; // get iface list
+pub const SIOCGIFFLAGS: usize = 0x8913; // get flags
+pub const SIOCSIFFLAGS: usize = 0x8914; // set flags
+pub const SIOCGIFADDR: usize = 0x8915; // get PA address
 pub const SIOCGIFINDEX: usize = 0x8933;
 pub const SIOCGIFHWADDR: usize = 0x8927;
 pub const SIOCSIFADDR: usize = 0x8916; // set PA address
+pub const SIOCGIFNETMASK: usize = 0x891b; // get network PA mask
 pub const SIOCSIFNETMASK: usize = 0x891c; // set network PA mask
+pub const SIOCADDRT: usize = 0x890b; // add routing table entry
+pub const SIOCDELRT: usize = 0x890c; // delete routing table entry
+
+pub const IF_NAME_SIZE: usize = 16;
 
-const IF_NAME_SIZE: usize = 16;
+bitflags::bitflags! {
+    #[derive(Default)]
+    pub struct InterfaceFlags: u16 {
+        const UP          = 0x1;
+        const BROADCAST   = 0x2;
+        const DEBUG       = 0x4;
+        const LOOPBACK    = 0x8;
+        const POINTOPOINT = 0x10;
+        const NOTRAILERS  = 0x20;
+        const RUNNING     = 0x40;
+        const NOARP       = 0x80;
+        const PROMISC     = 0x100;
+        const ALLMULTI    = 0x200;
+        const MULTICAST   = 0x1000;
+    }
+}
 
 #[derive(Clone, Copy)]
 #[repr(C)]
@@ -399,4 +510,56 @@ impl IfReq {
         let name = &self.name[..null_index];
         core::str::from_utf8(name).ok()
     }
+
+    /// Sets the interface name. The name is truncated if it is longer than
+    /// `IF_NAME_SIZE - 1` bytes.
+    pub fn set_name(&mut self, name: &str) {
+        let size = core::cmp::min(name.len(), IF_NAME_SIZE - 1);
+
+        self.name = [0; IF_NAME_SIZE];
+        self.name[..size].copy_from_slice(&name.as_bytes()[..size]);
+    }
+}
+
+bitflags::bitflags! {
+    #[derive(Default)]
+    pub struct RouteFlags: u16 {
+        /// The route is usable.
+        const UP      = 0x1;
+        /// The destination is reached through a gateway.
+        const GATEWAY = 0x2;
+        /// The route is to a single host.
+        const HOST    = 0x4;
+    }
+}
+
+#[repr(C)]
+pub struct RtEntry {
+    pub pad1: ffi::c_ulong,
+    /// Target address.
+    pub dst: SockAddrStorage,
+    /// Gateway address, if [`RouteFlags::GATEWAY`] is set.
+    pub gateway: SockAddrStorage,
+    /// Target network mask.
+    pub genmask: SockAddrStorage,
+    pub flags: ffi::c_ushort,
+    pub pad2: ffi::c_short,
+    pub pad3: ffi::c_ulong,
+    pub pad4: *mut u8,
+    /// The metric of the route plus one, or zero to use the default metric.
+    pub metric: ffi::c_short,
+    /// Name of the interface (null terminated), or null to select it automatically.
+    pub dev: *mut u8,
+    pub mtu: ffi::c_ulong,
+    pub window: ffi::c_ulong,
+    pub irtt: ffi::c_ushort,
+}
+
+#[repr(C)]
+pub struct IfConf {
+    /// Size of the buffer in bytes.
+    pub len: ffi::c_int,
+    /// Array of [`IfReq`] structures. If the buffer is null, the required size of the
+    /// buffer is returned in `len`.
+    pub buffer: *mut IfReq,
 }
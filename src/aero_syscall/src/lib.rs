@@ -24,6 +24,7 @@
 extern crate num_derive;
 
 pub mod consts;
+pub mod ptrace;
 pub mod signal;
 pub mod socket;
 pub mod syscall;
@@ -58,6 +59,40 @@ bitflags::bitflags! {
     }
 }
 
+bitflags::bitflags! {
+    pub struct MSyncFlags: usize {
+        const MS_ASYNC = 0x1;
+        const MS_SYNC = 0x2;
+        const MS_INVALIDATE = 0x4;
+    }
+}
+
+bitflags::bitflags! {
+    pub struct MemFdFlags: usize {
+        const MFD_CLOEXEC = 0x1;
+        const MFD_ALLOW_SEALING = 0x2;
+    }
+}
+
+bitflags::bitflags! {
+    pub struct GrndFlags: usize {
+        const GRND_NONBLOCK = 0x1;
+        const GRND_RANDOM = 0x2;
+        const GRND_INSECURE = 0x4;
+    }
+}
+
+// mlibc/options/posix/include/sys/mman.h
+#[derive(Debug, Copy, Clone, FromPrimitive, PartialEq)]
+pub enum MAdvice {
+    Normal = 0,
+    Random = 1,
+    Sequential = 2,
+    WillNeed = 3,
+    DontNeed = 4,
+    Free = 8,
+}
+
 bitflags::bitflags! {
     pub struct OpenFlags: usize {
         // reserve 3 bits for the access mode
@@ -90,6 +125,20 @@ bitflags::bitflags! {
     }
 }
 
+impl OpenFlags {
+    /// Returns [`true`] if the access mode allows reading (ie. `O_RDONLY` or `O_RDWR`).
+    pub fn is_readable(&self) -> bool {
+        let mode = *self & Self::O_ACCMODE;
+        mode == Self::O_RDONLY || mode == Self::O_RDWR
+    }
+
+    /// Returns [`true`] if the access mode allows writing (ie. `O_WRONLY` or `O_RDWR`).
+    pub fn is_writable(&self) -> bool {
+        let mode = *self & Self::O_ACCMODE;
+        mode == Self::O_WRONLY || mode == Self::O_RDWR
+    }
+}
+
 bitflags::bitflags! {
     pub struct WaitPidFlags: usize {
         const WNOHANG    = 1;
@@ -269,6 +318,29 @@ pub struct TimeSpec {
     pub tv_nsec: isize,
 }
 
+impl TimeSpec {
+    pub const NANOS_PER_SEC: u64 = 1_000_000_000;
+
+    pub fn from_nanos(nanos: u64) -> Self {
+        Self {
+            tv_sec: (nanos / Self::NANOS_PER_SEC) as isize,
+            tv_nsec: (nanos % Self::NANOS_PER_SEC) as isize,
+        }
+    }
+
+    /// Returns the time in nanoseconds or [`None`] if the time is negative or the
+    /// nanoseconds field is out of range.
+    pub fn as_nanos(&self) -> Option<u64> {
+        if self.tv_sec < 0 || !(0..Self::NANOS_PER_SEC as isize).contains(&self.tv_nsec) {
+            return None;
+        }
+
+        (self.tv_sec as u64)
+            .checked_mul(Self::NANOS_PER_SEC)?
+            .checked_add(self.tv_nsec as u64)
+    }
+}
+
 #[repr(usize)]
 #[derive(Debug)]
 pub enum SeekWhence {
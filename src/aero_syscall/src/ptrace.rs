@@ -0,0 +1,104 @@
+// Copyright (C) 2021-2023 The Aero Project Developers.
+//
+// This file is part of The Aero Project.
+//
+// Aero is free software: you can redistribute it and/or modify
+// it under the terms of the GNU General Public License as published by
+// the Free Software Foundation, either version 3 of the License, or
+// (at your option) any later version.
+//
+// Aero is distributed in the hope that it will be useful,
+// but WITHOUT ANY WARRANTY; without even the implied warranty of
+// MERCHANTABILITY or FITNESS FOR A PARTICULAR PURPOSE. See the
+// GNU General Public License for more details.
+//
+// You should have received a copy of the GNU General Public License
+// along with Aero. If not, see <https://www.gnu.org/licenses/>.
+
+// mlibc/abis/linux/ptrace.h
+#[derive(Debug, Copy, Clone, FromPrimitive, PartialEq)]
+pub enum PtraceRequest {
+    TraceMe = 0,
+    PeekText = 1,
+    PeekData = 2,
+    PeekUser = 3,
+    PokeText = 4,
+    PokeData = 5,
+    PokeUser = 6,
+    Cont = 7,
+    Kill = 8,
+    SingleStep = 9,
+    GetRegs = 12,
+    SetRegs = 13,
+    GetFpRegs = 14,
+    SetFpRegs = 15,
+    Attach = 16,
+    Detach = 17,
+    Syscall = 24,
+    SetOptions = 0x4200,
+    GetEventMsg = 0x4201,
+    GetSigInfo = 0x4202,
+    SetSigInfo = 0x4203,
+    Seize = 0x4206,
+    Interrupt = 0x4207,
+}
+
+bitflags::bitflags! {
+    #[derive(Default)]
+    pub struct PtraceOptions: usize {
+        const PTRACE_O_TRACESYSGOOD   = 0x1;
+        const PTRACE_O_TRACEFORK      = 0x2;
+        const PTRACE_O_TRACEVFORK     = 0x4;
+        const PTRACE_O_TRACECLONE     = 0x8;
+        const PTRACE_O_TRACEEXEC      = 0x10;
+        const PTRACE_O_TRACEVFORKDONE = 0x20;
+        const PTRACE_O_TRACEEXIT      = 0x40;
+        const PTRACE_O_TRACESECCOMP   = 0x80;
+        const PTRACE_O_EXITKILL       = 0x100000;
+        const PTRACE_O_SUSPEND_SECCOMP = 0x200000;
+    }
+}
+
+pub const PTRACE_EVENT_FORK: usize = 1;
+pub const PTRACE_EVENT_VFORK: usize = 2;
+pub const PTRACE_EVENT_CLONE: usize = 3;
+pub const PTRACE_EVENT_EXEC: usize = 4;
+pub const PTRACE_EVENT_VFORK_DONE: usize = 5;
+pub const PTRACE_EVENT_EXIT: usize = 6;
+pub const PTRACE_EVENT_STOP: usize = 128;
+
+/// The general purpose registers of a traced task (`struct user_regs_struct`).
+#[cfg(target_arch = "x86_64")]
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone)]
+pub struct UserRegs {
+    pub r15: u64,
+    pub r14: u64,
+    pub r13: u64,
+    pub r12: u64,
+    pub rbp: u64,
+    pub rbx: u64,
+    pub r11: u64,
+    pub r10: u64,
+    pub r9: u64,
+    pub r8: u64,
+    pub rax: u64,
+    pub rcx: u64,
+    pub rdx: u64,
+    pub rsi: u64,
+    pub rdi: u64,
+    /// The number of the syscall the task is stopped in or `u64::MAX` if it is not stopped
+    /// in a syscall.
+    pub orig_rax: u64,
+    pub rip: u64,
+    pub cs: u64,
+    pub rflags: u64,
+    pub rsp: u64,
+    pub ss: u64,
+    pub fs_base: u64,
+    pub gs_base: u64,
+    pub ds: u64,
+    pub es: u64,
+    pub fs: u64,
+    pub gs: u64,
+}
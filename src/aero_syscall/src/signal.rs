@@ -64,6 +64,46 @@ pub const SIG_ERR: i64 = -1; // error
 pub const SIG_DFL: i64 = 0; // default
 pub const SIG_IGN: i64 = 1; // ignore
 
+// mlibc/abis/linux/signal.h: values for siginfo_t::si_code
+pub const SI_USER: i32 = 0;
+pub const SI_KERNEL: i32 = 0x80;
+
+pub const ILL_ILLOPC: i32 = 1;
+pub const ILL_ILLOPN: i32 = 2;
+pub const ILL_ILLADR: i32 = 3;
+pub const ILL_ILLTRP: i32 = 4;
+pub const ILL_PRVOPC: i32 = 5;
+pub const ILL_PRVREG: i32 = 6;
+pub const ILL_COPROC: i32 = 7;
+pub const ILL_BADSTK: i32 = 8;
+
+pub const FPE_INTDIV: i32 = 1;
+pub const FPE_INTOVF: i32 = 2;
+pub const FPE_FLTDIV: i32 = 3;
+pub const FPE_FLTOVF: i32 = 4;
+pub const FPE_FLTUND: i32 = 5;
+pub const FPE_FLTRES: i32 = 6;
+pub const FPE_FLTINV: i32 = 7;
+pub const FPE_FLTSUB: i32 = 8;
+
+pub const SEGV_MAPERR: i32 = 1;
+pub const SEGV_ACCERR: i32 = 2;
+
+pub const BUS_ADRALN: i32 = 1;
+pub const BUS_ADRERR: i32 = 2;
+pub const BUS_OBJERR: i32 = 3;
+
+pub const TRAP_BRKPT: i32 = 1;
+pub const TRAP_TRACE: i32 = 2;
+
+pub const SI_QUEUE: i32 = -1;
+pub const SI_TKILL: i32 = -6;
+
+// constants for sigaltstack()
+pub const SS_ONSTACK: i32 = 1;
+pub const SS_DISABLE: i32 = 2;
+pub const MINSIGSTKSZ: usize = 2048;
+
 #[derive(Debug, Default, Copy, Clone, PartialEq)]
 pub enum SignalHandler {
     Ignore,
@@ -165,3 +205,111 @@ impl From<SigProcMask> for usize {
         s as u64 as usize
     }
 }
+
+/// Information about why a signal was sent (`siginfo_t`).
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone)]
+pub struct SigInfo {
+    pub si_signo: i32,
+    pub si_errno: i32,
+    pub si_code: i32,
+    _pad: i32,
+    /// The fields that depend on the signal (`si_pid` and `si_uid`, `si_addr`, ...).
+    fields: [u64; 14],
+}
+
+impl SigInfo {
+    pub fn new(signal: usize, code: i32) -> Self {
+        Self {
+            si_signo: signal as i32,
+            si_code: code,
+            ..Default::default()
+        }
+    }
+
+    /// Creates the information for a signal sent by the process `pid`.
+    pub fn from_process(signal: usize, code: i32, pid: usize, uid: u32) -> Self {
+        let mut info = Self::new(signal, code);
+        info.fields[0] = (pid as u32 as u64) | ((uid as u64) << 32);
+        info
+    }
+
+    /// Creates the information for a signal caused by a fault at `address`.
+    pub fn from_fault(signal: usize, code: i32, address: usize) -> Self {
+        let mut info = Self::new(signal, code);
+        info.fields[0] = address as u64;
+        info
+    }
+
+    pub fn si_pid(&self) -> i32 {
+        self.fields[0] as u32 as i32
+    }
+
+    pub fn si_uid(&self) -> u32 {
+        (self.fields[0] >> 32) as u32
+    }
+
+    pub fn si_addr(&self) -> usize {
+        self.fields[0] as usize
+    }
+}
+
+/// Description of an alternate signal stack (`stack_t`).
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone)]
+pub struct SignalStack {
+    pub ss_sp: u64,
+    pub ss_flags: i32,
+    pub ss_size: usize,
+}
+
+/// The registers of the interrupted context (`struct sigcontext`).
+#[cfg(target_arch = "x86_64")]
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone)]
+pub struct MContext {
+    pub r8: u64,
+    pub r9: u64,
+    pub r10: u64,
+    pub r11: u64,
+    pub r12: u64,
+    pub r13: u64,
+    pub r14: u64,
+    pub r15: u64,
+    pub rdi: u64,
+    pub rsi: u64,
+    pub rbp: u64,
+    pub rbx: u64,
+    pub rdx: u64,
+    pub rax: u64,
+    pub rcx: u64,
+    pub rsp: u64,
+    pub rip: u64,
+    pub rflags: u64,
+    pub cs: u16,
+    pub gs: u16,
+    pub fs: u16,
+    pub ss: u16,
+    pub err: u64,
+    pub trapno: u64,
+    pub oldmask: u64,
+    pub cr2: u64,
+    /// Pointer to the saved FPU state (in the XSAVE format).
+    pub fpstate: u64,
+    pub reserved: [u64; 8],
+}
+
+/// The FPU state in the context is in the XSAVE format.
+pub const UC_FP_XSTATE: u64 = 1;
+
+/// The context of a signal handler (`ucontext_t`).
+#[cfg(target_arch = "x86_64")]
+#[repr(C)]
+#[derive(Debug, Default, Copy, Clone)]
+pub struct UContext {
+    pub uc_flags: u64,
+    pub uc_link: u64,
+    pub uc_stack: SignalStack,
+    pub uc_mcontext: MContext,
+    pub uc_sigmask: u64,
+}
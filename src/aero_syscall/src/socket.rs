@@ -1,3 +1,5 @@
+use core::ffi;
+
 use crate::SocketAddr;
 
 bitflags::bitflags! {
@@ -22,6 +24,53 @@ bitflags::bitflags! {
     }
 }
 
+// mlibc/abis/mlibc/socket.h
+pub const SOL_SOCKET: usize = 1;
+
+// NOTE: In the mlibc ABI, `IPPROTO_IP` has the same value as `SOL_SOCKET`, so the IPv4
+// options use level zero instead (which is the value of `IPPROTO_IP` on Linux).
+pub const SOL_IP: usize = 0;
+pub const SOL_TCP: usize = crate::IpProtocol::Tcp as usize;
+
+pub const SO_ACCEPTCONN: usize = 1;
+pub const SO_BROADCAST: usize = 2;
+pub const SO_DEBUG: usize = 3;
+pub const SO_DONTROUTE: usize = 4;
+pub const SO_ERROR: usize = 5;
+pub const SO_KEEPALIVE: usize = 6;
+pub const SO_LINGER: usize = 7;
+pub const SO_OOBINLINE: usize = 8;
+pub const SO_RCVBUF: usize = 9;
+pub const SO_RCVLOWAT: usize = 10;
+pub const SO_RCVTIMEO: usize = 11;
+pub const SO_REUSEADDR: usize = 12;
+pub const SO_SNDBUF: usize = 13;
+pub const SO_SNDLOWAT: usize = 14;
+pub const SO_SNDTIMEO: usize = 15;
+pub const SO_TYPE: usize = 16;
+pub const SO_SNDBUFFORCE: usize = 17;
+pub const SO_PEERCRED: usize = 18;
+pub const SO_PASSCRED: usize = 20;
+pub const SO_RCVBUFFORCE: usize = 21;
+pub const SO_PROTOCOL: usize = 23;
+pub const SO_REUSEPORT: usize = 24;
+pub const SO_DOMAIN: usize = 28;
+
+// mlibc/abis/mlibc/socket.h
+pub const SCM_RIGHTS: usize = 1;
+pub const SCM_CREDENTIALS: usize = 2;
+
+// mlibc/abis/linux/in.h
+pub const IP_TOS: usize = 1;
+pub const IP_TTL: usize = 2;
+
+// mlibc/options/posix/include/netinet/tcp.h
+pub const TCP_NODELAY: usize = 1;
+pub const TCP_MAXSEG: usize = 2;
+pub const TCP_KEEPIDLE: usize = 4;
+pub const TCP_KEEPINTVL: usize = 5;
+pub const TCP_KEEPCNT: usize = 6;
+
 // mlibc/abis/mlibc/socket.h
 #[derive(Debug)]
 #[repr(C)]
@@ -34,7 +83,7 @@ pub struct MessageHeader {
     iovec: *mut IoVec, // todo: use Option<NonNull<IoVec>>
     iovec_len: i32,    // todo: use ffi::c_int
 
-    control: *const u8,
+    control: *mut u8,
     control_len: usize,
 
     flags: i32, // todo: use ffi::c_int
@@ -54,6 +103,26 @@ impl MessageHeader {
         unsafe { Some(&mut *(self.name as *mut T)) }
     }
 
+    /// Returns a copy of the socket address structure. The address provided by userland may
+    /// be shorter than `T` (e.g. the path of a UNIX socket address does not have to fill the
+    /// whole structure), in which case the rest of the address is left as the default.
+    pub fn name<T: SocketAddr + Default>(&self) -> Option<T> {
+        if self.name.is_null() {
+            return None;
+        }
+
+        let mut address = T::default();
+        let size = core::cmp::min(self.name_len, core::mem::size_of::<T>());
+
+        // SAFETY: We know that the `name` pointer is valid and initialized for `name_len`
+        // bytes and at most `size_of::<T>()` bytes are copied into `address`.
+        unsafe {
+            core::ptr::copy_nonoverlapping(self.name, (&mut address as *mut T).cast::<u8>(), size);
+        }
+
+        Some(address)
+    }
+
     pub fn iovecs(&self) -> &[IoVec] {
         // SAFETY: We know that the `iovec` pointer is valid, initialized.
         unsafe { core::slice::from_raw_parts(self.iovec, self.iovec_len as usize) }
@@ -64,6 +133,204 @@ impl MessageHeader {
         // access so, its safe to construct a mutable slice from it.
         unsafe { core::slice::from_raw_parts_mut(self.iovec, self.iovec_len as usize) }
     }
+
+    /// Returns an iterator over the control messages (ancillary data) of the message.
+    pub fn control_messages(&self) -> ControlMessages<'_> {
+        if self.control.is_null() {
+            return ControlMessages { buffer: &[] };
+        }
+
+        // SAFETY: We know that the `control` pointer is valid and initialized.
+        let buffer = unsafe { core::slice::from_raw_parts(self.control, self.control_len) };
+        ControlMessages { buffer }
+    }
+
+    /// Returns a writer for the control messages (ancillary data) of the message. The length
+    /// of the control buffer has to be updated with the number of bytes written, using
+    /// [`MessageHeader::set_control_len`].
+    pub fn control_writer(&mut self) -> ControlWriter<'_> {
+        if self.control.is_null() {
+            return ControlWriter::new(&mut []);
+        }
+
+        // SAFETY: We know that the `control` pointer is valid and we have exclusive access so,
+        // its safe to construct a mutable slice from it.
+        let buffer = unsafe { core::slice::from_raw_parts_mut(self.control, self.control_len) };
+        ControlWriter::new(buffer)
+    }
+
+    /// Returns the pointer to the control buffer.
+    pub fn control_ptr(&self) -> *mut u8 {
+        self.control
+    }
+
+    /// Returns the length of the control buffer.
+    pub fn control_len(&self) -> usize {
+        self.control_len
+    }
+
+    pub fn set_control_len(&mut self, control_len: usize) {
+        self.control_len = control_len;
+    }
+
+    pub fn flags(&self) -> MessageFlags {
+        MessageFlags::from_bits_truncate(self.flags as usize)
+    }
+
+    pub fn set_flags(&mut self, flags: MessageFlags) {
+        self.flags = flags.bits() as i32;
+    }
+}
+
+// mlibc/abis/mlibc/socket.h
+//
+// The control messages are aligned to the size of `usize`:
+//
+// +-------------------+---------+-------------------+
+// | ControlHeader     | padding | data              |
+// +-------------------+---------+-------------------+
+// ^                             ^
+#[derive(Debug, Copy, Clone)]
+#[repr(C)]
+pub struct ControlHeader {
+    /// Size of the control message, including the header.
+    pub len: usize,
+    pub level: ffi::c_int,
+    pub typ: ffi::c_int,
+}
+
+/// Rounds `size` up to the alignment of the control messages.
+pub const fn cmsg_align(size: usize) -> usize {
+    let align = core::mem::size_of::<usize>();
+    (size + align - 1) & !(align - 1)
+}
+
+/// Returns the value of the `len` field of a control message carrying `size` bytes of data.
+pub const fn cmsg_len(size: usize) -> usize {
+    cmsg_align(core::mem::size_of::<ControlHeader>()) + size
+}
+
+/// Returns the number of bytes occupied by a control message carrying `size` bytes of data.
+pub const fn cmsg_space(size: usize) -> usize {
+    cmsg_align(core::mem::size_of::<ControlHeader>()) + cmsg_align(size)
+}
+
+/// A control message (ancillary data) of a [`MessageHeader`].
+#[derive(Debug)]
+pub struct ControlMessage<'a> {
+    pub level: usize,
+    pub typ: usize,
+    pub data: &'a [u8],
+}
+
+pub struct ControlMessages<'a> {
+    buffer: &'a [u8],
+}
+
+impl<'a> Iterator for ControlMessages<'a> {
+    /// [`Err`] is returned if the control message is malformed.
+    type Item = Result<ControlMessage<'a>, ()>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.buffer.len() < core::mem::size_of::<ControlHeader>() {
+            return None;
+        }
+
+        // SAFETY: The size of the buffer was checked above.
+        let header = unsafe {
+            self.buffer
+                .as_ptr()
+                .cast::<ControlHeader>()
+                .read_unaligned()
+        };
+
+        if header.len < cmsg_len(0) || header.len > self.buffer.len() {
+            self.buffer = &[];
+            return Some(Err(()));
+        }
+
+        let data = &self.buffer[cmsg_len(0)..header.len];
+        let next = core::cmp::min(cmsg_align(header.len), self.buffer.len());
+
+        self.buffer = &self.buffer[next..];
+
+        Some(Ok(ControlMessage {
+            level: header.level as usize,
+            typ: header.typ as usize,
+            data,
+        }))
+    }
+}
+
+pub struct ControlWriter<'a> {
+    buffer: &'a mut [u8],
+    offset: usize,
+    truncated: bool,
+}
+
+impl<'a> ControlWriter<'a> {
+    fn new(buffer: &'a mut [u8]) -> Self {
+        Self {
+            buffer,
+            offset: 0,
+            truncated: false,
+        }
+    }
+
+    /// Returns the maximum size of the data of the next control message.
+    pub fn capacity(&self) -> usize {
+        (self.buffer.len() - self.offset).saturating_sub(cmsg_len(0))
+    }
+
+    /// Writes a control message. If there is not enough space in the buffer, the data is
+    /// truncated and [`ControlWriter::is_truncated`] will return [`true`].
+    pub fn write(&mut self, level: usize, typ: usize, data: &[u8]) {
+        if self.buffer.len() - self.offset < cmsg_len(0) {
+            self.truncated = true;
+            return;
+        }
+
+        let size = core::cmp::min(data.len(), self.capacity());
+        self.truncated |= size < data.len();
+
+        let header = ControlHeader {
+            len: cmsg_len(size),
+            level: level as ffi::c_int,
+            typ: typ as ffi::c_int,
+        };
+
+        let buffer = &mut self.buffer[self.offset..];
+
+        // SAFETY: The buffer is large enough to hold the header, as checked above.
+        unsafe {
+            buffer
+                .as_mut_ptr()
+                .cast::<ControlHeader>()
+                .write_unaligned(header)
+        };
+        buffer[cmsg_len(0)..cmsg_len(size)].copy_from_slice(&data[..size]);
+
+        self.offset = core::cmp::min(self.offset + cmsg_space(size), self.buffer.len());
+    }
+
+    /// Returns the number of bytes written.
+    pub fn bytes_written(&self) -> usize {
+        self.offset
+    }
+
+    /// Returns whether any of the control messages did not fit in the buffer (`MSG_CTRUNC`).
+    pub fn is_truncated(&self) -> bool {
+        self.truncated
+    }
+}
+
+// mlibc/abis/mlibc/socket.h
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+#[repr(C)]
+pub struct UCred {
+    pub pid: ffi::c_int,
+    pub uid: ffi::c_uint,
+    pub gid: ffi::c_uint,
 }
 
 // options/posix/include/bits/posix/iovec.h
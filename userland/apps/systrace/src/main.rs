@@ -3,14 +3,111 @@ use std::process::Command;
 
 use std::os::unix::process::CommandExt;
 
-use aero_syscall::syscall0;
+use aero_syscall::prelude::*;
+use aero_syscall::ptrace::*;
+use aero_syscall::signal::SIGTRAP;
+
+fn ptrace(
+    request: PtraceRequest,
+    pid: usize,
+    addr: usize,
+    data: usize,
+) -> Result<usize, SyscallError> {
+    let result = syscall4(SYS_PTRACE, request as usize, pid, addr, data);
+    isize_as_syscall_result(result as isize)
+}
+
+fn waitpid(pid: usize) -> Result<u32, SyscallError> {
+    let mut status = 0u32;
+    let result = syscall3(SYS_WAITPID, pid, &mut status as *mut u32 as usize, 0);
+
+    isize_as_syscall_result(result as isize)?;
+    Ok(status)
+}
 
 fn main() {
     // [1..] to ignore the name of our binary.
     let args = &env::args().collect::<Vec<_>>()[1..];
 
-    syscall0(aero_syscall::prelude::SYS_TRACE);
+    let mut command = Command::new(&args[0]);
+    command.args(&args[1..]);
+
+    // SAFETY: The closure only performs a syscall.
+    unsafe {
+        command.pre_exec(|| {
+            ptrace(PtraceRequest::TraceMe, 0, 0, 0)
+                .map(|_| ())
+                .map_err(|err| std::io::Error::from_raw_os_error(err as i32))
+        });
+    }
+
+    let child = command
+        .spawn()
+        .unwrap_or_else(|err| panic!("systrace: failed to execute target process {args:?}: {err}"));
+
+    let pid = child.id() as usize;
+
+    // The tracee stops with SIGTRAP after the exec.
+    waitpid(pid).expect("systrace: failed to wait for the tracee");
+
+    ptrace(
+        PtraceRequest::SetOptions,
+        pid,
+        0,
+        PtraceOptions::PTRACE_O_TRACESYSGOOD.bits(),
+    )
+    .expect("systrace: failed to set the ptrace options");
+
+    let mut signal = 0;
+    let mut in_syscall = false;
+
+    loop {
+        ptrace(PtraceRequest::Syscall, pid, 0, signal)
+            .expect("systrace: failed to resume the tracee");
+        signal = 0;
+
+        let status = waitpid(pid).expect("systrace: failed to wait for the tracee");
+
+        if status & 0xff != 0x7f && in_syscall {
+            // The tracee exited in the middle of a syscall (e.g. exit_group).
+            eprintln!(" = ?");
+        }
+
+        if status & 0x7f == 0 {
+            eprintln!("+++ exited with {} +++", (status >> 8) & 0xff);
+            break;
+        } else if status & 0xff != 0x7f {
+            eprintln!("+++ killed by signal {} +++", status & 0x7f);
+            break;
+        }
+
+        let stop_signal = ((status >> 8) & 0xff) as usize;
+
+        if stop_signal != (SIGTRAP | 0x80) {
+            // Signal-delivery-stop: pass the signal through to the tracee.
+            eprintln!("--- signal {stop_signal} ---");
+            signal = stop_signal;
+            continue;
+        }
+
+        let mut regs = UserRegs::default();
+        ptrace(
+            PtraceRequest::GetRegs,
+            pid,
+            0,
+            &mut regs as *mut UserRegs as usize,
+        )
+        .expect("systrace: failed to read the registers of the tracee");
+
+        if in_syscall {
+            eprintln!(" = {:#x}", regs.rax);
+        } else {
+            eprint!(
+                "syscall {}({:#x}, {:#x}, {:#x}, {:#x}, {:#x}, {:#x})",
+                regs.orig_rax, regs.rdi, regs.rsi, regs.rdx, regs.r10, regs.r8, regs.r9
+            );
+        }
 
-    Command::new(&args[0]).args(&args[1..]).exec();
-    unreachable!("systrace: failed to execute target process {args:?}")
+        in_syscall = !in_syscall;
+    }
 }